@@ -0,0 +1,7 @@
+//! Runs the benchmarks in `verit_proof_checker::bench`. Use `cargo bench --features bench`.
+
+fn main() {
+    for result in verit_proof_checker::bench::run_all() {
+        println!("{}", result);
+    }
+}
@@ -0,0 +1,409 @@
+//! Anonymization of a problem and its proof, so that failing cases with proprietary symbol names
+//! can be shared. Every user symbol is renamed to a generic name, consistently across the problem
+//! and the proof: sorts become `S1`, `S2`, ..., functions become `f1`, `f2`, ..., constants become
+//! `c1`, `c2`, ..., bound variables become `x1`, `x2`, ..., and the names given with `:named`
+//! become `n1`, `n2`, .... The ids of "assume" commands that reuse an assertion name are renamed in
+//! the same way. Comments are removed, and so are "set-info" commands, except for the ":status" and
+//! ":smt-lib-version" attributes, since the others, like ":source", usually describe the origin of
+//! the problem.
+//!
+//! This works on the tokens of the input, so the output has the same commands as the input, one
+//! per line, and checks in the same way.
+
+use crate::{
+    ast::Terminal,
+    parser::{
+        error::{ErrorKind, ParserResult},
+        lexer::{Lexer, Reserved, Token},
+    },
+};
+use std::{collections::HashMap, fmt::Write, io::BufRead, str::FromStr};
+
+/// Anonymizes `problem` and `proof`, returning the anonymized problem and proof.
+pub fn anonymize<R1: BufRead, R2: BufRead>(
+    problem: R1,
+    proof: R2,
+) -> ParserResult<(String, String)> {
+    let mut anonymizer = Anonymizer::default();
+    let problem = anonymizer.anonymize_commands(read_commands(problem)?);
+    let proof = anonymizer.anonymize_commands(read_commands(proof)?);
+    Ok((problem, proof))
+}
+
+/// An s-expression, as read from the tokens of the input.
+enum SExpr {
+    Atom(Token),
+    List(Vec<SExpr>),
+}
+
+/// Reads the top-level s-expressions of `input`. This uses an explicit stack instead of recursion,
+/// since the terms of a proof can be very deeply nested.
+fn read_commands<R: BufRead>(input: R) -> ParserResult<Vec<SExpr>> {
+    let mut lexer = Lexer::new(input)?;
+    let mut stack = vec![Vec::new()];
+    loop {
+        match lexer.next_token()? {
+            Token::OpenParen => stack.push(Vec::new()),
+            Token::CloseParen if stack.len() > 1 => {
+                let list = stack.pop().unwrap();
+                stack.last_mut().unwrap().push(SExpr::List(list));
+            }
+            Token::Eof if stack.len() == 1 => return Ok(stack.pop().unwrap()),
+            token @ (Token::CloseParen | Token::Eof) => {
+                return Err((ErrorKind::UnexpectedToken(token), lexer.token_start).into());
+            }
+            atom => stack.last_mut().unwrap().push(SExpr::Atom(atom)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    Sort,
+    Function,
+    Constant,
+    Variable,
+    Name,
+}
+
+impl Kind {
+    fn prefix(self) -> &'static str {
+        match self {
+            Kind::Sort => "S",
+            Kind::Function => "f",
+            Kind::Constant => "c",
+            Kind::Variable => "x",
+            Kind::Name => "n",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Anonymizer {
+    /// The new name of each renamed symbol, and the kind of symbol it was first renamed as.
+    names: HashMap<String, (String, Kind)>,
+    counters: HashMap<Kind, usize>,
+}
+
+impl Anonymizer {
+    fn anonymize_commands(&mut self, commands: Vec<SExpr>) -> String {
+        let mut result = String::new();
+        for mut command in commands {
+            if self.anonymize_command(&mut command) {
+                write_sexpr(&mut result, &command);
+                result.push('\n');
+            }
+        }
+        result
+    }
+
+    /// Renames `symbol` as a symbol of kind `kind`, if it was not already renamed.
+    fn rename(&mut self, symbol: &mut SExpr, kind: Kind) {
+        if let SExpr::Atom(Token::Symbol(s)) = symbol {
+            if !self.names.contains_key(s.as_str()) {
+                let counter = self.counters.entry(kind).or_insert(0);
+                *counter += 1;
+                let new_name = format!("{}{}", kind.prefix(), counter);
+                self.names.insert(s.as_str().to_owned(), (new_name, kind));
+            }
+            self.replace(symbol, |_| true);
+        }
+    }
+
+    /// Replaces `symbol` with its new name, if it was renamed as a symbol of a kind for which
+    /// `filter` returns true.
+    fn replace(&self, symbol: &mut SExpr, filter: impl Fn(Kind) -> bool) {
+        if let SExpr::Atom(Token::Symbol(s)) = symbol {
+            if let Some((new_name, _)) = self.names.get(s.as_str()).filter(|(_, k)| filter(*k)) {
+                *symbol = SExpr::Atom(Token::Symbol(new_name.as_str().into()));
+            }
+        }
+    }
+
+    /// Anonymizes a top-level command. Returns `false` if the command should be removed.
+    fn anonymize_command(&mut self, command: &mut SExpr) -> bool {
+        let items = match command {
+            SExpr::List(items) if !items.is_empty() => items,
+            other => {
+                self.anonymize_term(other);
+                return true;
+            }
+        };
+        let (head, rest) = items.split_first_mut().unwrap();
+        match (head, rest) {
+            (SExpr::Atom(Token::ReservedWord(Reserved::DeclareSort)), [name, ..]) => {
+                self.rename(name, Kind::Sort);
+            }
+            (
+                SExpr::Atom(Token::ReservedWord(Reserved::DeclareFun)),
+                [name, SExpr::List(args), sort],
+            ) => {
+                self.rename(name, Self::function_kind(args));
+                args.iter_mut().for_each(|t| self.anonymize_term(t));
+                self.anonymize_term(sort);
+            }
+            (
+                SExpr::Atom(Token::ReservedWord(Reserved::DefineFun)),
+                [name, SExpr::List(params), rest @ ..],
+            ) => {
+                self.rename(name, Self::function_kind(params));
+                self.anonymize_bindings(params);
+                rest.iter_mut().for_each(|t| self.anonymize_term(t));
+            }
+            (SExpr::Atom(Token::Symbol(s)), [attribute, ..]) if s.as_str() == "set-info" => {
+                return matches!(
+                    attribute,
+                    SExpr::Atom(Token::Keyword(k))
+                        if k.as_str() == "status" || k.as_str() == "smt-lib-version"
+                );
+            }
+            (SExpr::Atom(Token::ReservedWord(Reserved::Assume)), [id, rest @ ..]) => {
+                self.replace(id, |k| k == Kind::Name);
+                rest.iter_mut().for_each(|t| self.anonymize_term(t));
+            }
+            (SExpr::Atom(Token::ReservedWord(Reserved::Step)), [id, rest @ ..]) => {
+                self.replace(id, |k| k == Kind::Name);
+                self.anonymize_attributes(rest, false);
+            }
+            (SExpr::Atom(Token::ReservedWord(Reserved::Anchor)), rest) => {
+                self.anonymize_attributes(rest, true);
+            }
+            (_, rest) => rest.iter_mut().for_each(|t| self.anonymize_term(t)),
+        }
+        true
+    }
+
+    /// Returns the kind of a declared or defined function, given its arguments.
+    fn function_kind(args: &[SExpr]) -> Kind {
+        if args.is_empty() {
+            Kind::Constant
+        } else {
+            Kind::Function
+        }
+    }
+
+    /// Anonymizes the clause and attributes of a "step" command, or the attributes of an "anchor"
+    /// command, given by `is_anchor`.
+    fn anonymize_attributes(&mut self, items: &mut [SExpr], is_anchor: bool) {
+        let mut iter = items.iter_mut();
+        while let Some(item) = iter.next() {
+            let keyword = match item {
+                SExpr::Atom(Token::Keyword(k)) => k.as_str().to_owned(),
+                other => {
+                    self.anonymize_term(other);
+                    continue;
+                }
+            };
+            let value = match iter.next() {
+                Some(value) => value,
+                None => break,
+            };
+            match (keyword.as_str(), value) {
+                // Rule names are never renamed
+                ("rule", _) => (),
+                ("step", id) => self.replace(id, |k| k == Kind::Name),
+                ("premises" | "discharge", SExpr::List(ids)) => {
+                    ids.iter_mut()
+                        .for_each(|id| self.replace(id, |k| k == Kind::Name));
+                }
+                ("args", SExpr::List(args)) => {
+                    for arg in args {
+                        self.anonymize_argument(arg, is_anchor);
+                    }
+                }
+                (_, value) => self.anonymize_term(value),
+            }
+        }
+    }
+
+    /// Anonymizes an argument of a "step" or "anchor" command. The arguments of anchors that are
+    /// not assignments are variable bindings, while the other arguments of steps are terms. In an
+    /// anchor assignment of the form `(:= (x S) y)`, `y` is also a variable introduced by the
+    /// anchor.
+    fn anonymize_argument(&mut self, arg: &mut SExpr, is_anchor: bool) {
+        let items = match arg {
+            SExpr::List(items) => items,
+            other => return self.anonymize_term(other),
+        };
+        match items.as_mut_slice() {
+            [SExpr::Atom(Token::Keyword(k)), var, value] if k.as_str() == "=" => {
+                match (var, value) {
+                    (SExpr::List(binding), value @ SExpr::Atom(_)) if is_anchor => {
+                        self.rename(value, Kind::Variable);
+                        self.anonymize_binding(binding);
+                    }
+                    (SExpr::List(binding), value) => {
+                        self.anonymize_term(value);
+                        self.anonymize_binding(binding);
+                    }
+                    (var, value) => {
+                        self.anonymize_term(value);
+                        self.rename(var, Kind::Variable);
+                    }
+                }
+            }
+            binding if is_anchor => self.anonymize_binding(binding),
+            _ => self.anonymize_term(arg),
+        }
+    }
+
+    /// Anonymizes a list of sorted variables, like the bindings of a quantifier.
+    fn anonymize_bindings(&mut self, bindings: &mut [SExpr]) {
+        for binding in bindings {
+            if let SExpr::List(binding) = binding {
+                self.anonymize_binding(binding);
+            }
+        }
+    }
+
+    /// Anonymizes a binding of the form `(x S)`, or `(x t)` in a "let" term. In both cases, the
+    /// variable is renamed after the sort or term, since the variable is not in scope in it.
+    fn anonymize_binding(&mut self, binding: &mut [SExpr]) {
+        if let [var, value] = binding {
+            self.anonymize_term(value);
+            self.rename(var, Kind::Variable);
+        }
+    }
+
+    fn anonymize_term(&mut self, term: &mut SExpr) {
+        let items = match term {
+            SExpr::Atom(_) => return self.replace(term, |_| true),
+            SExpr::List(items) => items,
+        };
+        let (head, rest) = match items.split_first_mut() {
+            Some(pair) => pair,
+            None => return,
+        };
+        match (head, rest) {
+            (
+                SExpr::Atom(Token::ReservedWord(
+                    Reserved::Forall | Reserved::Exists | Reserved::Choice | Reserved::Let,
+                )),
+                [SExpr::List(bindings), body @ ..],
+            ) => {
+                self.anonymize_bindings(bindings);
+                body.iter_mut().for_each(|t| self.anonymize_term(t));
+            }
+            (SExpr::Atom(Token::ReservedWord(Reserved::Bang)), [inner, attributes @ ..]) => {
+                self.anonymize_term(inner);
+                let mut iter = attributes.iter_mut();
+                while let Some(attribute) = iter.next() {
+                    match attribute {
+                        SExpr::Atom(Token::Keyword(k)) if k.as_str() == "named" => {
+                            if let Some(name) = iter.next() {
+                                self.rename(name, Kind::Name);
+                            }
+                        }
+                        other => self.anonymize_term(other),
+                    }
+                }
+            }
+            (head, rest) => {
+                self.anonymize_term(head);
+                rest.iter_mut().for_each(|t| self.anonymize_term(t));
+            }
+        }
+    }
+}
+
+fn write_sexpr(out: &mut String, sexpr: &SExpr) {
+    let token = match sexpr {
+        SExpr::Atom(token) => token,
+        SExpr::List(items) => {
+            out.push('(');
+            for (i, item) in items.iter().enumerate() {
+                if i != 0 {
+                    out.push(' ');
+                }
+                write_sexpr(out, item);
+            }
+            out.push(')');
+            return;
+        }
+    };
+    match token {
+        Token::Symbol(s) => {
+            let s = s.as_str();
+            let is_simple = s.chars().all(Lexer::is_symbol_character)
+                && !s.starts_with(|c: char| c.is_ascii_digit())
+                && Reserved::from_str(s).is_err();
+            if is_simple && !s.is_empty() {
+                out.push_str(s);
+            } else {
+                write!(out, "|{}|", s).unwrap();
+            }
+        }
+        Token::Keyword(k) => write!(out, ":{}", k).unwrap(),
+        Token::Numeral(n) => write!(out, "{}", n).unwrap(),
+        Token::Decimal(r) => write!(out, "{:?}", Terminal::Real(r.clone())).unwrap(),
+        Token::String(s) => write!(out, "\"{}\"", s.replace('"', "\"\"")).unwrap(),
+        Token::ReservedWord(r) => write!(out, "{:?}", r).unwrap(),
+        Token::OpenParen | Token::CloseParen | Token::Eof => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        checker::{self, Correctness, ProofChecker},
+        parser::parse_proof_with_definitions,
+    };
+
+    #[test]
+    fn anonymize() {
+        let problem = "
+            (set-info :source |Confidential: ACME verification conditions|)
+            (set-info :status unsat)
+            (set-logic UF)
+            (declare-sort Widget 0)
+            (declare-fun isBroken (Widget) Bool)
+            (declare-fun gadget () Widget)
+            (define-fun fixed ((w Widget)) Bool (not (isBroken w)))
+            (assert (! (forall ((w Widget)) (fixed w)) :named allFixed))
+            (assert (isBroken gadget)) ; the secret bug
+            (check-sat)
+        ";
+        let proof = "
+            (assume allFixed (forall ((w Widget)) (not (isBroken w))))
+            (assume h2 (isBroken gadget))
+            (anchor :step t3 :args ((:= (w Widget) w2)))
+            (step t3.t1 (cl (= (not (isBroken w)) (not (isBroken w2)))) :rule refl)
+            (step t3 (cl (= (forall ((w Widget)) (not (isBroken w))) \
+                (forall ((w2 Widget)) (not (isBroken w2))))) :rule bind)
+            (step t4 (cl (or (not (forall ((w Widget)) (not (isBroken w)))) (not (isBroken gadget))))
+                :rule forall_inst :args ((:= w gadget)))
+            (step t5 (cl (not (forall ((w Widget)) (not (isBroken w)))) (not (isBroken gadget)))
+                :rule or :premises (t4))
+            (step t6 (cl) :rule resolution :premises (t5 allFixed h2))
+        ";
+        let (problem, proof) = super::anonymize(problem.as_bytes(), proof.as_bytes()).unwrap();
+        let expected_problem = "\
+            (set-info :status unsat)\n\
+            (set-logic UF)\n\
+            (declare-sort S1 0)\n\
+            (declare-fun f1 (S1) Bool)\n\
+            (declare-fun c1 () S1)\n\
+            (define-fun f2 ((x1 S1)) Bool (not (f1 x1)))\n\
+            (assert (! (forall ((x1 S1)) (f2 x1)) :named n1))\n\
+            (assert (f1 c1))\n\
+            (check-sat)\n";
+        assert_eq!(expected_problem, problem);
+        let expected_proof = "\
+            (assume n1 (forall ((x1 S1)) (not (f1 x1))))\n\
+            (assume h2 (f1 c1))\n\
+            (anchor :step t3 :args ((:= (x1 S1) x2)))\n\
+            (step t3.t1 (cl (= (not (f1 x1)) (not (f1 x2)))) :rule refl)\n\
+            (step t3 (cl (= (forall ((x1 S1)) (not (f1 x1))) \
+                (forall ((x2 S1)) (not (f1 x2))))) :rule bind)\n\
+            (step t4 (cl (or (not (forall ((x1 S1)) (not (f1 x1)))) (not (f1 c1)))) \
+                :rule forall_inst :args ((:= x1 c1)))\n\
+            (step t5 (cl (not (forall ((x1 S1)) (not (f1 x1)))) (not (f1 c1))) \
+                :rule or :premises (t4))\n\
+            (step t6 (cl) :rule resolution :premises (t5 n1 h2))\n";
+        assert_eq!(expected_proof, proof);
+
+        let (proof, mut pool) = parse_proof_with_definitions(&problem, &proof).unwrap();
+        let result = ProofChecker::new(&mut pool, checker::Config::default()).check(&proof);
+        assert!(matches!(result, Ok(Correctness::True)));
+    }
+}
@@ -0,0 +1,376 @@
+//! Comparisons between terms that ignore some differences between them, like the orientation of
+//! equalities or the names of bound variables. The functions in this module are the entry points
+//! meant for external tools. Each takes an optional time limit, and returns
+//! `Err(TimeLimitExceeded)` if the comparison doesn't finish in time, since some comparisons, like
+//! the ones modulo associativity and commutativity, may take a long time on large terms. Inside
+//! the checker, the same comparisons are done through the `DeepEq` trait.
+
+use super::{
+    ByRefRc, Identifier, Operator, ProofArg, ProofCommand, ProofStep, SortedVar, Term, Terminal,
+};
+use std::time::{Duration, Instant};
+
+/// The error returned when a comparison doesn't finish within its time limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeLimitExceeded;
+
+/// Compares two terms structurally, by value. This is the same as comparing them with `==` if
+/// they are in the same term pool, but also works for terms from different pools.
+pub fn eq_exact(
+    a: &Term,
+    b: &Term,
+    time_limit: Option<Duration>,
+) -> Result<bool, TimeLimitExceeded> {
+    compare(a, b, DeepEqPolicy::EXACT, time_limit)
+}
+
+/// Compares two terms, considering equalities equal to their reflections, meaning the terms
+/// (= a b) and (= b a) are considered equal.
+pub fn eq_modulo_eq_orientation(
+    a: &Term,
+    b: &Term,
+    time_limit: Option<Duration>,
+) -> Result<bool, TimeLimitExceeded> {
+    compare(a, b, DeepEqPolicy::FLIP_EQUALITIES, time_limit)
+}
+
+/// Compares two terms modulo the renaming of bound variables, meaning the terms
+/// (forall ((x Int)) (> x 0)) and (forall ((y Int)) (> y 0)) are considered equal.
+pub fn alpha_eq(
+    a: &Term,
+    b: &Term,
+    time_limit: Option<Duration>,
+) -> Result<bool, TimeLimitExceeded> {
+    compare(a, b, DeepEqPolicy::ALPHA_EQUIVALENCE, time_limit)
+}
+
+/// Compares two terms modulo the associativity and commutativity of "and" and "or", meaning the
+/// terms (and p (and q r)) and (and r q p) are considered equal.
+pub fn eq_modulo_ac(
+    a: &Term,
+    b: &Term,
+    time_limit: Option<Duration>,
+) -> Result<bool, TimeLimitExceeded> {
+    compare(a, b, DeepEqPolicy::AC_AND_OR, time_limit)
+}
+
+/// Compares two terms, ignoring the differences allowed by `policy`. This can be used to combine
+/// several of the comparisons above.
+pub fn compare(
+    a: &Term,
+    b: &Term,
+    policy: DeepEqPolicy,
+    time_limit: Option<Duration>,
+) -> Result<bool, TimeLimitExceeded> {
+    let mut state = DeepEqState::new(policy);
+    state.deadline = time_limit.map(|limit| Instant::now() + limit);
+    let result = DeepEq::eq_impl(a, b, &mut state);
+    if state.timed_out {
+        Err(TimeLimitExceeded)
+    } else {
+        Ok(result)
+    }
+}
+
+/// The differences between terms that are ignored when comparing them using `DeepEq`. Each rule
+/// should use the strictest policy allowed by the Alethe specification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeepEqPolicy {
+    /// Consider "=" terms that are "reflections" of each other as equal, meaning the terms (= a b)
+    /// and (= b a) are considered equal.
+    pub flip_equalities: bool,
+
+    /// Consider terms that only differ in the names of their bound variables as equal, meaning the
+    /// terms (forall ((x Int)) (> x 0)) and (forall ((y Int)) (> y 0)) are considered equal.
+    pub alpha_equivalence: bool,
+
+    /// Consider "and" and "or" terms equal modulo associativity and commutativity, meaning the
+    /// terms (and p (and q r)) and (and r q p) are considered equal.
+    pub ac_and_or: bool,
+}
+
+impl DeepEqPolicy {
+    pub const EXACT: Self = Self {
+        flip_equalities: false,
+        alpha_equivalence: false,
+        ac_and_or: false,
+    };
+
+    pub const FLIP_EQUALITIES: Self = Self {
+        flip_equalities: true,
+        ..Self::EXACT
+    };
+
+    pub const ALPHA_EQUIVALENCE: Self = Self {
+        alpha_equivalence: true,
+        ..Self::EXACT
+    };
+
+    pub const AC_AND_OR: Self = Self {
+        ac_and_or: true,
+        ..Self::EXACT
+    };
+}
+
+/// How many terms are compared between each check of the deadline. Reading the clock is much
+/// slower than comparing two terms, so we don't do it for every term.
+const DEADLINE_CHECK_INTERVAL: u32 = 1024;
+
+/// The state of a `DeepEq` comparison: the policy used, and the pairs of variables that are bound
+/// by the binders visited so far, when comparing modulo alpha equivalence.
+pub struct DeepEqState {
+    policy: DeepEqPolicy,
+    bound_vars: Vec<(String, String)>,
+
+    /// The time when the comparison should stop, if it has a time limit.
+    deadline: Option<Instant>,
+    steps_until_check: u32,
+    timed_out: bool,
+}
+
+impl DeepEqState {
+    fn new(policy: DeepEqPolicy) -> Self {
+        Self {
+            policy,
+            bound_vars: Vec::new(),
+            deadline: None,
+            steps_until_check: DEADLINE_CHECK_INTERVAL,
+            timed_out: false,
+        }
+    }
+
+    /// Returns `true` if the deadline has passed. Once it has, every comparison fails, so the
+    /// comparison stops quickly.
+    fn is_out_of_time(&mut self) -> bool {
+        let deadline = match self.deadline {
+            Some(d) => d,
+            None => return false,
+        };
+        if !self.timed_out {
+            self.steps_until_check -= 1;
+            if self.steps_until_check == 0 {
+                self.steps_until_check = DEADLINE_CHECK_INTERVAL;
+                self.timed_out = Instant::now() >= deadline;
+            }
+        }
+        self.timed_out
+    }
+
+    /// Compares two variable names. When comparing modulo alpha equivalence, a bound variable is
+    /// only equal to the variable bound in the same position in the other term.
+    fn vars_eq(&self, a: &Identifier, b: &Identifier) -> bool {
+        if let (Identifier::Simple(a), Identifier::Simple(b)) = (a, b) {
+            for (x, y) in self.bound_vars.iter().rev() {
+                if a == x.as_str() || b == y.as_str() {
+                    return a == x.as_str() && b == y.as_str();
+                }
+            }
+        }
+        a == b
+    }
+
+    /// Compares the bindings of two binder terms (quantifiers, "let" or "choice" terms), and then
+    /// compares their inner terms using `inner`.
+    fn binders_eq<F>(&mut self, binds_a: &[SortedVar], binds_b: &[SortedVar], inner: F) -> bool
+    where
+        F: FnOnce(&mut Self) -> bool,
+    {
+        if binds_a.len() != binds_b.len() {
+            return false;
+        }
+        let alpha = self.policy.alpha_equivalence;
+        for ((name_a, value_a), (name_b, value_b)) in binds_a.iter().zip(binds_b) {
+            if !(alpha || name_a == name_b) || !DeepEq::eq_impl(value_a, value_b, self) {
+                return false;
+            }
+        }
+        if !alpha {
+            return inner(self);
+        }
+        let previous_len = self.bound_vars.len();
+        self.bound_vars.extend(
+            binds_a
+                .iter()
+                .zip(binds_b)
+                .map(|((a, _), (b, _))| (a.clone(), b.clone())),
+        );
+        let result = inner(self);
+        self.bound_vars.truncate(previous_len);
+        result
+    }
+}
+
+/// A trait that implements less strict definitions of equality for terms. This trait represents
+/// definitions of equality that differ from `PartialEq`:
+/// - `DeepEq::eq` implements a "deep" equality, meaning that it compares `ByRefRc`s by value,
+///   instead of by reference
+/// - `DeepEq::eq_modulo_reordering` is also a "deep" equality, but it considers "=" terms that are
+///   "reflections" of each other as equal, meaning the terms (= a b) and (= b a) are considered
+///   equal by this method
+/// - `DeepEq::eq_with_policy` is a "deep" equality that ignores the differences allowed by the
+///   given `DeepEqPolicy`
+pub trait DeepEq {
+    fn eq(a: &Self, b: &Self) -> bool {
+        Self::eq_with_policy(a, b, DeepEqPolicy::EXACT)
+    }
+
+    fn eq_modulo_reordering(a: &Self, b: &Self) -> bool {
+        Self::eq_with_policy(a, b, DeepEqPolicy::FLIP_EQUALITIES)
+    }
+
+    fn eq_with_policy(a: &Self, b: &Self, policy: DeepEqPolicy) -> bool {
+        Self::eq_impl(a, b, &mut DeepEqState::new(policy))
+    }
+
+    fn eq_impl(a: &Self, b: &Self, state: &mut DeepEqState) -> bool;
+}
+
+/// Collects the arguments of nested applications of the operator `op`, as in the arguments of
+/// (and p (and q r)) are p, q and r.
+fn flatten_args<'a>(op: Operator, args: &'a [ByRefRc<Term>], acc: &mut Vec<&'a ByRefRc<Term>>) {
+    for arg in args {
+        match arg.as_ref() {
+            Term::Op(inner_op, inner_args) if *inner_op == op => flatten_args(op, inner_args, acc),
+            _ => acc.push(arg),
+        }
+    }
+}
+
+impl DeepEq for Term {
+    fn eq_impl(a: &Self, b: &Self, state: &mut DeepEqState) -> bool {
+        if state.is_out_of_time() {
+            return false;
+        }
+        match (a, b) {
+            (Term::App(f_a, args_a), Term::App(f_b, args_b)) => {
+                DeepEq::eq_impl(f_a.as_ref(), f_b.as_ref(), state)
+                    && DeepEq::eq_impl(args_a, args_b, state)
+            }
+            (Term::Op(op_a, args_a), Term::Op(op_b, args_b)) => {
+                if state.policy.flip_equalities {
+                    if let (Operator::Equals, [a_1, a_2], Operator::Equals, [b_1, b_2]) =
+                        (op_a, args_a.as_slice(), op_b, args_b.as_slice())
+                    {
+                        // If the term is an equality of two terms, we also check if they would be
+                        // equal if one of them was flipped
+                        return DeepEq::eq_impl(&(a_1, a_2), &(b_1, b_2), state)
+                            || DeepEq::eq_impl(&(a_1, a_2), &(b_2, b_1), state);
+                    }
+                }
+                if state.policy.ac_and_or
+                    && op_a == op_b
+                    && matches!(op_a, Operator::And | Operator::Or)
+                {
+                    let (mut flat_a, mut flat_b) = (Vec::new(), Vec::new());
+                    flatten_args(*op_a, args_a, &mut flat_a);
+                    flatten_args(*op_b, args_b, &mut flat_b);
+                    if flat_a.len() != flat_b.len() {
+                        return false;
+                    }
+                    // Since deep equality is an equivalence relation, we can greedily match each
+                    // argument of "b" with any equal argument of "a"
+                    for b in flat_b {
+                        match flat_a.iter().position(|a| DeepEq::eq_impl(*a, b, state)) {
+                            Some(i) => flat_a.swap_remove(i),
+                            None => return false,
+                        };
+                    }
+                    return true;
+                }
+                // General case
+                op_a == op_b && DeepEq::eq_impl(args_a, args_b, state)
+            }
+            (Term::Sort(kind_a, args_a), Term::Sort(kind_b, args_b)) => {
+                kind_a == kind_b && DeepEq::eq_impl(args_a, args_b, state)
+            }
+            (Term::Terminal(a), Term::Terminal(b)) => match (a, b) {
+                (Terminal::Var(iden_a, sort_a), Terminal::Var(iden_b, sort_b)) => {
+                    state.vars_eq(iden_a, iden_b) && DeepEq::eq_impl(sort_a, sort_b, state)
+                }
+                (a, b) => a == b,
+            },
+            (Term::Quant(q_a, binds_a, a), Term::Quant(q_b, binds_b, b)) => {
+                q_a == q_b && state.binders_eq(binds_a, binds_b, |s| DeepEq::eq_impl(a, b, s))
+            }
+            (Term::Choice(var_a, a), Term::Choice(var_b, b)) => state.binders_eq(
+                std::slice::from_ref(var_a),
+                std::slice::from_ref(var_b),
+                |s| DeepEq::eq_impl(a, b, s),
+            ),
+            (Term::Let(binds_a, a), Term::Let(binds_b, b)) => {
+                state.binders_eq(binds_a, binds_b, |s| DeepEq::eq_impl(a, b, s))
+            }
+            _ => false,
+        }
+    }
+}
+
+impl DeepEq for ProofArg {
+    fn eq_impl(a: &Self, b: &Self, state: &mut DeepEqState) -> bool {
+        match (a, b) {
+            (ProofArg::Term(a), ProofArg::Term(b)) => DeepEq::eq_impl(a, b, state),
+            (ProofArg::Assign(sa, ta), ProofArg::Assign(sb, tb)) => {
+                sa == sb && DeepEq::eq_impl(ta, tb, state)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl DeepEq for ProofCommand {
+    fn eq_impl(a: &Self, b: &Self, state: &mut DeepEqState) -> bool {
+        match (a, b) {
+            (
+                ProofCommand::Assume { id: a_id, term: a },
+                ProofCommand::Assume { id: b_id, term: b },
+            ) => a_id == b_id && DeepEq::eq_impl(a, b, state),
+            (ProofCommand::Step(a), ProofCommand::Step(b)) => DeepEq::eq_impl(a, b, state),
+            _ => false,
+        }
+    }
+}
+
+impl DeepEq for ProofStep {
+    fn eq_impl(a: &Self, b: &Self, state: &mut DeepEqState) -> bool {
+        a.id == b.id
+            && DeepEq::eq_impl(&a.clause, &b.clause, state)
+            && a.rule == b.rule
+            && a.premises == b.premises
+            && DeepEq::eq_impl(&a.args, &b.args, state)
+    }
+}
+
+impl<T: DeepEq> DeepEq for &T {
+    fn eq_impl(a: &Self, b: &Self, state: &mut DeepEqState) -> bool {
+        DeepEq::eq_impl(*a, *b, state)
+    }
+}
+
+impl<T: DeepEq> DeepEq for ByRefRc<T> {
+    fn eq_impl(a: &Self, b: &Self, state: &mut DeepEqState) -> bool {
+        // If there are bound variables, the same term may refer to different variables in "a" and
+        // "b", so we can only skip the comparison if there are none
+        (a == b && state.bound_vars.is_empty()) || DeepEq::eq_impl(a.as_ref(), b.as_ref(), state)
+    }
+}
+
+impl<T: DeepEq> DeepEq for Vec<T> {
+    fn eq_impl(a: &Self, b: &Self, state: &mut DeepEqState) -> bool {
+        a.len() == b.len()
+            && a.iter()
+                .zip(b.iter())
+                .all(|(a, b)| DeepEq::eq_impl(a, b, state))
+    }
+}
+
+impl<T: DeepEq> DeepEq for (T, T) {
+    fn eq_impl(a: &Self, b: &Self, state: &mut DeepEqState) -> bool {
+        DeepEq::eq_impl(&a.0, &b.0, state) && DeepEq::eq_impl(&a.1, &b.1, state)
+    }
+}
+
+impl DeepEq for SortedVar {
+    fn eq_impl(a: &Self, b: &Self, state: &mut DeepEqState) -> bool {
+        a.0 == b.0 && DeepEq::eq_impl(&a.1, &b.1, state)
+    }
+}
@@ -0,0 +1,141 @@
+use super::{Proof, ProofCommand};
+use std::collections::HashMap;
+
+/// The dependencies between the commands of a proof, computed once so that they can be queried
+/// many times. Every command is a node, including the commands inside subproofs, and there is an
+/// edge from each step to each of its premises. A subproof is represented by the step that ends it,
+/// which also depends on every other command in the subproof, since the rule that ends a subproof
+/// may refer to any of them.
+///
+/// Commands are identified by their ids. If an id is repeated, which is only allowed when steps
+/// shadow ids inside subproofs, it refers to the first command with that id.
+#[derive(Debug)]
+pub struct DependencyGraph<'a> {
+    /// The ids of the commands, in the order in which they appear in the proof.
+    ids: Vec<&'a str>,
+    indices: HashMap<&'a str, usize>,
+    premises: Vec<Vec<usize>>,
+    dependents: Vec<Vec<usize>>,
+}
+
+impl<'a> DependencyGraph<'a> {
+    pub fn new(proof: &'a Proof) -> Self {
+        let mut graph = Self {
+            ids: Vec::new(),
+            indices: HashMap::new(),
+            premises: Vec::new(),
+            dependents: Vec::new(),
+        };
+        graph.add_commands(&proof.0, &mut Vec::new());
+        graph.dependents = vec![Vec::new(); graph.ids.len()];
+        for (node, premises) in graph.premises.iter().enumerate() {
+            for &p in premises {
+                graph.dependents[p].push(node);
+            }
+        }
+        graph
+    }
+
+    /// Adds the nodes of `commands`, and returns them. `scopes` holds the nodes of the commands of
+    /// each enclosing scope, which the premises of the commands may refer to.
+    fn add_commands(
+        &mut self,
+        commands: &'a [ProofCommand],
+        scopes: &mut Vec<Vec<usize>>,
+    ) -> Vec<usize> {
+        scopes.push(Vec::with_capacity(commands.len()));
+        for command in commands {
+            let node = match command {
+                ProofCommand::Assume { id, .. } => self.add_node(id, Vec::new()),
+                ProofCommand::Step(step) => {
+                    let premises = step.premises.iter().map(|&(d, i)| scopes[d][i]).collect();
+                    self.add_node(&step.id, premises)
+                }
+                ProofCommand::Subproof { commands, .. } => {
+                    let inner = self.add_commands(commands, scopes);
+                    let (&last, rest) = inner.split_last().unwrap();
+                    for &node in rest {
+                        if !self.premises[last].contains(&node) {
+                            self.premises[last].push(node);
+                        }
+                    }
+                    last
+                }
+            };
+            scopes.last_mut().unwrap().push(node);
+        }
+        scopes.pop().unwrap()
+    }
+
+    fn add_node(&mut self, id: &'a str, premises: Vec<usize>) -> usize {
+        let node = self.ids.len();
+        self.ids.push(id);
+        self.indices.entry(id).or_insert(node);
+        self.premises.push(premises);
+        node
+    }
+
+    /// Returns the ids of the commands that the command with id `id` depends on, directly or
+    /// through other commands, in the order in which they appear in the proof. Returns `None` if
+    /// there is no command with that id.
+    pub fn dependencies(&self, id: &str) -> Option<Vec<&'a str>> {
+        self.reachable(id, &self.premises)
+    }
+
+    /// Returns the ids of the commands that depend on the command with id `id`, directly or
+    /// through other commands, in the order in which they appear in the proof. Returns `None` if
+    /// there is no command with that id.
+    pub fn dependents(&self, id: &str) -> Option<Vec<&'a str>> {
+        self.reachable(id, &self.dependents)
+    }
+
+    fn reachable(&self, id: &str, edges: &[Vec<usize>]) -> Option<Vec<&'a str>> {
+        let start = *self.indices.get(id)?;
+        let mut visited = vec![false; self.ids.len()];
+        let mut to_visit = edges[start].clone();
+        while let Some(node) = to_visit.pop() {
+            if !std::mem::replace(&mut visited[node], true) {
+                to_visit.extend(&edges[node]);
+            }
+        }
+        let result = visited
+            .into_iter()
+            .enumerate()
+            .filter(|&(node, visited)| visited && node != start)
+            .map(|(node, _)| self.ids[node])
+            .collect();
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::tests::parse_proof;
+
+    #[test]
+    fn dependencies() {
+        let proof = parse_proof(
+            "(assume h1 true)
+            (assume h2 true)
+            (step t3 (cl true) :rule trust_me :premises (h1))
+            (anchor :step t4)
+            (assume t4.h1 true)
+            (step t4.t2 (cl true) :rule trust_me :premises (h2))
+            (step t4 (cl true) :rule trust_me)
+            (step t5 (cl) :rule trust_me :premises (t3 t4))
+            (step t6 (cl true) :rule trust_me)",
+        );
+        assert_eq!(Some(vec!["h1"]), proof.dependencies("t3"));
+        assert_eq!(Some(vec!["h2", "t4.h1", "t4.t2"]), proof.dependencies("t4"));
+        assert_eq!(
+            Some(vec!["h1", "h2", "t3", "t4.h1", "t4.t2", "t4"]),
+            proof.dependencies("t5")
+        );
+        assert_eq!(Some(vec![]), proof.dependencies("t6"));
+
+        assert_eq!(Some(vec!["t4.t2", "t4", "t5"]), proof.dependents("h2"));
+        assert_eq!(Some(vec!["t4", "t5"]), proof.dependents("t4.h1"));
+        assert_eq!(Some(vec![]), proof.dependents("t5"));
+        assert_eq!(None, proof.dependents("t7"));
+    }
+}
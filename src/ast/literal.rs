@@ -0,0 +1,113 @@
+use super::Term;
+
+/// Identifies a term by its address. Since terms are hash consed by the `TermPool`, two terms in
+/// the same pool are equal exactly when they have the same id, so ids can be hashed and compared
+/// without looking at the terms. An id is only meaningful while its term is alive, and ids of
+/// terms from different pools should not be compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TermId(usize);
+
+impl TermId {
+    pub fn of(term: &Term) -> Self {
+        Self(term as *const Term as usize)
+    }
+}
+
+/// A literal of a clause, as a term with some number of leading negations. The term under the
+/// negations is the atom of the literal. For example, the term "(not (not p))" is the literal with
+/// atom "p" negated twice. Unlike in a purely propositional view, "p" and "(not (not p))" are
+/// different literals, since rules like "resolution" distinguish between them.
+///
+/// Literals are ordered by their atom first, so a sorted list of literals keeps the literals that
+/// share an atom next to each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Literal {
+    atom: TermId,
+    negated: u32,
+}
+
+impl Literal {
+    pub fn from_term(term: &Term) -> Self {
+        let (negated, atom) = term.remove_all_negations();
+        Self {
+            atom: TermId::of(atom),
+            negated,
+        }
+    }
+
+    /// Returns the id of the term under the leading negations.
+    pub fn atom(&self) -> TermId {
+        self.atom
+    }
+
+    /// Returns the number of leading negations.
+    pub fn negated(&self) -> u32 {
+        self.negated
+    }
+
+    /// Returns `true` if the literal has an even number of leading negations.
+    pub fn polarity(&self) -> bool {
+        self.negated.is_multiple_of(2)
+    }
+
+    /// Returns the literal with one more leading negation.
+    pub fn negate(self) -> Self {
+        Self {
+            negated: self.negated + 1,
+            ..self
+        }
+    }
+
+    /// Returns the literal with one less leading negation, or `None` if it has none.
+    pub fn remove_negation(self) -> Option<Self> {
+        Some(Self {
+            negated: self.negated.checked_sub(1)?,
+            ..self
+        })
+    }
+
+    /// Returns `true` if one of the literals is the negation of the other, that is, if they have
+    /// the same atom, and one of them has exactly one more leading negation.
+    pub fn is_complement(&self, other: &Literal) -> bool {
+        self.atom == other.atom && self.negated.abs_diff(other.negated) == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn literals() {
+        let proof = "(step t1 (cl p (not p) (not (not p)) q) :rule trust_me)";
+        let (proof, _pool) =
+            parser::parse_proof_only(proof.as_bytes(), parser::Config::default()).unwrap();
+        let (p, not_p, not_not_p, q) = match proof.0[0].clause() {
+            [p, not_p, not_not_p, q] => (p, not_p, not_not_p, q),
+            _ => panic!(),
+        };
+        let (p, not_p, not_not_p, q) = (
+            Literal::from_term(p),
+            Literal::from_term(not_p),
+            Literal::from_term(not_not_p),
+            Literal::from_term(q),
+        );
+
+        assert_eq!(p.atom(), not_not_p.atom());
+        assert_ne!(p, not_not_p);
+        assert_eq!(
+            (0, 1, 2),
+            (p.negated(), not_p.negated(), not_not_p.negated())
+        );
+        assert!(p.polarity() && !not_p.polarity() && not_not_p.polarity());
+
+        assert_eq!(not_p, p.negate());
+        assert_eq!(Some(not_p), not_not_p.remove_negation());
+        assert_eq!(None, p.remove_negation());
+
+        assert!(p.is_complement(&not_p) && not_p.is_complement(&not_not_p));
+        assert!(!p.is_complement(&not_not_p) && !p.is_complement(&q.negate()));
+        assert!(p.atom() != q.atom());
+    }
+}
@@ -1,20 +1,64 @@
 /// A macro to help deconstruct operation terms. Since a term holds references to other terms in
-/// `Vec`s and `Rc`s, pattern matching a complex term can be difficult and verbose. This macro
+/// `Vec`s and `ByRefRc`s, pattern matching a complex term can be difficult and verbose. This macro
 /// helps with that. The return type of this macro is an `Option` of a tree-like tuple. The
 /// structure of the tree will depend on the pattern passed, and the leaf nodes will be `&Term`s. An
 /// optional flag "RETURN_RCS" can be passed, in which case the leaf nodes will instead be
 /// `&ByRefRc<Term>`s.
+///
+/// Quantifiers can be matched with the patterns `(forall ... body)` and `(exists ... body)`. These
+/// return a tuple of the quantifier bindings, as a `&[SortedVar]`, and the result of matching the
+/// body. Similarly, `(let ... body)` returns the bindings of a "let" term, and `(choice ... body)`
+/// returns the bound variable of a "choice" term, as a `&SortedVar`, and the result of matching
+/// the body.
 macro_rules! match_term {
     ($bind:ident = $var:expr) => { Some($var.as_ref()) };
     ($bind:ident = $var:expr, RETURN_RCS) => { Some($var) };
-    (($op:tt $($args:tt)+) = $var:expr $(, $flag:ident)?) => {{
-        if let Term::Op(match_term!(@GET_VARIANT $op), args) = &$var as &Term {
+    ((forall ... $body:tt) = $var:expr $(, $flag:ident)?) => {
+        match_term!(@QUANT Quantifier::Forall, $body = $var $(, $flag)?)
+    };
+    ((exists ... $body:tt) = $var:expr $(, $flag:ident)?) => {
+        match_term!(@QUANT Quantifier::Exists, $body = $var $(, $flag)?)
+    };
+    ((let ... $body:tt) = $var:expr $(, $flag:ident)?) => {{
+        if let Term::Let(bindings, inner) = &$var as &Term {
+            match_term!($body = inner $(, $flag)?).map(|inner| (bindings.as_slice(), inner))
+        } else {
+            None
+        }
+    }};
+    ((choice ... $body:tt) = $var:expr $(, $flag:ident)?) => {{
+        if let Term::Choice(binding, inner) = &$var as &Term {
+            match_term!($body = inner $(, $flag)?).map(|inner| (binding, inner))
+        } else {
+            None
+        }
+    }};
+    // "-" applied to a single argument is negation, and applied to more arguments is subtraction
+    ((- ...) = $var:expr $(, $flag:ident)?) => {
+        match_term!(@OP Operator::Sub, (...) = $var $(, $flag)?)
+    };
+    ((- $arg:tt) = $var:expr $(, $flag:ident)?) => {
+        match_term!(@OP Operator::Neg, ($arg) = $var $(, $flag)?)
+    };
+    (($op:tt $($args:tt)+) = $var:expr $(, $flag:ident)?) => {
+        match_term!(@OP match_term!(@GET_VARIANT $op), ($($args)+) = $var $(, $flag)?)
+    };
+
+    (@OP $variant:pat, ($($args:tt)+) = $var:expr $(, $flag:ident)?) => {{
+        if let Term::Op($variant, args) = &$var as &Term {
             match_term!(@ARGS ($($args)+) = args.as_slice() $(, $flag)?)
         } else {
             None
         }
     }};
 
+    (@QUANT $quant:path, $body:tt = $var:expr $(, $flag:ident)?) => {{
+        if let Term::Quant($quant, bindings, inner) = &$var as &Term {
+            match_term!($body = inner $(, $flag)?).map(|inner| (bindings.as_slice(), inner))
+        } else {
+            None
+        }
+    }};
     (@ARGS (...) = $var:expr $(, $flag:ident)?) => { Some($var) };
     (@ARGS ($arg:tt) = $var:expr $(, $flag:ident)?) => {
         match_term!(@ARGS_IDENT (arg1: $arg) = $var $(, $flag)?)
@@ -25,6 +69,11 @@ macro_rules! match_term {
     (@ARGS ($arg1:tt $arg2:tt $arg3:tt) = $var:expr $(, $flag:ident)?) => {
         match_term!(@ARGS_IDENT (arg1: $arg1, arg2: $arg2, arg3: $arg3) = $var $(, $flag)?)
     };
+    (@ARGS ($arg1:tt $arg2:tt $arg3:tt $arg4:tt) = $var:expr $(, $flag:ident)?) => {
+        match_term!(
+            @ARGS_IDENT (arg1: $arg1, arg2: $arg2, arg3: $arg3, arg4: $arg4) = $var $(, $flag)?
+        )
+    };
     (@ARGS_IDENT ( $($name:ident : $arg:tt),* ) = $var:expr) => {
         if let [$($name),*] = $var {
             #[allow(unused_parens)]
@@ -68,12 +117,24 @@ macro_rules! match_term {
     (@GET_VARIANT >)        => { Operator::GreaterThan };
     (@GET_VARIANT <=)       => { Operator::LessEq };
     (@GET_VARIANT >=)       => { Operator::GreaterEq };
+    (@GET_VARIANT div)      => { Operator::IntDiv };
+    (@GET_VARIANT mod)      => { Operator::Mod };
+    (@GET_VARIANT abs)      => { Operator::Abs };
+    (@GET_VARIANT to_real)  => { Operator::ToReal };
+    (@GET_VARIANT to_int)   => { Operator::ToInt };
+    (@GET_VARIANT is_int)   => { Operator::IsInt };
+    (@GET_VARIANT divisible) => { Operator::Divisible };
 }
 
 /// A macro to help build new terms. Note that this macro will construct subterms by calling
 /// `ByRefRc::new` and does not make use of hash consing.
 macro_rules! build_term {
     ($pool:expr, {$terminal:expr}) => { $terminal };
+    // Like in `match_term`, "-" with a single argument is negation
+    ($pool:expr, (- $arg:tt)) => {{
+        let term = Term::Op(Operator::Neg, vec![build_term!($pool, $arg)]);
+        $pool.add_term(term)
+    }};
     ($pool:expr, ($op:tt $($args:tt)+)) => {{
         let term = Term::Op(
             match_term!(@GET_VARIANT $op),
@@ -108,8 +169,13 @@ macro_rules! terminal {
 }
 
 /// Implements `FromStr` and `Debug` for an enum, given a string representation for each variant.
+/// Variants listed after the braces, following a ";", are only printed, not parsed, since their
+/// string representations are shared with other variants.
 macro_rules! impl_str_conversion_traits {
-    ($enum_name:ident { $($variant:ident: $str:literal),* $(,)? }) => {
+    (
+        $enum_name:ident { $($variant:ident: $str:literal),* $(,)? }
+        $(; $($printed_variant:ident: $printed_str:literal),* $(,)?)?
+    ) => {
         impl FromStr for $enum_name {
             type Err = ();
 
@@ -125,6 +191,7 @@ macro_rules! impl_str_conversion_traits {
             fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
                 let s = match self {
                     $($enum_name::$variant => $str,)*
+                    $($($enum_name::$printed_variant => $printed_str,)*)?
                 };
                 write!(f, "{}", s)
             }
@@ -175,6 +242,75 @@ mod tests {
             ),
         );
 
+        let term = parse_term("(xor (not true) false)");
+        let (a, b) = match_term!((xor (not a) b) = &term).unwrap();
+        assert_deep_eq!(a, &terminal!(bool true));
+        assert_deep_eq!(b, &terminal!(bool false));
+        assert!(match_term!((or a b) = &term).is_none());
+
+        let term = parse_term("(distinct (+ 1.0 2.0) (- (- 3.0)) (/ 4.0 5.0) (* 6.0 7.0))");
+        let ((a, b), c, (d, e), f) =
+            match_term!((distinct (+ a b) (- c) (/ d e) (* ...)) = &term).unwrap();
+        assert_deep_eq!(a, &terminal!(real 1 / 1));
+        assert_deep_eq!(b, &terminal!(real 2 / 1));
+        assert_deep_eq!(c, &terminal!(real - 3 / 1));
+        assert_deep_eq!(d, &terminal!(real 4 / 1));
+        assert_deep_eq!(e, &terminal!(real 5 / 1));
+        assert_eq!(f.len(), 2);
+
+        let term = parse_term("(and (< 1 2) (> 3 4) (<= 5 6) (>= 7 8))");
+        let ((a, _), (b, _), (c, _), (d, _)) =
+            match_term!((and (< a b) (> c d) (<= e f) (>= g h)) = &term).unwrap();
+        assert_deep_eq!(a, &terminal!(int 1));
+        assert_deep_eq!(b, &terminal!(int 3));
+        assert_deep_eq!(c, &terminal!(int 5));
+        assert_deep_eq!(d, &terminal!(int 7));
+        assert!(match_term!((and (< a b) (< c d) (<= e f) (>= g h)) = &term).is_none());
+
+        // Test quantifier patterns
+        let term = parse_term("(forall ((x Int) (y Int)) (= (+ x y) 0))");
+        let (bindings, ((x, y), zero)) =
+            match_term!((forall ... (= (+ x y) zero)) = &term).unwrap();
+        assert_eq!(
+            bindings
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            ["x", "y"]
+        );
+        assert_deep_eq!(x, &terminal!(var "x"; INT_SORT));
+        assert_deep_eq!(y, &terminal!(var "y"; INT_SORT));
+        assert_deep_eq!(zero, &terminal!(int 0));
+        assert!(match_term!((exists ... body) = &term).is_none());
+        assert!(match_term!((forall ... (not body)) = &term).is_none());
+
+        let term = parse_term("(not (exists ((x Int)) (> x 0)))");
+        let (bindings, body) = match_term!((not (exists ... body)) = &term).unwrap();
+        assert_eq!(bindings.len(), 1);
+        assert!(match_term!((> x zero) = body).is_some());
+
+        // Test "let" and "choice" patterns
+        let term = parse_term("(let ((x 1) (y 2)) (< x y))");
+        let (bindings, (x, _)) = match_term!((let ... (< x y)) = &term).unwrap();
+        assert_eq!(
+            bindings
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            ["x", "y"]
+        );
+        assert_deep_eq!(x, &terminal!(var "x"; INT_SORT));
+        assert!(match_term!((forall ... body) = &term).is_none());
+        assert!(match_term!((let ... (> x y)) = &term).is_none());
+
+        let term = parse_term("(choice ((x Int)) (not (= x 0)))");
+        let ((name, sort), (x, zero)) = match_term!((choice ... (not (= x zero))) = &term).unwrap();
+        assert_eq!(name, "x");
+        assert_deep_eq!(sort.as_ref(), Term::INT_SORT);
+        assert_deep_eq!(x, &terminal!(var "x"; INT_SORT));
+        assert_deep_eq!(zero, &terminal!(int 0));
+        assert!(match_term!((let ... body) = &term).is_none());
+
         // Make sure that when "RETURN_RCS" flag is passed, the macro returns `&ByRefRc<Term>`
         // instead of `&Term`
         let term = parse_term("(= (not false) (=> true false) (or false false))");
@@ -232,7 +368,7 @@ mod tests {
         let (r#true, r#false) = (pool.bool_true(), pool.bool_false());
 
         let cases = [
-            ("(= a b)", build_term!(pool, (= {a} {b}))),
+            ("(= a b)", build_term!(pool, (= {a.clone()} {b.clone()}))),
             (
                 "(= 1 2)",
                 build_term!(pool, (= {one.clone()} {two.clone()})),
@@ -251,7 +387,21 @@ mod tests {
             ),
             (
                 "(distinct p q true)",
-                build_term!(pool, (distinct {p} {q} {r#true})),
+                build_term!(pool, (distinct {p.clone()} {q} {r#true})),
+            ),
+            (
+                "(xor p (<= (+ a 1) (* 2 b)))",
+                build_term!(pool, (xor
+                    {p.clone()}
+                    (<= (+ {a.clone()} {one.clone()}) (* {two.clone()} {b.clone()}))
+                )),
+            ),
+            (
+                "(and (< a b) (>= (- a b) (/ a b)))",
+                build_term!(pool, (and
+                    (< {a.clone()} {b.clone()})
+                    (>= (- {a.clone()} {b.clone()}) (/ {a.clone()} {b.clone()}))
+                )),
             ),
             (
                 "(or (not (= 2 3)) (= 1 1))",
@@ -2,38 +2,57 @@
 
 #[macro_use]
 mod macros;
+pub mod compare;
+mod dependencies;
+mod literal;
+mod substitution;
 mod subterms;
+mod symbol;
 #[cfg(test)]
 mod tests;
-
-pub use subterms::Subterms;
-
+mod truncate;
+mod visitor;
+
+pub use compare::{DeepEq, DeepEqPolicy, DeepEqState};
+pub use dependencies::DependencyGraph;
+pub use literal::{Literal, TermId};
+pub use substitution::Substitution;
+pub use subterms::{Subterms, SubtermsWithMultiplicity};
+pub use symbol::Symbol;
+pub use truncate::{PrintLimits, Truncated};
+pub use visitor::{rewrite_proof, visit_proof, ProofRewriter, ProofVisitor};
+
+use crate::parser::error::ParserError;
 use num_bigint::BigInt;
 use num_rational::BigRational;
-use num_traits::ToPrimitive;
+use num_traits::{One, Signed, Zero};
 use std::{
     borrow::Borrow,
     collections::{HashMap, HashSet},
     fmt::Debug,
     hash::Hash,
-    ops::Deref,
-    rc,
+    ops::{Deref, RangeInclusive},
     str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
 
-/// An `Rc` where equality and hashing are done by reference, instead of by value
+/// A reference-counted pointer where equality and hashing are done by reference, instead of by
+/// value. The reference count is atomic, so terms can be sent and shared between threads.
 #[derive(Clone, Eq)]
-pub struct ByRefRc<T>(rc::Rc<T>);
+pub struct ByRefRc<T>(Arc<T>);
 
 impl<T> PartialEq for ByRefRc<T> {
     fn eq(&self, other: &Self) -> bool {
-        rc::Rc::ptr_eq(&self.0, &other.0)
+        Arc::ptr_eq(&self.0, &other.0)
     }
 }
 
 impl<T> Hash for ByRefRc<T> {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        rc::Rc::as_ptr(&self.0).hash(state)
+        Arc::as_ptr(&self.0).hash(state)
     }
 }
 
@@ -71,15 +90,36 @@ impl<T: Debug> Debug for ByRefRc<T> {
 
 impl<T> ByRefRc<T> {
     pub fn new(value: T) -> Self {
-        Self(rc::Rc::new(value))
+        Self(Arc::new(value))
     }
 }
 
+/// The pool of hash consed terms. Cloning a pool shares its terms with the clone, so terms that
+/// were in the pool before it was cloned are equal, by reference, in both pools.
+#[derive(Clone)]
 pub struct TermPool {
-    pub terms: HashMap<Term, ByRefRc<Term>>,
-    pub free_vars_cache: HashMap<ByRefRc<Term>, HashSet<String>>,
+    /// The terms in the pool, each with its sort. The sort is computed when the term is added, and
+    /// is `None` if the term is not correctly sorted. See `TermPool::sort`.
+    pub terms: HashMap<Term, (ByRefRc<Term>, Option<ByRefRc<Term>>)>,
+    pub free_vars_cache: HashMap<ByRefRc<Term>, HashSet<Symbol>>,
+
+    /// A summary of the subterms of every term for which `TermPool::contains_subterm` was called,
+    /// computed lazily, and counted in `TermPool::memory_usage`. See `Occurrences`.
+    occurrences_cache: HashMap<ByRefRc<Term>, Occurrences>,
+
+    /// The limit set with `TermPool::set_max_term_size`, and upper bounds on the sizes of the terms
+    /// checked against it. The bounds are saturated at one more than the limit.
+    max_term_size: Option<usize>,
+    term_sizes: HashMap<ByRefRc<Term>, usize>,
+    term_size_exceeded: bool,
+
     bool_true: ByRefRc<Term>,
     bool_false: ByRefRc<Term>,
+    memory_usage: usize,
+
+    /// The memory usage shared with the clones of the pool, if `TermPool::share_memory_usage` was
+    /// called.
+    shared_memory_usage: Option<Arc<AtomicUsize>>,
 }
 
 impl Default for TermPool {
@@ -91,23 +131,40 @@ impl Default for TermPool {
 impl TermPool {
     pub fn new() -> Self {
         let mut terms = HashMap::new();
-        let bool_sort = Self::add_term_to_map(&mut terms, Term::BOOL_SORT.clone());
-        let bool_true = Self::add_term_to_map(
+
+        // The built-in sorts are always in the pool, so the sorts of literals and of most
+        // operations can be found without adding terms to it
+        for sort in [Term::INT_SORT, Term::REAL_SORT, Term::STRING_SORT] {
+            Self::insert_new_term(&mut terms, sort.clone(), None);
+        }
+        let bool_sort = Self::insert_new_term(&mut terms, Term::BOOL_SORT.clone(), None);
+        let bool_true = Self::insert_new_term(
             &mut terms,
             Term::Terminal(Terminal::Var(
                 Identifier::Simple("true".into()),
                 bool_sort.clone(),
             )),
+            Some(bool_sort.clone()),
         );
-        let bool_false = Self::add_term_to_map(
+        let bool_false = Self::insert_new_term(
             &mut terms,
-            Term::Terminal(Terminal::Var(Identifier::Simple("false".into()), bool_sort)),
+            Term::Terminal(Terminal::Var(
+                Identifier::Simple("false".into()),
+                bool_sort.clone(),
+            )),
+            Some(bool_sort),
         );
         Self {
             terms,
             free_vars_cache: HashMap::new(),
+            occurrences_cache: HashMap::new(),
+            max_term_size: None,
+            term_sizes: HashMap::new(),
+            term_size_exceeded: false,
             bool_true,
             bool_false,
+            memory_usage: 0,
+            shared_memory_usage: None,
         }
     }
 
@@ -119,19 +176,212 @@ impl TermPool {
         self.bool_false.clone()
     }
 
-    fn add_term_to_map(terms_map: &mut HashMap<Term, ByRefRc<Term>>, term: Term) -> ByRefRc<Term> {
-        use std::collections::hash_map::Entry;
-
-        match terms_map.entry(term.clone()) {
-            Entry::Occupied(occupied_entry) => occupied_entry.get().clone(),
-            Entry::Vacant(vacant_entry) => vacant_entry.insert(ByRefRc::new(term)).clone(),
-        }
+    /// Adds `term`, which must not be in `terms_map` yet, with the given sort. The sort of a sort
+    /// term is the term itself, so in that case `sort` is ignored.
+    fn insert_new_term(
+        terms_map: &mut HashMap<Term, (ByRefRc<Term>, Option<ByRefRc<Term>>)>,
+        term: Term,
+        sort: Option<ByRefRc<Term>>,
+    ) -> ByRefRc<Term> {
+        let result = ByRefRc::new(term.clone());
+        let sort = match term {
+            Term::Sort(..) => Some(result.clone()),
+            _ => sort,
+        };
+        terms_map.insert(term, (result.clone(), sort));
+        result
     }
 
     /// Takes a term and returns a `ByRefRc` referencing it. If the term was not originally in the
-    /// terms hash map, it is added to it.
+    /// terms hash map, it is added to it, together with its sort. Negated constants are added in
+    /// their canonical representation, so "(- 5)" is the same term as the constant -5 (see
+    /// `Term::as_negative_constant`).
     pub fn add_term(&mut self, term: Term) -> ByRefRc<Term> {
-        Self::add_term_to_map(&mut self.terms, term)
+        let term = term.as_negative_constant().unwrap_or(term);
+        if let Some((existing, _)) = self.terms.get(&term) {
+            return existing.clone();
+        }
+        let sort = self.sort(&term);
+        self.grow_memory_usage(Self::approximate_size(&term));
+        let result = Self::insert_new_term(&mut self.terms, term, sort);
+        if let Some(limit) = self.max_term_size {
+            let size = self.term_size_bound(&result, limit);
+            self.term_size_exceeded |= size > limit;
+        }
+        result
+    }
+
+    /// Sets a limit on the size of the terms added to the pool from now on, counted as the number
+    /// of distinct subterms, like in `Term::subterms`. This limits the terms built while checking a
+    /// proof, which are not seen by the parser. The pool can't refuse a term, so larger terms are
+    /// still added, but `TermPool::term_size_exceeded` returns `true` after that.
+    pub fn set_max_term_size(&mut self, limit: Option<usize>) {
+        if limit != self.max_term_size {
+            self.shrink_memory_usage(self.term_sizes.len() * Self::TERM_SIZE_ENTRY_SIZE);
+            self.term_sizes.clear();
+        }
+        self.max_term_size = limit;
+        self.term_size_exceeded = false;
+    }
+
+    /// Returns `true` if a term larger than the limit given to `TermPool::set_max_term_size` was
+    /// added to the pool since the limit was set.
+    pub fn term_size_exceeded(&self) -> bool {
+        self.term_size_exceeded
+    }
+
+    const TERM_SIZE_ENTRY_SIZE: usize = std::mem::size_of::<(ByRefRc<Term>, usize)>();
+
+    /// Returns an upper bound on the size of `term`, saturated at `limit + 1`, and records it. The
+    /// bound of a term is the sum of the bounds of its children, plus one, which only overestimates
+    /// the size when the children share subterms. Only if that exceeds the limit, or if the term
+    /// was added before the limit was set, the distinct subterms are counted, which takes time
+    /// proportional to the limit.
+    fn term_size_bound(&mut self, term: &ByRefRc<Term>, limit: usize) -> usize {
+        if let Some(&size) = self.term_sizes.get(term) {
+            return size;
+        }
+        let mut size = 1usize;
+        for child in subterms::children(term) {
+            let child_size = match self.term_sizes.get(child) {
+                Some(&child_size) => child_size,
+                None => self.count_subterms(child, limit),
+            };
+            size = size.saturating_add(child_size);
+        }
+        if size > limit {
+            size = term.subterms().take(limit + 1).count();
+        }
+        self.record_term_size(term, size)
+    }
+
+    fn count_subterms(&mut self, term: &ByRefRc<Term>, limit: usize) -> usize {
+        let size = term.subterms().take(limit + 1).count();
+        self.record_term_size(term, size)
+    }
+
+    fn record_term_size(&mut self, term: &ByRefRc<Term>, size: usize) -> usize {
+        self.term_sizes.insert(term.clone(), size);
+        self.grow_memory_usage(Self::TERM_SIZE_ENTRY_SIZE);
+        size
+    }
+
+    /// Returns the sort of `term`, as a term in the pool. For terms in the pool, the sort was
+    /// computed when the term was added, so this is just a hash map lookup. Other terms, like the
+    /// ones built by the parser before they are added, must have their immediate subterms in the
+    /// pool. Returns `None` if the term is not correctly sorted, for example if it applies a term
+    /// that is not a function, or if it is a sort that is not in the pool.
+    pub fn sort(&self, term: &Term) -> Option<ByRefRc<Term>> {
+        if let Some((_, sort)) = self.terms.get(term) {
+            return sort.clone();
+        }
+        let constant_sort = match term {
+            Term::Terminal(terminal) => match terminal {
+                Terminal::Integer(_) => Term::INT_SORT,
+                Terminal::Real(_) => Term::REAL_SORT,
+                Terminal::String(_) => Term::STRING_SORT,
+                Terminal::Var(_, sort) => return Some(sort.clone()),
+            },
+            Term::Op(op, args) => match op {
+                Operator::Not
+                | Operator::Implies
+                | Operator::And
+                | Operator::Or
+                | Operator::Xor
+                | Operator::Equals
+                | Operator::Distinct
+                | Operator::LessThan
+                | Operator::GreaterThan
+                | Operator::LessEq
+                | Operator::GreaterEq
+                | Operator::IsInt
+                | Operator::Divisible => Term::BOOL_SORT,
+                Operator::Ite => return self.sort(args.get(1)?),
+                Operator::Add | Operator::Sub | Operator::Neg | Operator::Mult | Operator::Div => {
+                    return self.sort(args.first()?)
+                }
+                Operator::ToReal => Term::REAL_SORT,
+                Operator::ToInt | Operator::IntDiv | Operator::Mod | Operator::Abs => {
+                    Term::INT_SORT
+                }
+            },
+            Term::App(f, _) => {
+                return match self.sort(f)?.as_ref() {
+                    Term::Sort(SortKind::Function, sorts) => sorts.last().cloned(),
+                    _ => None,
+                }
+            }
+            Term::Sort(_, _) => return None,
+            Term::Quant(_, _, _) => Term::BOOL_SORT,
+            Term::Choice((_, sort), _) => return Some(sort.clone()),
+            Term::Let(_, inner) => return self.sort(inner),
+        };
+        self.terms.get(constant_sort).map(|(sort, _)| sort.clone())
+    }
+
+    /// Returns an approximation of how much memory, in bytes, is used by the terms added to the
+    /// pool, by the sizes recorded for `TermPool::set_max_term_size`, and by the summaries used by
+    /// `TermPool::contains_subterm`. This doesn't include the memory used by the other caches. If
+    /// `TermPool::share_memory_usage` was called, this is the total for the pool and its clones.
+    pub fn memory_usage(&self) -> usize {
+        match &self.shared_memory_usage {
+            Some(shared) => shared.load(Ordering::Relaxed),
+            None => self.memory_usage,
+        }
+    }
+
+    /// Makes the pool and the clones made from it from now on count their memory usage together,
+    /// so `TermPool::memory_usage` returns the total for all of them, and a memory limit applies to
+    /// the total. The memory used by the pool so far is counted once, since it is shared with the
+    /// clones, and each pool then adds what it uses for the terms added to it.
+    pub fn share_memory_usage(&mut self) {
+        let shared = Arc::new(AtomicUsize::new(self.memory_usage()));
+        self.shared_memory_usage = Some(shared);
+    }
+
+    fn grow_memory_usage(&mut self, bytes: usize) {
+        self.memory_usage += bytes;
+        if let Some(shared) = &self.shared_memory_usage {
+            shared.fetch_add(bytes, Ordering::Relaxed);
+        }
+    }
+
+    fn shrink_memory_usage(&mut self, bytes: usize) {
+        self.memory_usage -= bytes;
+        if let Some(shared) = &self.shared_memory_usage {
+            // What is freed may have been counted only once, if it was in the pool before it was
+            // cloned, so the total saturates instead of underflowing
+            let _ = shared.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total| {
+                Some(total.saturating_sub(bytes))
+            });
+        }
+    }
+
+    /// Estimates how much memory adding a new term to the pool uses. Each term is stored twice,
+    /// once as the hash map key and once in the `Arc`, whose allocation also holds the strong and
+    /// weak reference counts. The hash map entry also holds the `Arc` pointer itself and the sort
+    /// of the term.
+    fn approximate_size(term: &Term) -> usize {
+        use std::mem::size_of;
+
+        let heap_size = match term {
+            Term::App(_, args) | Term::Op(_, args) | Term::Sort(_, args) => {
+                args.len() * size_of::<ByRefRc<Term>>()
+            }
+            Term::Quant(_, bindings, _) | Term::Let(bindings, _) => bindings
+                .iter()
+                .map(|(name, _)| name.len() + size_of::<SortedVar>())
+                .sum(),
+            Term::Choice((name, _), _) => name.len(),
+            Term::Terminal(Terminal::String(s)) => s.len(),
+
+            // Variable names are interned symbols, so they are shared between terms
+            Term::Terminal(_) => 0,
+        };
+        let arc_header = 2 * size_of::<AtomicUsize>();
+        2 * (size_of::<Term>() + heap_size)
+            + arc_header
+            + size_of::<(ByRefRc<Term>, Option<ByRefRc<Term>>)>()
     }
 
     // Takes a vector of terms and calls `add_term` on each.
@@ -139,57 +389,74 @@ impl TermPool {
         terms.into_iter().map(|t| self.add_term(t)).collect()
     }
 
-    /// Takes a term and a hash map of variables to terms and substitutes every ocurrence of those
-    /// variables with the associated term. This method uses the given substitutions hash map as a
-    /// cache, and will therefore mutate it.
-    pub fn apply_substitutions<'a>(
+    /// Adds `term`, which may come from a different pool, to this pool, together with all of its
+    /// subterms, and returns the equal term in this pool. `imported` maps the terms that were
+    /// already imported to their counterparts in this pool, and is updated with the subterms of
+    /// `term`, so subterms shared by several terms are only imported once.
+    pub fn import(
         &mut self,
-        term: &'a ByRefRc<Term>,
-        substitutions: &mut HashMap<ByRefRc<Term>, ByRefRc<Term>>,
+        term: &ByRefRc<Term>,
+        imported: &mut HashMap<ByRefRc<Term>, ByRefRc<Term>>,
     ) -> ByRefRc<Term> {
-        macro_rules! apply_to_sequence {
-            ($sequence:expr) => {
-                $sequence
-                    .iter()
-                    .map(|a| self.apply_substitutions(a, substitutions))
-                    .collect()
-            };
+        if let Some(result) = imported.get(term) {
+            return result.clone();
         }
-
-        if let Some(t) = substitutions.get(term) {
-            return t.clone();
-        }
-
-        let result = match term.as_ref() {
-            Term::App(func, args) => {
-                let new_args = apply_to_sequence!(args);
-                let new_func = self.apply_substitutions(func, substitutions);
-                Term::App(new_func, new_args)
+        let new_term = match term.as_ref() {
+            Term::Terminal(Terminal::Var(iden, sort)) => {
+                let iden = match iden {
+                    Identifier::Qualified(name, sort) => {
+                        Identifier::Qualified(name.clone(), self.import(sort, imported))
+                    }
+                    other => other.clone(),
+                };
+                Term::Terminal(Terminal::Var(iden, self.import(sort, imported)))
             }
-            Term::Op(op, args) => {
-                let new_args = apply_to_sequence!(args);
-                Term::Op(*op, new_args)
+            Term::Terminal(terminal) => Term::Terminal(terminal.clone()),
+            Term::App(f, args) => {
+                let f = self.import(f, imported);
+                Term::App(f, self.import_all(args, imported))
+            }
+            Term::Op(op, args) => Term::Op(*op, self.import_all(args, imported)),
+            Term::Sort(kind, args) => Term::Sort(*kind, self.import_all(args, imported)),
+            Term::Quant(q, bindings, inner) => {
+                let bindings = self.import_bindings(bindings, imported);
+                Term::Quant(*q, bindings, self.import(inner, imported))
             }
-            Term::Quant(q, b, t) => {
-                let new_term = self.apply_substitutions(t, substitutions);
-                Term::Quant(*q, b.clone(), new_term)
+            Term::Choice((name, sort), inner) => {
+                let var = (name.clone(), self.import(sort, imported));
+                Term::Choice(var, self.import(inner, imported))
+            }
+            Term::Let(bindings, inner) => {
+                let bindings = self.import_bindings(bindings, imported);
+                Term::Let(bindings, self.import(inner, imported))
             }
-            other => other.clone(),
         };
-        let result = self.add_term(result);
-
-        // Since frequently a term will have more than one identical subterms, we insert the
-        // calculated substitution in the substitutions hash map so it may be reused later. This
-        // means we don't re-visit already seen terms, so this method traverses the term as a DAG,
-        // not as a tree
-        if *term != result {
-            substitutions.insert(term.clone(), result.clone());
-        }
+        let result = self.add_term(new_term);
+        imported.insert(term.clone(), result.clone());
         result
     }
 
+    fn import_all(
+        &mut self,
+        terms: &[ByRefRc<Term>],
+        imported: &mut HashMap<ByRefRc<Term>, ByRefRc<Term>>,
+    ) -> Vec<ByRefRc<Term>> {
+        terms.iter().map(|t| self.import(t, imported)).collect()
+    }
+
+    fn import_bindings(
+        &mut self,
+        bindings: &[SortedVar],
+        imported: &mut HashMap<ByRefRc<Term>, ByRefRc<Term>>,
+    ) -> Vec<SortedVar> {
+        bindings
+            .iter()
+            .map(|(name, value)| (name.clone(), self.import(value, imported)))
+            .collect()
+    }
+
     /// Returns a `HashSet` containing all the free variables in this term.
-    pub fn free_vars<'t>(&mut self, term: &'t ByRefRc<Term>) -> &HashSet<String> {
+    pub fn free_vars(&mut self, term: &ByRefRc<Term>) -> &HashSet<Symbol> {
         // Here, I would like to do
         // ```
         // if let Some(vars) = self.free_vars_cache.get(term) {
@@ -240,41 +507,593 @@ impl TermPool {
         self.free_vars_cache.insert(term.clone(), set);
         self.free_vars_cache.get(term).unwrap()
     }
+
+    /// Returns `true` if `sub` occurs in `term`, including if they are the same term. Both terms
+    /// must have been added to the pool, since subterms are compared by reference. This only
+    /// traverses the parts of `term` that may contain `sub`, according to the summaries stored in
+    /// the occurrences cache, and visits each distinct subterm at most once, so it never takes
+    /// longer than a full traversal with `Term::subterms`.
+    pub fn contains_subterm(&mut self, term: &ByRefRc<Term>, sub: &ByRefRc<Term>) -> bool {
+        let sub_info = self.occurrences(sub);
+        let mut visited = HashSet::new();
+        let mut stack = vec![term.clone()];
+        while let Some(current) = stack.pop() {
+            if current == *sub {
+                return true;
+            }
+            if !visited.insert(current.clone()) {
+                continue;
+            }
+            let info = self.occurrences(&current);
+
+            // A term can only contain terms that are strictly shallower than itself, and whose
+            // bits are all set in its filter
+            if info.depth <= sub_info.depth || info.filter & sub_info.own_bits != sub_info.own_bits
+            {
+                continue;
+            }
+            stack.extend(Occurrences::children(&current).into_iter().cloned());
+        }
+        false
+    }
+
+    /// Returns the depth of `term`, that is, the length of the longest path from it to one of its
+    /// leaves, so terminals have depth zero. The term must have been added to the pool. The depths
+    /// are stored in the occurrences cache, so this takes constant time for terms whose subterms
+    /// were already visited, and works on arbitrarily deep terms.
+    pub fn depth(&mut self, term: &ByRefRc<Term>) -> usize {
+        self.occurrences(term).depth
+    }
+
+    const OCCURRENCES_ENTRY_SIZE: usize = std::mem::size_of::<(ByRefRc<Term>, Occurrences)>();
+
+    /// Returns the occurrence summary of `term`, computing it, and the summaries of its subterms,
+    /// if it isn't in the cache. The subterms are visited in post-order, using an explicit stack,
+    /// so this works on arbitrarily deep terms.
+    fn occurrences(&mut self, term: &ByRefRc<Term>) -> Occurrences {
+        if let Some(info) = self.occurrences_cache.get(term) {
+            return *info;
+        }
+
+        // Each term is pushed first with `false`, to push its children, and then with `true`, to
+        // compute its summary once all of its children have one
+        let mut stack = vec![(term.clone(), false)];
+        while let Some((current, children_done)) = stack.pop() {
+            if self.occurrences_cache.contains_key(&current) {
+                continue;
+            }
+            let children = Occurrences::children(&current);
+            if !children_done {
+                let pending: Vec<_> = children
+                    .into_iter()
+                    .filter(|child| !self.occurrences_cache.contains_key(*child))
+                    .map(|child| (child.clone(), false))
+                    .collect();
+                stack.push((current.clone(), true));
+                stack.extend(pending);
+                continue;
+            }
+            let own_bits = Occurrences::bits_for(&current);
+            let mut info = Occurrences {
+                own_bits,
+                filter: own_bits,
+                depth: 0,
+            };
+            for child in children {
+                let child_info = self.occurrences_cache[child];
+                info.filter |= child_info.filter;
+                info.depth = std::cmp::max(info.depth, child_info.depth + 1);
+            }
+            self.occurrences_cache.insert(current, info);
+            self.grow_memory_usage(Self::OCCURRENCES_ENTRY_SIZE);
+        }
+        self.occurrences_cache[term]
+    }
+}
+
+/// A summary of the subterms of a term, used to quickly rule out that a term occurs in another.
+/// Each term is assigned a few bits in a 128-bit bloom filter, based on its address. The filter of
+/// a term is the union of the bits of all of its subterms, so if some bit of a term `s` is not set
+/// in the filter of a term `t`, `s` certainly doesn't occur in `t`. The filters of large terms have
+/// most of their bits set, so they rarely rule anything out, and `TermPool::contains_subterm` has
+/// to rely on the depths and on not visiting the same subterm twice.
+#[derive(Debug, Clone, Copy)]
+struct Occurrences {
+    own_bits: u128,
+    filter: u128,
+    depth: usize,
+}
+
+impl Occurrences {
+    /// The number of bits each term sets in the filter.
+    const BITS_PER_TERM: usize = 2;
+
+    fn bits_for(term: &ByRefRc<Term>) -> u128 {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::Hasher;
+
+        let mut hasher = DefaultHasher::new();
+        term.hash(&mut hasher);
+        let mut hash = hasher.finish();
+        let mut bits = 0;
+        for _ in 0..Self::BITS_PER_TERM {
+            bits |= 1 << (hash % 128);
+            hash /= 128;
+        }
+        bits
+    }
+
+    /// Returns the immediate subterms of `term`.
+    fn children(term: &Term) -> Vec<&ByRefRc<Term>> {
+        match term {
+            Term::App(f, args) => std::iter::once(f).chain(args).collect(),
+            Term::Op(_, args) => args.iter().collect(),
+            Term::Quant(_, _, inner) | Term::Choice(_, inner) => vec![inner],
+            Term::Let(bindings, inner) => bindings
+                .iter()
+                .map(|(_, value)| value)
+                .chain(std::iter::once(inner))
+                .collect(),
+            Term::Terminal(_) | Term::Sort(..) => Vec::new(),
+        }
+    }
+}
+
+/// The expected result of an SMT-LIB problem, as given by the ":status" attribute of a "set-info"
+/// command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Sat,
+    Unsat,
+    Unknown,
+}
+
+impl Status {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sat" => Some(Status::Sat),
+            "unsat" => Some(Status::Unsat),
+            "unknown" => Some(Status::Unknown),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Status::Sat => write!(f, "sat"),
+            Status::Unsat => write!(f, "unsat"),
+            Status::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// The declarations, definitions and assertions of an SMT-LIB problem, in the order in which they
+/// appear in the problem.
+#[derive(Debug, Default)]
+pub struct Problem {
+    /// The logic given in the "set-logic" command, if there is one.
+    pub logic: Option<String>,
+
+    /// The status given in the ":status" attribute of a "set-info" command, if there is one.
+    pub status: Option<Status>,
+
+    /// The attributes given in "set-info" commands, in order, as pairs of the keyword, without the
+    /// leading ":", and the value. Values that are not a symbol, numeral, decimal or string are
+    /// given as an empty string.
+    pub info: Vec<(String, String)>,
+
+    /// The sorts declared with the "declare-sort" command, and their arities.
+    pub sort_declarations: Vec<(String, u64)>,
+
+    /// The functions and constants declared with the "declare-fun" command, and their sorts.
+    pub function_declarations: Vec<(String, ByRefRc<Term>)>,
+
+    /// The functions defined with the "define-fun" command.
+    pub function_definitions: Vec<(String, FunctionDef)>,
+
+    /// The asserted terms, and the names given to them with the `:named` attribute, if any. This
+    /// includes the assertions that were later removed by a "pop" command.
+    pub assertions: Vec<(Option<String>, ByRefRc<Term>)>,
+
+    /// For each "check-sat" command, the indices in `assertions` of the assertions that were
+    /// active when it was issued. Assertions made in a scope opened by "push" stop being active
+    /// when that scope is closed by "pop".
+    pub queries: Vec<Vec<usize>>,
+
+    /// The errors found while parsing assertions. Assertions that can't be parsed are skipped, so
+    /// they are not in `assertions`, or in any of the `queries`.
+    pub skipped_assertions: Vec<ParserError>,
+}
+
+impl Problem {
+    /// Returns the terms asserted in the query with index `query`, or `None` if there is no such
+    /// query.
+    pub fn query_assertions(&self, query: usize) -> Option<Vec<&ByRefRc<Term>>> {
+        let indices = self.queries.get(query)?;
+        Some(indices.iter().map(|&i| &self.assertions[i].1).collect())
+    }
+
+    /// Finds the first query whose assertions include all of the given assumptions, and returns
+    /// its index.
+    pub fn find_query<'a, I>(&self, assumptions: I) -> Option<usize>
+    where
+        I: IntoIterator<Item = &'a ByRefRc<Term>> + Clone,
+    {
+        (0..self.queries.len()).find(|&i| {
+            let assertions = self.query_assertions(i).unwrap();
+            assumptions
+                .clone()
+                .into_iter()
+                .all(|a| assertions.contains(&a))
+        })
+    }
+}
+
+/// Information about where a proof came from, taken from the comments at the start of the proof
+/// file, which solvers use to identify themselves and the options they were run with, and from the
+/// logic and "set-info" attributes of the problem. See `parser::parse_proof_metadata`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProofMetadata {
+    /// The comment lines before the first command of the proof, without the leading ";" and the
+    /// surrounding whitespace.
+    pub comments: Vec<String>,
+
+    /// The logic given in the "set-logic" command of the problem, if there is one.
+    pub logic: Option<String>,
+
+    /// The attributes given in "set-info" commands of the problem. See `Problem::info`.
+    pub info: Vec<(String, String)>,
+}
+
+impl ProofMetadata {
+    /// Returns the value of the last "set-info" attribute with keyword `key`, given without the
+    /// leading ":".
+    pub fn info(&self, key: &str) -> Option<&str> {
+        self.info
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns the first non-empty comment of the proof, which usually names the solver that
+    /// produced it and its version. This can be used to group the results of checking many proofs.
+    pub fn producer(&self) -> Option<&str> {
+        self.comments
+            .iter()
+            .find(|c| !c.is_empty())
+            .map(String::as_str)
+    }
+}
+
+/// A model for a problem, as printed by the "get-model" SMT-LIB command. Each function or constant
+/// in the problem is given a value by a "define-fun" command.
+#[derive(Debug, Default)]
+pub struct Model {
+    pub definitions: Vec<(String, FunctionDef)>,
+
+    /// The constants declared in the model with "declare-fun", and their sorts. These denote
+    /// distinct elements of uninterpreted sorts.
+    pub elements: Vec<(String, ByRefRc<Term>)>,
 }
 
 /// A proof in the veriT Proof Format.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Proof(pub Vec<ProofCommand>);
 
+impl Proof {
+    /// Returns the terms of the "assume" commands in the top-level proof.
+    pub fn assumptions(&self) -> impl Iterator<Item = &ByRefRc<Term>> + Clone {
+        self.0.iter().filter_map(|c| match c {
+            ProofCommand::Assume { term, .. } => Some(term),
+            _ => None,
+        })
+    }
+
+    /// Returns the ids and terms of the "assume" commands in the top-level proof that the last
+    /// command of the proof depends on, directly or through other commands, in the order in which
+    /// they appear in the proof.
+    pub fn used_assumptions(&self) -> Vec<(&str, &ByRefRc<Term>)> {
+        let mut used = vec![false; self.0.len()];
+        let mut to_visit: Vec<usize> = self.0.len().checked_sub(1).into_iter().collect();
+        while let Some(i) = to_visit.pop() {
+            if std::mem::replace(&mut used[i], true) {
+                continue;
+            }
+            to_visit.extend(self.0[i].premises_at_depth(0));
+        }
+        self.0
+            .iter()
+            .zip(used)
+            .filter_map(|(command, used)| match command {
+                ProofCommand::Assume { id, term } if used => Some((id.as_str(), term)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the ids of the commands that the command with id `id` depends on, directly or
+    /// through other commands. See `DependencyGraph::dependencies`. To make many queries on the
+    /// same proof, build a `DependencyGraph` once instead.
+    pub fn dependencies(&self, id: &str) -> Option<Vec<&str>> {
+        DependencyGraph::new(self).dependencies(id)
+    }
+
+    /// Returns the ids of the commands that depend on the command with id `id`, directly or
+    /// through other commands. See `DependencyGraph::dependents`. To make many queries on the same
+    /// proof, build a `DependencyGraph` once instead.
+    pub fn dependents(&self, id: &str) -> Option<Vec<&str>> {
+        DependencyGraph::new(self).dependents(id)
+    }
+
+    /// Finds the command with the given id, searching inside subproofs as well. If the id is that
+    /// of the last step of a subproof, the location of the subproof itself is returned.
+    pub fn find_command(&self, id: &str) -> Option<CommandLocation<'_>> {
+        fn find<'a>(
+            commands: &'a [ProofCommand],
+            id: &str,
+            enclosing: &mut Vec<&'a ProofCommand>,
+            scopes: &mut Vec<&'a [ProofCommand]>,
+        ) -> Option<CommandLocation<'a>> {
+            scopes.push(commands);
+            for (index, command) in commands.iter().enumerate() {
+                // The last step of a subproof is found through the subproof itself
+                if !enclosing.is_empty() && index == commands.len() - 1 {
+                    break;
+                }
+                if command.id() == id {
+                    return Some(CommandLocation {
+                        enclosing: enclosing.clone(),
+                        scopes: scopes.clone(),
+                        commands,
+                        index,
+                    });
+                }
+                if let ProofCommand::Subproof {
+                    commands: inner, ..
+                } = command
+                {
+                    enclosing.push(command);
+                    if let Some(location) = find(inner, id, enclosing, scopes) {
+                        return Some(location);
+                    }
+                    enclosing.pop();
+                }
+            }
+            scopes.pop();
+            None
+        }
+        find(&self.0, id, &mut Vec::new(), &mut Vec::new())
+    }
+
+    /// Prints the whole proof in Alethe syntax, with one command per line. Each subproof is
+    /// printed as an "anchor" command followed by the commands of the subproof. The variable
+    /// arguments of an anchor are printed before its assignment arguments.
+    pub fn to_alethe(&self) -> String {
+        use std::fmt::Write;
+
+        fn write_commands<'a>(
+            commands: &'a [ProofCommand],
+            scopes: &mut Vec<&'a [ProofCommand]>,
+            result: &mut String,
+        ) {
+            scopes.push(commands);
+            for command in commands {
+                if let ProofCommand::Subproof {
+                    commands: inner,
+                    assignment_args,
+                    variable_args,
+                } = command
+                {
+                    write!(result, "(anchor :step {}", command.id()).unwrap();
+                    if !assignment_args.is_empty() || !variable_args.is_empty() {
+                        let variables = variable_args
+                            .iter()
+                            .map(|(name, sort)| format!("({} {:?})", name, sort));
+                        let assignments = assignment_args
+                            .iter()
+                            .map(|((name, sort), t)| format!("(:= ({} {:?}) {:?})", name, sort, t));
+                        let args: Vec<_> = variables.chain(assignments).collect();
+                        write!(result, " :args ({})", args.join(" ")).unwrap();
+                    }
+                    result.push_str(")\n");
+                    write_commands(inner, scopes, result);
+                } else {
+                    writeln!(result, "{}", command.to_alethe(scopes)).unwrap();
+                }
+            }
+            scopes.pop();
+        }
+        let mut result = String::new();
+        write_commands(&self.0, &mut Vec::new(), &mut result);
+        result
+    }
+
+    /// Returns the total number of "step" commands in the proof, including the ones inside
+    /// subproofs.
+    pub fn num_steps(&self) -> usize {
+        fn count(commands: &[ProofCommand]) -> usize {
+            commands
+                .iter()
+                .map(|c| match c {
+                    ProofCommand::Assume { .. } => 0,
+                    ProofCommand::Step(_) => 1,
+                    ProofCommand::Subproof { commands, .. } => count(commands),
+                })
+                .sum()
+        }
+        count(&self.0)
+    }
+}
+
+/// The location of a command in a proof, as returned by `Proof::find_command`.
+#[derive(Debug, Clone)]
+pub struct CommandLocation<'a> {
+    /// The subproofs that contain the command, from the outermost to the innermost.
+    pub enclosing: Vec<&'a ProofCommand>,
+
+    /// The commands of the top-level proof and of each subproof that contains the command, from
+    /// the outermost to the innermost. The command's premises are indices into these slices.
+    pub scopes: Vec<&'a [ProofCommand]>,
+
+    /// The commands in the innermost subproof that contains the command, or the top-level proof
+    /// commands. This is the same as the last element of `scopes`.
+    pub commands: &'a [ProofCommand],
+
+    /// The index of the command in `commands`.
+    pub index: usize,
+}
+
+impl<'a> CommandLocation<'a> {
+    pub fn command(&self) -> &'a ProofCommand {
+        &self.commands[self.index]
+    }
+}
+
 /// A proof command.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ProofCommand {
     /// An "assume" command, of the form "(assume <symbol> <term>)".
-    Assume(ByRefRc<Term>),
+    Assume { id: String, term: ByRefRc<Term> },
 
     /// A "step" command.
     Step(ProofStep),
 
-    /// A subproof.
+    /// A subproof. Each assignment argument, of the form "(:= (<symbol> <sort>) <term>)", holds the
+    /// variable with its declared sort, and the term assigned to it.
     Subproof {
         commands: Vec<ProofCommand>,
-        assignment_args: Vec<(String, ByRefRc<Term>)>,
+        assignment_args: Vec<(SortedVar, ByRefRc<Term>)>,
         variable_args: Vec<SortedVar>,
     },
 }
 
+impl ProofCommand {
+    /// Returns the unique id of this command. For subproofs, this is the id of the step that ends
+    /// the subproof.
+    pub fn id(&self) -> &str {
+        match self {
+            ProofCommand::Assume { id, .. } => id,
+            ProofCommand::Step(s) => &s.id,
+            ProofCommand::Subproof { commands, .. } => commands.last().unwrap().id(),
+        }
+    }
+
+    /// Returns the clause concluded by this command. "assume" commands are interpreted as a clause
+    /// with a single term, and subproofs as the clause of the step that ends them.
+    pub fn clause(&self) -> &[ByRefRc<Term>] {
+        match self {
+            ProofCommand::Assume { term, .. } => std::slice::from_ref(term),
+            ProofCommand::Step(s) => &s.clause,
+            ProofCommand::Subproof { commands, .. } => commands.last().unwrap().clause(),
+        }
+    }
+
+    /// Returns the premises, as indices into the commands of the scope at depth `depth`, of this
+    /// command. For subproofs, this includes the premises of every step inside the subproof that
+    /// refer to that scope.
+    pub fn premises_at_depth(&self, depth: usize) -> Vec<usize> {
+        match self {
+            ProofCommand::Assume { .. } => Vec::new(),
+            ProofCommand::Step(step) => step
+                .premises
+                .iter()
+                .filter(|(d, _)| *d == depth)
+                .map(|&(_, i)| i)
+                .collect(),
+            ProofCommand::Subproof { commands, .. } => commands
+                .iter()
+                .flat_map(|c| c.premises_at_depth(depth))
+                .collect(),
+        }
+    }
+
+    /// Prints the command in Alethe syntax. `scopes` are the commands of the scopes that the
+    /// premises of the command refer to, from the outermost to the innermost. For subproofs, only
+    /// the step that ends the subproof is printed.
+    pub fn to_alethe(&self, scopes: &[&[ProofCommand]]) -> String {
+        self.to_alethe_truncated(scopes, PrintLimits::UNLIMITED)
+    }
+
+    /// Prints the command in Alethe syntax, like `ProofCommand::to_alethe`, but with its terms
+    /// truncated according to `limits`.
+    pub fn to_alethe_truncated(&self, scopes: &[&[ProofCommand]], limits: PrintLimits) -> String {
+        match self {
+            ProofCommand::Assume { id, term } => {
+                format!("(assume {} {})", id, term.truncated(limits))
+            }
+            ProofCommand::Step(step) => step.to_alethe_truncated(scopes, limits),
+            ProofCommand::Subproof { commands, .. } => {
+                commands.last().unwrap().to_alethe_truncated(scopes, limits)
+            }
+        }
+    }
+}
+
 /// A "step" command, of the form `(step <symbol> <clause> :rule <symbol> [:premises (<symbol>+)]?
 /// [:args <proof_args>]?)`.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ProofStep {
+    pub id: String,
     pub clause: Vec<ByRefRc<Term>>,
     pub rule: String,
-    pub premises: Vec<usize>,
+
+    /// The premises of the step, each given as the depth of the scope that contains it (where the
+    /// top-level proof has depth 0, and each subproof adds one level) and its index in the commands
+    /// of that scope.
+    pub premises: Vec<(usize, usize)>,
     pub args: Vec<ProofArg>,
+
+    /// The line and column in the proof file where the step starts. This is ignored when comparing
+    /// steps with `DeepEq`.
+    pub position: (usize, usize),
+}
+
+impl ProofStep {
+    /// Prints the step in Alethe syntax. `scopes` are the commands of the scopes that the premises
+    /// of the step refer to, from the outermost to the innermost.
+    pub fn to_alethe(&self, scopes: &[&[ProofCommand]]) -> String {
+        self.to_alethe_truncated(scopes, PrintLimits::UNLIMITED)
+    }
+
+    /// Prints the step in Alethe syntax, like `ProofStep::to_alethe`, but with its terms truncated
+    /// according to `limits`.
+    pub fn to_alethe_truncated(&self, scopes: &[&[ProofCommand]], limits: PrintLimits) -> String {
+        use std::fmt::Write;
+
+        let mut result = format!("(step {} (cl", self.id);
+        for term in &self.clause {
+            write!(result, " {}", term.truncated(limits)).unwrap();
+        }
+        write!(result, ") :rule {}", self.rule).unwrap();
+        if !self.premises.is_empty() {
+            let premises: Vec<_> = self
+                .premises
+                .iter()
+                .map(|&(d, i)| scopes[d][i].id())
+                .collect();
+            write!(result, " :premises ({})", premises.join(" ")).unwrap();
+        }
+        if !self.args.is_empty() {
+            let args: Vec<_> = self
+                .args
+                .iter()
+                .map(|arg| match arg {
+                    ProofArg::Term(t) => format!("{}", t.truncated(limits)),
+                    ProofArg::Assign(name, t) => format!("(:= {} {})", name, t.truncated(limits)),
+                })
+                .collect();
+            write!(result, " :args ({})", args.join(" ")).unwrap();
+        }
+        result.push(')');
+        result
+    }
 }
 
 /// An argument for a "step" or "anchor" command.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ProofArg {
     /// An argument that is just a term.
     Term(ByRefRc<Term>),
@@ -283,9 +1102,29 @@ pub enum ProofArg {
     Assign(String, ByRefRc<Term>),
 }
 
+impl ProofArg {
+    /// Returns the term if the argument is a simple term, and `None` if it is an assignment.
+    pub fn as_term(&self) -> Option<&ByRefRc<Term>> {
+        match self {
+            ProofArg::Term(t) => Some(t),
+            ProofArg::Assign(..) => None,
+        }
+    }
+
+    /// Returns the symbol and the value if the argument is an assignment of the form
+    /// `(:= <symbol> <term>)`, and `None` otherwise.
+    pub fn as_assign(&self) -> Option<(&str, &ByRefRc<Term>)> {
+        match self {
+            ProofArg::Assign(name, value) => Some((name.as_str(), value)),
+            ProofArg::Term(_) => None,
+        }
+    }
+}
+
 /// A function definition. Functions are defined using the "function-def" command, of the form
 /// `(define-fun <symbol> (<sorted_var>*) <sort> <term>)`. These definitions are substituted in
 /// during parsing, so these commands don't appear in the final AST.
+#[derive(Debug, Clone)]
 pub struct FunctionDef {
     pub params: Vec<SortedVar>,
     pub body: ByRefRc<Term>,
@@ -306,12 +1145,31 @@ pub enum Operator {
     // Arithmetic
     Add,
     Sub,
+
+    /// Unary negation. Like subtraction, it is written "-", but it takes a single argument, as in
+    /// "(- x)". The parser reads "-" applied to one argument as negation, and to two or more as
+    /// subtraction.
+    Neg,
     Mult,
     Div,
     LessThan,
     GreaterThan,
     LessEq,
     GreaterEq,
+
+    // Integer arithmetic
+    IntDiv,
+    Mod,
+    Abs,
+
+    // Conversions between Int and Real
+    ToReal,
+    ToInt,
+    IsInt,
+
+    /// The indexed "divisible" operator. A term "((_ divisible n) t)" is represented with the
+    /// index as its first argument, that is, as the arguments "n" and "t".
+    Divisible,
 }
 
 impl_str_conversion_traits!(Operator {
@@ -332,7 +1190,89 @@ impl_str_conversion_traits!(Operator {
     GreaterThan: ">",
     LessEq: "<=",
     GreaterEq: ">=",
-});
+
+    IntDiv: "div",
+    Mod: "mod",
+    Abs: "abs",
+
+    ToReal: "to_real",
+    ToInt: "to_int",
+    IsInt: "is_int",
+    Divisible: "divisible",
+}; Neg: "-");
+
+impl Operator {
+    /// Every operator, in the order they are declared.
+    pub const ALL: &'static [Operator] = &[
+        Operator::Not,
+        Operator::Implies,
+        Operator::And,
+        Operator::Or,
+        Operator::Xor,
+        Operator::Equals,
+        Operator::Distinct,
+        Operator::Ite,
+        Operator::Add,
+        Operator::Sub,
+        Operator::Neg,
+        Operator::Mult,
+        Operator::Div,
+        Operator::LessThan,
+        Operator::GreaterThan,
+        Operator::LessEq,
+        Operator::GreaterEq,
+        Operator::IntDiv,
+        Operator::Mod,
+        Operator::Abs,
+        Operator::ToReal,
+        Operator::ToInt,
+        Operator::IsInt,
+        Operator::Divisible,
+    ];
+
+    /// Returns the range of the number of arguments this operator accepts in SMT-LIB. Operators
+    /// that accept any number of arguments above a minimum have `usize::MAX` as the end of the
+    /// range. For "divisible", the index counts as an argument.
+    pub fn arity(self) -> RangeInclusive<usize> {
+        match self {
+            // "=>" is right associative, and all of the other variadic operators are chainable or
+            // left associative
+            Operator::And
+            | Operator::Or
+            | Operator::Xor
+            | Operator::Implies
+            | Operator::Equals
+            | Operator::Distinct
+            | Operator::Add
+            | Operator::Sub
+            | Operator::Mult
+            | Operator::Div
+            | Operator::LessThan
+            | Operator::GreaterThan
+            | Operator::LessEq
+            | Operator::GreaterEq
+            | Operator::IntDiv => 2..=usize::MAX,
+
+            Operator::Not
+            | Operator::Neg
+            | Operator::Abs
+            | Operator::ToReal
+            | Operator::ToInt
+            | Operator::IsInt => 1..=1,
+            Operator::Mod | Operator::Divisible => 2..=2,
+            Operator::Ite => 3..=3,
+        }
+    }
+
+    /// Like `arity`, but "and", "or" and "xor" also accept a single argument. Some solvers print
+    /// terms like "(or true)" in their proofs, even though SMT-LIB doesn't allow them.
+    pub fn permissive_arity(self) -> RangeInclusive<usize> {
+        match self {
+            Operator::And | Operator::Or | Operator::Xor => 1..=usize::MAX,
+            other => other.arity(),
+        }
+    }
+}
 
 pub type SortedVar = (String, ByRefRc<Term>);
 
@@ -344,6 +1284,11 @@ pub enum SortKind {
     Int,
     Real,
     String,
+
+    /// A fresh sort given to a symbol that was used without being declared, when the parser is
+    /// inferring declarations. Its argument is an integer terminal that identifies it. Since the
+    /// real sort of the symbol is not known, inferred sorts are accepted anywhere by sort checking.
+    Inferred,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -390,7 +1335,7 @@ pub enum Term {
 
 impl From<SortedVar> for Term {
     fn from(var: SortedVar) -> Self {
-        Term::Terminal(Terminal::Var(Identifier::Simple(var.0), var.1))
+        Term::Terminal(Terminal::Var(Identifier::Simple(var.0.into()), var.1))
     }
 }
 
@@ -407,55 +1352,22 @@ impl Term {
     /// The "String" built-in sort.
     pub const STRING_SORT: &'static Term = &Term::Sort(SortKind::String, Vec::new());
 
-    /// Returns the sort of this term. For operations and application terms, this method assumes that
-    /// the arguments' sorts have already been checked, and are correct. If `self` is a sort, this
-    /// method does nothing and returns `self`.
-    pub fn sort(&self) -> &Term {
-        match self {
-            Term::Terminal(t) => match t {
-                Terminal::Integer(_) => Term::INT_SORT,
-                Terminal::Real(_) => Term::REAL_SORT,
-                Terminal::String(_) => Term::STRING_SORT,
-                Terminal::Var(_, sort) => sort.as_ref(),
-            },
-            Term::Op(op, args) => match op {
-                Operator::Not
-                | Operator::Implies
-                | Operator::And
-                | Operator::Or
-                | Operator::Xor
-                | Operator::Equals
-                | Operator::Distinct
-                | Operator::LessThan
-                | Operator::GreaterThan
-                | Operator::LessEq
-                | Operator::GreaterEq => Term::BOOL_SORT,
-                Operator::Ite => args[1].sort(),
-                Operator::Add | Operator::Sub | Operator::Mult | Operator::Div => args[0].sort(),
-            },
-            Term::App(f, _) => {
-                let function_sort = f.sort();
-                if let Term::Sort(SortKind::Function, sorts) = function_sort {
-                    sorts.last().unwrap()
-                } else {
-                    unreachable!() // We assume that the function is correctly sorted
-                }
-            }
-            sort @ Term::Sort(_, _) => sort,
-            Term::Quant(_, _, _) => Term::BOOL_SORT,
-            Term::Choice((_, sort), _) => sort,
-            Term::Let(_, inner) => inner.sort(),
-        }
-    }
-
     /// Returns an iterator over this term and all its subterms, in topological ordering. For
     /// example, calling this method on the term (+ (f a b) 2) would return an iterator over the
     /// terms (+ (f a b) 2), (f a b), f, a, b and 2. This method traverses the term as a DAG, and
     /// the resulting iterator will not contain any duplicate terms. This ignores sort terms.
-    pub fn subterms(&self) -> Subterms {
+    pub fn subterms(&self) -> Subterms<'_> {
         Subterms::new(self)
     }
 
+    /// Returns an iterator over this term and all its subterms, traversing the term as a tree, in
+    /// the same order as `Term::subterms`. Subterms that appear more than once are visited once
+    /// for each occurrence, so this can take time exponential in the size of the term DAG. Prefer
+    /// `Term::subterms` unless the number of occurrences of each subterm matters.
+    pub fn subterms_with_multiplicity(&self) -> SubtermsWithMultiplicity<'_> {
+        SubtermsWithMultiplicity::new(self)
+    }
+
     /// Removes a leading negation from the term, if it exists. Same thing as `match_term!((not t)
     /// = term)`.
     pub fn remove_negation(&self) -> Option<&Self> {
@@ -507,6 +1419,29 @@ impl Term {
         }
     }
 
+    /// If the term is a non-negative integer or real constant negated with the "-" operator, like
+    /// "(- 5)" or "(- 0.5)", returns the equivalent constant, -5 or -0.5. Negative constants are
+    /// always represented this way in the term pool, so that a constant is interned to the same
+    /// term regardless of how it was written in the proof.
+    pub fn as_negative_constant(&self) -> Option<Term> {
+        let inner = match self {
+            Term::Op(Operator::Neg, args) => match args.as_slice() {
+                [inner] => inner,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        match inner.as_ref() {
+            Term::Terminal(Terminal::Integer(i)) if !i.is_negative() => {
+                Some(Term::Terminal(Terminal::Integer(-i)))
+            }
+            Term::Terminal(Terminal::Real(r)) if !r.is_negative() => {
+                Some(Term::Terminal(Terminal::Real(-r)))
+            }
+            _ => None,
+        }
+    }
+
     /// Tries to extract a `BigRational` from a term, allowing negative values represented with the
     /// unary "-" operator. Returns `Some` if the term is an integer or real constant, or one such
     /// constant negated with the "-" operator.
@@ -537,12 +1472,21 @@ impl Term {
 
     /// Returns `true` if the term is the boolean constant "true".
     pub fn is_bool_true(&self) -> bool {
-        self.sort() == Term::BOOL_SORT && self.try_as_var() == Some("true")
+        self.is_bool_constant("true")
     }
 
     /// Returns `true` if the term is the boolean constant "false".
     pub fn is_bool_false(&self) -> bool {
-        self.sort() == Term::BOOL_SORT && self.try_as_var() == Some("false")
+        self.is_bool_constant("false")
+    }
+
+    fn is_bool_constant(&self, name: &str) -> bool {
+        match self {
+            Term::Terminal(Terminal::Var(Identifier::Simple(var), sort)) => {
+                sort.as_ref() == Term::BOOL_SORT && var.as_str() == name
+            }
+            _ => false,
+        }
     }
 }
 
@@ -557,6 +1501,13 @@ impl Debug for Term {
                 }
                 write!(f, ")")
             }
+            Term::Op(Operator::Divisible, args) => {
+                write!(f, "((_ divisible {:?})", args[0])?;
+                for a in &args[1..] {
+                    write!(f, " {:?}", a)?;
+                }
+                write!(f, ")")
+            }
             Term::Op(op, args) => {
                 write!(f, "({:?}", op)?;
                 for a in args {
@@ -566,16 +1517,24 @@ impl Debug for Term {
             }
             Term::Sort(sort_kind, args) => match sort_kind {
                 SortKind::Atom => {
-                    if let Term::Terminal(Terminal::String(s)) = args[0].as_ref() {
-                        write!(f, "{}", s)
-                    } else {
-                        panic!()
+                    let name = match args[0].as_ref() {
+                        Term::Terminal(Terminal::String(s)) => s,
+                        _ => panic!(),
+                    };
+                    if args.len() == 1 {
+                        return write!(f, "{}", name);
+                    }
+                    write!(f, "({}", name)?;
+                    for a in &args[1..] {
+                        write!(f, " {:?}", a)?;
                     }
+                    write!(f, ")")
                 }
                 SortKind::Bool => write!(f, "Bool"),
                 SortKind::Int => write!(f, "Int"),
                 SortKind::Real => write!(f, "Real"),
                 SortKind::String => write!(f, "String"),
+                SortKind::Inferred => write!(f, "?{:?}", args[0]),
                 SortKind::Function => panic!(),
             },
             Term::Quant(quantifier, bindings, term) => {
@@ -622,163 +1581,68 @@ pub enum Terminal {
 impl Debug for Terminal {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
+            Terminal::Integer(i) if i.is_negative() => write!(f, "(- {})", -i),
             Terminal::Integer(i) => write!(f, "{}", i),
-            Terminal::Real(r) => write!(
-                f,
-                "{}",
-                (r.numer().to_f64().unwrap() / r.denom().to_f64().unwrap())
-            ),
+            Terminal::Real(r) if r.is_negative() => write!(f, "(- {:?})", Terminal::Real(-r)),
+            Terminal::Real(r) => write_real(f, r),
             Terminal::String(s) => write!(f, "\"{}\"", s),
             Terminal::Var(Identifier::Simple(s), _) => write!(f, "{}", s),
+            Terminal::Var(Identifier::Qualified(s, sort), _) => write!(f, "(as {} {:?})", s, sort),
             Terminal::Var(_, _) => todo!(),
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum Identifier {
-    Simple(String),
-    Indexed(String, Vec<Index>),
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum Index {
-    Numeral(u64),
-    Symbol(String),
-}
-
-/// A trait that implements less strict definitions of equality for terms. This trait represents
-/// two definitions of equality that differ from `PartialEq`:
-/// - `DeepEq::eq` implements a "deep" equality, meaning that it compares `ByRefRc`s by value,
-/// instead of by reference
-/// - `DeepEq::eq_modulo_reordering` is also a "deep" equality, but it considers "=" terms that are
-/// "reflections" of each other as equal, meaning the terms (= a b) and (= b a) are considered
-/// equal by this method
-pub trait DeepEq {
-    fn eq(a: &Self, b: &Self) -> bool {
-        DeepEq::eq_impl(a, b, false)
-    }
-
-    fn eq_modulo_reordering(a: &Self, b: &Self) -> bool {
-        DeepEq::eq_impl(a, b, true)
-    }
-
-    fn eq_impl(a: &Self, b: &Self, is_mod_reordering: bool) -> bool;
-}
-
-impl DeepEq for Term {
-    fn eq_impl(a: &Self, b: &Self, is_mod_reordering: bool) -> bool {
-        match (a, b) {
-            (Term::App(f_a, args_a), Term::App(f_b, args_b)) => {
-                DeepEq::eq_impl(f_a.as_ref(), f_b.as_ref(), is_mod_reordering)
-                    && DeepEq::eq_impl(args_a, args_b, is_mod_reordering)
-            }
-            (Term::Op(op_a, args_a), Term::Op(op_b, args_b)) => {
-                if is_mod_reordering {
-                    if let (Operator::Equals, [a_1, a_2], Operator::Equals, [b_1, b_2]) =
-                        (op_a, args_a.as_slice(), op_b, args_b.as_slice())
-                    {
-                        // If the term is an equality of two terms, we also check if they would be
-                        // equal if one of them was flipped
-                        return DeepEq::eq_impl(&(a_1, a_2), &(b_1, b_2), true)
-                            || DeepEq::eq_impl(&(a_1, a_2), &(b_2, b_1), true);
-                    }
-                }
-                // General case
-                op_a == op_b && DeepEq::eq_impl(args_a, args_b, is_mod_reordering)
-            }
-            (Term::Sort(kind_a, args_a), Term::Sort(kind_b, args_b)) => {
-                kind_a == kind_b && DeepEq::eq_impl(args_a, args_b, is_mod_reordering)
-            }
-            (Term::Terminal(a), Term::Terminal(b)) => match (a, b) {
-                (Terminal::Var(iden_a, sort_a), Terminal::Var(iden_b, sort_b)) => {
-                    iden_a == iden_b && DeepEq::eq_impl(sort_a, sort_b, is_mod_reordering)
-                }
-                (a, b) => a == b,
-            },
-            (Term::Quant(q_a, binds_a, a), Term::Quant(q_b, binds_b, b)) => {
-                q_a == q_b
-                    && DeepEq::eq_impl(binds_a, binds_b, is_mod_reordering)
-                    && DeepEq::eq_impl(a, b, is_mod_reordering)
-            }
-            (Term::Choice(var_a, a), Term::Choice(var_b, b)) => {
-                DeepEq::eq_impl(var_a, var_b, is_mod_reordering)
-                    && DeepEq::eq_impl(a, b, is_mod_reordering)
-            }
-            (Term::Let(binds_a, a), Term::Let(binds_b, b)) => {
-                DeepEq::eq_impl(binds_a, binds_b, is_mod_reordering)
-                    && DeepEq::eq_impl(a, b, is_mod_reordering)
-            }
-            _ => false,
-        }
-    }
-}
-
-impl DeepEq for ProofArg {
-    fn eq_impl(a: &Self, b: &Self, is_mod_reordering: bool) -> bool {
-        match (a, b) {
-            (ProofArg::Term(a), ProofArg::Term(b)) => DeepEq::eq_impl(a, b, is_mod_reordering),
-            (ProofArg::Assign(sa, ta), ProofArg::Assign(sb, tb)) => {
-                sa == sb && DeepEq::eq_impl(ta, tb, is_mod_reordering)
-            }
-            _ => false,
-        }
-    }
-}
-
-impl DeepEq for ProofCommand {
-    fn eq_impl(a: &Self, b: &Self, is_mod_reordering: bool) -> bool {
-        match (a, b) {
-            (ProofCommand::Assume(a), ProofCommand::Assume(b)) => {
-                DeepEq::eq_impl(a, b, is_mod_reordering)
-            }
-            (ProofCommand::Step(a), ProofCommand::Step(b)) => {
-                DeepEq::eq_impl(a, b, is_mod_reordering)
-            }
-            _ => false,
-        }
+/// Writes a non-negative real number exactly, so that different numbers are never printed the
+/// same way. If the number has a finite decimal expansion, it is written as a decimal, like "2.0"
+/// or "0.125". Otherwise, it is written as a division of two decimals, like "(/ 1.0 3.0)".
+fn write_real(f: &mut std::fmt::Formatter, r: &BigRational) -> std::fmt::Result {
+    // The expansion is finite if and only if the denominator has no prime factors other than 2
+    // and 5. In that case, the number of decimal places is the largest power of these factors
+    let (two, five) = (BigInt::from(2), BigInt::from(5));
+    let mut remainder = r.denom().clone();
+    let (mut twos, mut fives) = (0, 0);
+    while (&remainder % &two).is_zero() {
+        remainder /= &two;
+        twos += 1;
     }
-}
-
-impl DeepEq for ProofStep {
-    fn eq_impl(a: &Self, b: &Self, is_mod_reordering: bool) -> bool {
-        DeepEq::eq_impl(&a.clause, &b.clause, is_mod_reordering)
-            && a.rule == b.rule
-            && a.premises == b.premises
-            && DeepEq::eq_impl(&a.args, &b.args, is_mod_reordering)
+    while (&remainder % &five).is_zero() {
+        remainder /= &five;
+        fives += 1;
     }
-}
-
-impl<T: DeepEq> DeepEq for &T {
-    fn eq_impl(a: &Self, b: &Self, is_mod_reordering: bool) -> bool {
-        DeepEq::eq_impl(*a, *b, is_mod_reordering)
+    if !remainder.is_one() {
+        return write!(f, "(/ {}.0 {}.0)", r.numer(), r.denom());
     }
-}
-
-impl<T: DeepEq> DeepEq for ByRefRc<T> {
-    fn eq_impl(a: &Self, b: &Self, is_mod_reordering: bool) -> bool {
-        a == b || DeepEq::eq_impl(a.as_ref(), b.as_ref(), is_mod_reordering)
+    let places = std::cmp::max(twos, fives);
+    let scale = BigInt::from(10).pow(places);
+    let scaled = r.numer() * &scale / r.denom();
+    let (integer, fraction) = (&scaled / &scale, &scaled % &scale);
+    if places == 0 {
+        write!(f, "{}.0", integer)
+    } else {
+        write!(
+            f,
+            "{}.{:0>width$}",
+            integer,
+            fraction,
+            width = places as usize
+        )
     }
 }
 
-impl<T: DeepEq> DeepEq for Vec<T> {
-    fn eq_impl(a: &Self, b: &Self, is_mod_reordering: bool) -> bool {
-        a.len() == b.len()
-            && a.iter()
-                .zip(b.iter())
-                .all(|(a, b)| DeepEq::eq_impl(a, b, is_mod_reordering))
-    }
-}
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Identifier {
+    Simple(Symbol),
+    Indexed(Symbol, Vec<Index>),
 
-impl<T: DeepEq> DeepEq for (T, T) {
-    fn eq_impl(a: &Self, b: &Self, is_mod_reordering: bool) -> bool {
-        DeepEq::eq_impl(&a.0, &b.0, is_mod_reordering)
-            && DeepEq::eq_impl(&a.1, &b.1, is_mod_reordering)
-    }
+    /// A symbol qualified with a sort, written "(as <symbol> <sort>)". Qualified identifiers are
+    /// only kept for symbols that can't be resolved without their sort, like "const" in
+    /// "(as const (Array Int Int))". Otherwise, they are parsed as the symbol alone.
+    Qualified(Symbol, ByRefRc<Term>),
 }
 
-impl DeepEq for SortedVar {
-    fn eq_impl(a: &Self, b: &Self, is_mod_reordering: bool) -> bool {
-        a.0 == b.0 && DeepEq::eq_impl(&a.1, &b.1, is_mod_reordering)
-    }
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Index {
+    Numeral(BigInt),
+    Symbol(Symbol),
 }
@@ -0,0 +1,195 @@
+use super::{ByRefRc, Term, TermPool};
+use std::{collections::HashMap, iter::FromIterator};
+
+/// A substitution, that maps terms (usually variables) to the terms that should replace them.
+/// Substitutions are immutable once built, so the same substitution can be applied to any number
+/// of terms without the result of one application affecting the next.
+///
+/// Substitutions don't rename bound variables, so they are not capture-avoiding, and the bodies of
+/// "let" and "choice" terms are left untouched.
+#[derive(Debug, Clone, Default)]
+pub struct Substitution {
+    /// The pairs of the substitution, in the order they were inserted. This is the order used by
+    /// `Substitution::apply_sequentially`.
+    pairs: Vec<(ByRefRc<Term>, ByRefRc<Term>)>,
+    map: HashMap<ByRefRc<Term>, ByRefRc<Term>>,
+}
+
+impl Substitution {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a substitution that replaces `from` with `to`.
+    pub fn single(from: ByRefRc<Term>, to: ByRefRc<Term>) -> Self {
+        let mut result = Self::new();
+        result.insert(from, to);
+        result
+    }
+
+    /// Adds a pair to the substitution. If `from` was already in the substitution, its previous
+    /// pair is replaced.
+    pub fn insert(&mut self, from: ByRefRc<Term>, to: ByRefRc<Term>) {
+        if self.map.insert(from.clone(), to.clone()).is_some() {
+            self.pairs.retain(|(k, _)| *k != from);
+        }
+        self.pairs.push((from, to));
+    }
+
+    /// Returns the term that replaces `term`, if it is in the domain of the substitution.
+    pub fn get(&self, term: &ByRefRc<Term>) -> Option<&ByRefRc<Term>> {
+        self.map.get(term)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Returns an iterator over the pairs of the substitution, in the order they were inserted.
+    pub fn iter(&self) -> impl Iterator<Item = (&ByRefRc<Term>, &ByRefRc<Term>)> {
+        self.pairs.iter().map(|(k, v)| (k, v))
+    }
+
+    /// Returns an iterator over the terms replaced by the substitution.
+    pub fn domain(&self) -> impl Iterator<Item = &ByRefRc<Term>> {
+        self.pairs.iter().map(|(k, _)| k)
+    }
+
+    /// Applies the substitution to `term`, replacing every occurrence of the terms in its domain
+    /// simultaneously. That is, the terms introduced by a replacement are not substituted again.
+    pub fn apply(&self, pool: &mut TermPool, term: &ByRefRc<Term>) -> ByRefRc<Term> {
+        // Since frequently a term will have more than one identical subterms, we cache the result
+        // of every subterm so it may be reused later. This means we don't re-visit already seen
+        // terms, so this method traverses the term as a DAG, not as a tree
+        let mut cache = HashMap::new();
+        self.apply_with_cache(pool, term, &mut cache)
+    }
+
+    /// Applies the substitution to `term`, like `Substitution::apply`, and also returns whether
+    /// any term was replaced.
+    pub fn apply_checked(
+        &self,
+        pool: &mut TermPool,
+        term: &ByRefRc<Term>,
+    ) -> (ByRefRc<Term>, bool) {
+        let result = self.apply(pool, term);
+        let changed = result != *term;
+        (result, changed)
+    }
+
+    /// Applies each pair of the substitution to `term` in the order they were inserted, so the
+    /// terms introduced by a pair may be replaced by the pairs that come after it. For example,
+    /// applying "(:= x y)" and then "(:= y z)" to "(f x y)" results in "(f z z)", while applying
+    /// them simultaneously results in "(f y z)".
+    pub fn apply_sequentially(&self, pool: &mut TermPool, term: &ByRefRc<Term>) -> ByRefRc<Term> {
+        self.pairs.iter().fold(term.clone(), |current, (from, to)| {
+            Self::single(from.clone(), to.clone()).apply(pool, &current)
+        })
+    }
+
+    /// Applies the substitution to `term`, replacing at most `limit` occurrences of the terms in
+    /// its domain. Occurrences are counted with multiplicity, and are replaced from left to right.
+    /// Returns the resulting term and the number of occurrences that were replaced.
+    pub fn apply_limited(
+        &self,
+        pool: &mut TermPool,
+        term: &ByRefRc<Term>,
+        limit: usize,
+    ) -> (ByRefRc<Term>, usize) {
+        let mut remaining = limit;
+        let result = self.apply_limited_rec(pool, term, &mut remaining);
+        (result, limit - remaining)
+    }
+
+    fn apply_with_cache(
+        &self,
+        pool: &mut TermPool,
+        term: &ByRefRc<Term>,
+        cache: &mut HashMap<ByRefRc<Term>, ByRefRc<Term>>,
+    ) -> ByRefRc<Term> {
+        if let Some(t) = self.map.get(term).or_else(|| cache.get(term)) {
+            return t.clone();
+        }
+
+        let result = match term.as_ref() {
+            Term::App(func, args) => {
+                let new_args = args
+                    .iter()
+                    .map(|a| self.apply_with_cache(pool, a, cache))
+                    .collect();
+                let new_func = self.apply_with_cache(pool, func, cache);
+                Term::App(new_func, new_args)
+            }
+            Term::Op(op, args) => {
+                let new_args = args
+                    .iter()
+                    .map(|a| self.apply_with_cache(pool, a, cache))
+                    .collect();
+                Term::Op(*op, new_args)
+            }
+            Term::Quant(q, b, t) => {
+                let new_term = self.apply_with_cache(pool, t, cache);
+                Term::Quant(*q, b.clone(), new_term)
+            }
+            _ => return term.clone(),
+        };
+        let result = pool.add_term(result);
+        cache.insert(term.clone(), result.clone());
+        result
+    }
+
+    fn apply_limited_rec(
+        &self,
+        pool: &mut TermPool,
+        term: &ByRefRc<Term>,
+        remaining: &mut usize,
+    ) -> ByRefRc<Term> {
+        if *remaining == 0 {
+            return term.clone();
+        }
+        if let Some(t) = self.map.get(term) {
+            *remaining -= 1;
+            return t.clone();
+        }
+
+        // Since the number of replacements depends on the position of each occurrence, we can't
+        // reuse the results for repeated subterms, so the term is traversed as a tree
+        let result = match term.as_ref() {
+            Term::App(func, args) => {
+                let new_func = self.apply_limited_rec(pool, func, remaining);
+                let new_args = args
+                    .iter()
+                    .map(|a| self.apply_limited_rec(pool, a, remaining))
+                    .collect();
+                Term::App(new_func, new_args)
+            }
+            Term::Op(op, args) => {
+                let new_args = args
+                    .iter()
+                    .map(|a| self.apply_limited_rec(pool, a, remaining))
+                    .collect();
+                Term::Op(*op, new_args)
+            }
+            Term::Quant(q, b, t) => {
+                let new_term = self.apply_limited_rec(pool, t, remaining);
+                Term::Quant(*q, b.clone(), new_term)
+            }
+            _ => return term.clone(),
+        };
+        pool.add_term(result)
+    }
+}
+
+impl FromIterator<(ByRefRc<Term>, ByRefRc<Term>)> for Substitution {
+    fn from_iter<T: IntoIterator<Item = (ByRefRc<Term>, ByRefRc<Term>)>>(iter: T) -> Self {
+        let mut result = Self::new();
+        for (from, to) in iter {
+            result.insert(from, to);
+        }
+        result
+    }
+}
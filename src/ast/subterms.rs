@@ -23,6 +23,42 @@ impl<'a> Iterator for Subterms<'a> {
     }
 }
 
+/// An iterator over a term and all its subterms, that traverses the term as a tree. Unlike
+/// `Subterms`, subterms that appear more than once are visited once for each occurrence.
+pub struct SubtermsWithMultiplicity<'a> {
+    stack: Vec<&'a Term>,
+}
+
+impl<'a> SubtermsWithMultiplicity<'a> {
+    pub fn new(root: &'a Term) -> Self {
+        Self { stack: vec![root] }
+    }
+}
+
+impl<'a> Iterator for SubtermsWithMultiplicity<'a> {
+    type Item = &'a Term;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.stack.pop()?;
+
+        // The children are pushed in reverse order, so they are visited from left to right
+        let mut children: Vec<_> = children(current).map(ByRefRc::as_ref).collect();
+        children.reverse();
+        self.stack.extend(children);
+        Some(current)
+    }
+}
+
+/// Returns an iterator over the immediate subterms of `term`, ignoring sort terms.
+pub(super) fn children<'a>(term: &'a Term) -> Box<dyn Iterator<Item = &'a ByRefRc<Term>> + 'a> {
+    match term {
+        Term::App(f, args) => Box::new(iter::once(f).chain(args.iter())),
+        Term::Op(_, args) => Box::new(args.iter()),
+        Term::Quant(_, _, t) => Box::new(iter::once(t)),
+        _ => Box::new(iter::empty()),
+    }
+}
+
 struct SubtermsInner<'a> {
     root: &'a Term,
     visited_root: bool,
@@ -32,17 +68,11 @@ struct SubtermsInner<'a> {
 
 impl<'a> SubtermsInner<'a> {
     fn new(root: &'a Term) -> Self {
-        let children: Box<dyn Iterator<Item = _>> = match root {
-            Term::App(f, args) => Box::new(iter::once(f).chain(args.iter())),
-            Term::Op(_, args) => Box::new(args.iter()),
-            Term::Quant(_, _, t) => Box::new(iter::once(t)),
-            _ => Box::new(iter::empty()),
-        };
         Self {
             root,
             visited_root: false,
             current: None,
-            children,
+            children: children(root),
         }
     }
 
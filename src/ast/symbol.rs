@@ -0,0 +1,139 @@
+use std::{
+    borrow::Borrow,
+    collections::HashSet,
+    fmt::{self, Debug, Display},
+    hash::{Hash, Hasher},
+    ops::Deref,
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
+};
+
+/// The interned symbols. The table is shared by all threads, so that symbols created in different
+/// threads can still be compared by reference. A symbol is removed from the table when its last
+/// `Symbol` is dropped, so long running processes, like the server, don't keep the symbols of
+/// every proof they have read.
+fn interned_symbols() -> MutexGuard<'static, HashSet<Arc<str>>> {
+    static INTERNED_SYMBOLS: OnceLock<Mutex<HashSet<Arc<str>>>> = OnceLock::new();
+
+    // A panic while the lock is held can't leave the table in an inconsistent state, so a poisoned
+    // lock can be used normally
+    INTERNED_SYMBOLS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// An interned symbol. All symbols with the same name share the same allocation, so cloning and
+/// comparing them is cheap. Symbols are created with `Symbol::new`, or from a `&str` or `String`
+/// using `into`.
+///
+/// Symbols are hashed by their contents, so a `HashSet<Symbol>` or `HashMap<Symbol, _>` can be
+/// queried with a `&str`.
+#[derive(Clone)]
+pub struct Symbol(Arc<str>);
+
+impl Symbol {
+    pub fn new(name: &str) -> Self {
+        let mut symbols = interned_symbols();
+        if let Some(existing) = symbols.get(name) {
+            return Symbol(existing.clone());
+        }
+        let new: Arc<str> = Arc::from(name);
+        symbols.insert(new.clone());
+        Symbol(new)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Drop for Symbol {
+    fn drop(&mut self) {
+        // If the only other reference is the one in the table, this is the last `Symbol` with this
+        // name. No new `Symbol` can be created from the table while we hold the lock, so if that
+        // still holds once we have it, the symbol can be safely removed
+        if Arc::strong_count(&self.0) == 2 {
+            let mut symbols = interned_symbols();
+            if Arc::strong_count(&self.0) == 2 {
+                symbols.remove(self.as_str());
+            }
+        }
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        // Since symbols are interned, two symbols are equal only if they share the same allocation
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl Borrow<str> for Symbol {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::new(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Self {
+        Symbol::new(&name)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(name: &String) -> Self {
+        Symbol::new(name)
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(self.as_str(), f)
+    }
+}
+
+/// Returns `true` if a symbol with this name is currently interned.
+#[cfg(test)]
+pub(super) fn is_interned(name: &str) -> bool {
+    interned_symbols().contains(name)
+}
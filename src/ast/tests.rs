@@ -1,5 +1,5 @@
 use super::*;
-use crate::parser::tests::{parse_term, parse_term_with_definitions};
+use crate::parser::tests::{parse_proof, parse_term, parse_term_with_definitions};
 use std::collections::HashSet;
 
 #[test]
@@ -20,6 +20,48 @@ fn test_subterms_no_duplicates() {
     ]);
 }
 
+#[test]
+fn test_subterms_with_multiplicity() {
+    let cases: &[(&str, &[&str])] = &[
+        ("(= 0 1)", &["(= 0 1)", "0", "1"]),
+        (
+            "(= (= 1 2) (not (= 1 2)))",
+            &[
+                "(= (= 1 2) (not (= 1 2)))",
+                "(= 1 2)",
+                "1",
+                "2",
+                "(not (= 1 2))",
+                "(= 1 2)",
+                "1",
+                "2",
+            ],
+        ),
+        (
+            "(+ (* 1 2) (- 2 (* 1 2)))",
+            &[
+                "(+ (* 1 2) (- 2 (* 1 2)))",
+                "(* 1 2)",
+                "1",
+                "2",
+                "(- 2 (* 1 2))",
+                "2",
+                "(* 1 2)",
+                "1",
+                "2",
+            ],
+        ),
+    ];
+    for (term, expected) in cases {
+        let term = parse_term(term);
+        let got: Vec<_> = term
+            .subterms_with_multiplicity()
+            .map(|t| format!("{:?}", t))
+            .collect();
+        assert_eq!(expected, &got);
+    }
+}
+
 #[test]
 fn test_subterms() {
     fn run_tests(definitions: &str, cases: &[&[&str]]) {
@@ -84,7 +126,7 @@ fn test_free_vars() {
             let root = parse_term_with_definitions(definitions, term);
             let expected = expected
                 .iter()
-                .map(|&s| s.to_string())
+                .map(|&s| Symbol::new(s))
                 .collect::<HashSet<_>>();
 
             // Since we are only using the term pool as a cache for `free_vars`, it doesn't need to
@@ -114,19 +156,163 @@ fn test_free_vars() {
     )
 }
 
+#[test]
+fn test_contains_subterm() {
+    let definitions = "
+        (declare-fun f (Int) Int)
+        (declare-fun a () Int)
+        (declare-fun b () Int)
+        (declare-fun p () Bool)
+    ";
+    let cases = [
+        ("(= (f a) b)", "(f a)", true),
+        ("(= (f a) b)", "a", true),
+        ("(= (f a) b)", "(= (f a) b)", true),
+        ("(= (f a) b)", "(f b)", false),
+        ("(ite p (f (f a)) (+ a b))", "(+ a b)", true),
+        ("(ite p (f (f a)) (+ a b))", "(f (f (f a)))", false),
+        ("(forall ((x Int)) (= (f x) (f a)))", "(f a)", true),
+        ("(and p (not p))", "(not (not p))", false),
+    ];
+    for (term, sub, expected) in cases {
+        // Subterms are compared by reference, so both terms must be parsed into the same pool. To
+        // do that, we parse them as parts of a single term
+        let root = format!("(and (= {0} {0}) (= {1} {1}))", term, sub);
+        let (root, mut pool) = crate::parser::parse_term_with_definitions(definitions, &root)
+            .expect("parser error during test");
+        let ((term, _), (sub, _)) =
+            match_term!((and (= t _t) (= s _s)) = root, RETURN_RCS).unwrap();
+        assert_eq!(
+            expected,
+            pool.contains_subterm(term, sub),
+            "{:?} in {:?}",
+            sub,
+            term
+        );
+    }
+}
+
+#[test]
+fn test_contains_subterm_shared() {
+    // Each of these terms appears twice in the next one, so it has exponentially many occurrences
+    // in the last one, and its filter has almost every bit set
+    let mut pool = TermPool::new();
+    let leaves: Vec<_> = (0..1000).map(|i| pool.add_term(terminal!(int i))).collect();
+    let mut term = leaves[0].clone();
+    for leaf in &leaves {
+        term = pool.add_term(Term::Op(
+            Operator::Add,
+            vec![term.clone(), term, leaf.clone()],
+        ));
+    }
+    let absent = pool.add_term(Term::Op(
+        Operator::Sub,
+        vec![leaves[0].clone(), leaves[1].clone()],
+    ));
+
+    // The summaries computed for the query are counted as memory used by the pool
+    let before = pool.memory_usage();
+    assert!(pool.contains_subterm(&term, &leaves[500]));
+    assert!(pool.memory_usage() > before);
+    assert!(!pool.contains_subterm(&term, &absent));
+}
+
+#[test]
+fn test_pool_shared_memory_usage() {
+    let mut pool = TermPool::new();
+    pool.add_term(terminal!(int 1));
+    let before = pool.memory_usage();
+    pool.share_memory_usage();
+    assert_eq!(before, pool.memory_usage());
+
+    // The terms added to a clone are counted in the total of both pools, but the terms that were
+    // in the pool before it was cloned are only counted once
+    let mut clone = pool.clone();
+    assert_eq!(before, clone.memory_usage());
+    clone.add_term(terminal!(int 2));
+    let after_clone = pool.memory_usage();
+    assert!(after_clone > before);
+    assert_eq!(after_clone, clone.memory_usage());
+    pool.add_term(terminal!(int 2));
+    assert_eq!(2 * after_clone - before, clone.memory_usage());
+}
+
+#[test]
+fn test_substitution() {
+    let definitions = "
+        (declare-fun f (Int Int) Int)
+        (declare-fun x () Int)
+        (declare-fun y () Int)
+        (declare-fun z () Int)
+    ";
+    // All terms must be parsed into the same pool, so we parse them as parts of a single term
+    let root = "(and
+        (= x y z)
+        (= (f x y) (f y z) (f z z))
+        (= (f (f x y) x) (f (f z y) x) (f (f z y) z)))";
+    let (root, mut pool) = crate::parser::parse_term_with_definitions(definitions, root)
+        .expect("parser error during test");
+    let terms: Vec<&[ByRefRc<Term>]> = match root.as_ref() {
+        Term::Op(Operator::And, args) => args
+            .iter()
+            .map(|a| match a.as_ref() {
+                Term::Op(Operator::Equals, args) => args.as_slice(),
+                _ => unreachable!(),
+            })
+            .collect(),
+        _ => unreachable!(),
+    };
+    let [x, y, z] = [&terms[0][0], &terms[0][1], &terms[0][2]];
+    let pairs = [(x.clone(), y.clone()), (y.clone(), z.clone())];
+    let substitution: Substitution = pairs.iter().cloned().collect();
+    let [f_x_y, f_y_z, f_z_z] = [&terms[1][0], &terms[1][1], &terms[1][2]];
+
+    assert_eq!(*f_y_z, substitution.apply(&mut pool, f_x_y));
+    assert_eq!(*f_z_z, substitution.apply_sequentially(&mut pool, f_x_y));
+
+    // The same substitution can be applied again, with the same result
+    assert_eq!(*f_y_z, substitution.apply(&mut pool, f_x_y));
+
+    let z_to_x = Substitution::single(z.clone(), x.clone());
+    assert_eq!(
+        (f_x_y.clone(), false),
+        z_to_x.apply_checked(&mut pool, f_x_y)
+    );
+    let (result, changed) = z_to_x.apply_checked(&mut pool, f_y_z);
+    assert!(changed && result != *f_y_z);
+
+    let x_to_z = Substitution::single(x.clone(), z.clone());
+    let [original, once, twice] = [&terms[2][0], &terms[2][1], &terms[2][2]];
+    assert_eq!(
+        (original.clone(), 0),
+        x_to_z.apply_limited(&mut pool, original, 0)
+    );
+    assert_eq!(
+        (once.clone(), 1),
+        x_to_z.apply_limited(&mut pool, original, 1)
+    );
+    assert_eq!(
+        (twice.clone(), 2),
+        x_to_z.apply_limited(&mut pool, original, 5)
+    );
+}
+
 #[test]
 fn test_deep_eq() {
-    fn run_tests(definitions: &str, cases: &[(&str, &str)], is_mod_reordering: bool) {
+    fn run_tests(definitions: &str, cases: &[(&str, &str)], policy: DeepEqPolicy, expected: bool) {
         for (a, b) in cases {
             let (a, b) = (
                 parse_term_with_definitions(definitions, a),
                 parse_term_with_definitions(definitions, b),
             );
-            if is_mod_reordering {
-                assert!(DeepEq::eq_modulo_reordering(&a, &b))
-            } else {
-                assert!(DeepEq::eq(&a, &b))
-            }
+            assert_eq!(
+                expected,
+                DeepEq::eq_with_policy(&a, &b, policy),
+                "{:?} and {:?} with policy {:?}",
+                a,
+                b,
+                policy
+            );
         }
     }
     let definitions = "
@@ -135,6 +321,7 @@ fn test_deep_eq() {
             (declare-fun b () T)
             (declare-fun p () Bool)
             (declare-fun q () Bool)
+            (declare-fun r () Bool)
             (declare-fun x () Int)
             (declare-fun y () Int)
         ";
@@ -148,6 +335,17 @@ fn test_deep_eq() {
                 "(ite (and (not p) q) (* x y) (- 0 y))",
             ),
         ],
+        DeepEqPolicy::EXACT,
+        true,
+    );
+    run_tests(
+        definitions,
+        &[
+            ("(= a b)", "(= b a)"),
+            ("(and p q)", "(and q p)"),
+            ("(forall ((z Int)) (= z x))", "(forall ((w Int)) (= w x))"),
+        ],
+        DeepEqPolicy::EXACT,
         false,
     );
     run_tests(
@@ -160,6 +358,327 @@ fn test_deep_eq() {
                 "(ite (= b a) (= (+ x y) x) (and p (not (= y x))))",
             ),
         ],
+        DeepEqPolicy::FLIP_EQUALITIES,
+        true,
+    );
+    run_tests(
+        definitions,
+        &[
+            ("(distinct a b)", "(distinct b a)"),
+            ("(< x y)", "(> y x)"),
+            ("(and p q)", "(and q p)"),
+            ("(forall ((z Int)) (= z x))", "(forall ((w Int)) (= x w))"),
+        ],
+        DeepEqPolicy::FLIP_EQUALITIES,
+        false,
+    );
+    run_tests(
+        definitions,
+        &[
+            ("(forall ((z Int)) (= z x))", "(forall ((w Int)) (= w x))"),
+            (
+                "(forall ((z Int) (w Int)) (< z w))",
+                "(forall ((w Int) (z Int)) (< w z))",
+            ),
+            (
+                "(exists ((z Int)) (forall ((w Int)) (< z w)))",
+                "(exists ((w Int)) (forall ((z Int)) (< w z)))",
+            ),
+            ("(let ((z x)) (< z y))", "(let ((w x)) (< w y))"),
+        ],
+        DeepEqPolicy::ALPHA_EQUIVALENCE,
+        true,
+    );
+    run_tests(
+        definitions,
+        &[
+            // Bound variables must be bound in the same position
+            (
+                "(forall ((z Int) (w Int)) (< z w))",
+                "(forall ((w Int) (z Int)) (< z w))",
+            ),
+            // Free variables are not renamed
+            ("(forall ((z Int)) (< z x))", "(forall ((z Int)) (< z y))"),
+            ("(forall ((z Int)) (< z x))", "(forall ((x Int)) (< x x))"),
+            // Sorts of bound variables must match
+            ("(forall ((z Int)) true)", "(forall ((w Real)) true)"),
+            // "let" values are not in the scope of the bound variables
+            ("(let ((z x)) (< z y))", "(let ((w y)) (< w y))"),
+            ("(= a b)", "(= b a)"),
+        ],
+        DeepEqPolicy::ALPHA_EQUIVALENCE,
+        false,
+    );
+    run_tests(
+        definitions,
+        &[
+            ("(and p q)", "(and q p)"),
+            ("(and p (and q r))", "(and r q p)"),
+            ("(or (or p q) (and q r))", "(or (and r q) q p)"),
+            ("(or p p q)", "(or p q p)"),
+        ],
+        DeepEqPolicy::AC_AND_OR,
         true,
     );
+    run_tests(
+        definitions,
+        &[
+            ("(and p q)", "(and p q q)"),
+            ("(or p p q)", "(or p q q)"),
+            ("(and p (or q r))", "(and p q r)"),
+            ("(=> p q)", "(=> q p)"),
+            ("(= (and p q) r)", "(= r (and q p))"),
+        ],
+        DeepEqPolicy::AC_AND_OR,
+        false,
+    );
+}
+
+#[test]
+fn test_compare() {
+    use std::{fmt::Write, time::Duration};
+
+    let definitions = "
+        (declare-fun p () Bool)
+        (declare-fun q () Bool)
+        (declare-fun x () Int)
+    ";
+    let parse = |term| parse_term_with_definitions(definitions, term);
+    let cases: &[(&str, &str, [bool; 4])] = &[
+        ("(and p q)", "(and p q)", [true, true, true, true]),
+        ("(= x 0)", "(= 0 x)", [false, true, false, false]),
+        (
+            "(forall ((y Int)) (> y x))",
+            "(forall ((z Int)) (> z x))",
+            [false, false, true, false],
+        ),
+        (
+            "(and p (and q p))",
+            "(and q p p)",
+            [false, false, false, true],
+        ),
+        ("(and p q)", "(or p q)", [false, false, false, false]),
+    ];
+    for (a, b, expected) in cases {
+        let (a, b) = (parse(a), parse(b));
+        let got = [
+            compare::eq_exact(&a, &b, None),
+            compare::eq_modulo_eq_orientation(&a, &b, None),
+            compare::alpha_eq(&a, &b, None),
+            compare::eq_modulo_ac(&a, &b, None),
+        ];
+        assert_eq!(expected.map(Ok), got, "{:?} and {:?}", a, b);
+    }
+
+    // Comparing large "and" terms modulo AC compares every pair of arguments, so it doesn't
+    // finish if the time limit is zero
+    let mut definitions = String::new();
+    let mut args = Vec::new();
+    for i in 0..200 {
+        writeln!(definitions, "(declare-fun p{} () Bool)", i).unwrap();
+        args.push(format!("p{}", i));
+    }
+    let a = parse_term_with_definitions(&definitions, &format!("(and {})", args.join(" ")));
+    args.reverse();
+    let b = parse_term_with_definitions(&definitions, &format!("(and {})", args.join(" ")));
+    assert_eq!(Ok(true), compare::eq_modulo_ac(&a, &b, None));
+    assert_eq!(
+        Err(compare::TimeLimitExceeded),
+        compare::eq_modulo_ac(&a, &b, Some(Duration::ZERO))
+    );
+}
+
+#[test]
+fn test_num_steps() {
+    let proof = parse_proof(
+        "(assume h1 true)
+        (step t1 (cl true) :rule true)
+        (anchor :step t4)
+        (assume t2 false)
+        (step t3 (cl (not false)) :rule false)
+        (step t4 (cl (not false) (not false)) :rule subproof)
+        (step t5 (cl) :rule resolution :premises (t1 t4))",
+    );
+    assert_eq!(4, proof.num_steps());
+}
+
+#[test]
+fn test_find_command() {
+    let proof = parse_proof(
+        "(assume h1 true)
+        (anchor :step t4)
+        (assume t2 false)
+        (step t3 (cl (not false)) :rule false)
+        (step t4 (cl (not false) (not false)) :rule subproof)
+        (step t5 (cl) :rule resolution :premises (h1 t4))",
+    );
+
+    let location = proof.find_command("t3").unwrap();
+    assert_eq!(1, location.enclosing.len());
+    assert_eq!(1, location.index);
+    assert_eq!("t3", location.command().id());
+
+    // The last step of a subproof is found as the subproof itself
+    let location = proof.find_command("t4").unwrap();
+    assert!(location.enclosing.is_empty());
+    assert!(matches!(location.command(), ProofCommand::Subproof { .. }));
+
+    let location = proof.find_command("t5").unwrap();
+    assert_eq!(
+        "(step t5 (cl) :rule resolution :premises (h1 t4))",
+        location.command().to_alethe(&location.scopes)
+    );
+    assert!(proof.find_command("t6").is_none());
+}
+
+#[test]
+fn test_pool_sort() {
+    let mut pool = TermPool::new();
+    let int_sort = pool.add_term(Term::INT_SORT.clone());
+    let bool_sort = pool.add_term(Term::BOOL_SORT.clone());
+    let one = pool.add_term(terminal!(int 1));
+    let two = pool.add_term(terminal!(int 2));
+    let sum = pool.add_term(Term::Op(Operator::Add, vec![one, two.clone()]));
+    let ite = pool.add_term(Term::Op(
+        Operator::Ite,
+        vec![pool.bool_true(), sum.clone(), two],
+    ));
+    let eq = pool.add_term(Term::Op(Operator::Equals, vec![ite.clone(), sum]));
+
+    // The sorts are terms in the pool, and `ByRefRc`s are compared by reference
+    assert_eq!(Some(int_sort.clone()), pool.sort(&ite));
+    assert_eq!(Some(bool_sort), pool.sort(&eq));
+    assert_eq!(Some(int_sort.clone()), pool.sort(&int_sort));
+
+    // Terms that are not correctly sorted can be added to the pool, but have no sort
+    let app = pool.add_term(Term::App(ite.clone(), vec![eq]));
+    assert_eq!(None, pool.sort(&app));
+    assert_eq!(None, pool.sort(&Term::Op(Operator::Add, Vec::new())));
+    let neg = pool.add_term(Term::Op(Operator::Neg, Vec::new()));
+    assert_eq!(None, pool.sort(&neg));
+}
+
+#[test]
+fn test_pool_max_term_size() {
+    let mut pool = TermPool::new();
+    pool.set_max_term_size(Some(4));
+    let one = pool.add_term(terminal!(int 1));
+    let two = pool.add_term(terminal!(int 2));
+    let sum = pool.add_term(Term::Op(Operator::Add, vec![one, two.clone()]));
+
+    // Shared subterms are counted once, so this term has only 4 distinct subterms
+    let double = pool.add_term(Term::Op(Operator::Add, vec![sum.clone(), sum]));
+    assert!(!pool.term_size_exceeded());
+
+    pool.add_term(Term::Op(Operator::Mult, vec![double, two]));
+    assert!(pool.term_size_exceeded());
+    pool.set_max_term_size(None);
+    assert!(!pool.term_size_exceeded());
+}
+
+#[test]
+fn test_symbol_interning() {
+    let a = Symbol::new("foo");
+    let b: Symbol = String::from("foo").into();
+    let c: Symbol = "bar".into();
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+    assert_eq!(a, "foo");
+    assert_eq!("\"foo\"", format!("{:?}", a));
+
+    // Sets of symbols can be queried with a `&str`
+    let set: HashSet<_> = vec![a, c].into_iter().collect();
+    assert!(set.contains("foo") && set.contains("bar") && !set.contains("baz"));
+
+    // Symbols are removed from the table when they are no longer used
+    let name = "symbol only used in this test";
+    let a = Symbol::new(name);
+    let b = a.clone();
+    drop(a);
+    assert!(symbol::is_interned(name));
+    drop(b);
+    assert!(!symbol::is_interned(name));
+}
+
+#[test]
+fn test_real_printing() {
+    let cases = [
+        (terminal!(real 2 / 1), "2.0"),
+        (terminal!(real 314 / 100), "3.14"),
+        (terminal!(real 1 / 8), "0.125"),
+        (terminal!(real 1 / 3), "(/ 1.0 3.0)"),
+        (terminal!(real - 5 / 2), "(- 2.5)"),
+        (terminal!(real 0 / 1), "0.0"),
+    ];
+    for (term, expected) in &cases {
+        assert_eq!(*expected, format!("{:?}", term));
+    }
+
+    // Numbers that are too large to be represented exactly as floats are still printed exactly
+    let big = BigInt::from(10).pow(30) + 1;
+    let term = Term::Terminal(Terminal::Real(BigRational::from_integer(big)));
+    assert_eq!("1000000000000000000000000000001.0", format!("{:?}", term));
+}
+
+#[test]
+fn test_operator_names() {
+    let mut seen = HashSet::new();
+    for &op in Operator::ALL {
+        assert!(seen.insert(op), "{:?} is repeated", op);
+        // Negation is printed like subtraction, and is told apart from it by its number of
+        // arguments
+        let expected = if op == Operator::Neg {
+            Operator::Sub
+        } else {
+            op
+        };
+        assert_eq!(Ok(expected), Operator::from_str(&format!("{:?}", op)));
+    }
+}
+
+#[test]
+fn test_proof_to_alethe() {
+    let definitions = "(declare-sort U 0) (declare-fun a () U) (declare-fun p (U) Bool)";
+    let proof = "\
+        (assume h1 (p a))\n\
+        (anchor :step t2 :args ((x U) (:= (y U) a)))\n\
+        (assume t2.h1 (p x))\n\
+        (step t2.t2 (cl (p y)) :rule trust_me :premises (t2.h1 h1))\n\
+        (step t2 (cl (p y)) :rule trust_me :premises (h1))\n\
+        (step t3 (cl) :rule trust_me :premises (h1 t2))\n";
+    let (parsed, _) = crate::parser::parse_proof_with_definitions(definitions, proof).unwrap();
+    assert_eq!(proof, parsed.to_alethe());
+}
+
+#[test]
+fn test_truncated_printing() {
+    let limits = |max_depth, max_width| PrintLimits {
+        max_depth,
+        max_width,
+    };
+    let cases = [
+        (
+            "(and true false true false)",
+            limits(None, Some(2)),
+            "(and true false … 2 more)",
+        ),
+        (
+            "(+ 1 (* 2 (- 3 4)))",
+            limits(Some(2), None),
+            "(+ 1 (* 2 …))",
+        ),
+        ("(not (= 1 2))", limits(Some(1), None), "(not …)"),
+        ("(not (= 1 2))", limits(Some(0), None), "…"),
+        ("5", limits(Some(0), Some(0)), "5"),
+        (
+            "(forall ((x Int)) (= x (+ x 0)))",
+            limits(Some(2), Some(1)),
+            "(forall ((x Int)) (= x … 1 more))",
+        ),
+        ("(+ 1 2 3)", PrintLimits::UNLIMITED, "(+ 1 2 3)"),
+    ];
+    for (term, limits, expected) in cases {
+        let term = parse_term(term);
+        assert_eq!(expected, term.truncated(limits).to_string());
+    }
 }
@@ -0,0 +1,131 @@
+use super::{Operator, Quantifier, Term};
+use std::fmt::{self, Debug, Display};
+
+/// Limits on how much of a term is printed, used to keep diagnostics about huge terms readable.
+/// Subterms nested deeper than `max_depth` are printed as "…", and only the first `max_width`
+/// arguments of each application are printed, followed by the number of omitted arguments, like in
+/// `(and p q … 3 more)`. Atoms, sorts and the bindings of binder terms are always printed in full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrintLimits {
+    pub max_depth: Option<usize>,
+    pub max_width: Option<usize>,
+}
+
+impl PrintLimits {
+    /// No limits, so terms are printed in full.
+    pub const UNLIMITED: Self = Self {
+        max_depth: None,
+        max_width: None,
+    };
+
+    pub const DEFAULT_MAX_DEPTH: usize = 8;
+    pub const DEFAULT_MAX_WIDTH: usize = 8;
+}
+
+impl Default for PrintLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: Some(Self::DEFAULT_MAX_DEPTH),
+            max_width: Some(Self::DEFAULT_MAX_WIDTH),
+        }
+    }
+}
+
+/// A term printed with `PrintLimits`. See `Term::truncated`.
+pub struct Truncated<'a> {
+    term: &'a Term,
+    limits: PrintLimits,
+}
+
+impl Term {
+    /// Returns a value that prints the term like its `Debug` implementation, but truncated
+    /// according to `limits`.
+    pub fn truncated(&self, limits: PrintLimits) -> Truncated<'_> {
+        Truncated { term: self, limits }
+    }
+}
+
+impl Display for Truncated<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_term(f, self.term, self.limits, 0)
+    }
+}
+
+impl Debug for Truncated<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+fn write_term(
+    f: &mut fmt::Formatter,
+    term: &Term,
+    limits: PrintLimits,
+    depth: usize,
+) -> fmt::Result {
+    if let Term::Terminal(_) | Term::Sort(..) = term {
+        return write!(f, "{:?}", term);
+    }
+    if limits.max_depth.is_some_and(|max| depth >= max) {
+        return write!(f, "…");
+    }
+    match term {
+        Term::App(func, args) => {
+            write!(f, "({:?}", func)?;
+            write_args(f, args, limits, depth)?;
+        }
+        Term::Op(Operator::Divisible, args) => {
+            write!(f, "((_ divisible {:?})", args[0])?;
+            write_args(f, &args[1..], limits, depth)?;
+        }
+        Term::Op(op, args) => {
+            write!(f, "({:?}", op)?;
+            write_args(f, args, limits, depth)?;
+        }
+        Term::Quant(quantifier, bindings, body) => {
+            let quantifier = match quantifier {
+                Quantifier::Forall => "forall",
+                Quantifier::Exists => "exists",
+            };
+            let bindings: Vec<_> = bindings
+                .iter()
+                .map(|(symbol, sort)| format!("({} {:?})", symbol, sort))
+                .collect();
+            write!(f, "({} ({}) ", quantifier, bindings.join(" "))?;
+            write_term(f, body, limits, depth + 1)?;
+        }
+        Term::Choice((symbol, sort), body) => {
+            write!(f, "(choice (({} {:?})) ", symbol, sort)?;
+            write_term(f, body, limits, depth + 1)?;
+        }
+        Term::Let(bindings, body) => {
+            let bindings: Vec<_> = bindings
+                .iter()
+                .map(|(symbol, value)| format!("({} {:?})", symbol, value))
+                .collect();
+            write!(f, "(let ({}) ", bindings.join(" "))?;
+            write_term(f, body, limits, depth + 1)?;
+        }
+        Term::Terminal(_) | Term::Sort(..) => unreachable!(),
+    }
+    write!(f, ")")
+}
+
+fn write_args<T: AsRef<Term>>(
+    f: &mut fmt::Formatter,
+    args: &[T],
+    limits: PrintLimits,
+    depth: usize,
+) -> fmt::Result {
+    let shown = limits
+        .max_width
+        .map_or(args.len(), |max| max.min(args.len()));
+    for a in &args[..shown] {
+        write!(f, " ")?;
+        write_term(f, a.as_ref(), limits, depth + 1)?;
+    }
+    if shown < args.len() {
+        write!(f, " … {} more", args.len() - shown)?;
+    }
+    Ok(())
+}
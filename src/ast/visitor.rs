@@ -0,0 +1,317 @@
+//! Traversals of proofs, shared by the analyses and transformations that need to walk every command
+//! of a proof, including the commands inside subproofs.
+//!
+//! A `ProofVisitor` only inspects the proof, while a `ProofRewriter` builds a new proof, and has
+//! access to the term pool to construct replacement terms. In both cases, the terms of a command
+//! are the terms in its clause, the term of an "assume" command, the terms in the `:args` of a step
+//! and the values of the assignment arguments of a subproof. The sorts of the variable and
+//! assignment arguments of a subproof are not included.
+
+use super::{ByRefRc, Proof, ProofArg, ProofCommand, ProofStep, Term, TermPool};
+
+/// Inspects the commands and terms of a proof. All methods do nothing by default, so implementors
+/// only need to override the ones they are interested in.
+pub trait ProofVisitor<'a> {
+    /// Called for every command, in the order in which they appear in the proof. For a subproof,
+    /// this is called before its commands are visited. `scopes` are the commands of the top-level
+    /// proof and of each enclosing subproof, from the outermost to the innermost, so the command's
+    /// premises are indices into them.
+    fn visit_command(&mut self, _command: &'a ProofCommand, _scopes: &[&'a [ProofCommand]]) {}
+
+    /// Called for a subproof after all of its commands were visited.
+    fn exit_subproof(&mut self, _command: &'a ProofCommand, _scopes: &[&'a [ProofCommand]]) {}
+
+    /// Called for each term of a command, after `visit_command` is called for it.
+    fn visit_term(&mut self, _term: &'a ByRefRc<Term>) {}
+}
+
+/// Walks `proof` with `visitor`.
+pub fn visit_proof<'a, V: ProofVisitor<'a> + ?Sized>(visitor: &mut V, proof: &'a Proof) {
+    fn visit<'a, V: ProofVisitor<'a> + ?Sized>(
+        visitor: &mut V,
+        commands: &'a [ProofCommand],
+        scopes: &mut Vec<&'a [ProofCommand]>,
+    ) {
+        scopes.push(commands);
+        for command in commands {
+            visitor.visit_command(command, scopes);
+            match command {
+                ProofCommand::Assume { term, .. } => visitor.visit_term(term),
+                ProofCommand::Step(step) => {
+                    for term in &step.clause {
+                        visitor.visit_term(term);
+                    }
+                    for arg in &step.args {
+                        match arg {
+                            ProofArg::Term(term) | ProofArg::Assign(_, term) => {
+                                visitor.visit_term(term)
+                            }
+                        }
+                    }
+                }
+                ProofCommand::Subproof {
+                    commands: inner,
+                    assignment_args,
+                    ..
+                } => {
+                    for (_, term) in assignment_args {
+                        visitor.visit_term(term);
+                    }
+                    visit(visitor, inner, scopes);
+                    visitor.exit_subproof(command, scopes);
+                }
+            }
+        }
+        scopes.pop();
+    }
+    visit(visitor, &proof.0, &mut Vec::new());
+}
+
+/// Builds a new proof from the commands and terms of a proof. By default, every command and term is
+/// kept unchanged, so implementors only need to override the methods for what they change.
+pub trait ProofRewriter {
+    /// Rewrites a term of a command.
+    fn rewrite_term(&mut self, _pool: &mut TermPool, term: &ByRefRc<Term>) -> ByRefRc<Term> {
+        term.clone()
+    }
+
+    /// Decides whether a step should be replaced by one of its premises. If this returns the index
+    /// of one of the step's premises, the step is removed, and the premises of later steps that
+    /// referred to it refer to that premise instead. This is called after the step's terms were
+    /// rewritten and its premises were updated, and `rewrite_command` is not called for a replaced
+    /// step. `scopes` are the commands already rewritten in the top-level proof and in each
+    /// enclosing subproof, so the step's premises are indices into them, and the step's depth is
+    /// `scopes.len() - 1`.
+    fn replace_with_premise(
+        &mut self,
+        _pool: &mut TermPool,
+        _step: &ProofStep,
+        _scopes: &[Vec<ProofCommand>],
+    ) -> Option<usize> {
+        None
+    }
+
+    /// Rewrites a command, after its terms were rewritten and its premises were updated. For
+    /// subproofs, this is called after their commands were rewritten. `depth` is the depth of the
+    /// scope that contains the command, where the top-level proof has depth 0. Returning `None`
+    /// removes the command from the proof.
+    fn rewrite_command(
+        &mut self,
+        _pool: &mut TermPool,
+        command: ProofCommand,
+        _depth: usize,
+    ) -> Option<ProofCommand> {
+        Some(command)
+    }
+}
+
+/// Rewrites `proof` with `rewriter`. The premises of the steps are updated to refer to the new
+/// positions of the commands, and premises that refer to removed commands are dropped, unless the
+/// command was replaced by one of its premises. A subproof whose commands are all removed is also
+/// removed.
+pub fn rewrite_proof<R: ProofRewriter + ?Sized>(
+    rewriter: &mut R,
+    pool: &mut TermPool,
+    proof: Proof,
+) -> Proof {
+    /// Rewrites `commands`. `new_indices` holds, for each enclosing scope, the new location of each
+    /// of its commands, as a premise, or `None` if it was removed. `scopes` holds the commands
+    /// already rewritten in each enclosing scope.
+    fn rewrite<R: ProofRewriter + ?Sized>(
+        rewriter: &mut R,
+        pool: &mut TermPool,
+        commands: Vec<ProofCommand>,
+        new_indices: &mut Vec<Vec<Option<(usize, usize)>>>,
+        scopes: &mut Vec<Vec<ProofCommand>>,
+    ) -> Vec<ProofCommand> {
+        let depth = new_indices.len();
+        new_indices.push(Vec::with_capacity(commands.len()));
+        scopes.push(Vec::with_capacity(commands.len()));
+        for command in commands {
+            let command = match command {
+                ProofCommand::Assume { id, term } => ProofCommand::Assume {
+                    id,
+                    term: rewriter.rewrite_term(pool, &term),
+                },
+                ProofCommand::Step(step) => {
+                    let premises = step
+                        .premises
+                        .iter()
+                        .filter_map(|&(d, i)| new_indices[d][i])
+                        .collect();
+                    let clause = step
+                        .clause
+                        .iter()
+                        .map(|t| rewriter.rewrite_term(pool, t))
+                        .collect();
+                    let args = step
+                        .args
+                        .iter()
+                        .map(|arg| match arg {
+                            ProofArg::Term(t) => ProofArg::Term(rewriter.rewrite_term(pool, t)),
+                            ProofArg::Assign(name, t) => {
+                                ProofArg::Assign(name.clone(), rewriter.rewrite_term(pool, t))
+                            }
+                        })
+                        .collect();
+                    let step = ProofStep {
+                        clause,
+                        premises,
+                        args,
+                        ..step
+                    };
+                    if let Some(k) = rewriter.replace_with_premise(pool, &step, scopes) {
+                        new_indices[depth].push(Some(step.premises[k]));
+                        continue;
+                    }
+                    ProofCommand::Step(step)
+                }
+                ProofCommand::Subproof {
+                    commands,
+                    assignment_args,
+                    variable_args,
+                } => {
+                    let assignment_args = assignment_args
+                        .into_iter()
+                        .map(|(name, t)| (name, rewriter.rewrite_term(pool, &t)))
+                        .collect();
+                    let commands = rewrite(rewriter, pool, commands, new_indices, scopes);
+                    if commands.is_empty() {
+                        new_indices[depth].push(None);
+                        continue;
+                    }
+                    ProofCommand::Subproof {
+                        commands,
+                        assignment_args,
+                        variable_args,
+                    }
+                }
+            };
+            let new_index = rewriter
+                .rewrite_command(pool, command, depth)
+                .map(|command| {
+                    let result = &mut scopes[depth];
+                    result.push(command);
+                    (depth, result.len() - 1)
+                });
+            new_indices[depth].push(new_index);
+        }
+        new_indices.pop();
+        scopes.pop().unwrap()
+    }
+    Proof(rewrite(
+        rewriter,
+        pool,
+        proof.0,
+        &mut Vec::new(),
+        &mut Vec::new(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::*;
+    use crate::parser::parse_proof_with_definitions;
+
+    const DEFINITIONS: &str = "
+        (declare-fun p () Bool)
+        (declare-fun q () Bool)
+    ";
+    const PROOF: &str = "
+        (assume h1 p)
+        (assume h2 q)
+        (anchor :step t3)
+        (assume t3.h1 (and p q))
+        (step t3 (cl (not (and p q)) p) :rule subproof :discharge (t3.h1))
+        (step t4 (cl p) :rule trust_me :premises (h1 t3) :args (q))
+    ";
+
+    #[test]
+    fn visitor() {
+        #[derive(Default)]
+        struct Counter {
+            commands: Vec<(String, usize)>,
+            subproofs_exited: usize,
+            terms: usize,
+        }
+
+        impl<'a> ProofVisitor<'a> for Counter {
+            fn visit_command(&mut self, command: &'a ProofCommand, scopes: &[&'a [ProofCommand]]) {
+                self.commands.push((command.id().to_owned(), scopes.len()));
+            }
+
+            fn exit_subproof(&mut self, _: &'a ProofCommand, _: &[&'a [ProofCommand]]) {
+                self.subproofs_exited += 1;
+            }
+
+            fn visit_term(&mut self, _: &'a ByRefRc<Term>) {
+                self.terms += 1;
+            }
+        }
+
+        let (proof, _) = parse_proof_with_definitions(DEFINITIONS, PROOF).unwrap();
+        let mut counter = Counter::default();
+        visit_proof(&mut counter, &proof);
+        let expected: Vec<_> = [
+            ("h1", 1),
+            ("h2", 1),
+            ("t3", 1),
+            ("t3.h1", 2),
+            ("t3", 2),
+            ("t4", 1),
+        ]
+        .iter()
+        .map(|&(id, depth)| (id.to_owned(), depth))
+        .collect();
+        assert_eq!(expected, counter.commands);
+        assert_eq!(1, counter.subproofs_exited);
+        assert_eq!(7, counter.terms);
+    }
+
+    #[test]
+    fn rewriter() {
+        // Replaces "q" with "(not p)", and removes the unused assumption "h2"
+        struct Rewriter(ByRefRc<Term>, ByRefRc<Term>);
+
+        impl ProofRewriter for Rewriter {
+            fn rewrite_term(&mut self, pool: &mut TermPool, term: &ByRefRc<Term>) -> ByRefRc<Term> {
+                Substitution::single(self.0.clone(), self.1.clone()).apply(pool, term)
+            }
+
+            fn rewrite_command(
+                &mut self,
+                _: &mut TermPool,
+                command: ProofCommand,
+                _: usize,
+            ) -> Option<ProofCommand> {
+                Some(command).filter(|c| c.id() != "h2")
+            }
+        }
+
+        let (proof, mut pool) = parse_proof_with_definitions(DEFINITIONS, PROOF).unwrap();
+        let bool_sort = pool.add_term(Term::BOOL_SORT.clone());
+        let p = pool.add_term(terminal!(var "p"; bool_sort.clone()));
+        let q = pool.add_term(terminal!(var "q"; bool_sort.clone()));
+        let not_p = build_term!(pool, (not {p.clone()}));
+
+        let proof = rewrite_proof(&mut Rewriter(q, not_p.clone()), &mut pool, proof);
+        let ids: Vec<_> = proof.0.iter().map(ProofCommand::id).collect();
+        assert_eq!(vec!["h1", "t3", "t4"], ids);
+        match &proof.0[2] {
+            ProofCommand::Step(step) => {
+                assert_eq!(vec![(0, 0), (0, 1)], step.premises);
+                assert_eq!(Some(&not_p), step.args[0].as_term());
+            }
+            _ => panic!(),
+        }
+        match &proof.0[1] {
+            ProofCommand::Subproof { commands, .. } => match &commands[0] {
+                ProofCommand::Assume { term, .. } => {
+                    assert_eq!(build_term!(pool, (and {p} {not_p})), *term)
+                }
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Benchmark entry points for the core primitives of the checker: term interning, deep equality,
+//! substitution and resolution. Each benchmark builds a synthetic input of a fixed size, so results
+//! are comparable between runs, and measures only the operation itself. This module is only
+//! compiled with the `bench` feature, and the benchmarks can be run with
+//! `cargo bench --features bench`.
+
+use crate::{
+    ast::*,
+    checker::{self, Correctness, ProofChecker},
+    parser::{self, parse_problem_proof},
+};
+use std::{
+    fmt::{self, Write},
+    hint::black_box,
+    io::Cursor,
+    time::{Duration, Instant},
+};
+
+/// The result of running a benchmark.
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub name: String,
+    pub iterations: usize,
+
+    /// The time taken by the fastest iteration.
+    pub min: Duration,
+
+    /// The mean time taken by each iteration.
+    pub mean: Duration,
+}
+
+impl fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: mean {:?}, min {:?} ({} iterations)",
+            self.name, self.mean, self.min, self.iterations
+        )
+    }
+}
+
+/// Runs `routine` `iterations` times, after a single warm-up run, and measures how long each run
+/// takes. Before each run, `setup` is called to build the input of `routine`, and the time it
+/// takes is not measured.
+pub fn measure<I, O>(
+    name: &str,
+    iterations: usize,
+    mut setup: impl FnMut() -> I,
+    mut routine: impl FnMut(I) -> O,
+) -> BenchResult {
+    black_box(routine(setup()));
+    let mut min = Duration::MAX;
+    let mut total = Duration::ZERO;
+    for _ in 0..iterations {
+        let input = setup();
+        let start = Instant::now();
+        black_box(routine(input));
+        let elapsed = start.elapsed();
+        min = min.min(elapsed);
+        total += elapsed;
+    }
+    BenchResult {
+        name: name.to_owned(),
+        iterations,
+        min,
+        mean: total / iterations.max(1) as u32,
+    }
+}
+
+/// Builds a balanced binary tree of "+" applications of the given depth, whose leaves are the
+/// integer variables `x0`, `x1`, and so on, cycling through `num_vars` of them. The term is added
+/// to `pool` bottom-up.
+fn balanced_sum(pool: &mut TermPool, depth: usize, num_vars: usize) -> ByRefRc<Term> {
+    fn build(
+        pool: &mut TermPool,
+        depth: usize,
+        num_vars: usize,
+        next: &mut usize,
+    ) -> ByRefRc<Term> {
+        if depth == 0 {
+            let name = format!("x{}", *next % num_vars);
+            *next += 1;
+            let int_sort = pool.add_term(Term::INT_SORT.clone());
+            return pool.add_term(terminal!(var name; int_sort.clone()));
+        }
+        let a = build(pool, depth - 1, num_vars, next);
+        let b = build(pool, depth - 1, num_vars, next);
+        pool.add_term(Term::Op(Operator::Add, vec![a, b]))
+    }
+    build(pool, depth, num_vars, &mut 0)
+}
+
+/// Measures adding a term with `2^depth` leaves to an empty term pool. Since the leaves cycle
+/// through a few variables, many subterms are repeated, and are found in the pool when added.
+pub fn term_interning(depth: usize, iterations: usize) -> BenchResult {
+    measure(
+        &format!("term_interning (depth {})", depth),
+        iterations,
+        TermPool::new,
+        |mut pool| balanced_sum(&mut pool, depth, 16),
+    )
+}
+
+/// Measures comparing two identical terms with `2^depth` leaves with `DeepEq`. The terms are added
+/// to different pools, so they don't share any subterms and have to be compared in full.
+pub fn deep_equality(depth: usize, iterations: usize) -> BenchResult {
+    let a = balanced_sum(&mut TermPool::new(), depth, 1 << depth);
+    let b = balanced_sum(&mut TermPool::new(), depth, 1 << depth);
+    measure(
+        &format!("deep_equality (depth {})", depth),
+        iterations,
+        || (),
+        |()| DeepEq::eq(&a, &b),
+    )
+}
+
+/// Measures substituting one of the variables in a term with `2^depth` distinct leaves by another
+/// variable.
+pub fn substitution(depth: usize, iterations: usize) -> BenchResult {
+    let mut pool = TermPool::new();
+    let term = balanced_sum(&mut pool, depth, 1 << depth);
+    let int_sort = pool.add_term(Term::INT_SORT.clone());
+    let x = pool.add_term(terminal!(var "x0"; int_sort.clone()));
+    let y = pool.add_term(terminal!(var "y"; int_sort.clone()));
+    measure(
+        &format!("substitution (depth {})", depth),
+        iterations,
+        || Substitution::single(x.clone(), y.clone()),
+        |substitution| substitution.apply(&mut pool, &term),
+    )
+}
+
+/// Measures checking a "resolution" step with `num_clauses` premises. The first premise is a
+/// clause with `num_clauses - 1` atoms, and each of the others is the negation of one of them, so
+/// the conclusion is the empty clause. The premises are introduced with the "trust_me" rule.
+pub fn resolution(num_clauses: usize, iterations: usize) -> BenchResult {
+    let mut definitions = String::new();
+    let mut proof = String::from("(step t0 (cl");
+    for i in 1..num_clauses {
+        writeln!(definitions, "(declare-fun p{} () Bool)", i).unwrap();
+        write!(proof, " p{}", i).unwrap();
+    }
+    proof.push_str(") :rule trust_me)\n");
+    for i in 1..num_clauses {
+        writeln!(proof, "(step t{} (cl (not p{})) :rule trust_me)", i, i).unwrap();
+    }
+    let premises: Vec<_> = (0..num_clauses).map(|i| format!("t{}", i)).collect();
+    writeln!(
+        proof,
+        "(step t{} (cl) :rule resolution :premises ({}))",
+        num_clauses,
+        premises.join(" ")
+    )
+    .unwrap();
+
+    let (_, proof, mut pool) = parse_problem_proof(
+        Cursor::new(definitions),
+        Cursor::new(proof),
+        parser::Config::default(),
+    )
+    .unwrap();
+    let config = checker::Config {
+        allow_test_rule: true,
+        ..Default::default()
+    };
+    let mut checker = ProofChecker::new(&mut pool, config);
+    let result = measure(
+        &format!("resolution ({} clauses)", num_clauses),
+        iterations,
+        || (),
+        |()| checker.check(&proof),
+    );
+    assert!(matches!(checker.check(&proof), Ok(Correctness::True)));
+    result
+}
+
+/// Runs all benchmarks with their default sizes.
+pub fn run_all() -> Vec<BenchResult> {
+    vec![
+        term_interning(12, 50),
+        deep_equality(14, 50),
+        substitution(14, 50),
+        resolution(1000, 50),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn benchmarks_run() {
+        // Small sizes, so this only checks that the benchmarks work
+        let results = [
+            term_interning(3, 2),
+            deep_equality(3, 2),
+            substitution(3, 2),
+            resolution(5, 2),
+        ];
+        for r in &results {
+            assert_eq!(2, r.iterations);
+            assert!(r.min <= r.mean);
+        }
+    }
+}
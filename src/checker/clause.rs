@@ -0,0 +1,202 @@
+use super::rules::Premise;
+use crate::ast::{ByRefRc, Literal, Term};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+/// A clause in canonical form, as a set of literals. The literals are kept sorted and without
+/// repetitions, and the hash of the clause is computed once, when it is built, so clauses can be
+/// compared and looked up cheaply, regardless of the order and repetitions of their terms. Clauses
+/// are only built by the rules that need them, so steps checked by other rules pay nothing for
+/// them.
+#[derive(Debug, Clone)]
+pub(super) struct Clause {
+    literals: Vec<Literal>,
+    hash: u64,
+
+    /// The literals of the terms the clause was built from, in their original order and with
+    /// repetitions.
+    terms: Vec<Literal>,
+}
+
+impl Clause {
+    pub fn new(terms: &[ByRefRc<Term>]) -> Self {
+        let terms: Vec<_> = terms.iter().map(|t| Literal::from_term(t)).collect();
+        let mut literals = terms.clone();
+        literals.sort_unstable();
+        literals.dedup();
+        let mut hasher = DefaultHasher::new();
+        literals.hash(&mut hasher);
+        Self {
+            literals,
+            hash: hasher.finish(),
+            terms,
+        }
+    }
+
+    /// Returns the number of distinct literals in the clause.
+    pub fn len(&self) -> usize {
+        self.literals.len()
+    }
+
+    /// Returns true if the clause was built from terms with repetitions.
+    pub fn had_repetitions(&self) -> bool {
+        self.terms.len() != self.literals.len()
+    }
+
+    /// Returns the literals of the terms the clause was built from, in their original order and
+    /// with repetitions.
+    pub fn terms(&self) -> &[Literal] {
+        &self.terms
+    }
+
+    /// Returns true if the clause contains a literal and its negation, possibly with extra double
+    /// negations, like "p" and "(not (not (not p)))".
+    pub fn has_complementary_literals(&self) -> bool {
+        // Literals are sorted by their atom first, so if there are literals with the same atom and
+        // opposite polarities, two of them must be next to each other
+        self.literals
+            .windows(2)
+            .any(|w| w[0].atom() == w[1].atom() && w[0].polarity() != w[1].polarity())
+    }
+
+    /// Returns the index of `literal` in the sorted literals of the clause, or `None` if the clause
+    /// doesn't contain it.
+    pub fn position(&self, literal: &Literal) -> Option<usize> {
+        self.literals.binary_search(literal).ok()
+    }
+
+    /// Returns true if every literal of the clause is also in `other`.
+    pub fn is_subset(&self, other: &Clause) -> bool {
+        let mut others = other.literals.iter();
+        self.literals.iter().all(|l| others.any(|o| o == l))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Literal> {
+        self.literals.iter()
+    }
+}
+
+/// The canonical clauses of the premises of the steps checked so far, indexed by the location of
+/// their commands. A step's conclusion is usually used as a premise by several later steps, so its
+/// canonical clause is only built the first time it is needed. There is one map for each scope
+/// depth, and the maps of a depth are discarded when a new subproof starts at it, since the indices
+/// then refer to the commands of the new subproof.
+#[derive(Debug, Default)]
+pub(super) struct ClauseCache {
+    scopes: Vec<HashMap<usize, Clause>>,
+    memory_usage: usize,
+}
+
+impl ClauseCache {
+    /// Returns the approximate size, in bytes, of the cache entry for `clause`.
+    fn entry_size(clause: &Clause) -> usize {
+        let literals = clause.literals.len() + clause.terms.len();
+        std::mem::size_of::<(usize, Clause)>() + literals * std::mem::size_of::<Literal>()
+    }
+
+    /// Returns the canonical clause of `premise`, building it if it is not in the cache.
+    pub fn get(&mut self, premise: &Premise) -> &Clause {
+        let (depth, i) = premise.location;
+        if self.scopes.len() <= depth {
+            self.scopes.resize_with(depth + 1, HashMap::new);
+        }
+        let memory_usage = &mut self.memory_usage;
+        self.scopes[depth].entry(i).or_insert_with(|| {
+            let clause = Clause::new(premise.clause);
+            *memory_usage += Self::entry_size(&clause);
+            clause
+        })
+    }
+
+    /// Discards the clauses of the commands in the scopes at depth `depth` or deeper. This must be
+    /// called when a subproof starts at that depth.
+    pub fn start_scope(&mut self, depth: usize) {
+        for scope in self.scopes.drain(depth.min(self.scopes.len())..) {
+            self.memory_usage -= scope.values().map(Self::entry_size).sum::<usize>();
+        }
+    }
+
+    /// Returns an approximation of how much memory, in bytes, is used by the cached clauses.
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage
+    }
+
+    pub fn clear(&mut self) {
+        self.scopes.clear();
+        self.memory_usage = 0;
+    }
+}
+
+impl PartialEq for Clause {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash && self.literals == other.literals
+    }
+}
+
+impl Eq for Clause {}
+
+impl Hash for Clause {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn canonical_clauses() {
+        let proof = "
+            (step t1 (cl (not p) q (not (not r))) :rule trust_me)
+            (step t2 (cl q (not (not r)) (not p) q) :rule trust_me)
+            (step t3 (cl q (not (not r))) :rule trust_me)
+            (step t4 (cl q (not r)) :rule trust_me)
+        ";
+        let (proof, _pool) =
+            parser::parse_proof_only(proof.as_bytes(), parser::Config::default()).unwrap();
+        let clauses: Vec<_> = proof.0.iter().map(|c| Clause::new(c.clause())).collect();
+        assert_eq!(clauses[0], clauses[1]);
+        assert!(!clauses[0].had_repetitions() && clauses[1].had_repetitions());
+        assert_eq!(3, clauses[1].len());
+
+        assert!(clauses[2].is_subset(&clauses[0]));
+        assert!(!clauses[0].is_subset(&clauses[2]));
+        assert!(!clauses[3].is_subset(&clauses[0]));
+        assert_ne!(clauses[2], clauses[3]);
+
+        let not_r = clauses[3]
+            .iter()
+            .find(|l| l.negated() == 1)
+            .copied()
+            .unwrap();
+        assert_eq!(4, clauses[1].terms().len());
+        assert!(!clauses[0].has_complementary_literals());
+        assert!(
+            Clause::new(&[proof.0[3].clause(), proof.0[0].clause()].concat())
+                .has_complementary_literals()
+        );
+
+        let mut cache = ClauseCache::default();
+        let scopes = [&proof.0[..]];
+        assert_eq!(&clauses[1], cache.get(&Premise::new(&scopes, (0, 1))));
+        assert!(cache.get(&Premise::new(&scopes, (0, 1))).had_repetitions());
+        assert_eq!(1, cache.scopes[0].len());
+        let usage = cache.memory_usage();
+        assert!(usage > 0);
+
+        // The clauses of deeper scopes are discarded when a new subproof starts
+        cache.get(&Premise::new(&[&proof.0[..], &proof.0[..]], (1, 0)));
+        assert!(cache.memory_usage() > usage);
+        cache.start_scope(1);
+        assert_eq!(usage, cache.memory_usage());
+        assert_eq!(1, cache.scopes.len());
+
+        assert!(clauses[0].position(&not_r.negate()).is_some());
+        assert_eq!(None, clauses[0].position(&not_r));
+        assert_eq!(None, clauses[0].position(&not_r.remove_negation().unwrap()));
+    }
+}
@@ -0,0 +1,139 @@
+use crate::ast::*;
+use std::collections::HashSet;
+
+/// The context introduced by the "anchor" command of a subproof. This holds the substitutions
+/// given by the assignment arguments, and the variables given by the variable arguments.
+pub struct Context {
+    pub(super) substitutions: Substitution,
+    pub(super) substitutions_until_fixed_point: Substitution,
+    pub(super) bindings: HashSet<SortedVar>,
+}
+
+/// The contexts of the subproofs that enclose the step being checked, from the outermost to the
+/// innermost. A context is pushed when the checker enters a subproof, and popped when it leaves it,
+/// so rules that depend on the context, like "refl", "bind" and the skolemization rules, can query
+/// the substitutions that are in effect.
+#[derive(Default)]
+pub struct ContextStack {
+    stack: Vec<Context>,
+}
+
+impl ContextStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    /// Pushes the context introduced by an "anchor" command with the given arguments.
+    pub fn push(
+        &mut self,
+        pool: &mut TermPool,
+        assignment_args: &[(SortedVar, ByRefRc<Term>)],
+        variable_args: &[SortedVar],
+    ) {
+        // Since some rules (like "refl") need to apply substitutions until a fixed point, we
+        // precompute these substitutions into a separate hash map. This assumes that the assignment
+        // arguments are in the correct order.
+        let mut substitutions = Substitution::new();
+        let mut substitutions_until_fixed_point = Substitution::new();
+
+        // We build the `substitutions_until_fixed_point` substitution from the bottom up, by using
+        // the substitutions already introduced to transform the result of a new substitution before
+        // inserting it into the hash map. So for instance, if the substitutions are "(:= y z)" and
+        // "(:= x (f y))", we insert the first substitution, and then, when introducing the second,
+        // we use the current state of the hash map to transform "(f y)" into "(f z)". The resulting
+        // hash map will then contain "(:= y z)" and "(:= x (f z))". However, the arguments are
+        // given in the opposite order, that is, "(:= x (f y))" would come first, followed by
+        // "(:= y z)". Because of that, we traverse the assignment arguments slice in reverse.
+        for (var, value) in assignment_args.iter().rev() {
+            let var_term = pool.add_term(Term::from(var.clone()));
+            substitutions.insert(var_term.clone(), value.clone());
+
+            let new_value = substitutions_until_fixed_point.apply(pool, value);
+            substitutions_until_fixed_point.insert(var_term, new_value);
+        }
+
+        let bindings = variable_args.iter().cloned().collect();
+        self.stack.push(Context {
+            substitutions,
+            substitutions_until_fixed_point,
+            bindings,
+        });
+    }
+
+    pub fn pop(&mut self) {
+        self.stack.pop();
+    }
+
+    /// Returns the context of the innermost subproof, or `None` if the stack is empty.
+    pub fn last(&self) -> Option<&Context> {
+        self.stack.last()
+    }
+
+    /// Applies the substitutions of every context to `term`, from the outermost context to the
+    /// innermost. The substitutions of each context are applied until a fixed point.
+    pub fn apply_cumulative(&self, pool: &mut TermPool, term: &ByRefRc<Term>) -> ByRefRc<Term> {
+        let mut current = term.clone();
+        for c in &self.stack {
+            current = c.substitutions_until_fixed_point.apply(pool, &current);
+        }
+        current
+    }
+
+    /// Applies the substitutions of every context except the innermost one to `term`, from the
+    /// outermost context to the innermost. Unlike in `apply_cumulative`, the substitutions of each
+    /// context are applied only once.
+    pub fn apply_enclosing(&self, pool: &mut TermPool, term: &ByRefRc<Term>) -> ByRefRc<Term> {
+        let mut current = term.clone();
+        let n = self.stack.len().saturating_sub(1);
+        for c in &self.stack[..n] {
+            current = c.substitutions.apply(pool, &current);
+        }
+        current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_term_with_definitions;
+
+    #[test]
+    fn context_stack() {
+        let definitions = "
+            (declare-fun x () Bool)
+            (declare-fun y () Bool)
+            (declare-fun z () Bool)
+        ";
+        let (term, mut pool) = parse_term_with_definitions(definitions, "(not x)").unwrap();
+        let bool_sort = pool.sort(&term).unwrap();
+        let y = pool.add_term(terminal!(var "y"; bool_sort.clone()));
+        let z = pool.add_term(terminal!(var "z"; bool_sort.clone()));
+        let not_z = build_term!(pool, (not {z.clone()}));
+
+        let mut stack = ContextStack::new();
+        let x_var = ("x".to_owned(), bool_sort.clone());
+        let y_var = ("y".to_owned(), bool_sort.clone());
+        stack.push(&mut pool, &[(x_var, y.clone())], &[]);
+        stack.push(&mut pool, &[(y_var, z.clone())], &[]);
+        assert_eq!(2, stack.len());
+
+        // The substitutions of all contexts are applied, from the outermost to the innermost
+        assert_eq!(not_z, stack.apply_cumulative(&mut pool, &term));
+
+        // Only the substitutions of the enclosing contexts are applied
+        let not_y = build_term!(pool, (not {y.clone()}));
+        assert_eq!(not_y, stack.apply_enclosing(&mut pool, &term));
+
+        stack.pop();
+        assert_eq!(not_y, stack.apply_cumulative(&mut pool, &term));
+        assert_eq!(term, stack.apply_enclosing(&mut pool, &term));
+    }
+}
@@ -0,0 +1,351 @@
+use super::rules::get_clause_from_command;
+use crate::{ast::*, json::Json};
+use std::{
+    fmt::{self, Display, Write},
+    ops::Range,
+};
+
+/// A description of a step that failed to check, meant to help find out why it failed. This
+/// includes the failing step and its premises in Alethe syntax, the shape of the conclusion that
+/// the rule expects, and the relevant sub-terms of the conclusion, that is, the arguments of each
+/// of its literals.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub id: String,
+    pub rule: String,
+
+    /// The failing command, in Alethe syntax.
+    pub command: String,
+
+    /// The premises of the failing step, in Alethe syntax.
+    pub premises: Vec<String>,
+
+    /// A description of the conclusion the rule expects, if the rule is known.
+    pub expected: Option<&'static str>,
+
+    /// The arguments of each literal in the conclusion, ignoring a leading negation, without
+    /// repetitions.
+    pub subterms: Vec<String>,
+    /// The line and column in the proof file where the failing command starts, if it is known.
+    pub position: Option<(usize, usize)>,
+
+    /// The byte ranges in `command` of the parts of the conclusion that most likely caused the
+    /// failure.
+    pub highlighted: Vec<Range<usize>>,
+
+    /// The annotations the solver emitted for the failing step, as pairs of an attribute name and
+    /// its value. The checker leaves this empty; it is filled in from a companion annotation file
+    /// (see `parser::parse_annotations`).
+    pub annotations: Vec<(String, String)>,
+}
+
+impl Explanation {
+    /// Builds the explanation for a failing "step" command. `scopes` are the commands of the scopes
+    /// that the step's premises refer to, from the outermost to the innermost. Terms are truncated
+    /// according to `limits`.
+    pub(super) fn from_step(
+        step: &ProofStep,
+        scopes: &[&[ProofCommand]],
+        limits: PrintLimits,
+    ) -> Self {
+        let premises = step
+            .premises
+            .iter()
+            .map(|&(d, i)| scopes[d][i].to_alethe_truncated(scopes, limits))
+            .collect();
+
+        let mut subterms = Vec::new();
+        for literal in &step.clause {
+            let literal = match_term!((not t) = literal).unwrap_or(literal);
+            let args = match literal {
+                Term::App(_, args) | Term::Op(_, args) => args.as_slice(),
+                _ => &[],
+            };
+            for arg in args {
+                let arg = arg.truncated(limits).to_string();
+                if !subterms.contains(&arg) {
+                    subterms.push(arg);
+                }
+            }
+        }
+
+        // The literals in the conclusion that don't appear in any premise are the most likely
+        // culprits. If the step has no premises, or if there are no such literals, we highlight
+        // the whole clause instead. This mirrors how `ProofStep::to_alethe` prints the step
+        let premise_literals: Vec<_> = step
+            .premises
+            .iter()
+            .flat_map(|&(d, i)| get_clause_from_command(&scopes[d][i]))
+            .collect();
+        let clause_start = "(step  ".len() + step.id.len();
+        let mut highlighted = Vec::new();
+        let mut end = clause_start + "(cl".len();
+        for literal in &step.clause {
+            let start = end + 1;
+            end = start + literal.truncated(limits).to_string().len();
+            if !premise_literals.is_empty() && !premise_literals.contains(&literal) {
+                highlighted.push(start..end);
+            }
+        }
+        if highlighted.is_empty() {
+            highlighted.push(clause_start..end + 1);
+        }
+
+        Self {
+            id: step.id.clone(),
+            rule: step.rule.clone(),
+            command: step.to_alethe_truncated(scopes, limits),
+            premises,
+            expected: super::rule_info(&step.rule).map(|info| info.conclusion),
+            subterms,
+            position: Some(step.position),
+            highlighted,
+            annotations: Vec::new(),
+        }
+    }
+
+    /// Builds the explanation for an "assume" command whose term was not asserted in the problem.
+    pub(super) fn from_assume(id: &str, term: &ByRefRc<Term>, limits: PrintLimits) -> Self {
+        let command = format!("(assume {} {})", id, term.truncated(limits));
+        let command_len = command.len();
+        Self {
+            id: id.to_owned(),
+            rule: "assume".to_owned(),
+            command,
+            premises: Vec::new(),
+            expected: Some("a term that was asserted in the problem"),
+            subterms: Vec::new(),
+            position: None,
+            highlighted: vec![Range {
+                start: "(assume  ".len() + id.len(),
+                end: command_len - 1,
+            }],
+            annotations: Vec::new(),
+        }
+    }
+
+    /// Returns the explanation as a JSON object.
+    pub fn to_json(&self) -> Json {
+        let annotations = self
+            .annotations
+            .iter()
+            .map(|(name, value)| Json::strings([name, value]))
+            .collect();
+        let position = self.position.map_or(Json::Null, |(line, column)| {
+            Json::Array(vec![Json::Number(line as f64), Json::Number(column as f64)])
+        });
+        Json::object([
+            ("id", Json::String(self.id.clone())),
+            ("rule", Json::String(self.rule.clone())),
+            ("command", Json::String(self.command.clone())),
+            ("premises", Json::strings(&self.premises)),
+            (
+                "expected",
+                self.expected
+                    .map_or(Json::Null, |e| Json::String(e.to_owned())),
+            ),
+            ("subterms", Json::strings(&self.subterms)),
+            ("position", position),
+            ("annotations", Json::Array(annotations)),
+        ])
+    }
+}
+
+/// ANSI escape codes used when printing diagnostics in color.
+const BOLD_RED: &str = "\x1b[1;31m";
+const BOLD_BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+impl Explanation {
+    /// Returns the explanation as a diagnostic in the style of the Rust compiler, with the parts of
+    /// the conclusion that most likely caused the failure underlined. `file_name` is the name of
+    /// the proof file. If `color` is true, the diagnostic uses ANSI colors.
+    pub fn to_diagnostic(&self, file_name: &str, color: bool) -> String {
+        let paint = |text: &str, style: &str| {
+            if color {
+                format!("{}{}{}", style, text, RESET)
+            } else {
+                text.to_owned()
+            }
+        };
+        let line_number = self.position.map(|(line, _)| line.to_string());
+        let gutter_width = line_number.as_ref().map_or(0, String::len);
+        let gutter = |text: &str| paint(&format!("{:>1$} |", text, gutter_width), BOLD_BLUE);
+
+        let mut underline = String::new();
+        for (i, c) in self.command.char_indices() {
+            if self.highlighted.iter().any(|range| range.contains(&i)) {
+                underline.push('^');
+            } else if self.highlighted.iter().any(|range| range.start > i) {
+                underline.push(if c == '\t' { '\t' } else { ' ' });
+            }
+        }
+
+        let mut result = String::new();
+        writeln!(
+            result,
+            "{}{}",
+            paint("error", BOLD_RED),
+            paint(
+                &format!(": step {} failed on rule \"{}\"", self.id, self.rule),
+                BOLD
+            ),
+        )
+        .unwrap();
+        if let Some((line, column)) = self.position {
+            let arrow = paint(&format!("{:1$}-->", "", gutter_width), BOLD_BLUE);
+            writeln!(result, "{} {}:{}:{}", arrow, file_name, line, column).unwrap();
+        }
+        writeln!(result, "{}", gutter("")).unwrap();
+        writeln!(
+            result,
+            "{} {}",
+            gutter(line_number.as_deref().unwrap_or("")),
+            self.command
+        )
+        .unwrap();
+        writeln!(result, "{} {}", gutter(""), paint(&underline, BOLD_RED)).unwrap();
+
+        let note = |result: &mut String, label: &str, text: &str| {
+            let equals = paint(&format!("{:1$}=", "", gutter_width + 1), BOLD_BLUE);
+            writeln!(result, "{} {}: {}", equals, paint(label, BOLD), text).unwrap();
+        };
+        if let Some(expected) = self.expected {
+            note(&mut result, "expected", expected);
+        }
+        for premise in &self.premises {
+            note(&mut result, "premise", premise);
+        }
+        for (name, value) in &self.annotations {
+            note(&mut result, &format!("solver {}", name), value);
+        }
+        result
+    }
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "step {} failed on rule \"{}\"", self.id, self.rule)?;
+        writeln!(f, "  {}", self.command)?;
+        if !self.premises.is_empty() {
+            writeln!(f, "premises:")?;
+            for p in &self.premises {
+                writeln!(f, "  {}", p)?;
+            }
+        }
+        if let Some(expected) = self.expected {
+            writeln!(f, "expected: {}", expected)?;
+        }
+        if !self.subterms.is_empty() {
+            writeln!(f, "subterms:")?;
+            for t in &self.subterms {
+                writeln!(f, "  {}", t)?;
+            }
+        }
+        if !self.annotations.is_empty() {
+            writeln!(f, "solver annotations:")?;
+            for (name, value) in &self.annotations {
+                writeln!(f, "  {}: {}", name, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        checker::{Config, ProofChecker},
+        parser::parse_proof_with_definitions,
+    };
+
+    #[test]
+    fn explain_failing_step() {
+        let definitions = "
+            (declare-sort T 0)
+            (declare-fun a () T)
+            (declare-fun b () T)
+            (declare-fun c () T)
+        ";
+        let proof = "
+            (assume h1 (= a b))
+            (assume h2 (= \"x\" \"y\"))
+            (step t3 (cl (= a c)) :rule trans :premises (h1 h2))
+        ";
+        let (proof, mut pool) = parse_proof_with_definitions(definitions, proof).unwrap();
+        let mut checker = ProofChecker::new(&mut pool, Config::default());
+        let _ = checker.check(&proof).unwrap();
+        let explanation = checker.explanation().unwrap();
+
+        assert_eq!("t3", explanation.id);
+        assert_eq!(
+            "(step t3 (cl (= a c)) :rule trans :premises (h1 h2))",
+            explanation.command
+        );
+        assert_eq!(
+            vec!["(assume h1 (= a b))", "(assume h2 (= \"x\" \"y\"))"],
+            explanation.premises
+        );
+        assert!(explanation.expected.is_some());
+        assert_eq!(vec!["a", "c"], explanation.subterms);
+        assert_eq!(
+            "{\"annotations\":[],\"command\":\"(step t3 (cl (= a c)) :rule trans :premises (h1 h2))\",\
+            \"expected\":\"(cl (= t_1 t_n)), given premises (= t_1 t_2) ... (= t_n-1 t_n)\",\
+            \"id\":\"t3\",\"position\":[4,13],\
+            \"premises\":[\"(assume h1 (= a b))\",\"(assume h2 (= \\\"x\\\" \\\"y\\\"))\"],\
+            \"rule\":\"trans\",\"subterms\":[\"a\",\"c\"]}",
+            explanation.to_json().to_string()
+        );
+        assert_eq!(
+            "error: step t3 failed on rule \"trans\"\n \
+             --> ex.proof:4:13\n  \
+              |\n\
+            4 | (step t3 (cl (= a c)) :rule trans :premises (h1 h2))\n  \
+              |              ^^^^^^^\n  \
+              = expected: (cl (= t_1 t_n)), given premises (= t_1 t_2) ... (= t_n-1 t_n)\n  \
+              = premise: (assume h1 (= a b))\n  \
+              = premise: (assume h2 (= \"x\" \"y\"))\n",
+            explanation.to_diagnostic("ex.proof", false)
+        );
+        assert!(explanation
+            .to_diagnostic("ex.proof", true)
+            .starts_with("\x1b[1;31merror\x1b[0m"));
+
+        let mut annotated = explanation.clone();
+        annotated.annotations = vec![("justification".to_owned(), "cc: a = b = c".to_owned())];
+        assert!(annotated
+            .to_json()
+            .to_string()
+            .starts_with("{\"annotations\":[[\"justification\",\"cc: a = b = c\"]],"));
+        assert!(annotated
+            .to_diagnostic("ex.proof", false)
+            .ends_with("  = solver justification: cc: a = b = c\n"));
+        assert!(annotated
+            .to_string()
+            .ends_with("solver annotations:\n  justification: cc: a = b = c\n"));
+    }
+
+    #[test]
+    fn highlighted_literals() {
+        let proof = "
+            (assume h1 (or p q))
+            (step t2 (cl p q) :rule or :premises (h1))
+            (step t3 (cl p r) :rule resolution :premises (t2))
+        ";
+        let (proof, mut pool) = parse_proof_with_definitions(
+            "(declare-fun p () Bool) (declare-fun q () Bool) (declare-fun r () Bool)",
+            proof,
+        )
+        .unwrap();
+        let mut checker = ProofChecker::new(&mut pool, Config::default());
+        let _ = checker.check(&proof).unwrap();
+        let explanation = checker.explanation().unwrap();
+        let highlighted: Vec<_> = explanation
+            .highlighted
+            .iter()
+            .map(|range| &explanation.command[range.clone()])
+            .collect();
+        assert_eq!(vec!["r"], highlighted);
+    }
+}
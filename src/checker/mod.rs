@@ -1,224 +1,1666 @@
+mod clause;
+mod context;
+mod explanation;
+mod normalization;
+mod registry;
 mod rules;
+mod solver;
+mod stats;
 
-use crate::ast::*;
-use rules::{Rule, RuleArgs};
-use std::collections::{HashMap, HashSet};
+pub use explanation::Explanation;
+pub use normalization::{NormalizationHook, NormalizationPreset};
+pub use registry::{
+    replacement_rule_name, rule_info, Count, RuleCategory, RuleInfo, DEPRECATED_RULE_NAMES, RULES,
+};
+pub(crate) use solver::{write_declare_fun, SmtLib};
+pub use solver::{Obligation, SolverConfig};
+pub use stats::{RuleStats, SlowStep, Stats};
+
+use crate::{ast::*, eval};
+use clause::ClauseCache;
+use context::ContextStack;
+use rules::{Premise, Rule, RuleArgs};
+use std::{
+    collections::HashSet,
+    fmt,
+    time::{Duration, Instant},
+};
 
 #[derive(Debug)]
 pub enum CheckerError {
     UnknownRule(String),
+    UnknownStepId(String),
     LastSubproofStepIsNotStep,
+    MemoryLimitExceeded(usize),
+
+    /// A rule built a term with more distinct subterms than `Config::max_term_size`.
+    TermSizeExceeded(usize),
+
+    /// The external solver could not be run.
+    Solver(std::io::Error),
+
+    /// The proof is valid, but there were warnings, and `Config::deny_warnings` is set.
+    DeniedWarnings(Vec<Warning>),
+
+    /// The proof is valid, but no step in the top-level proof concludes the clause in
+    /// `Config::target`, or the empty clause if `Config::require_empty_clause` is set.
+    IncompleteProof,
+
+    /// A term in the conclusion of the command with id `id`, or in the clause of one of its
+    /// premises, is not of sort Bool. This is only checked if `Config::check_sorts` is set.
+    IllSortedClause {
+        id: String,
+        term: String,
+    },
 }
 
 /// Represents the correctness of a proof or a proof step.
 #[must_use]
 #[derive(Debug)]
 pub enum Correctness {
-    True,          // The proof/step is valid
+    True, // The proof/step is valid, and every step was checked by its rule
+
+    /// The proof/step is valid, assuming that the steps that were not checked by their rules are
+    /// valid.
+    TrueModulo(Holes),
+
     False(String), // The proof/step is invalid, and checking failed on the given rule
 }
 
 impl Correctness {
-    fn as_bool(&self) -> bool {
+    /// Returns `true` if the proof/step is valid, even if some steps were not checked by their
+    /// rules.
+    pub fn is_valid(&self) -> bool {
         match self {
-            Correctness::True => true,
+            Correctness::True | Correctness::TrueModulo(_) => true,
             Correctness::False(_) => false,
         }
     }
 }
 
+impl fmt::Display for Correctness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Correctness::True => write!(f, "true"),
+            Correctness::TrueModulo(holes) => write!(f, "true, checked modulo {}", holes),
+            Correctness::False(rule) => write!(f, "false ({})", rule),
+        }
+    }
+}
+
+/// The steps of a valid proof that were not checked by their rules.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Holes {
+    /// The ids of the steps whose rules are not implemented, and that were checked by the external
+    /// solver instead.
+    pub solver_checked: Vec<String>,
+
+    /// The rules that are not implemented, and whose steps were skipped, in order of first
+    /// appearance.
+    pub skipped_rules: Vec<String>,
+}
+
+impl Holes {
+    pub fn is_empty(&self) -> bool {
+        self.solver_checked.is_empty() && self.skipped_rules.is_empty()
+    }
+}
+
+impl fmt::Display for Holes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        if !self.solver_checked.is_empty() {
+            let n = self.solver_checked.len();
+            let plural = if n == 1 { "" } else { "s" };
+            parts.push(format!("{} solver-discharged step{}", n, plural));
+        }
+        if !self.skipped_rules.is_empty() {
+            parts.push(format!("skipped rules {}", self.skipped_rules.join(", ")));
+        }
+        write!(f, "{}", parts.join(" and "))
+    }
+}
+
+/// A condition that doesn't make a proof invalid, but that may indicate a problem with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// An "assume" command in the top-level proof that is never used as a premise.
+    UnusedAssumption(String),
+
+    /// A step in the top-level proof, other than the last one, that is never used as a premise.
+    UnreferencedStep(String),
+
+    /// A step that uses a rule name from an older version of veriT. The step is checked using the
+    /// rule that replaced it.
+    DeprecatedRuleName {
+        step: String,
+        name: String,
+        replacement: &'static str,
+    },
+
+    /// A step that is only valid because the strictness is not `Strictness::Strict`.
+    LenientStep { step: String, rule: String },
+
+    /// A step that is only valid after its literals and the literals of its premises are
+    /// normalized with `Config::normalization`.
+    NormalizedStep { step: String, rule: String },
+}
+
+/// Returns warnings for the commands in the top-level proof, other than the last one, that are
+/// never used as premises.
+pub(crate) fn unused_command_warnings(commands: &[ProofCommand]) -> Vec<Warning> {
+    let mut used = vec![false; commands.len()];
+    for command in commands {
+        for i in command.premises_at_depth(0) {
+            used[i] = true;
+        }
+    }
+    let commands = &commands[..commands.len().saturating_sub(1)];
+    commands
+        .iter()
+        .zip(used)
+        .filter(|(_, used)| !used)
+        .map(|(command, _)| match command {
+            ProofCommand::Assume { id, .. } => Warning::UnusedAssumption(id.clone()),
+            other => Warning::UnreferencedStep(other.id().to_owned()),
+        })
+        .collect()
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::UnusedAssumption(id) => write!(f, "assumption \"{}\" is never used", id),
+            Warning::UnreferencedStep(id) => {
+                write!(f, "step \"{}\" is never used as a premise", id)
+            }
+            Warning::DeprecatedRuleName {
+                step,
+                name,
+                replacement,
+            } => write!(
+                f,
+                "step \"{}\" uses the deprecated rule name \"{}\", which was replaced by \"{}\"",
+                step, name, replacement
+            ),
+            Warning::LenientStep { step, rule } => write!(
+                f,
+                "step \"{}\" is only valid because the \"{}\" rule is checked leniently",
+                step, rule
+            ),
+            Warning::NormalizedStep { step, rule } => write!(
+                f,
+                "step \"{}\" is only valid by the \"{}\" rule after normalizing its literals",
+                step, rule
+            ),
+        }
+    }
+}
+
 type CheckerResult = Result<Correctness, CheckerError>;
 
-struct Context {
-    substitutions: HashMap<ByRefRc<Term>, ByRefRc<Term>>,
-    substitutions_until_fixed_point: HashMap<ByRefRc<Term>, ByRefRc<Term>>,
-    bindings: HashSet<SortedVar>,
+/// The outcome of checking a single step, as reported in a `StepTrace`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    Valid,
+    Invalid,
+
+    /// The step's rule is not implemented, and it was skipped because `skip_unknown_rules` is set.
+    Skipped,
+
+    /// The step's rule is not implemented, and the step was checked by the external solver.
+    SolverChecked,
+}
+
+/// An event emitted after each step is checked, if a trace callback is given in the `Config`.
+#[derive(Debug)]
+pub struct StepTrace<'a> {
+    pub id: &'a str,
+    pub rule: &'a str,
+
+    /// How many subproofs deep the step is. Steps in the top-level proof have depth 0.
+    pub depth: usize,
+    pub duration: Duration,
+    pub outcome: StepOutcome,
+}
+
+pub type TraceCallback = Box<dyn FnMut(&StepTrace) + Send + Sync>;
+
+/// What the checker does after a step fails to check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FailureMode {
+    /// Checking stops at the first step that fails.
+    #[default]
+    Stop,
+
+    /// If a step inside a subproof fails, the other steps of the innermost subproof that contains
+    /// it are still checked, and checking stops after that subproof. The step that ends the
+    /// subproof is not checked. A failure in the top-level proof stops checking immediately.
+    FinishSubproof,
+
+    /// Every step is checked, including the steps that end subproofs with failing steps, so all
+    /// failures can be reported at once.
+    CheckAll,
+}
+
+/// How lenient the checker is with small deviations from the rule specifications. Different solvers
+/// and solver versions produce proofs that need different levels of leniency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strictness {
+    /// Rules are checked exactly as specified. Equalities can't be flipped in the "cong", "trans",
+    /// "eq_congruent", "eq_congruent_pred", "eq_transitive" and "distinct_elim" rules, in the
+    /// "resolution" rule, a unit conclusion can't have extra double negations, in the "weakening"
+    /// rule, the new literals must come after the literals of the premise, and in the
+    /// "distinct_elim" rule, "xor" can't be used instead of a disequality.
+    Strict,
+
+    /// The behaviour expected by the proofs currently produced by veriT. Equalities may be flipped,
+    /// and extra double negations are allowed in unit resolution conclusions.
+    #[default]
+    Default,
+
+    /// Same as `Default`, but the order of the literals in the conclusion of the "contraction" and
+    /// "or" rules, and of the disequalities in the "distinct_elim" rule, doesn't matter, and the
+    /// "cong", "trans", "eq_congruent", "eq_congruent_pred" and "eq_transitive" rules accept any
+    /// equality that follows from their premise equalities by congruence closure.
+    Permissive,
+}
+
+#[derive(Default)]
+pub struct Config {
+    pub skip_unknown_rules: bool,
+    pub allow_test_rule: bool,
+    pub strictness: Strictness,
+
+    /// An approximate limit, in bytes, on the memory used by the term pool and by the canonical
+    /// clauses cached by the rules. If it is exceeded, checking stops with a
+    /// `CheckerError::MemoryLimitExceeded` error.
+    pub memory_limit: Option<usize>,
+
+    /// A limit on the size, counted as the number of distinct subterms, of the terms built by the
+    /// rules while checking. The terms in the proof itself are limited by the parser. If it is
+    /// exceeded, checking stops with a `CheckerError::TermSizeExceeded` error.
+    pub max_term_size: Option<usize>,
+
+    /// If true, a step that fails to check is still considered valid if its conclusion has no
+    /// variables or uninterpreted symbols, and evaluates to true. This is useful for steps that
+    /// only involve constants, but whose rule is not fully implemented.
+    pub eval_fallback: bool,
+
+    /// An external SMT solver used to check steps whose rules are not implemented. Such a step is
+    /// valid if the solver shows that its premises imply its conclusion. Steps inside subproofs,
+    /// and steps that end subproofs, are not checked this way, since their validity depends on the
+    /// context.
+    pub external_solver: Option<SolverConfig>,
+
+    /// If true, a valid proof that has warnings results in a `CheckerError::DeniedWarnings` error.
+    pub deny_warnings: bool,
+
+    /// If true, a step that is only valid because the strictness is not `Strictness::Strict`
+    /// results in a `Warning::LenientStep` warning. Finding these steps means checking every step
+    /// of a lenient rule a second time, in strict mode, so this is only done if this or
+    /// `deny_warnings` is set.
+    pub lenient_warnings: bool,
+
+    /// A callback that is called after each step is checked. This can be used to watch the
+    /// progress of long checks, or to find out which steps are slow to check.
+    pub trace: Option<TraceCallback>,
+
+    /// If set, statistics about the time spent checking each rule are collected, and can be read
+    /// with `ProofChecker::stats`. The value is how many of the slowest steps are kept.
+    pub stats: Option<usize>,
+
+    /// If true, a valid proof in which no step of the top-level proof concludes the empty clause
+    /// results in a `CheckerError::IncompleteProof` error. Such a proof, which includes an empty
+    /// proof, doesn't show that the problem is unsatisfiable. If false, partial proofs are
+    /// accepted.
+    pub require_empty_clause: bool,
+
+    /// If set, a valid proof in which no step of the top-level proof concludes this clause results
+    /// in a `CheckerError::IncompleteProof` error. The order of the literals doesn't matter. This
+    /// allows checking partial proofs, like lemmas, that are meant to derive a specific clause.
+    /// When set, `require_empty_clause` is ignored. The terms must be in the same pool as the
+    /// proof.
+    pub target: Option<Vec<ByRefRc<Term>>>,
+
+    /// If true, the checker verifies that every term in the conclusion of each command, and in the
+    /// clauses of its premises, is of sort Bool, and returns a `CheckerError::IllSortedClause`
+    /// error otherwise. The parser already checks this, so this is only needed for proofs that were
+    /// built or modified after parsing.
+    pub check_sorts: bool,
+
+    /// If true, an `Obligation` is built for each step that fails to check or is skipped, and can
+    /// be read with `ProofChecker::obligations`. Like with the external solver, steps inside
+    /// subproofs, and steps that end subproofs, are ignored, since their validity depends on the
+    /// context. Steps whose terms can't be expressed in SMT-LIB are also ignored.
+    pub collect_obligations: bool,
+
+    /// The limits used when printing terms in diagnostics, like the explanation of a failing step
+    /// and `CheckerError::IllSortedClause` errors. By default, huge terms are truncated.
+    pub print_limits: PrintLimits,
+
+    /// If set, a step that fails to check is checked again after applying this function to each
+    /// literal of its conclusion and of its premises. A step that is only valid this way results in
+    /// a `Warning::NormalizedStep` warning. See `NormalizationPreset` for the built-in hooks.
+    pub normalization: Option<NormalizationHook>,
+
+    /// What to do after a step fails to check. In any case, the returned `Correctness::False`
+    /// holds the rule of the first step that failed, and `ProofChecker::explanations` describes
+    /// each of the failing steps.
+    pub failure_mode: FailureMode,
 }
 
-pub struct ProofChecker {
-    pool: TermPool,
-    skip_unknown_rules: bool,
-    allow_test_rule: bool,
-    context: Vec<Context>,
+/// A proof checker. The checker holds a mutable reference to the term pool that the proofs were
+/// parsed into, so several proofs that share the same pool, for example proofs of the same problem,
+/// can be checked with a single checker by calling `check` repeatedly.
+pub struct ProofChecker<'c> {
+    pool: &'c mut TermPool,
+    config: Config,
+    context: ContextStack,
+    explanations: Vec<Explanation>,
+    holes: Holes,
+    warnings: Vec<Warning>,
+    stats: Option<Stats>,
+    obligations: Vec<Obligation>,
+    clause_cache: ClauseCache,
 }
 
-impl ProofChecker {
-    pub fn new(pool: TermPool, skip_unknown_rules: bool, allow_test_rule: bool) -> Self {
+impl<'c> ProofChecker<'c> {
+    pub fn new(pool: &'c mut TermPool, config: Config) -> Self {
+        pool.set_max_term_size(config.max_term_size);
         ProofChecker {
             pool,
-            skip_unknown_rules,
-            allow_test_rule,
-            context: Vec::new(),
+            context: ContextStack::new(),
+            explanations: Vec::new(),
+            holes: Holes::default(),
+            warnings: Vec::new(),
+            stats: config.stats.map(Stats::new),
+            obligations: Vec::new(),
+            clause_cache: ClauseCache::default(),
+            config,
         }
     }
 
     pub fn check(&mut self, proof: &Proof) -> CheckerResult {
-        self.check_subproof(&proof.0)
+        self.reset();
+        let result = self.check_subproof(&proof.0, &[&proof.0]);
+        self.finish_proof(proof, result)
+    }
+
+    /// Checks a proof whose top-level commands are received one at a time, for example from a
+    /// parser running on another thread. `next_command` is called to receive each command, and is
+    /// given the term pool, so it can add the terms of the command to it. Each command is checked
+    /// as soon as it is received, and then added to `proof`. Once checking stops, either because
+    /// `next_command` returned `None` or because a step failed, no more commands are received.
+    pub fn check_stream<F>(&mut self, proof: &mut Proof, mut next_command: F) -> CheckerResult
+    where
+        F: FnMut(&mut TermPool) -> Option<ProofCommand>,
+    {
+        self.reset();
+        let mut first_failure = None;
+        let result = loop {
+            let Some(command) = next_command(self.pool) else {
+                break Ok(first_failure.unwrap_or(Correctness::True));
+            };
+            proof.0.push(command);
+            let commands = &proof.0[..];
+            match self.check_next(commands.last().unwrap(), &[commands], &mut first_failure) {
+                Ok(None) => (),
+                Ok(Some(correctness)) => break Ok(correctness),
+                Err(e) => break Err(e),
+            }
+        };
+        self.finish_proof(proof, result)
+    }
+
+    /// Discards the state left by a previous check, so the checker can be reused.
+    fn reset(&mut self) {
+        self.context = ContextStack::new();
+        self.explanations.clear();
+        self.holes = Holes::default();
+        self.warnings.clear();
+        self.stats = self.config.stats.map(Stats::new);
+        self.obligations.clear();
+        self.clause_cache.clear();
+    }
+
+    /// Does the checks that need the whole proof, once all of its commands were checked, and
+    /// returns the final result.
+    fn finish_proof(&mut self, proof: &Proof, result: CheckerResult) -> CheckerResult {
+        if matches!(result, Ok(ref c) if c.is_valid()) {
+            let is_complete = match &self.config.target {
+                Some(target) => Self::concludes_clause(&proof.0, target),
+                None => !self.config.require_empty_clause || Self::concludes_clause(&proof.0, &[]),
+            };
+            if !is_complete {
+                return Err(CheckerError::IncompleteProof);
+            }
+            self.warnings.extend(unused_command_warnings(&proof.0));
+        }
+        self.finish(result)
+    }
+
+    /// Returns `true` if some step in `commands` concludes `clause`, ignoring the order of the
+    /// literals. Steps inside subproofs are not considered, since their conclusions only hold in
+    /// their contexts.
+    fn concludes_clause(commands: &[ProofCommand], clause: &[ByRefRc<Term>]) -> bool {
+        let clause: HashSet<_> = clause.iter().collect();
+        commands.iter().any(|c| match c {
+            ProofCommand::Step(s) => s.clause.iter().collect::<HashSet<_>>() == clause,
+            _ => false,
+        })
+    }
+
+    /// If `result` is `Correctness::True` but some steps were not checked by their rules, returns
+    /// `Correctness::TrueModulo` instead. If the result is valid but there were warnings, and
+    /// `deny_warnings` is set, returns an error.
+    fn finish(&self, result: CheckerResult) -> CheckerResult {
+        match result {
+            Ok(c) if c.is_valid() && self.config.deny_warnings && !self.warnings.is_empty() => {
+                Err(CheckerError::DeniedWarnings(self.warnings.clone()))
+            }
+            Ok(Correctness::True) if !self.holes.is_empty() => {
+                Ok(Correctness::TrueModulo(self.holes.clone()))
+            }
+            other => other,
+        }
+    }
+
+    /// Returns the warnings found in the last check.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Returns the obligations built in the last check, in the order their steps were checked. This
+    /// is always empty if `Config::collect_obligations` is not set.
+    pub fn obligations(&self) -> &[Obligation] {
+        &self.obligations
+    }
+
+    /// Returns the statistics collected in the last check, or `None` if `Config::stats` is not set.
+    pub fn stats(&self) -> Option<&Stats> {
+        self.stats.as_ref()
+    }
+
+    /// Checks a proof, and also checks that every "assume" command in the top-level proof assumes
+    /// one of the terms in `assertions`. If one of them doesn't, the proof is considered invalid,
+    /// and the returned `Correctness::False` holds "assume" as the rule.
+    pub fn check_with_assertions(
+        &mut self,
+        proof: &Proof,
+        assertions: &[&ByRefRc<Term>],
+    ) -> CheckerResult {
+        let assertions: HashSet<_> = assertions.iter().copied().collect();
+        for command in &proof.0 {
+            if let ProofCommand::Assume { id, term } = command {
+                if !assertions.contains(term) {
+                    self.explanations =
+                        vec![Explanation::from_assume(id, term, self.config.print_limits)];
+                    return Ok(Correctness::False("assume".to_owned()));
+                }
+            }
+        }
+        self.check(proof)
+    }
+
+    /// Checks only the step with id `id` in `proof`, assuming that its premises are valid. This is
+    /// useful to re-check individual steps without checking the whole proof again. Returns an
+    /// error if there is no command with that id in the proof.
+    pub fn check_step(&mut self, proof: &Proof, id: &str) -> CheckerResult {
+        let location = proof
+            .find_command(id)
+            .ok_or_else(|| CheckerError::UnknownStepId(id.to_owned()))?;
+        self.check_command_at(&location)
+    }
+
+    /// Checks only the command at `location`, assuming that its premises are valid. The contexts of
+    /// the subproofs that enclose the command are built before checking it. If the command is a
+    /// subproof, only the step that ends it is checked.
+    pub fn check_command_at(&mut self, location: &CommandLocation) -> CheckerResult {
+        let mut contexts = ContextStack::new();
+        let subproofs = location.enclosing.iter().copied();
+        for subproof in subproofs.chain(std::iter::once(location.command())) {
+            if let ProofCommand::Subproof {
+                assignment_args,
+                variable_args,
+                ..
+            } = subproof
+            {
+                contexts.push(self.pool, assignment_args, variable_args);
+            }
+        }
+        let previous_context = std::mem::replace(&mut self.context, contexts);
+        self.clause_cache.clear();
+        self.explanations.clear();
+        self.holes = Holes::default();
+        self.warnings.clear();
+        self.stats = self.config.stats.map(Stats::new);
+        self.obligations.clear();
+
+        let result = match location.command() {
+            ProofCommand::Assume { .. } => Ok(Correctness::True),
+            ProofCommand::Step(step) => self.check_proof_step(step, &location.scopes, None),
+            ProofCommand::Subproof { commands, .. } => match commands.last().unwrap() {
+                ProofCommand::Step(s) => self.check_proof_step(s, &location.scopes, Some(commands)),
+                _ => Err(CheckerError::LastSubproofStepIsNotStep),
+            },
+        };
+        self.context = previous_context;
+        self.finish(result)
+    }
+
+    /// Checks `step` as if it were a top-level step in a proof whose commands are `all_commands`,
+    /// assuming that its premises are valid. The step doesn't need to be one of `all_commands`, so
+    /// this can be used to find out if a modified version of a step is still valid.
+    pub fn check_standalone_step(
+        &mut self,
+        step: &ProofStep,
+        all_commands: &[ProofCommand],
+    ) -> CheckerResult {
+        let previous_context = std::mem::take(&mut self.context);
+        self.clause_cache.clear();
+        self.explanations.clear();
+        self.holes = Holes::default();
+        self.warnings.clear();
+        self.stats = self.config.stats.map(Stats::new);
+        self.obligations.clear();
+        let result = self.check_proof_step(step, &[all_commands], None);
+        self.context = previous_context;
+        self.finish(result)
     }
 
-    fn check_subproof(&mut self, commands: &[ProofCommand]) -> CheckerResult {
-        for step in commands {
-            let correctness = self.check_command(step, commands)?;
-            if !correctness.as_bool() {
+    /// Returns an explanation of why the first step that failed to check in the last check was
+    /// invalid, if a step failed.
+    pub fn explanation(&self) -> Option<&Explanation> {
+        self.explanations.first()
+    }
+
+    /// Returns the explanations of all the steps that failed to check in the last check, in the
+    /// order they were checked. Unless `Config::failure_mode` is `FailureMode::Stop`, there may be
+    /// more than one.
+    pub fn explanations(&self) -> &[Explanation] {
+        &self.explanations
+    }
+
+    /// Checks each command in `commands`. `scopes` are the commands of the top-level proof and of
+    /// each subproof that encloses `commands`, from the outermost to the innermost, and are used to
+    /// resolve the premises of the steps.
+    fn check_subproof(
+        &mut self,
+        commands: &[ProofCommand],
+        scopes: &[&[ProofCommand]],
+    ) -> CheckerResult {
+        let mut first_failure = None;
+        for command in commands {
+            if let Some(correctness) = self.check_next(command, scopes, &mut first_failure)? {
                 return Ok(correctness);
             }
         }
-        Ok(Correctness::True)
+        Ok(first_failure.unwrap_or(Correctness::True))
+    }
+
+    /// Checks `command`, one of the commands of the innermost scope in `scopes`, as part of
+    /// checking all of them in order. `first_failure` holds the result of the first command in the
+    /// scope that failed, if checking continued after it. Returns the result of the whole scope if
+    /// checking should stop at this command, according to `Config::failure_mode`.
+    fn check_next(
+        &mut self,
+        command: &ProofCommand,
+        scopes: &[&[ProofCommand]],
+        first_failure: &mut Option<Correctness>,
+    ) -> Result<Option<Correctness>, CheckerError> {
+        let correctness = self.check_command(command, scopes)?;
+        if !correctness.is_valid() {
+            // A failing subproof was already finished, so checking stops at it when finishing
+            // subproofs, as it does in the top-level proof
+            let stop = match self.config.failure_mode {
+                FailureMode::Stop => true,
+                FailureMode::FinishSubproof => {
+                    scopes.len() == 1 || matches!(command, ProofCommand::Subproof { .. })
+                }
+                FailureMode::CheckAll => false,
+            };
+            if stop {
+                return Ok(Some(first_failure.take().unwrap_or(correctness)));
+            }
+            first_failure.get_or_insert(correctness);
+        }
+        self.check_resource_limits()?;
+        Ok(None)
+    }
+
+    /// Returns an error if the memory used by the term pool and by the cached clauses exceeds
+    /// `Config::memory_limit`, or if a term larger than `Config::max_term_size` was added to the
+    /// pool.
+    fn check_resource_limits(&self) -> Result<(), CheckerError> {
+        if let Some(limit) = self.config.memory_limit {
+            if self.pool.memory_usage() + self.clause_cache.memory_usage() > limit {
+                return Err(CheckerError::MemoryLimitExceeded(limit));
+            }
+        }
+        match self.config.max_term_size {
+            Some(limit) if self.pool.term_size_exceeded() => {
+                Err(CheckerError::TermSizeExceeded(limit))
+            }
+            _ => Ok(()),
+        }
     }
 
     fn check_command(
         &mut self,
         command: &ProofCommand,
-        all_commands: &[ProofCommand],
+        scopes: &[&[ProofCommand]],
     ) -> CheckerResult {
         match command {
-            ProofCommand::Step(step) => self.check_step(step, all_commands, None),
+            ProofCommand::Step(step) => self.check_proof_step(step, scopes, None),
             ProofCommand::Subproof {
                 commands: inner_commands,
                 assignment_args,
                 variable_args,
             } => {
-                let new_context = self.build_context(assignment_args, variable_args);
-                self.context.push(new_context);
-                let subproof_correctness =
-                    self.check_subproof(&inner_commands[..inner_commands.len() - 1])?;
-                if !subproof_correctness.as_bool() {
+                self.context.push(self.pool, assignment_args, variable_args);
+                self.clause_cache.start_scope(scopes.len());
+                let mut inner_scopes = scopes.to_vec();
+                inner_scopes.push(inner_commands);
+                let subproof_correctness = self
+                    .check_subproof(&inner_commands[..inner_commands.len() - 1], &inner_scopes)?;
+                if !subproof_correctness.is_valid()
+                    && self.config.failure_mode != FailureMode::CheckAll
+                {
+                    self.context.pop();
                     return Ok(subproof_correctness);
                 }
                 let last_step = match inner_commands.last().unwrap() {
                     ProofCommand::Step(s) => s,
                     _ => return Err(CheckerError::LastSubproofStepIsNotStep),
                 };
-                let correctness = self.check_step(last_step, all_commands, Some(inner_commands))?;
+                let correctness = self.check_proof_step(last_step, scopes, Some(inner_commands))?;
                 self.context.pop();
+                if !subproof_correctness.is_valid() {
+                    return Ok(subproof_correctness);
+                }
                 Ok(correctness)
             }
-            ProofCommand::Assume(_) => Ok(Correctness::True),
+            ProofCommand::Assume { id, term } => {
+                if self.config.check_sorts {
+                    self.check_clause_sorts(id, std::slice::from_ref(term))?;
+                }
+                Ok(Correctness::True)
+            }
         }
     }
 
-    fn check_step<'a>(
+    /// Returns an error if some term in `clause` is not of sort Bool. `id` is the id of the command
+    /// that concludes `clause`.
+    fn check_clause_sorts(
         &mut self,
-        ProofStep {
+        id: &str,
+        clause: &[ByRefRc<Term>],
+    ) -> Result<(), CheckerError> {
+        let pool = &*self.pool;
+        match clause
+            .iter()
+            .find(|t| pool.sort(t).as_deref() != Some(Term::BOOL_SORT))
+        {
+            Some(term) => Err(CheckerError::IllSortedClause {
+                id: id.to_owned(),
+                term: term.truncated(self.config.print_limits).to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn check_proof_step<'a>(
+        &mut self,
+        step: &'a ProofStep,
+        scopes: &[&'a [ProofCommand]],
+        subproof_commands: Option<&'a [ProofCommand]>,
+    ) -> CheckerResult {
+        let ProofStep {
+            id,
             clause,
             rule: rule_name,
             premises,
             args,
-        }: &'a ProofStep,
-        all_commands: &'a [ProofCommand],
-        subproof_commands: Option<&'a [ProofCommand]>,
-    ) -> CheckerResult {
-        let rule = match Self::get_rule(rule_name, self.allow_test_rule) {
+            ..
+        } = step;
+        if self.config.check_sorts {
+            self.check_clause_sorts(id, clause)?;
+            for &(d, i) in premises {
+                let premise = Premise::new(scopes, (d, i));
+                self.check_clause_sorts(premise.id, premise.clause)?;
+            }
+        }
+
+        let start_time = Instant::now();
+        let mut checked_as = rule_name.as_str();
+        let rule = Self::get_rule(rule_name, self.config.allow_test_rule).or_else(|| {
+            let replacement = registry::replacement_rule_name(rule_name)?;
+            self.warnings.push(Warning::DeprecatedRuleName {
+                step: id.clone(),
+                name: rule_name.clone(),
+                replacement,
+            });
+            checked_as = replacement;
+            Self::get_rule(replacement, false)
+        });
+        let rule = match rule {
             Some(r) => r,
-            None if self.skip_unknown_rules => return Ok(Correctness::True),
+            None if self.config.external_solver.is_some()
+                && self.context.is_empty()
+                && subproof_commands.is_none() =>
+            {
+                return self.check_with_solver(step, scopes, start_time);
+            }
+            None if self.config.skip_unknown_rules => {
+                if !self.holes.skipped_rules.contains(rule_name) {
+                    self.holes.skipped_rules.push(rule_name.clone());
+                }
+                self.add_obligation(step, scopes, subproof_commands.is_some());
+                self.trace(id, rule_name, start_time, StepOutcome::Skipped);
+                return Ok(Correctness::True);
+            }
             None => return Err(CheckerError::UnknownRule(rule_name.to_string())),
         };
-        let premises = premises.iter().map(|&i| &all_commands[i]).collect();
-        let rule_args = RuleArgs {
-            conclusion: &clause,
+        let premises: Vec<_> = premises
+            .iter()
+            .map(|&(d, i)| Premise::new(scopes, (d, i)))
+            .collect();
+        let run_rule = |checker: &mut Self, strictness| {
+            rule(RuleArgs {
+                conclusion: clause,
+                premises: premises.clone(),
+                args,
+                pool: checker.pool,
+                context: &mut checker.context,
+                subproof_commands,
+                strictness,
+                clause_cache: &mut checker.clause_cache,
+            })
+        };
+        let result = match run_rule(self, self.config.strictness) {
+            None if self.config.eval_fallback && eval::eval_ground_clause(clause) == Some(true) => {
+                Some(())
+            }
+            None if self.check_normalized(rule, step, &premises, subproof_commands) => {
+                self.warnings.push(Warning::NormalizedStep {
+                    step: id.clone(),
+                    rule: checked_as.to_owned(),
+                });
+                Some(())
+            }
+            Some(()) => {
+                // If the rule may be checked leniently, and these warnings are wanted, we check the
+                // step again in strict mode, to find out if it only holds because of the leniency
+                let wanted = self.config.lenient_warnings || self.config.deny_warnings;
+                let is_lenient = registry::rule_info(checked_as).is_some_and(|i| i.lenient);
+                if wanted
+                    && is_lenient
+                    && self.config.strictness != Strictness::Strict
+                    && run_rule(self, Strictness::Strict).is_none()
+                {
+                    self.warnings.push(Warning::LenientStep {
+                        step: id.clone(),
+                        rule: checked_as.to_owned(),
+                    });
+                }
+                Some(())
+            }
+            result => result,
+        };
+        // Rules may add many terms to the pool, so the limit is checked after each one runs, and
+        // not only between commands
+        self.check_resource_limits()?;
+        Ok(match result {
+            Some(()) => {
+                self.trace(id, rule_name, start_time, StepOutcome::Valid);
+                Correctness::True
+            }
+            None => {
+                self.trace(id, rule_name, start_time, StepOutcome::Invalid);
+                self.explanations.push(Explanation::from_step(
+                    step,
+                    scopes,
+                    self.config.print_limits,
+                ));
+                self.add_obligation(step, scopes, subproof_commands.is_some());
+                Correctness::False(rule_name.clone())
+            }
+        })
+    }
+
+    /// If `Config::normalization` is set, checks `step` by `rule` again with the normalization
+    /// applied to the literals of its conclusion and of its premises. Returns `false` if the
+    /// normalization is not set or doesn't change any literal.
+    fn check_normalized(
+        &mut self,
+        rule: Rule,
+        step: &ProofStep,
+        premises: &[Premise],
+        subproof_commands: Option<&[ProofCommand]>,
+    ) -> bool {
+        let Some(normalize) = &self.config.normalization else {
+            return false;
+        };
+        let pool = &mut *self.pool;
+        let mut changed = false;
+        let mut normalize_clause = |clause: &[ByRefRc<Term>]| {
+            let normalized: Vec<_> = clause.iter().map(|t| normalize(pool, t)).collect();
+            changed |= normalized.as_slice() != clause;
+            normalized
+        };
+        let conclusion = normalize_clause(&step.clause);
+        let premise_clauses: Vec<_> = premises
+            .iter()
+            .map(|p| normalize_clause(p.clause))
+            .collect();
+        if !changed {
+            return false;
+        }
+        let premises = premises
+            .iter()
+            .zip(&premise_clauses)
+            .map(|(p, clause)| Premise { clause, ..*p })
+            .collect();
+        rule(RuleArgs {
+            conclusion: &conclusion,
             premises,
-            args: &args,
-            pool: &mut self.pool,
+            args: &step.args,
+            pool: self.pool,
             context: &mut self.context,
             subproof_commands,
-        };
-        Ok(match rule(rule_args) {
-            Some(()) => Correctness::True,
-            None => Correctness::False(rule_name.clone()),
+            strictness: self.config.strictness,
+            // The normalized clauses are only used once, so they are not cached
+            clause_cache: &mut ClauseCache::default(),
         })
+        .is_some()
     }
 
-    fn build_context(
+    /// If `Config::collect_obligations` is set, builds the obligation for `step`, unless it is
+    /// inside a subproof or ends one.
+    fn add_obligation(
         &mut self,
-        assignment_args: &[(String, ByRefRc<Term>)],
-        variable_args: &[SortedVar],
-    ) -> Context {
-        // Since some rules (like "refl") need to apply substitutions until a fixed point, we
-        // precompute these substitutions into a separate hash map. This assumes that the assignment
-        // arguments are in the correct order.
-        let mut substitutions = HashMap::new();
-        let mut substitutions_until_fixed_point = HashMap::new();
-
-        // We build the `substitutions_until_fixed_point` hash map from the bottom up, by using the
-        // substitutions already introduced to transform the result of a new substitution before
-        // inserting it into the hash map. So for instance, if the substitutions are "(:= y z)" and
-        // "(:= x (f y))", we insert the first substitution, and then, when introducing the second,
-        // we use the current state of the hash map to transform "(f y)" into "(f z)". The
-        // resulting hash map will then contain "(:= y z)" and "(:= x (f z))". However, the
-        // arguments are given in the opposite order, that is, "(:= x (f y))" would come first,
-        // followed by "(:= y z)". Because of that, we traverse the assignment arguments slice in
-        // reverse.
-        for (var, value) in assignment_args.iter().rev() {
-            let var_term = terminal!(var var; self.pool.add_term(value.sort().clone()));
-            let var_term = self.pool.add_term(var_term);
-            substitutions.insert(var_term.clone(), value.clone());
-
-            let new_value = self
-                .pool
-                .apply_substitutions(value, &mut substitutions_until_fixed_point);
-            substitutions_until_fixed_point.insert(var_term, new_value);
-        }
-
-        let bindings = variable_args.iter().cloned().collect();
-        Context {
-            substitutions,
-            substitutions_until_fixed_point,
-            bindings,
+        step: &ProofStep,
+        scopes: &[&[ProofCommand]],
+        ends_subproof: bool,
+    ) {
+        if !self.config.collect_obligations || ends_subproof || !self.context.is_empty() {
+            return;
+        }
+        let premises: Vec<_> = step
+            .premises
+            .iter()
+            .map(|&(d, i)| Premise::new(scopes, (d, i)))
+            .collect();
+        if let Some(query) = solver::build_query(&premises, &step.clause) {
+            let header = format!(
+                "; proof obligation for step {}, by rule {}\n",
+                step.id, step.rule
+            );
+            self.obligations.push(Obligation {
+                id: step.id.clone(),
+                query: header + &query,
+            });
+        }
+    }
+
+    /// Checks a step using the external solver, by asking it whether the premises of the step imply
+    /// its conclusion.
+    fn check_with_solver(
+        &mut self,
+        step: &ProofStep,
+        scopes: &[&[ProofCommand]],
+        start_time: Instant,
+    ) -> CheckerResult {
+        let premises: Vec<_> = step
+            .premises
+            .iter()
+            .map(|&(d, i)| Premise::new(scopes, (d, i)))
+            .collect();
+        let solver = self.config.external_solver.as_ref().unwrap();
+        let is_valid = match solver::build_query(&premises, &step.clause) {
+            Some(query) => solver::run_solver(solver, &query).map_err(CheckerError::Solver)?,
+            None => false,
+        };
+        if is_valid {
+            self.trace(&step.id, &step.rule, start_time, StepOutcome::SolverChecked);
+            self.holes.solver_checked.push(step.id.clone());
+            Ok(Correctness::True)
+        } else {
+            self.trace(&step.id, &step.rule, start_time, StepOutcome::Invalid);
+            self.explanations.push(Explanation::from_step(
+                step,
+                scopes,
+                self.config.print_limits,
+            ));
+            self.add_obligation(step, scopes, false);
+            Ok(Correctness::False(step.rule.clone()))
+        }
+    }
+
+    /// Returns the ids of the steps that were checked by the external solver in the last check, in
+    /// the order they were checked.
+    pub fn solver_checked_steps(&self) -> &[String] {
+        &self.holes.solver_checked
+    }
+
+    /// Calls the trace callback, if there is one, with an event for the step that started being
+    /// checked at `start_time`. The event is also recorded in the statistics, if they are being
+    /// collected.
+    fn trace(&mut self, id: &str, rule: &str, start_time: Instant, outcome: StepOutcome) {
+        if self.config.trace.is_none() && self.stats.is_none() {
+            return;
+        }
+        let event = StepTrace {
+            id,
+            rule,
+            depth: self.context.len(),
+            duration: start_time.elapsed(),
+            outcome,
+        };
+        if let Some(callback) = &mut self.config.trace {
+            callback(&event);
+        }
+        if let Some(stats) = &mut self.stats {
+            stats.record(&event);
         }
     }
 
     pub fn get_rule(rule_name: &str, allow_test_rule: bool) -> Option<Rule> {
-        use rules::*;
-        Some(match rule_name {
-            "true" => tautology::r#true,
-            "false" => tautology::r#false,
-            "not_not" => tautology::not_not,
-            "and_pos" => tautology::and_pos,
-            "and_neg" => tautology::and_neg,
-            "or_pos" => tautology::or_pos,
-            "or_neg" => tautology::or_neg,
-            "equiv_pos1" => tautology::equiv_pos1,
-            "equiv_pos2" => tautology::equiv_pos2,
-            "eq_reflexive" => reflexivity::eq_reflexive,
-            "eq_transitive" => transitivity::eq_transitive,
-            "eq_congruent" => congruence::eq_congruent,
-            "eq_congruent_pred" => congruence::eq_congruent_pred,
-            "distinct_elim" => clausification::distinct_elim,
-            "la_rw_eq" => linear_arithmetic::la_rw_eq,
-            "la_generic" => linear_arithmetic::la_generic,
-            "la_disequality" => linear_arithmetic::la_disequality,
-            "forall_inst" => quantifier::forall_inst,
-            "qnt_join" => quantifier::qnt_join,
-            "qnt_rm_unused" => quantifier::qnt_rm_unused,
-            "th_resolution" | "resolution" => resolution::resolution,
-            "refl" => reflexivity::refl,
-            "trans" => transitivity::trans,
-            "cong" => congruence::cong,
-            "and" => clausification::and,
-            "tautology" => resolution::tautology,
-            "or" => clausification::or,
-            "implies" => clausification::implies,
-            "ite1" => tautology::ite1,
-            "ite2" => tautology::ite2,
-            "ite_intro" => tautology::ite_intro,
-            "contraction" => resolution::contraction,
-            "connective_def" => tautology::connective_def,
-            "eq_simplify" => simplification::eq_simplify,
-            "or_simplify" => simplification::or_simplify,
-            "not_simplify" => simplification::not_simplify,
-            "equiv_simplify" => simplification::equiv_simplify,
-            "bool_simplify" => simplification::bool_simplify,
-            "prod_simplify" => simplification::prod_simplify,
-            "nary_elim" => clausification::nary_elim,
-            "ac_simp" => simplification::ac_simp,
-            "bind" => subproof::bind,
-            "subproof" => subproof::subproof,
-            "let" => subproof::r#let,
-            "onepoint" => subproof::onepoint,
-            "sko_ex" => subproof::sko_ex,
-            "sko_forall" => subproof::sko_forall,
-            "trust_me" if allow_test_rule => |_| Some(()),
-            _ => return None,
+        if rule_name == "trust_me" && allow_test_rule {
+            return Some(|_| Some(()));
+        }
+        registry::rule_info(rule_name).map(|info| info.rule)
+    }
+}
+
+/// Checks a single inference, outside of any proof: that the clause `conclusion` follows from the
+/// clauses in `premises` by the rule `rule_name`, with the arguments `args`. This lets tools like a
+/// solver checking its own inferences, or a test generator, validate them without building a
+/// `Proof`. The terms must belong to `pool`. The inference is checked with the default
+/// configuration, so unknown rules are an error. Rules that end subproofs can't be checked this
+/// way, since they need the commands of the subproof, and rules that depend on the context of a
+/// subproof are checked in an empty context.
+pub fn check_rule(
+    rule_name: &str,
+    conclusion: &[ByRefRc<Term>],
+    premises: &[&[ByRefRc<Term>]],
+    args: &[ProofArg],
+    pool: &mut TermPool,
+) -> CheckerResult {
+    let commands: Vec<_> = premises
+        .iter()
+        .enumerate()
+        .map(|(i, clause)| {
+            ProofCommand::Step(ProofStep {
+                id: format!("p{}", i),
+                clause: clause.to_vec(),
+                rule: String::new(),
+                premises: Vec::new(),
+                args: Vec::new(),
+                position: (0, 0),
+            })
         })
+        .collect();
+    let step = ProofStep {
+        id: "t".to_owned(),
+        clause: conclusion.to_vec(),
+        rule: rule_name.to_owned(),
+        premises: (0..premises.len()).map(|i| (0, i)).collect(),
+        args: args.to_vec(),
+        position: (0, 0),
+    };
+    ProofChecker::new(pool, Config::default()).check_standalone_step(&step, &commands)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{self, parse_proof_with_definitions};
+    use std::collections::HashSet;
+
+    #[test]
+    fn check_single_step() {
+        let definitions = "
+            (declare-fun p () Bool)
+            (declare-fun q () Bool)
+        ";
+        let proof = "
+            (assume h1 (and p q))
+            (step t2 (cl q) :rule and :premises (h1))
+            (step t3 (cl p) :rule and :premises (t2))
+            (anchor :step t5 :args ((:= (x Bool) p)))
+            (step t5.t1 (cl (= x p)) :rule refl)
+            (step t5 (cl (= (not x) (not p))) :rule bind)
+        ";
+        let (proof, mut pool) = parse_proof_with_definitions(definitions, proof).unwrap();
+        let mut checker = ProofChecker::new(&mut pool, Config::default());
+
+        assert!(matches!(
+            checker.check_step(&proof, "t2"),
+            Ok(Correctness::True)
+        ));
+        assert!(matches!(
+            checker.check_step(&proof, "t3"),
+            Ok(Correctness::False(_))
+        ));
+
+        // Steps inside subproofs are checked in the context of the subproof
+        assert!(matches!(
+            checker.check_step(&proof, "t5.t1"),
+            Ok(Correctness::True)
+        ));
+        assert!(matches!(
+            checker.check_step(&proof, "t6"),
+            Err(CheckerError::UnknownStepId(_))
+        ));
+    }
+
+    #[test]
+    fn check_single_rule() {
+        let proof = "
+            (step t1 (cl (and p q)) :rule trust_me)
+            (step t2 (cl p (not q)) :rule trust_me)
+            (step t3 (cl q r) :rule trust_me)
+            (step t4 (cl p r) :rule trust_me)
+        ";
+        let (proof, mut pool) =
+            parser::parse_proof_only(proof.as_bytes(), parser::Config::default()).unwrap();
+        let clauses: Vec<_> = proof.0.iter().map(ProofCommand::clause).collect();
+        let (and_p_q, p) = (&clauses[0][0], &clauses[1][..1]);
+
+        let result = check_rule("and", p, &clauses[..1], &[], &mut pool);
+        assert!(matches!(result, Ok(Correctness::True)));
+        let result = check_rule("resolution", clauses[3], &clauses[1..3], &[], &mut pool);
+        assert!(matches!(result, Ok(Correctness::True)));
+        let result = check_rule("resolution", clauses[3], &clauses[1..2], &[], &mut pool);
+        assert!(matches!(result, Ok(Correctness::False(_))));
+        let result = check_rule(
+            "and",
+            p,
+            &clauses[..1],
+            &[ProofArg::Term(and_p_q.clone())],
+            &mut pool,
+        );
+        assert!(matches!(result, Ok(Correctness::False(_))));
+        assert!(matches!(
+            check_rule("foo", p, &[], &[], &mut pool),
+            Err(CheckerError::UnknownRule(_))
+        ));
+    }
+
+    #[test]
+    fn interleaved_assumptions() {
+        let definitions = "
+            (declare-fun p () Bool)
+            (declare-fun q () Bool)
+        ";
+        let proof = "
+            (assume h1 (and p q))
+            (step t2 (cl p) :rule and :premises (h1))
+            (assume h3 (not p))
+            (anchor :step t4)
+            (step t4.t1 (cl q) :rule and :premises (h1))
+            (assume t4.h2 p)
+            (assume t4.h3 q)
+            (step t4.t4 (cl p) :rule and :premises (h1))
+            (step t4 (cl (not p) (not q) p) :rule subproof :discharge (t4.h2 t4.h3))
+            (step t5 (cl) :rule resolution :premises (t2 h3))
+        ";
+        let (proof, mut pool) = parse_proof_with_definitions(definitions, proof).unwrap();
+        let mut checker = ProofChecker::new(&mut pool, Config::default());
+        assert!(matches!(checker.check(&proof), Ok(Correctness::True)));
+        let assumptions: Vec<_> = proof.assumptions().map(|t| format!("{:?}", t)).collect();
+        assert_eq!(vec!["(and p q)", "(not p)"], assumptions);
+    }
+
+    #[test]
+    fn reuse_checker() {
+        let definitions = "
+            (declare-fun p () Bool)
+            (declare-fun q () Bool)
+        ";
+        let proofs = [
+            "(assume h1 (and p q))
+            (step t2 (cl q) :rule and :premises (h1))",
+            "(assume h1 (and p q))
+            (step t2 (cl q) :rule or :premises (h1))",
+            "(assume h1 (and q p))
+            (step t2 (cl q) :rule and :premises (h1))",
+        ];
+        let (_, proofs, mut pool) = parser::parse_problem_proofs(
+            definitions.as_bytes(),
+            proofs.iter().map(|p| p.as_bytes()).collect(),
+            parser::Config::default(),
+        )
+        .unwrap();
+
+        // Identical terms in different proofs are shared in the pool
+        let assumed_term = |proof: &Proof| match &proof.0[0] {
+            ProofCommand::Assume { term, .. } => term.clone(),
+            _ => unreachable!(),
+        };
+        assert_eq!(assumed_term(&proofs[0]), assumed_term(&proofs[1]));
+
+        let mut checker = ProofChecker::new(&mut pool, Config::default());
+        assert!(matches!(checker.check(&proofs[0]), Ok(Correctness::True)));
+        assert!(matches!(
+            checker.check(&proofs[1]),
+            Ok(Correctness::False(_))
+        ));
+        assert!(checker.explanation().is_some());
+        assert!(matches!(checker.check(&proofs[2]), Ok(Correctness::True)));
+        assert!(checker.explanation().is_none());
+    }
+
+    #[test]
+    fn rule_registry() {
+        let mut names = HashSet::new();
+        for info in RULES {
+            assert!(names.insert(info.name), "duplicate rule \"{}\"", info.name);
+            assert_eq!(info.name, rule_info(info.name).unwrap().name);
+        }
+        assert!(rule_info("trust_me").is_none());
+        assert!(ProofChecker::get_rule("trust_me", true).is_some());
+        assert!(ProofChecker::get_rule("trust_me", false).is_none());
+
+        let info = rule_info("trans").unwrap();
+        assert_eq!(RuleCategory::Equality, info.category);
+        assert!(info.premises.allows(3) && !info.premises.allows(0));
+        assert!(rule_info("and").unwrap().args.allows(1));
+        assert!(!rule_info("and").unwrap().args.allows(2));
+    }
+
+    #[test]
+    fn memory_limit() {
+        let definitions = "
+            (declare-fun p () Bool)
+            (declare-fun q () Bool)
+        ";
+        let proof = "
+            (assume h1 (and p q))
+            (step t2 (cl q) :rule and :premises (h1))
+        ";
+        let (proof, mut pool) = parse_proof_with_definitions(definitions, proof).unwrap();
+        let check = |pool: &mut TermPool, memory_limit| {
+            let config = Config {
+                memory_limit,
+                ..Config::default()
+            };
+            ProofChecker::new(pool, config).check_step(&proof, "t2")
+        };
+        assert!(matches!(check(&mut pool, None), Ok(Correctness::True)));
+
+        // The limit is also checked when a single step is checked on its own
+        assert!(matches!(
+            check(&mut pool, Some(1)),
+            Err(CheckerError::MemoryLimitExceeded(1))
+        ));
+    }
+
+    #[test]
+    fn max_term_size() {
+        // The substitution builds "(= (+ 1 2) (+ 1 2))", which is not in the proof
+        let proof = "
+            (step t1 (cl (or (not (forall ((x Int)) (= x x))) (= 3 3)))
+                :rule forall_inst :args ((:= x (+ 1 2))))
+        ";
+        let check = |max_term_size| {
+            let (proof, mut pool) = parse_proof_with_definitions("", proof).unwrap();
+            let config = Config {
+                max_term_size,
+                ..Config::default()
+            };
+            ProofChecker::new(&mut pool, config).check(&proof)
+        };
+        assert!(matches!(check(None), Ok(Correctness::False(_))));
+        assert!(matches!(check(Some(4)), Ok(Correctness::False(_))));
+        assert!(matches!(
+            check(Some(3)),
+            Err(CheckerError::TermSizeExceeded(3))
+        ));
+    }
+
+    #[test]
+    fn eval_fallback() {
+        let proof = "
+            (step t1 (cl (= (+ 1 2) 3)) :rule la_generic)
+            (step t2 (cl (< 3 2) (distinct 1.0 (/ 3.0 3.0))) :rule la_generic)
+        ";
+        let check = |eval_fallback, id| {
+            let (proof, mut pool) = parse_proof_with_definitions("", proof).unwrap();
+            let config = Config {
+                eval_fallback,
+                ..Config::default()
+            };
+            matches!(
+                ProofChecker::new(&mut pool, config).check_step(&proof, id),
+                Ok(Correctness::True)
+            )
+        };
+        assert!(!check(false, "t1"));
+        assert!(check(true, "t1"));
+        assert!(!check(true, "t2"));
+    }
+
+    #[test]
+    fn normalization() {
+        let definitions = "
+            (declare-fun p () Bool)
+            (declare-fun a () Int)
+            (declare-fun b () Int)
+        ";
+        let proof = "
+            (assume h1 (or (> a b) p))
+            (assume h2 (<= a b))
+            (step t3 (cl (> a b) p) :rule or :premises (h1))
+            (step t4 (cl p) :rule resolution :premises (t3 h2))
+        ";
+        let check = |preset: Option<NormalizationPreset>| {
+            let (proof, mut pool) = parse_proof_with_definitions(definitions, proof).unwrap();
+            let config = Config {
+                normalization: preset.map(NormalizationPreset::hook),
+                ..Config::default()
+            };
+            let mut checker = ProofChecker::new(&mut pool, config);
+            let result = checker.check_step(&proof, "t4");
+            (result, checker.warnings().to_vec())
+        };
+        assert!(matches!(check(None).0, Ok(Correctness::False(_))));
+        assert!(matches!(
+            check(Some(NormalizationPreset::VeriT)).0,
+            Ok(Correctness::False(_))
+        ));
+        let (result, warnings) = check(Some(NormalizationPreset::Cvc5));
+        assert!(matches!(result, Ok(Correctness::True)));
+        assert_eq!(
+            vec![Warning::NormalizedStep {
+                step: "t4".to_owned(),
+                rule: "resolution".to_owned(),
+            }],
+            warnings
+        );
+    }
+
+    #[test]
+    fn thread_safety() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ProofChecker>();
+        assert_send_sync::<Proof>();
+        assert_send_sync::<TermPool>();
+        assert_send_sync::<CheckerError>();
+
+        // A proof parsed in one thread can be checked in another, and terms created in different
+        // threads are still shared
+        let definitions = "(declare-fun p () Bool)";
+        let proof = "
+            (assume h1 p)
+            (assume h2 (not p))
+            (step t3 (cl) :rule resolution :premises (h1 h2))
+        ";
+        let (proof, mut pool) = parse_proof_with_definitions(definitions, proof).unwrap();
+        let p = proof.assumptions().next().unwrap().clone();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let mut checker = ProofChecker::new(&mut pool, Config::default());
+                assert!(matches!(checker.check(&proof), Ok(Correctness::True)));
+                let term = terminal!(var "p"; pool.sort(&p).unwrap());
+                assert_eq!(p, pool.add_term(term));
+            });
+        });
+    }
+
+    #[test]
+    fn failure_modes() {
+        let definitions = "
+            (declare-fun p () Bool)
+            (declare-fun q () Bool)
+            (declare-fun r () Bool)
+        ";
+        let proof = "
+            (assume h1 p)
+            (anchor :step t2)
+            (step t2.t1 (cl q) :rule resolution :premises (h1))
+            (step t2.t2 (cl p) :rule resolution :premises (h1))
+            (step t2.t3 (cl r) :rule resolution :premises (h1))
+            (step t2 (cl r) :rule resolution :premises (h1))
+            (step t3 (cl q) :rule resolution :premises (h1))
+            (step t4 (cl p) :rule resolution :premises (h1))
+            (step t5 (cl r) :rule resolution :premises (h1))
+        ";
+        let (proof, mut pool) = parse_proof_with_definitions(definitions, proof).unwrap();
+        let mut check = |failure_mode| {
+            let config = Config {
+                failure_mode,
+                ..Config::default()
+            };
+            let mut checker = ProofChecker::new(&mut pool, config);
+            let result = checker.check(&proof);
+            assert!(matches!(result, Ok(Correctness::False(rule)) if rule == "resolution"));
+            let failed: Vec<_> = checker
+                .explanations()
+                .iter()
+                .map(|e| e.id.clone())
+                .collect();
+            assert_eq!(Some(&failed[0]), checker.explanation().map(|e| &e.id));
+            failed
+        };
+        assert_eq!(vec!["t2.t1"], check(FailureMode::Stop));
+        assert_eq!(vec!["t2.t1", "t2.t3"], check(FailureMode::FinishSubproof));
+        assert_eq!(
+            vec!["t2.t1", "t2.t3", "t2", "t3", "t5"],
+            check(FailureMode::CheckAll)
+        );
+    }
+
+    #[test]
+    fn holes() {
+        let proof = "
+            (assume h1 (and p q))
+            (step t2 (cl q) :rule and :premises (h1))
+            (step t3 (cl p) :rule foo :premises (h1))
+            (step t4 (cl q) :rule bar :premises (h1))
+            (step t5 (cl p) :rule foo :premises (h1))
+        ";
+        let (proof, mut pool) =
+            parse_proof_with_definitions("(declare-fun p () Bool) (declare-fun q () Bool)", proof)
+                .unwrap();
+        let config = Config {
+            skip_unknown_rules: true,
+            ..Config::default()
+        };
+        let mut checker = ProofChecker::new(&mut pool, config);
+
+        let result = checker.check(&proof).unwrap();
+        let expected = Holes {
+            solver_checked: Vec::new(),
+            skipped_rules: vec!["foo".to_owned(), "bar".to_owned()],
+        };
+        assert!(matches!(&result, Correctness::TrueModulo(holes) if *holes == expected));
+        assert_eq!(
+            "true, checked modulo skipped rules foo, bar",
+            result.to_string()
+        );
+
+        // Holes are only reported for the steps checked in each call
+        assert!(matches!(
+            checker.check_step(&proof, "t2"),
+            Ok(Correctness::True)
+        ));
+
+        let holes = Holes {
+            solver_checked: vec!["t1".to_owned(), "t2".to_owned()],
+            skipped_rules: vec!["foo".to_owned()],
+        };
+        assert_eq!(
+            "2 solver-discharged steps and skipped rules foo",
+            holes.to_string()
+        );
+    }
+
+    #[test]
+    fn warnings() {
+        let definitions = "
+            (declare-sort T 0)
+            (declare-fun a () T)
+            (declare-fun b () T)
+            (declare-fun c () T)
+        ";
+        let proof = "
+            (assume h1 (= a b))
+            (assume h2 (= c b))
+            (assume h3 (= b c))
+            (step t4 (cl (= (= a b c) (and (= a b) (= b c)))) :rule tmp_nary_elim)
+            (step t5 (cl (= a c)) :rule trans :premises (h1 h2))
+        ";
+        let check = |config: Config| {
+            let (proof, mut pool) = parse_proof_with_definitions(definitions, proof).unwrap();
+            let mut checker = ProofChecker::new(&mut pool, config);
+            let result = checker.check(&proof);
+            (result, checker.warnings().to_vec())
+        };
+
+        let (result, warnings) = check(Config {
+            lenient_warnings: true,
+            ..Config::default()
+        });
+        assert!(matches!(result, Ok(Correctness::True)));
+        let expected = vec![
+            Warning::DeprecatedRuleName {
+                step: "t4".to_owned(),
+                name: "tmp_nary_elim".to_owned(),
+                replacement: "nary_elim",
+            },
+            Warning::LenientStep {
+                step: "t5".to_owned(),
+                rule: "trans".to_owned(),
+            },
+            Warning::UnusedAssumption("h3".to_owned()),
+            Warning::UnreferencedStep("t4".to_owned()),
+        ];
+        assert_eq!(expected, warnings);
+        assert_eq!(
+            "step \"t5\" is only valid because the \"trans\" rule is checked leniently",
+            warnings[1].to_string()
+        );
+
+        // By default, steps are not checked a second time to find the ones that rely on leniency
+        let (_, warnings) = check(Config::default());
+        assert!(!warnings
+            .iter()
+            .any(|w| matches!(w, Warning::LenientStep { .. })));
+
+        // In strict mode, the step that relies on leniency is invalid
+        let (result, _) = check(Config {
+            strictness: Strictness::Strict,
+            ..Config::default()
+        });
+        assert!(matches!(result, Ok(Correctness::False(_))));
+
+        let (result, _) = check(Config {
+            deny_warnings: true,
+            ..Config::default()
+        });
+        assert!(matches!(result, Err(CheckerError::DeniedWarnings(w)) if w == expected));
+    }
+
+    #[test]
+    fn require_empty_clause() {
+        let definitions = "(declare-fun p () Bool)";
+        let check = |proof: &str, require_empty_clause| {
+            let (proof, mut pool) = parse_proof_with_definitions(definitions, proof).unwrap();
+            let config = Config {
+                require_empty_clause,
+                ..Config::default()
+            };
+            ProofChecker::new(&mut pool, config).check(&proof)
+        };
+
+        let complete = "
+            (assume h1 p)
+            (assume h2 (not p))
+            (step t3 (cl) :rule resolution :premises (h1 h2))
+        ";
+        assert!(matches!(check(complete, true), Ok(Correctness::True)));
+
+        let partial = "
+            (assume h1 (and p p))
+            (step t2 (cl p) :rule and :premises (h1) :args (0))
+        ";
+        assert!(matches!(check(partial, false), Ok(Correctness::True)));
+        assert!(matches!(
+            check(partial, true),
+            Err(CheckerError::IncompleteProof)
+        ));
+        assert!(matches!(
+            check("", true),
+            Err(CheckerError::IncompleteProof)
+        ));
+    }
+
+    #[test]
+    fn target() {
+        let definitions = "(declare-fun p () Bool) (declare-fun q () Bool)";
+        let proof = "
+            (assume h1 (and p q))
+            (step t2 (cl p) :rule and :premises (h1) :args (0))
+            (step t3 (cl (or p q)) :rule trust_me)
+            (step t4 (cl p q) :rule or :premises (t3))
+        ";
+        let check = |target: &str| {
+            let (_, proof, target, mut pool) = parser::parse_problem_proof_with_target(
+                Vec::new(),
+                definitions.as_bytes(),
+                proof.as_bytes(),
+                target,
+                parser::Config::default(),
+            )
+            .unwrap();
+            let config = Config {
+                allow_test_rule: true,
+                require_empty_clause: true,
+                target: Some(target),
+                ..Config::default()
+            };
+            ProofChecker::new(&mut pool, config).check(&proof)
+        };
+        assert!(matches!(check("(cl p)"), Ok(Correctness::True)));
+        assert!(matches!(check("(cl q p)"), Ok(Correctness::True)));
+        assert!(matches!(
+            check("(cl q)"),
+            Err(CheckerError::IncompleteProof)
+        ));
+        assert!(matches!(check("(cl)"), Err(CheckerError::IncompleteProof)));
+    }
+
+    #[test]
+    fn obligations() {
+        let definitions = "
+            (declare-fun p () Bool)
+            (declare-fun q () Bool)
+            (declare-fun r () Bool)
+        ";
+        let proof = "
+            (assume h1 p)
+            (step t2 (cl q) :rule unknown_rule :premises (h1))
+            (step t3 (cl r) :rule resolution :premises (t2))
+        ";
+        let (proof, mut pool) = parse_proof_with_definitions(definitions, proof).unwrap();
+        let config = Config {
+            skip_unknown_rules: true,
+            collect_obligations: true,
+            ..Config::default()
+        };
+        let mut checker = ProofChecker::new(&mut pool, config);
+        assert!(matches!(checker.check(&proof), Ok(Correctness::False(_))));
+
+        // Both the skipped step and the failing step have obligations
+        let obligations = checker.obligations();
+        assert_eq!(
+            ["t2", "t3"],
+            [obligations[0].id.as_str(), obligations[1].id.as_str()]
+        );
+        let expected = "; proof obligation for step t3, by rule resolution
+            (declare-fun q () Bool)
+            (declare-fun r () Bool)
+            ; premise t2, by rule unknown_rule
+            (assert q)
+            (assert (not r))
+            (check-sat)";
+        let lines = |s: &str| s.lines().map(str::trim).collect::<Vec<_>>().join("\n");
+        assert_eq!(lines(expected), lines(&obligations[1].query));
+    }
+
+    #[test]
+    fn check_sorts() {
+        let definitions = "(declare-fun p () Bool) (declare-fun a () Int)";
+        let proof = "
+            (assume h1 p)
+            (step t2 (cl (= a a)) :rule eq_reflexive)
+            (step t3 (cl p) :rule trust_me :premises (h1))
+        ";
+        let (mut proof, mut pool) = parse_proof_with_definitions(definitions, proof).unwrap();
+        let config = |check_sorts| Config {
+            allow_test_rule: true,
+            check_sorts,
+            ..Config::default()
+        };
+        assert!(matches!(
+            ProofChecker::new(&mut pool, config(true)).check(&proof),
+            Ok(Correctness::True)
+        ));
+
+        // Proofs built or modified after parsing may have terms that are not of sort Bool in their
+        // clauses
+        let a = match &proof.0[1] {
+            ProofCommand::Step(s) => match_term!((= a _a) = s.clause[0], RETURN_RCS).unwrap().0,
+            _ => unreachable!(),
+        }
+        .clone();
+        if let ProofCommand::Step(s) = &mut proof.0[1] {
+            s.clause[0] = a.clone();
+        }
+        assert!(matches!(
+            ProofChecker::new(&mut pool, config(false)).check(&proof),
+            Ok(Correctness::False(_))
+        ));
+        assert!(matches!(
+            ProofChecker::new(&mut pool, config(true)).check(&proof),
+            Err(CheckerError::IllSortedClause { id, term }) if id == "t2" && term == "a"
+        ));
+
+        // The clauses of the premises are also checked, even if only a single step is checked
+        if let ProofCommand::Assume { term, .. } = &mut proof.0[0] {
+            *term = a;
+        }
+        assert!(matches!(
+            ProofChecker::new(&mut pool, config(true)).check_step(&proof, "t3"),
+            Err(CheckerError::IllSortedClause { id, .. }) if id == "h1"
+        ));
+    }
+
+    #[test]
+    fn truncated_explanation() {
+        let definitions = "
+            (declare-fun p () Bool)
+            (declare-fun q () Bool)
+        ";
+        let proof = "
+            (assume h1 (and p q p q))
+            (step t2 (cl (not (not (not p))) (or p q p q)) :rule and :premises (h1))
+        ";
+        let explain = |print_limits| {
+            let (proof, mut pool) = parse_proof_with_definitions(definitions, proof).unwrap();
+            let config = Config {
+                print_limits,
+                ..Config::default()
+            };
+            let mut checker = ProofChecker::new(&mut pool, config);
+            assert!(matches!(checker.check(&proof), Ok(Correctness::False(_))));
+            checker.explanation().unwrap().clone()
+        };
+
+        let limits = PrintLimits {
+            max_depth: Some(2),
+            max_width: Some(2),
+        };
+        let explanation = explain(limits);
+        assert_eq!(
+            "(step t2 (cl (not (not …)) (or p q … 2 more)) :rule and :premises (h1))",
+            explanation.command,
+        );
+        assert_eq!(vec!["(assume h1 (and p q … 2 more))"], explanation.premises);
+        let highlighted: Vec<_> = explanation
+            .highlighted
+            .iter()
+            .map(|r| &explanation.command[r.clone()])
+            .collect();
+        assert_eq!(vec!["(not (not …))", "(or p q … 2 more)"], highlighted);
+
+        let explanation = explain(PrintLimits::UNLIMITED);
+        assert_eq!(
+            "(step t2 (cl (not (not (not p))) (or p q p q)) :rule and :premises (h1))",
+            explanation.command,
+        );
     }
 }
@@ -0,0 +1,117 @@
+use crate::ast::*;
+
+/// A function that rewrites a literal into a canonical form. If a step fails to check, and
+/// `Config::normalization` is set, the step is checked again with this function applied to each
+/// literal of its conclusion and of its premises. This lets the checker accept proofs from
+/// producers that canonicalize literals differently from what the rules expect.
+pub type NormalizationHook =
+    Box<dyn Fn(&mut TermPool, &ByRefRc<Term>) -> ByRefRc<Term> + Send + Sync>;
+
+/// The built-in literal normalizations, each tailored to the quirks of a proof producer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalizationPreset {
+    /// Removes double negations, like the extra ones that veriT sometimes adds to literals.
+    VeriT,
+
+    /// Removes double negations, and writes comparisons using only "<" and negations. For example,
+    /// `(not (<= a b))` and `(> a b)` both become `(< b a)`, and `(>= a b)` becomes
+    /// `(not (< a b))`.
+    Cvc5,
+}
+
+impl NormalizationPreset {
+    pub fn hook(self) -> NormalizationHook {
+        match self {
+            NormalizationPreset::VeriT => {
+                Box::new(|_: &mut TermPool, term: &ByRefRc<Term>| remove_double_negations(term))
+            }
+            NormalizationPreset::Cvc5 => Box::new(|pool: &mut TermPool, term: &ByRefRc<Term>| {
+                canonical_comparison(pool, term)
+            }),
+        }
+    }
+}
+
+/// Removes any number of pairs of negations from the start of `term`.
+fn remove_double_negations(mut term: &ByRefRc<Term>) -> ByRefRc<Term> {
+    while let Some(inner) = negated(term).and_then(negated) {
+        term = inner;
+    }
+    term.clone()
+}
+
+/// If `term` is a negation, returns the negated term.
+fn negated(term: &ByRefRc<Term>) -> Option<&ByRefRc<Term>> {
+    match term.as_ref() {
+        Term::Op(Operator::Not, args) => args.first(),
+        _ => None,
+    }
+}
+
+/// Writes `term`, which may be a negated comparison, using only "<" comparisons. A leading
+/// negation is kept, so literals that are complementary stay complementary.
+fn canonical_comparison(pool: &mut TermPool, term: &ByRefRc<Term>) -> ByRefRc<Term> {
+    if let Some(inner) = negated(term) {
+        let inner = canonical_comparison(pool, inner);
+        let negation = pool.add_term(Term::Op(Operator::Not, vec![inner]));
+        return remove_double_negations(&negation);
+    }
+    let (op, a, b) = match term.as_ref() {
+        Term::Op(op, args) if args.len() == 2 => (*op, &args[0], &args[1]),
+        _ => return term.clone(),
+    };
+    let (a, b, is_negated) = match op {
+        Operator::GreaterThan => (b, a, false),
+        Operator::LessEq => (b, a, true),
+        Operator::GreaterEq => (a, b, true),
+        _ => return term.clone(),
+    };
+    let less_than = pool.add_term(Term::Op(Operator::LessThan, vec![a.clone(), b.clone()]));
+    if is_negated {
+        pool.add_term(Term::Op(Operator::Not, vec![less_than]))
+    } else {
+        less_than
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_term_with_definitions;
+
+    #[test]
+    fn presets() {
+        let definitions = "
+            (declare-fun p () Bool)
+            (declare-fun a () Int)
+            (declare-fun b () Int)
+        ";
+        let cases = [
+            (NormalizationPreset::VeriT, "(not (not (not p)))", "(not p)"),
+            (NormalizationPreset::VeriT, "(not (not p))", "p"),
+            (
+                NormalizationPreset::VeriT,
+                "(not (<= a b))",
+                "(not (<= a b))",
+            ),
+            (NormalizationPreset::Cvc5, "(not (not p))", "p"),
+            (NormalizationPreset::Cvc5, "(< a b)", "(< a b)"),
+            (NormalizationPreset::Cvc5, "(> a b)", "(< b a)"),
+            (NormalizationPreset::Cvc5, "(<= a b)", "(not (< b a))"),
+            (NormalizationPreset::Cvc5, "(>= a b)", "(not (< a b))"),
+            (NormalizationPreset::Cvc5, "(not (<= a b))", "(< b a)"),
+            (NormalizationPreset::Cvc5, "(not (< a b))", "(not (< a b))"),
+            (NormalizationPreset::Cvc5, "(not (> a b))", "(not (< b a))"),
+            (
+                NormalizationPreset::Cvc5,
+                "(not (not (>= a b)))",
+                "(not (< a b))",
+            ),
+        ];
+        for (preset, term, expected) in cases {
+            let (term, mut pool) = parse_term_with_definitions(definitions, term).unwrap();
+            let normalized = preset.hook()(&mut pool, &term);
+            assert_eq!(expected, format!("{:?}", normalized));
+        }
+    }
+}
@@ -0,0 +1,447 @@
+use super::rules::{self, Rule};
+use std::{collections::HashMap, fmt, sync::OnceLock};
+
+/// The group a rule belongs to, following the organization of the Alethe specification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RuleCategory {
+    Tautology,
+    Clausification,
+    Resolution,
+    Equality,
+    Arithmetic,
+    Quantifier,
+    Simplification,
+    Subproof,
+}
+
+impl RuleCategory {
+    /// Returns true if the rules in this category reason about a background theory, like equality
+    /// with uninterpreted functions or linear arithmetic, instead of about the Boolean structure of
+    /// terms.
+    pub fn is_theory(self) -> bool {
+        matches!(self, RuleCategory::Equality | RuleCategory::Arithmetic)
+    }
+}
+
+impl fmt::Display for RuleCategory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            RuleCategory::Tautology => "tautology",
+            RuleCategory::Clausification => "clausification",
+            RuleCategory::Resolution => "resolution",
+            RuleCategory::Equality => "equality",
+            RuleCategory::Arithmetic => "arithmetic",
+            RuleCategory::Quantifier => "quantifier",
+            RuleCategory::Simplification => "simplification",
+            RuleCategory::Subproof => "subproof",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// How many premises or arguments a rule expects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Count {
+    Exactly(usize),
+    AtMost(usize),
+    AtLeast(usize),
+}
+
+impl Count {
+    pub fn allows(&self, n: usize) -> bool {
+        match *self {
+            Count::Exactly(m) => n == m,
+            Count::AtMost(m) => n <= m,
+            Count::AtLeast(m) => n >= m,
+        }
+    }
+}
+
+impl fmt::Display for Count {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Count::Exactly(n) => write!(f, "{}", n),
+            Count::AtMost(n) => write!(f, "at most {}", n),
+            Count::AtLeast(0) => write!(f, "any number"),
+            Count::AtLeast(n) => write!(f, "at least {}", n),
+        }
+    }
+}
+
+/// The metadata of a rule implemented by the checker.
+#[derive(Debug, Clone, Copy)]
+pub struct RuleInfo {
+    pub name: &'static str,
+    pub category: RuleCategory,
+
+    /// How many premises the rule expects. For rules that end a subproof, this doesn't include the
+    /// commands in the subproof.
+    pub premises: Count,
+    pub args: Count,
+    pub description: &'static str,
+
+    /// A short description of the conclusion that the rule expects.
+    pub conclusion: &'static str,
+
+    /// Whether the rule accepts some small deviations from its specification, unless the
+    /// strictness is `Strictness::Strict`.
+    pub lenient: bool,
+
+    pub(super) rule: Rule,
+}
+
+macro_rules! registry {
+    ($($name:literal => (
+        $rule:path, $category:ident, $premises:expr, $args:expr, $description:literal,
+        $conclusion:literal $(, $lenient:ident)? $(,)?
+    )),* $(,)?) => {
+        &[$(RuleInfo {
+            name: $name,
+            category: RuleCategory::$category,
+            premises: $premises,
+            args: $args,
+            description: $description,
+            conclusion: $conclusion,
+            lenient: registry!(@LENIENT $($lenient)?),
+            rule: $rule,
+        }),*]
+    };
+    (@LENIENT lenient) => { true };
+    (@LENIENT) => { false };
+}
+
+use Count::*;
+
+/// All the rules implemented by the checker, except for the "trust_me" test rule.
+pub static RULES: &[RuleInfo] = registry! {
+    "true" => (
+        rules::tautology::r#true, Tautology, Exactly(0), Exactly(0),
+        "Introduces the constant true.",
+        "(cl true)",
+    ),
+    "false" => (
+        rules::tautology::r#false, Tautology, Exactly(0), Exactly(0),
+        "Introduces the negation of the constant false.",
+        "(cl (not false))",
+    ),
+    "not_not" => (
+        rules::tautology::not_not, Tautology, Exactly(0), Exactly(0),
+        "Removes a double negation.",
+        "(cl (not (not (not p))) p)",
+    ),
+    "and_pos" => (
+        rules::tautology::and_pos, Tautology, Exactly(0), AtMost(1),
+        "A conjunction implies each of its conjuncts. The optional argument is the index of the \
+        conjunct.",
+        "(cl (not (and p_1 ... p_n)) p_i)",
+    ),
+    "and_neg" => (
+        rules::tautology::and_neg, Tautology, Exactly(0), Exactly(0),
+        "A conjunction holds if all of its conjuncts hold.",
+        "(cl (and p_1 ... p_n) (not p_1) ... (not p_n))",
+    ),
+    "or_pos" => (
+        rules::tautology::or_pos, Tautology, Exactly(0), Exactly(0),
+        "A disjunction implies that one of its disjuncts holds.",
+        "(cl (not (or p_1 ... p_n)) p_1 ... p_n)",
+    ),
+    "or_neg" => (
+        rules::tautology::or_neg, Tautology, Exactly(0), AtMost(1),
+        "Each disjunct implies the disjunction. The optional argument is the index of the \
+        disjunct.",
+        "(cl (or p_1 ... p_n) (not p_i))",
+    ),
+    "equiv_pos1" => (
+        rules::tautology::equiv_pos1, Tautology, Exactly(0), Exactly(0),
+        "One direction of a boolean equivalence.",
+        "(cl (not (= p q)) p (not q))",
+    ),
+    "equiv_pos2" => (
+        rules::tautology::equiv_pos2, Tautology, Exactly(0), Exactly(0),
+        "The other direction of a boolean equivalence.",
+        "(cl (not (= p q)) (not p) q)",
+    ),
+    "ite1" => (
+        rules::tautology::ite1, Tautology, Exactly(1), Exactly(0),
+        "If an \"ite\" term holds, either its condition or its \"else\" branch holds.",
+        "(cl b q), given a premise (ite b p q)",
+    ),
+    "ite2" => (
+        rules::tautology::ite2, Tautology, Exactly(1), Exactly(0),
+        "If an \"ite\" term holds, either its condition is false or its \"then\" branch holds.",
+        "(cl (not b) p), given a premise (ite b p q)",
+    ),
+    "ite_intro" => (
+        rules::tautology::ite_intro, Tautology, Exactly(0), Exactly(0),
+        "Adds the definitions of the \"ite\" subterms of a term.",
+        "(cl (= t (and t (= (ite b p q) ...) ...)))",
+    ),
+    "connective_def" => (
+        rules::tautology::connective_def, Tautology, Exactly(0), Exactly(0),
+        "Replaces a boolean connective by its definition.",
+        "(cl (= p q)), where q is the definition of the connective in p",
+    ),
+    "and" => (
+        rules::clausification::and, Clausification, Exactly(1), AtMost(1),
+        "Extracts one of the conjuncts of a conjunction. The optional argument is the index of the \
+        conjunct.",
+        "(cl p_i), given a premise (and p_1 ... p_n)",
+    ),
+    "or" => (
+        rules::clausification::or, Clausification, Exactly(1), Exactly(0),
+        "Turns a disjunction into a clause.",
+        "(cl p_1 ... p_n), given a premise (or p_1 ... p_n)",
+    ),
+    "implies" => (
+        rules::clausification::implies, Clausification, Exactly(1), Exactly(0),
+        "Turns an implication into a clause.",
+        "(cl (not p) q), given a premise (=> p q)",
+    ),
+    "distinct_elim" => (
+        rules::clausification::distinct_elim, Clausification, Exactly(0), Exactly(0),
+        "Replaces a \"distinct\" term by pairwise disequalities.",
+        "(cl (= (distinct t_1 ... t_n) (and (not (= t_i t_j)) ...)))",
+        lenient,
+    ),
+    "nary_elim" => (
+        rules::clausification::nary_elim, Clausification, Exactly(0), Exactly(0),
+        "Replaces an n-ary operator application by nested binary applications.",
+        "(cl (= p q)), where q is p with n-ary operators made binary",
+    ),
+    "resolution" => (
+        rules::resolution::resolution, Resolution, AtLeast(1), Exactly(0),
+        "Chain resolution of the premises, done by the SAT solver.",
+        "the resolvent of the premises",
+        lenient,
+    ),
+    "th_resolution" => (
+        rules::resolution::th_resolution, Resolution, AtLeast(1), Exactly(0),
+        "Chain resolution of the premises, done by a theory solver.",
+        "the resolvent of the premises, possibly with extra literals",
+        lenient,
+    ),
+    "tautology" => (
+        rules::resolution::tautology, Resolution, AtMost(1), Exactly(0),
+        "A clause that contains a literal and its negation is a tautology.",
+        "(cl true), given a premise containing both p and (not p), or, without premises, a clause \
+        containing both p and (not p)",
+    ),
+    "contraction" => (
+        rules::resolution::contraction, Resolution, Exactly(1), Exactly(0),
+        "Removes repeated literals from a clause.",
+        "the premise clause without repeated literals",
+        lenient,
+    ),
+    "weakening" => (
+        rules::resolution::weakening, Resolution, Exactly(1), Exactly(0),
+        "Adds literals to a clause.",
+        "(cl l_1 ... l_n l_n+1 ... l_m), given a premise (cl l_1 ... l_n)",
+    ),
+    "eq_reflexive" => (
+        rules::reflexivity::eq_reflexive, Equality, Exactly(0), Exactly(0),
+        "Every term is equal to itself.",
+        "(cl (= t t))",
+    ),
+    "eq_transitive" => (
+        rules::transitivity::eq_transitive, Equality, Exactly(0), Exactly(0),
+        "The transitivity axiom of equality, as a tautology.",
+        "(cl (not (= t_1 t_2)) ... (not (= t_n-1 t_n)) (= t_1 t_n))",
+        lenient,
+    ),
+    "eq_congruent" => (
+        rules::congruence::eq_congruent, Equality, Exactly(0), Exactly(0),
+        "The congruence axiom of equality for functions, as a tautology.",
+        "(cl (not (= t_1 u_1)) ... (not (= t_n u_n)) (= (f t_1 ... t_n) (f u_1 ... u_n)))",
+        lenient,
+    ),
+    "eq_congruent_pred" => (
+        rules::congruence::eq_congruent_pred, Equality, Exactly(0), Exactly(0),
+        "The congruence axiom of equality for predicates, as a tautology.",
+        "(cl (not (= t_1 u_1)) ... (not (= t_n u_n)) (not (p t_1 ... t_n)) (p u_1 ... u_n))",
+        lenient,
+    ),
+    "refl" => (
+        rules::reflexivity::refl, Equality, Exactly(0), Exactly(0),
+        "Two terms are equal if they are the same after applying the substitutions of the \
+        context.",
+        "(cl (= t u)), where t and u are equal after applying the context",
+    ),
+    "trans" => (
+        rules::transitivity::trans, Equality, AtLeast(1), Exactly(0),
+        "Derives an equality from a chain of equalities.",
+        "(cl (= t_1 t_n)), given premises (= t_1 t_2) ... (= t_n-1 t_n)",
+        lenient,
+    ),
+    "cong" => (
+        rules::congruence::cong, Equality, AtLeast(1), Exactly(0),
+        "Two applications of the same function are equal if their arguments are equal.",
+        "(cl (= (f t_1 ... t_n) (f u_1 ... u_n))), given premises (= t_i u_i)",
+        lenient,
+    ),
+    "la_rw_eq" => (
+        rules::linear_arithmetic::la_rw_eq, Arithmetic, Exactly(0), Exactly(0),
+        "An arithmetic equality is equivalent to two inequalities.",
+        "(cl (= (= t u) (and (<= t u) (<= u t))))",
+    ),
+    "la_generic" => (
+        rules::linear_arithmetic::la_generic, Arithmetic, Exactly(0), AtLeast(1),
+        "A tautological clause of linear inequalities. The arguments are the coefficients that \
+        refute the negation of the clause.",
+        "a clause of linear inequalities that is refuted by the coefficients",
+    ),
+    "la_disequality" => (
+        rules::linear_arithmetic::la_disequality, Arithmetic, Exactly(0), Exactly(0),
+        "Two numbers are either equal, or one is less than the other.",
+        "(cl (or (= t u) (not (<= t u)) (not (<= u t))))",
+    ),
+    "la_mult_pos" => (
+        rules::linear_arithmetic::la_mult_pos, Arithmetic, Exactly(0), Exactly(0),
+        "Multiplying both sides of a comparison by a positive factor preserves it.",
+        "(cl (=> (and (> m 0) (op t u)) (op (* m t) (* m u)))), where op is <, >, <=, >= or =",
+    ),
+    "la_mult_neg" => (
+        rules::linear_arithmetic::la_mult_neg, Arithmetic, Exactly(0), Exactly(0),
+        "Multiplying both sides of a comparison by a negative factor reverses it.",
+        "(cl (=> (and (< m 0) (op t u)) (op' (* m t) (* m u)))), where op' is op reversed",
+    ),
+    "forall_inst" => (
+        rules::quantifier::forall_inst, Quantifier, Exactly(0), AtLeast(1),
+        "Instantiates a universal quantifier. The arguments are the substitutions for the bound \
+        variables.",
+        "(cl (or (not (forall (x_1 ... x_n) p)) p[t_1/x_1 ... t_n/x_n]))",
+    ),
+    "exists_inst" => (
+        rules::quantifier::exists_inst, Quantifier, AtMost(1), AtLeast(1),
+        "Introduces an existential quantifier from an instance of its body. The arguments are the \
+        witnesses for the bound variables.",
+        "(cl (exists (x_1 ... x_n) p)), given premise p[t_1/x_1 ... t_n/x_n], or \
+        (cl (or (not p[t_1/x_1 ... t_n/x_n]) (exists (x_1 ... x_n) p)))",
+    ),
+    "qnt_join" => (
+        rules::quantifier::qnt_join, Quantifier, Exactly(0), Exactly(0),
+        "Joins two nested quantifiers of the same kind.",
+        "(cl (= (Q x (Q y p)) (Q (x y) p)))",
+    ),
+    "qnt_merge" => (
+        rules::quantifier::qnt_merge, Quantifier, Exactly(0), Exactly(0),
+        "Merges any number of nested quantifiers of the same kind, in either direction.",
+        "(cl (= (Q x_1 ... (Q x_n p)) (Q (x_1 ... x_n) p))), or the symmetric equality",
+    ),
+    "qnt_rm_unused" => (
+        rules::quantifier::qnt_rm_unused, Quantifier, Exactly(0), Exactly(0),
+        "Removes quantified variables that don't appear in the quantifier body.",
+        "(cl (= (Q x p) (Q y p))), where y are the variables of x free in p",
+    ),
+    "eq_simplify" => (
+        rules::simplification::eq_simplify, Simplification, Exactly(0), Exactly(0),
+        "Simplifies equalities.",
+        "(cl (= p q)), where q is p with equalities simplified",
+    ),
+    "or_simplify" => (
+        rules::simplification::or_simplify, Simplification, Exactly(0), Exactly(0),
+        "Simplifies disjunctions.",
+        "(cl (= p q)), where q is p with disjunctions simplified",
+    ),
+    "not_simplify" => (
+        rules::simplification::not_simplify, Simplification, Exactly(0), Exactly(0),
+        "Simplifies negations.",
+        "(cl (= p q)), where q is p with negations simplified",
+    ),
+    "equiv_simplify" => (
+        rules::simplification::equiv_simplify, Simplification, Exactly(0), Exactly(0),
+        "Simplifies boolean equivalences.",
+        "(cl (= p q)), where q is p with equivalences simplified",
+    ),
+    "bool_simplify" => (
+        rules::simplification::bool_simplify, Simplification, Exactly(0), Exactly(0),
+        "Simplifies boolean operators.",
+        "(cl (= p q)), where q is p with boolean operators simplified",
+    ),
+    "prod_simplify" => (
+        rules::simplification::prod_simplify, Simplification, Exactly(0), Exactly(0),
+        "Simplifies products.",
+        "(cl (= p q)), where q is p with products simplified",
+    ),
+    "div_simplify" => (
+        rules::simplification::div_simplify, Simplification, Exactly(0), Exactly(0),
+        "Simplifies divisions by one, divisions of a term by itself, and divisions of constants. \
+        Integer division follows the SMT-LIB semantics, so the remainder is never negative.",
+        "(cl (= (div t_1 t_2) u)) or (cl (= (/ t_1 t_2) u)), where u is the simplified quotient",
+    ),
+    "mod_simplify" => (
+        rules::simplification::mod_simplify, Simplification, Exactly(0), Exactly(0),
+        "Simplifies the remainder of divisions by one or minus one, of a term by itself, and of \
+        constants. The remainder is never negative, following the SMT-LIB semantics.",
+        "(cl (= (mod t_1 t_2) u)), where u is the simplified remainder",
+    ),
+    "ac_simp" => (
+        rules::simplification::ac_simp, Simplification, Exactly(0), Exactly(0),
+        "Flattens nested applications of associative operators, and removes duplicate arguments \
+        of idempotent ones.",
+        "(cl (= p q)), where q is p with nested associative operators flattened",
+    ),
+    "bfun_elim" => (
+        rules::simplification::bfun_elim, Simplification, Exactly(1), Exactly(0),
+        "Eliminates Boolean arguments of functions, by expanding quantified Boolean variables and \
+        branching on the value of each non-constant Boolean argument.",
+        "(cl q), given premise p, where q is p with Boolean arguments eliminated",
+    ),
+    "bind" => (
+        rules::subproof::bind, Subproof, Exactly(0), Exactly(0),
+        "Renames the bound variables of a quantifier.",
+        "(cl (= (Q x p) (Q y q))), where the subproof proves (= p q)",
+    ),
+    "subproof" => (
+        rules::subproof::subproof, Subproof, Exactly(0), Exactly(0),
+        "Discharges the assumptions of a subproof.",
+        "(cl (not p_1) ... (not p_n) q), where the subproof assumes p_i and proves q",
+    ),
+    "let" => (
+        rules::subproof::r#let, Subproof, AtLeast(0), Exactly(0),
+        "Eliminates a \"let\" term. The premises are the equalities between the bound terms and \
+        their substitutions, when they differ.",
+        "(cl (= (let (x t) p) q)), where the subproof proves (= p q)",
+    ),
+    "onepoint" => (
+        rules::subproof::onepoint, Subproof, Exactly(0), Exactly(0),
+        "Eliminates quantified variables that are forced to be equal to a term.",
+        "(cl (= (Q x p) (Q y q))), where the subproof proves (= p q)",
+    ),
+    "sko_ex" => (
+        rules::subproof::sko_ex, Subproof, Exactly(0), Exactly(0),
+        "Skolemizes an existential quantifier.",
+        "(cl (= (exists x p) q)), where the subproof proves (= p q)",
+    ),
+    "sko_forall" => (
+        rules::subproof::sko_forall, Subproof, Exactly(0), Exactly(0),
+        "Skolemizes a universal quantifier.",
+        "(cl (= (forall x p) q)), where the subproof proves (= p q)",
+    ),
+};
+
+/// Rule names used by older versions of veriT, and the names of the rules that replaced them.
+/// Steps that use these names are checked with the new rules, with a warning.
+pub static DEPRECATED_RULE_NAMES: &[(&str, &str)] = &[
+    ("tmp_AC_simp", "ac_simp"),
+    ("tmp_distinct_elim", "distinct_elim"),
+    ("tmp_ite_elim", "ite_intro"),
+    ("tmp_nary_elim", "nary_elim"),
+];
+
+/// If `name` is a deprecated rule name, returns the name of the rule that replaced it.
+pub fn replacement_rule_name(name: &str) -> Option<&'static str> {
+    DEPRECATED_RULE_NAMES
+        .iter()
+        .find(|(old, _)| *old == name)
+        .map(|(_, new)| *new)
+}
+
+/// Returns the metadata of the rule with the given name, or `None` if the rule is not
+/// implemented.
+pub fn rule_info(name: &str) -> Option<&'static RuleInfo> {
+    static BY_NAME: OnceLock<HashMap<&'static str, &'static RuleInfo>> = OnceLock::new();
+    BY_NAME
+        .get_or_init(|| RULES.iter().map(|info| (info.name, info)).collect())
+        .get(name)
+        .copied()
+}
@@ -1,31 +1,68 @@
-use super::{get_single_term_from_command, to_option, RuleArgs};
-use crate::ast::*;
-
-pub fn distinct_elim(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
+use super::{get_index_arg, get_premise_term, to_option, RuleArgs};
+use crate::{
+    ast::*,
+    checker::{clause::Clause, Strictness},
+};
+use std::collections::HashMap;
+
+pub fn distinct_elim(
+    RuleArgs {
+        conclusion,
+        pool,
+        strictness,
+        ..
+    }: RuleArgs,
+) -> Option<()> {
     rassert!(conclusion.len() == 1);
 
     let (distinct_args, second_term) = match_term!((= (distinct ...) second) = conclusion[0])?;
+    let allow_flipping = strictness != Strictness::Strict;
+    let is_pair = |got: (&Term, &Term), (a, b): (&Term, &Term)| {
+        got == (a, b) || (allow_flipping && got == (b, a))
+    };
     match distinct_args {
         [] | [_] => unreachable!(),
         [a, b] => {
-            let got: (&Term, &Term) = match_term!((not (= x y)) = second_term)?;
-            to_option(got == (a, b) || got == (b, a))
+            // For two boolean arguments, some solvers use (xor a b) instead of (not (= a b))
+            let got = match_term!((not (= x y)) = second_term).or_else(|| {
+                match_term!((xor x y) = second_term).filter(|_| strictness != Strictness::Strict)
+            })?;
+            to_option(is_pair(got, (a, b)))
         }
         args => {
-            if args[0].sort() == Term::BOOL_SORT {
-                // If there are more than two boolean arguments to the distinct operator, the
-                // second term must be "false"
-                return to_option(second_term.is_bool_false());
+            // If there are more than two boolean arguments to the distinct operator, they can't
+            // all be different, so the second term may also be "false"
+            if pool.sort(&args[0])?.as_ref() == Term::BOOL_SORT && second_term.is_bool_false() {
+                return Some(());
             }
             let got = match_term!((and ...) = second_term)?;
-            let mut k = 0;
-            for i in 0..args.len() {
-                for j in i + 1..args.len() {
-                    let (a, b) = (args[i].as_ref(), args[j].as_ref());
-                    let got = match_term!((not (= x y)) = got[k])?;
-                    to_option(got == (a, b) || got == (b, a))?;
-                    k += 1;
-                }
+            let n = args.len();
+            rassert!(got.len() == n * (n - 1) / 2);
+            let pairs =
+                (0..n).flat_map(|i| (i + 1..n).map(move |j| (args[i].as_ref(), args[j].as_ref())));
+
+            if strictness != Strictness::Permissive {
+                // The pairs must be in the order given by the specification, that is, (i, j) for
+                // each i < j, in lexicographic order
+                return to_option(got.iter().zip(pairs).all(|(t, pair)| {
+                    match_term!((not (= x y)) = t).is_some_and(|got| is_pair(got, pair))
+                }));
+            }
+
+            // Otherwise, the pairs may be in any order, so we count how many times each unordered
+            // pair is expected, and check that each conjunct matches one of them
+            let key = |(a, b): (&Term, &Term)| {
+                let (a, b) = (TermId::of(a), TermId::of(b));
+                (a.min(b), a.max(b))
+            };
+            let mut expected: HashMap<_, usize> = HashMap::new();
+            for pair in pairs {
+                *expected.entry(key(pair)).or_default() += 1;
+            }
+            for t in got {
+                let count = expected.get_mut(&key(match_term!((not (= x y)) = t)?))?;
+                rassert!(*count > 0);
+                *count -= 1;
             }
             Some(())
         }
@@ -36,29 +73,40 @@ pub fn and(
     RuleArgs {
         conclusion,
         premises,
+        args,
         ..
     }: RuleArgs,
 ) -> Option<()> {
     rassert!(premises.len() == 1 && conclusion.len() == 1);
 
-    let and_term = get_single_term_from_command(premises[0])?;
+    let and_term = get_premise_term(&premises[0])?;
     let and_contents = match_term!((and ...) = and_term)?;
 
-    to_option(and_contents.iter().any(|t| t == &conclusion[0]))
+    // If the index of the conjunct is given as an argument, we can check it directly. Otherwise,
+    // we have to search for the conclusion in the conjunction
+    match get_index_arg(args)? {
+        Some(i) => to_option(and_contents.get(i)? == &conclusion[0]),
+        None => to_option(and_contents.iter().any(|t| t == &conclusion[0])),
+    }
 }
 
 pub fn or(
     RuleArgs {
         conclusion,
         premises,
+        strictness,
         ..
     }: RuleArgs,
 ) -> Option<()> {
     rassert!(premises.len() == 1);
 
-    let or_term = get_single_term_from_command(premises[0])?;
+    let or_term = get_premise_term(&premises[0])?;
     let or_contents = match_term!((or ...) = or_term)?;
 
+    // When checking permissively, the order and repetitions of the disjuncts don't matter
+    if strictness == Strictness::Permissive {
+        return to_option(Clause::new(or_contents) == Clause::new(conclusion));
+    }
     to_option(or_contents == conclusion)
 }
 
@@ -71,7 +119,7 @@ pub fn implies(
 ) -> Option<()> {
     rassert!(premises.len() == 1 && conclusion.len() == 2);
 
-    let premise_term = get_single_term_from_command(premises[0])?;
+    let premise_term = get_premise_term(&premises[0])?;
     let (phi_1, phi_2) = match_term!((=> phi_1 phi_2) = premise_term)?;
 
     to_option(phi_1 == conclusion[0].remove_negation()? && phi_2 == conclusion[1].as_ref())
@@ -155,7 +203,7 @@ pub fn nary_elim(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
                 // iterator over the pairs of adjacent terms
                 args.windows(2).map(Some).eq(chain)
             }
-            assoc_case => check_assoc(*op, &args, result, assoc_case == Case::RightAssoc),
+            assoc_case => check_assoc(*op, args, result, assoc_case == Case::RightAssoc),
         })
     } else {
         None
@@ -201,13 +249,81 @@ mod tests {
                     (not (= a c))
                 ))) :rule distinct_elim)": false,
             }
-            "\"distinct\" on more than two booleans should be \"false\"" {
+            "Wrong number of conjunction terms" {
+                "(step t1 (cl (= (distinct a b c) (and
+                    (not (= a b))
+                    (not (= a c))
+                ))) :rule distinct_elim)": false,
+
+                "(step t1 (cl (= (distinct a b c) (and
+                    (not (= a b))
+                    (not (= a c))
+                    (not (= b c))
+                    (not (= b c))
+                ))) :rule distinct_elim)": false,
+            }
+            "\"distinct\" on two booleans may use \"xor\"" {
+                "(step t1 (cl (= (distinct p q) (xor p q))) :rule distinct_elim)": true,
+                "(step t1 (cl (= (distinct p q) (xor q p))) :rule distinct_elim)": true,
+                "(step t1 (cl (= (distinct p q) (not (= p q)))) :rule distinct_elim)": true,
+                "(step t1 (cl (= (distinct p q) (xor p r))) :rule distinct_elim)": false,
+                "(step t1 (cl (= (distinct p q r) (xor p q))) :rule distinct_elim)": false,
+            }
+            "\"distinct\" on more than two booleans may be \"false\"" {
                 "(step t1 (cl (= (distinct p q r) false)) :rule distinct_elim)": true,
 
                 "(step t1 (cl (= (distinct p q r) (and
                     (not (= p q))
                     (not (= p r))
                     (not (= q r))
+                ))) :rule distinct_elim)": true,
+
+                "(step t1 (cl (= (distinct p q r) true)) :rule distinct_elim)": false,
+            }
+        }
+        test_cases! {
+            definitions = "
+                (declare-sort T 0)
+                (declare-fun a () T)
+                (declare-fun b () T)
+                (declare-fun c () T)
+                (declare-fun p () Bool)
+                (declare-fun q () Bool)
+            ",
+            strictness = crate::checker::Strictness::Strict,
+            "Inequalities can't be flipped in strict mode" {
+                "(step t1 (cl (= (distinct a b) (not (= a b)))) :rule distinct_elim)": true,
+                "(step t1 (cl (= (distinct a b) (not (= b a)))) :rule distinct_elim)": false,
+
+                "(step t1 (cl (= (distinct a b c) (and
+                    (not (= a b))
+                    (not (= a c))
+                    (not (= c b))
+                ))) :rule distinct_elim)": false,
+            }
+            "\"xor\" is not allowed in strict mode" {
+                "(step t1 (cl (= (distinct p q) (xor p q))) :rule distinct_elim)": false,
+            }
+        }
+        test_cases! {
+            definitions = "
+                (declare-sort T 0)
+                (declare-fun a () T)
+                (declare-fun b () T)
+                (declare-fun c () T)
+            ",
+            strictness = crate::checker::Strictness::Permissive,
+            "Conjunction terms may be in any order in permissive mode" {
+                "(step t1 (cl (= (distinct a b c) (and
+                    (not (= c b))
+                    (not (= a b))
+                    (not (= a c))
+                ))) :rule distinct_elim)": true,
+
+                "(step t1 (cl (= (distinct a b c) (and
+                    (not (= c b))
+                    (not (= a b))
+                    (not (= b c))
                 ))) :rule distinct_elim)": false,
             }
         }
@@ -254,6 +370,19 @@ mod tests {
                 "(assume h1 (or p q r s))
                 (step t2 (cl r) :rule and :premises (h1))": false,
             }
+            "Index of conjunct given as argument" {
+                "(assume h1 (and p q r s))
+                (step t2 (cl r) :rule and :premises (h1) :args (2))": true,
+
+                "(assume h1 (and p q r s))
+                (step t2 (cl r) :rule and :premises (h1) :args (1))": false,
+
+                "(assume h1 (and p q r s))
+                (step t2 (cl r) :rule and :premises (h1) :args (4))": false,
+
+                "(assume h1 (and p q r s))
+                (step t2 (cl r) :rule and :premises (h1) :args (2 3))": false,
+            }
             "Conclusion term is not in premise" {
                 "(assume h1 (and p q r))
                 (step t2 (cl s) :rule and :premises (h1))": false,
@@ -304,6 +433,24 @@ mod tests {
                 (step t2 (cl p q) :rule or :premises (h1))": false,
             }
         }
+        test_cases! {
+            definitions = "
+                (declare-fun p () Bool)
+                (declare-fun q () Bool)
+                (declare-fun r () Bool)
+            ",
+            strictness = crate::checker::Strictness::Permissive,
+            "Order and repetitions don't matter when checking permissively" {
+                "(assume h1 (or q p))
+                (step t2 (cl p q) :rule or :premises (h1))": true,
+
+                "(assume h1 (or p q p))
+                (step t2 (cl q p) :rule or :premises (h1))": true,
+
+                "(assume h1 (or p q r))
+                (step t2 (cl p q) :rule or :premises (h1))": false,
+            }
+        }
     }
 
     #[test]
@@ -1,23 +1,39 @@
-use super::{get_single_term_from_command, to_option, RuleArgs};
-use crate::ast::*;
+use super::{congruence_closure::equality_follows_from, get_premise_term, to_option, RuleArgs};
+use crate::{ast::*, checker::Strictness};
 
-pub fn eq_congruent(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
+pub fn eq_congruent(
+    RuleArgs {
+        conclusion,
+        pool,
+        strictness,
+        ..
+    }: RuleArgs,
+) -> Option<()> {
     rassert!(conclusion.len() >= 2);
 
+    let constants = (pool.bool_true(), pool.bool_false());
     let premises = conclusion[..conclusion.len() - 1]
         .iter()
-        .map(|t| t.remove_negation());
+        .map(|t| argument_equality(t, &constants));
     let conclusion = match_term!((= f g) = conclusion.last().unwrap())?;
 
-    generic_congruent_rule(premises, conclusion)
+    generic_congruent_rule(premises, conclusion, strictness)
 }
 
-pub fn eq_congruent_pred(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
+pub fn eq_congruent_pred(
+    RuleArgs {
+        conclusion,
+        pool,
+        strictness,
+        ..
+    }: RuleArgs,
+) -> Option<()> {
     rassert!(conclusion.len() >= 3);
 
+    let constants = (pool.bool_true(), pool.bool_false());
     let premises = conclusion[..conclusion.len() - 2]
         .iter()
-        .map(|t| t.remove_negation());
+        .map(|t| argument_equality(t, &constants));
 
     let (p, q) = (
         &conclusion[conclusion.len() - 2],
@@ -28,23 +44,48 @@ pub fn eq_congruent_pred(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
         None => (p.as_ref(), q.remove_negation()?),
     };
 
-    generic_congruent_rule(premises, conclusion)
+    generic_congruent_rule(premises, conclusion, strictness)
+}
+
+/// Returns the equality of arguments that a literal in the clause of an "eq_congruent" or
+/// "eq_congruent_pred" step assumes, as a pair of terms. Usually, the literal is the negation of an
+/// equality "(not (= t u))". For arguments of sort Bool, veriT may instead assume that an argument
+/// is true or false directly: the literal "(not t)" assumes that "t" is equal to "true", and the
+/// literal "t" assumes that it is equal to "false". `constants` are the terms "true" and "false".
+fn argument_equality<'a>(
+    literal: &'a Term,
+    constants: &'a (ByRefRc<Term>, ByRefRc<Term>),
+) -> Option<(&'a Term, &'a Term)> {
+    match literal.remove_negation() {
+        Some(negated) => Some(match_term!((= t u) = negated).unwrap_or((negated, &constants.0))),
+        None => Some((literal, &constants.1)),
+    }
 }
 
 /// A function to check congruency. Useful for the "eq_congruent" and "eq_congruent_pred"
 /// rules. `premises` should be an iterator over the argument equalities, and `conclusion`
-/// should be the two function applications.
-fn generic_congruent_rule<'a, T>(premises: T, conclusion: (&Term, &Term)) -> Option<()>
+/// should be the two function applications. In strict mode, the argument equalities must be in
+/// the same order as the arguments. In permissive mode, the conclusion only needs to follow from
+/// the argument equalities by congruence closure.
+fn generic_congruent_rule<'a, T>(
+    premises: T,
+    conclusion: (&Term, &Term),
+    strictness: Strictness,
+) -> Option<()>
 where
-    T: Iterator<Item = Option<&'a Term>>,
+    T: Iterator<Item = Option<(&'a Term, &'a Term)>>,
 {
     let mut ts = Vec::new();
     let mut us = Vec::new();
-    for term in premises {
-        let (t, u) = match_term!((= t u) = term?)?;
+    for premise in premises {
+        let (t, u) = premise?;
         ts.push(t);
         us.push(u);
     }
+    if strictness == Strictness::Permissive {
+        return to_option(equality_follows_from(ts.into_iter().zip(us), conclusion));
+    }
+    let allow_flipping = strictness != Strictness::Strict;
 
     let (f_args, g_args) = match conclusion {
         (Term::App(f, f_args), Term::App(g, g_args)) if f == g => (f_args, g_args),
@@ -55,7 +96,7 @@ where
 
     for i in 0..ts.len() {
         let expected = (f_args[i].as_ref(), g_args[i].as_ref());
-        rassert!(expected == (ts[i], us[i]) || expected == (us[i], ts[i]));
+        rassert!(expected == (ts[i], us[i]) || allow_flipping && expected == (us[i], ts[i]));
     }
     Some(())
 }
@@ -64,6 +105,7 @@ pub fn cong(
     RuleArgs {
         conclusion,
         premises,
+        strictness,
         ..
     }: RuleArgs,
 ) -> Option<()> {
@@ -73,6 +115,7 @@ pub fn cong(
         premises: &[Option<(&'a Term, &'a Term)>],
         f_args: &[ByRefRc<Term>],
         g_args: &[ByRefRc<Term>],
+        allow_flipping: bool,
     ) -> bool {
         let mut premises = premises.iter().peekable();
         for (f_arg, g_arg) in f_args.iter().zip(g_args) {
@@ -80,7 +123,9 @@ pub fn cong(
             match premises.peek() {
                 // If the next premise can justify that the arguments are equal, we consume it. We
                 // prefer consuming the premise even if the arguments are directly equal
-                Some(Some((t, u))) if expected == (t, u) || expected == (u, t) => {
+                Some(Some((t, u)))
+                    if expected == (t, u) || allow_flipping && expected == (u, t) =>
+                {
                     premises.next();
                 }
                 // If there are no more premises, or the next premise does not match the current
@@ -100,30 +145,34 @@ pub fn cong(
     }
 
     rassert!(!premises.is_empty() && conclusion.len() == 1);
+    let allow_flipping = strictness != Strictness::Strict;
 
     let premises: Vec<_> = premises
         .into_iter()
-        .map(|command| {
-            get_single_term_from_command(command).and_then(|term| match_term!((= t u) = term))
-        })
+        .map(|premise| get_premise_term(&premise).and_then(|term| match_term!((= t u) = term)))
         .collect();
+    if strictness == Strictness::Permissive {
+        let premises = premises.into_iter().collect::<Option<Vec<_>>>()?;
+        let conclusion = match_term!((= f g) = conclusion[0].as_ref())?;
+        return to_option(equality_follows_from(premises, conclusion));
+    }
 
     let (f_args, g_args) = match match_term!((= f g) = conclusion[0].as_ref())? {
         // Because of the way veriT handles equality terms, when the "cong" rule is called with two
         // equalities of two terms, the order of their arguments may be flipped. Because of that,
         // we have to treat this special case separately
         (Term::Op(Operator::Equals, f_args), Term::Op(Operator::Equals, g_args))
-            if f_args.len() == 2 && g_args.len() == 2 =>
+            if allow_flipping && f_args.len() == 2 && g_args.len() == 2 =>
         {
             // We have to test all four possibilites: neither f nor g are flipped, only f is
             // flipped, only g is flipped, or both f and g are flipped
             let f_args_flipped = [f_args[1].clone(), f_args[0].clone()];
             let g_args_flipped = [g_args[1].clone(), g_args[0].clone()];
             return to_option(
-                check_cong(&premises, f_args, g_args)
-                    || check_cong(&premises, &f_args_flipped, g_args)
-                    || check_cong(&premises, f_args, &g_args_flipped)
-                    || check_cong(&premises, &f_args_flipped, &g_args_flipped),
+                check_cong(&premises, f_args, g_args, true)
+                    || check_cong(&premises, &f_args_flipped, g_args, true)
+                    || check_cong(&premises, f_args, &g_args_flipped, true)
+                    || check_cong(&premises, &f_args_flipped, &g_args_flipped, true),
             );
         }
 
@@ -132,7 +181,7 @@ pub fn cong(
         _ => return None,
     };
     rassert!(f_args.len() == g_args.len());
-    to_option(check_cong(&premises, f_args, g_args))
+    to_option(check_cong(&premises, f_args, g_args, allow_flipping))
 }
 
 #[cfg(test)]
@@ -215,6 +264,9 @@ mod tests {
                 (declare-fun q (Bool Bool) Bool)
                 (declare-fun p-1 (Bool) Bool)
                 (declare-fun p-3 (Bool Bool Bool) Bool)
+                (declare-fun i () Int)
+                (declare-fun j () Int)
+                (declare-fun r (Bool Int) Bool)
             ",
             "Simple working examples" {
                 "(step t1 (cl (not (= a b)) (not (p-1 a)) (p-1 b)) :rule eq_congruent_pred)": true,
@@ -256,6 +308,21 @@ mod tests {
                 "(step t1 (cl (not (= a x)) (not (= b y)) (not (and a b)) (and x y z))
                     :rule eq_congruent_pred)": false,
             }
+            "Boolean arguments may be assumed true or false directly" {
+                "(step t1 (cl (not a) (not (= i j)) (not (r a i)) (r true j))
+                    :rule eq_congruent_pred)": true,
+
+                "(step t1 (cl a (not (= i j)) (not (r a i)) (r false j))
+                    :rule eq_congruent_pred)": true,
+
+                "(step t1 (cl (not a) b (not (p a b)) (p true false)) :rule eq_congruent_pred)": true,
+
+                "(step t1 (cl a (not (= i j)) (not (r a i)) (r true j))
+                    :rule eq_congruent_pred)": false,
+
+                "(step t1 (cl (not a) (not (= i j)) (not (r a i)) (r false j))
+                    :rule eq_congruent_pred)": false,
+            }
             "Terms don't match" {
                 "(step t1 (cl (not (= a x)) (not (= b y)) (not (p b a)) (p x y))
                     :rule eq_congruent_pred)": false,
@@ -355,5 +422,58 @@ mod tests {
                 (step t3 (cl (= (= c a) (= d b))) :rule cong :premises (h1 h2))": true,
             }
         }
+        test_cases! {
+            definitions = "
+                (declare-sort T 0)
+                (declare-fun a () T)
+                (declare-fun b () T)
+                (declare-fun c () T)
+                (declare-fun d () T)
+                (declare-fun f (T T) T)
+            ",
+            strictness = crate::checker::Strictness::Strict,
+            "Equalities can't be flipped when checking strictly" {
+                "(assume h1 (= a b)) (assume h2 (= c d))
+                (step t3 (cl (= (f a c) (f b d))) :rule cong :premises (h1 h2))": true,
+
+                "(assume h1 (= a b)) (assume h2 (= c d))
+                (step t3 (cl (= (f b c) (f a d))) :rule cong :premises (h1 h2))": false,
+
+                "(assume h1 (= a b)) (assume h2 (= c d))
+                (step t3 (cl (= (= c a) (= d b))) :rule cong :premises (h1 h2))": false,
+            }
+        }
+        test_cases! {
+            definitions = "
+                (declare-sort T 0)
+                (declare-fun a () T)
+                (declare-fun b () T)
+                (declare-fun c () T)
+                (declare-fun d () T)
+                (declare-fun f (T T) T)
+                (declare-fun p (T) Bool)
+            ",
+            strictness = crate::checker::Strictness::Permissive,
+            "Equalities follow by congruence closure when checking permissively" {
+                "(assume h1 (= a b)) (assume h2 (= c d))
+                (step t3 (cl (= (f d b) (f c a))) :rule cong :premises (h1 h2))": true,
+
+                "(assume h1 (= a b)) (assume h2 (= b c))
+                (step t3 (cl (= (f a a) (f c b))) :rule cong :premises (h1 h2))": true,
+
+                "(assume h1 (= a b)) (assume h2 (= c d))
+                (step t3 (cl (= (= c a) (= d b))) :rule cong :premises (h1 h2))": true,
+
+                "(step t1 (cl (not (= a b)) (not (= b c)) (= (f a a) (f c b)))
+                    :rule eq_congruent)": true,
+
+                "(step t1 (cl (not (= b a)) (not (p a)) (p b)) :rule eq_congruent_pred)": true,
+
+                "(assume h1 (= a b))
+                (step t2 (cl (= (f a c) (f b d))) :rule cong :premises (h1))": false,
+
+                "(step t1 (cl (not (= a b)) (not (p a)) (p c)) :rule eq_congruent_pred)": false,
+            }
+        }
     }
 }
@@ -0,0 +1,202 @@
+use crate::ast::{Operator, Term, TermId};
+use std::collections::{hash_map::Entry, HashMap};
+
+/// The head of an application, that is, the function or operator that is applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Head {
+    App(TermId),
+    Op(Operator),
+}
+
+/// A congruence closure over terms. Terms are added as nodes, which are grouped into classes of
+/// terms that are known to be equal. Merging two classes also merges the classes of applications
+/// that become congruent, that is, applications of the same function whose arguments are in the
+/// same classes. Equalities are treated as symmetric, so "(= a b)" and "(= b a)" are always in the
+/// same class. Quantifiers and other binder terms are treated as atoms.
+///
+/// Terms are identified by their address, so all terms must come from the same pool.
+#[derive(Debug, Default)]
+pub(super) struct CongruenceClosure {
+    ids: HashMap<TermId, usize>,
+
+    /// The parent of each node in the union-find forest. A node is the representative of its class
+    /// if it is its own parent.
+    parents: Vec<usize>,
+
+    /// The number of nodes in each class, indexed by its representative.
+    sizes: Vec<usize>,
+
+    /// The applications that have a node of each class as an argument, indexed by the
+    /// representative of the class.
+    uses: Vec<Vec<usize>>,
+
+    /// The head and argument nodes of each node, or `None` if the node is an atom.
+    applications: Vec<Option<(Head, Vec<usize>)>>,
+
+    /// An application for each signature, that is, each head and classes of the arguments.
+    signatures: HashMap<(Head, Vec<usize>), usize>,
+}
+
+impl CongruenceClosure {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `term` and its sub-terms, and returns the node of `term`. If `term` is congruent to an
+    /// application that was already added, their classes are merged.
+    pub fn add_term(&mut self, term: &Term) -> usize {
+        if let Some(&id) = self.ids.get(&TermId::of(term)) {
+            return id;
+        }
+        let application = match term {
+            Term::App(f, args) => Some((Head::App(TermId::of(f)), args)),
+            Term::Op(op, args) => Some((Head::Op(*op), args)),
+            _ => None,
+        };
+        let application =
+            application.map(|(head, args)| (head, args.iter().map(|a| self.add_term(a)).collect()));
+
+        let id = self.parents.len();
+        self.ids.insert(TermId::of(term), id);
+        self.parents.push(id);
+        self.sizes.push(1);
+        self.uses.push(Vec::new());
+        if let Some((_, args)) = &application {
+            for &arg in args {
+                let class = self.find(arg);
+                self.uses[class].push(id);
+            }
+        }
+        self.applications.push(application);
+        if let Some(other) = self.insert_signature(id) {
+            self.merge(id, other);
+        }
+        id
+    }
+
+    /// Adds `a` and `b`, and merges their classes.
+    pub fn add_equality(&mut self, a: &Term, b: &Term) {
+        let (a, b) = (self.add_term(a), self.add_term(b));
+        self.merge(a, b);
+    }
+
+    /// Returns `true` if `a` and `b` are in the same class.
+    pub fn are_equal(&mut self, a: &Term, b: &Term) -> bool {
+        let (a, b) = (self.add_term(a), self.add_term(b));
+        self.find(a) == self.find(b)
+    }
+
+    fn find(&mut self, mut node: usize) -> usize {
+        let mut root = node;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+        while self.parents[node] != root {
+            node = std::mem::replace(&mut self.parents[node], root);
+        }
+        root
+    }
+
+    fn merge(&mut self, a: usize, b: usize) {
+        let mut pending = vec![(a, b)];
+        while let Some((a, b)) = pending.pop() {
+            let (mut a, mut b) = (self.find(a), self.find(b));
+            if a == b {
+                continue;
+            }
+            if self.sizes[a] < self.sizes[b] {
+                std::mem::swap(&mut a, &mut b);
+            }
+            self.parents[b] = a;
+            self.sizes[a] += self.sizes[b];
+
+            // The signatures of the applications that use the class of `b` changed, so they may
+            // now be congruent to other applications
+            let moved = std::mem::take(&mut self.uses[b]);
+            for &application in &moved {
+                if let Some(other) = self.insert_signature(application) {
+                    pending.push((application, other));
+                }
+            }
+            self.uses[a].extend(moved);
+        }
+    }
+
+    /// Records the current signature of the application `id`. If another application already has
+    /// the same signature, returns it instead. Signatures that were recorded before a merge are
+    /// never removed, but they can't match any current signature, since they refer to classes that
+    /// no longer have that representative.
+    fn insert_signature(&mut self, id: usize) -> Option<usize> {
+        let (head, args) = self.applications[id].clone()?;
+        let mut classes: Vec<_> = args.into_iter().map(|a| self.find(a)).collect();
+        if head == Head::Op(Operator::Equals) {
+            classes.sort_unstable();
+        }
+        match self.signatures.entry((head, classes)) {
+            Entry::Occupied(e) => Some(*e.get()),
+            Entry::Vacant(e) => {
+                e.insert(id);
+                None
+            }
+        }
+    }
+}
+
+/// Returns `true` if the equality of the terms in `conclusion` follows from the equalities in
+/// `premises`, by reflexivity, symmetry, transitivity and congruence.
+pub(super) fn equality_follows_from<'a, T>(premises: T, conclusion: (&Term, &Term)) -> bool
+where
+    T: IntoIterator<Item = (&'a Term, &'a Term)>,
+{
+    let mut closure = CongruenceClosure::new();
+    for (a, b) in premises {
+        closure.add_equality(a, b);
+    }
+    closure.are_equal(conclusion.0, conclusion.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn congruence_closure() {
+        let proof = "
+            (step t1 (cl (= a b) (= b c) (= (f c) d)) :rule trust_me)
+            (step t2 (cl (= (f a) d) (= (g a b) (g c a)) (= (= a x) (= x c))) :rule trust_me)
+            (step t3 (cl (= (f x) d) (= a d) (= (g a a) (g x a))) :rule trust_me)
+        ";
+        let (proof, _pool) =
+            parser::parse_proof_only(proof.as_bytes(), parser::Config::default()).unwrap();
+        let equalities: Vec<_> = proof
+            .0
+            .iter()
+            .map(|c| {
+                c.clause()
+                    .iter()
+                    .map(|t| match_term!((= a b) = t).unwrap())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let premises = || equalities[0].iter().copied();
+
+        for &conclusion in &equalities[1] {
+            assert!(equality_follows_from(premises(), conclusion));
+        }
+        for &conclusion in &equalities[2] {
+            assert!(!equality_follows_from(premises(), conclusion));
+        }
+
+        // Classes merged after an application was added still make it congruent to others
+        let mut closure = CongruenceClosure::new();
+        let (f_c, f_a) = (equalities[0][2].0, equalities[1][0].0);
+        closure.add_term(f_c);
+        closure.add_term(f_a);
+        assert!(!closure.are_equal(f_a, f_c));
+        for &(a, b) in &equalities[0][..2] {
+            closure.add_equality(a, b);
+        }
+        assert!(closure.are_equal(f_a, f_c));
+    }
+}
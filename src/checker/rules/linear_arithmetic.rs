@@ -1,4 +1,4 @@
-use super::{to_option, RuleArgs};
+use super::{to_option, ProofArgs, RuleArgs};
 use crate::ast::*;
 use num_rational::BigRational;
 use num_traits::{One, Signed, Zero};
@@ -13,10 +13,20 @@ pub fn la_rw_eq(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
     to_option(t_1 == t_2 && t_2 == t_3 && u_1 == u_2 && u_2 == u_3)
 }
 
+/// Removes the "to_real" conversions around a term. Since the conversion preserves the value of
+/// integers, linear arithmetic over the reals treats "(to_real t)" the same as "t".
+fn strip_to_real(mut term: &Term) -> &Term {
+    while let Some(inner) = match_term!((to_real t) = term) {
+        term = inner;
+    }
+    term
+}
+
 /// Converts a rational represented with division and negation to the resulting rational value. For
 /// example, the term "(/ (- 5) 2)" is converted to the rational value "-2.5".
 fn simple_operation_to_rational(term: &Term) -> Option<BigRational> {
     // TODO: Add tests for this
+    let term = strip_to_real(term);
     if let Some((n, d)) = match_term!((/ n d) = term) {
         Some(simple_operation_to_rational(n)? / simple_operation_to_rational(d)?)
     } else if let Some(t) = match_term!((-t) = term) {
@@ -32,6 +42,7 @@ fn simple_operation_to_rational(term: &Term) -> Option<BigRational> {
 /// positive polarity.
 fn flatten_sum(term: &Term) -> Vec<(&Term, bool)> {
     // TODO: Add tests for this
+    let term = strip_to_real(term);
     if let Some(args) = match_term!((+ ...) = term) {
         args.iter().flat_map(|t| flatten_sum(t.as_ref())).collect()
     } else if let Some(t) = match_term!((-t) = term) {
@@ -51,6 +62,19 @@ fn flatten_sum(term: &Term) -> Vec<(&Term, bool)> {
     }
 }
 
+/// Removes the negations around a term, treating "(- t)" as "(* -1 t)". Returns the inner term
+/// and its coefficient, which is -1 if the term was negated an odd number of times, and 1
+/// otherwise.
+fn strip_negations(term: &Term) -> (&Term, BigRational) {
+    let mut term = strip_to_real(term);
+    let mut coeff = BigRational::one();
+    while let Some(inner) = match_term!((-t) = term) {
+        term = strip_to_real(inner);
+        coeff = -coeff;
+    }
+    (term, coeff)
+}
+
 /// Takes a disequality term and returns its negation, represented by an operator and arguments.
 /// The disequality can be:
 /// * An application of the "<", ">", "<=" or ">=" operators
@@ -101,13 +125,15 @@ impl<'a> LinearComb<'a> {
             };
             match match_term!((* a b) = arg) {
                 Some((a, b)) => {
+                    let (a, b) = (strip_to_real(a), strip_to_real(b));
                     let (var, coeff) = match (a.try_as_signed_ratio(), b.try_as_signed_ratio()) {
                         (None, None) => (arg, BigRational::one()),
                         (None, Some(r)) => (a, r),
                         (Some(r), None) => (b, r),
                         (Some(_), Some(_)) => return None,
                     };
-                    result.insert(var, coeff * polarity_coeff);
+                    let (var, sign) = strip_negations(var);
+                    result.insert(var, coeff * sign * polarity_coeff);
                 }
                 None => match arg.try_as_ratio() {
                     Some(r) => {
@@ -174,6 +200,7 @@ pub fn la_generic(
         conclusion, args, ..
     }: RuleArgs,
 ) -> Option<()> {
+    let args = args.as_terms()?;
     rassert!(conclusion.len() == args.len());
 
     let final_disequality = conclusion
@@ -181,10 +208,7 @@ pub fn la_generic(
         .zip(args)
         .map(|(phi, a)| {
             let phi = phi.as_ref();
-            let a = match a {
-                ProofArg::Term(a) => simple_operation_to_rational(a)?,
-                ProofArg::Assign(_, _) => return None,
-            };
+            let a = simple_operation_to_rational(a)?;
 
             // Steps 1 and 2: Negate the disequality
             let (mut op, args) = negate_disequality(phi)?;
@@ -272,6 +296,63 @@ pub fn la_disequality(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
     to_option(t1_1 == t1_2 && t1_2 == t1_3 && t2_1 == t2_2 && t2_2 == t2_3)
 }
 
+pub fn la_mult_pos(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
+    la_mult(conclusion, Operator::GreaterThan, false)
+}
+
+pub fn la_mult_neg(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
+    la_mult(conclusion, Operator::LessThan, true)
+}
+
+/// Checks a clause that multiplies both sides of a comparison by a factor "m", whose sign is given
+/// by the first conjunct of the hypothesis. The clause must be of the form
+/// "(cl (=> (and (sign m 0) (op t_1 t_2)) (op' (* m t_1) (* m t_2))))", or the equivalent
+/// "(cl (not (and (sign m 0) (op t_1 t_2))) (op' (* m t_1) (* m t_2)))", where "op" is one of "<",
+/// ">", "<=", ">=" or "=". If `flip` is true, "op'" is "op" with its sides swapped, so "<" becomes
+/// ">" and vice versa, otherwise it is "op" itself. The zero can be written as any constant
+/// expression that is exactly zero, like "0", "0.0" or "(- 0)".
+fn la_mult(conclusion: &[ByRefRc<Term>], sign: Operator, flip: bool) -> Option<()> {
+    use Operator::*;
+
+    fn as_comparison(term: &Term) -> Option<(Operator, &ByRefRc<Term>, &ByRefRc<Term>)> {
+        match term {
+            Term::Op(op @ (LessThan | GreaterThan | LessEq | GreaterEq | Equals), args) => {
+                match args.as_slice() {
+                    [a, b] => Some((*op, a, b)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    let (hypothesis, result) = match conclusion {
+        [implication] => match_term!((=> h r) = implication)?,
+        [negated, result] => (match_term!((not h) = negated)?, result.as_ref()),
+        _ => return None,
+    };
+    let (sign_condition, comparison) = match_term!((and s c) = hypothesis)?;
+
+    let (sign_op, m, zero) = as_comparison(sign_condition)?;
+    rassert!(sign_op == sign);
+    rassert!(simple_operation_to_rational(zero)?.is_zero());
+
+    let (op, t_1, t_2) = as_comparison(comparison)?;
+    let expected_op = match (op, flip) {
+        (LessThan, true) => GreaterThan,
+        (GreaterThan, true) => LessThan,
+        (LessEq, true) => GreaterEq,
+        (GreaterEq, true) => LessEq,
+        (op, _) => op,
+    };
+    let (result_op, left, right) = as_comparison(result)?;
+    rassert!(result_op == expected_op);
+
+    let (m_1, u_1) = match_term!((* m t) = left)?;
+    let (m_2, u_2) = match_term!((* m t) = right)?;
+    to_option(m_1 == m.as_ref() && m_2 == m.as_ref() && u_1 == t_1.as_ref() && u_2 == t_2.as_ref())
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -301,6 +382,7 @@ mod tests {
                 (declare-fun a () Real)
                 (declare-fun b () Real)
                 (declare-fun c () Real)
+                (declare-fun n () Int)
             ",
             "Simple working examples" {
                 "(step t1 (cl (> a 0.0) (<= a 0.0)) :rule la_generic :args (1.0 1.0))": true,
@@ -309,12 +391,35 @@ mod tests {
 
                 "(step t1 (cl (< (+ a b) 1.0) (> (+ a b) 0.0))
                     :rule la_generic :args (1.0 (- 1.0)))": true,
+                "(step t1 (cl (< (+ a b) 1.0) (> (+ a b) 0.0))
+                    :rule la_generic :args (1.0 -1.0))": true,
+                "(step t1 (cl (< (+ a b) 1.0) (> (+ a b) 0.0))
+                    :rule la_generic :args (0.5 -1/2))": true,
 
                 "(step t1 (cl (<= (+ a (- b a)) b)) :rule la_generic :args (1.0))": true,
 
                 "(step t1 (cl (not (<= (- a b) (- c 1.0))) (<= (+ 1.0 (- a c)) b))
                     :rule la_generic :args (1.0 1.0))": true,
             }
+            "Integer literals and conversions" {
+                "(step t1 (cl (> a 0) (<= a 0.0)) :rule la_generic :args (1 1.0))": true,
+                "(step t1 (cl (> (to_real n) 0.0) (<= (to_real n) 0.0))
+                    :rule la_generic :args (1.0 1.0))": true,
+                "(step t1 (cl (<= (+ a (to_real 1)) 1.0) (> a 0.0))
+                    :rule la_generic :args (1.0 (to_real 1)))": true,
+                "(step t1 (cl (<= (* (to_real 2) a) 2.0) (> a 1.0))
+                    :rule la_generic :args (1.0 2.0))": true,
+            }
+            "Unary negation is multiplication by -1" {
+                "(step t1 (cl (> (* 2.0 (- a)) 0.0) (>= a 0.0)) :rule la_generic :args (1.0 2.0))":
+                    true,
+                "(step t1 (cl (< (+ (- a) (* (- 1.0) b)) 0.0) (<= (+ a b) 0.0))
+                    :rule la_generic :args (1.0 1.0))": true,
+                "(step t1 (cl (<= (+ (* 3.0 (- (- a))) (* (- 3.0) a)) 0.0))
+                    :rule la_generic :args (1.0))": true,
+                "(step t1 (cl (> (* 2.0 (- a)) 0.0) (> a 0.0)) :rule la_generic :args (1.0 2.0))":
+                    false,
+            }
             "Empty clause" {
                 "(step t1 (cl) :rule la_generic)": false,
             }
@@ -360,4 +465,70 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn la_mult_pos() {
+        test_cases! {
+            definitions = "
+                (declare-fun m () Real)
+                (declare-fun x () Real)
+                (declare-fun y () Real)
+                (declare-fun a () Int)
+                (declare-fun b () Int)
+            ",
+            "Simple working examples" {
+                "(step t1 (cl (=> (and (> m 0.0) (< x y)) (< (* m x) (* m y))))
+                    :rule la_mult_pos)": true,
+                "(step t1 (cl (not (and (> m 0.0) (>= x y))) (>= (* m x) (* m y)))
+                    :rule la_mult_pos)": true,
+                "(step t1 (cl (=> (and (> a 0) (= a b)) (= (* a a) (* a b))))
+                    :rule la_mult_pos)": true,
+                "(step t1 (cl (=> (and (> m (- 0.0)) (<= x y)) (<= (* m x) (* m y))))
+                    :rule la_mult_pos)": true,
+            }
+            "Sign condition is not valid" {
+                "(step t1 (cl (=> (and (< m 0.0) (< x y)) (< (* m x) (* m y))))
+                    :rule la_mult_pos)": false,
+                "(step t1 (cl (=> (and (> m 0.5) (< x y)) (< (* m x) (* m y))))
+                    :rule la_mult_pos)": false,
+                "(step t1 (cl (=> (and (> m x) (< x y)) (< (* m x) (* m y))))
+                    :rule la_mult_pos)": false,
+            }
+            "Conclusion is not the scaled comparison" {
+                "(step t1 (cl (=> (and (> m 0.0) (< x y)) (> (* m x) (* m y))))
+                    :rule la_mult_pos)": false,
+                "(step t1 (cl (=> (and (> m 0.0) (< x y)) (< (* m y) (* m x))))
+                    :rule la_mult_pos)": false,
+                "(step t1 (cl (=> (and (> m 0.0) (< x y)) (< (* x m) (* m y))))
+                    :rule la_mult_pos)": false,
+                "(step t1 (cl (=> (and (> m 0.0) (< x y)) (< (* 2.0 x) (* 2.0 y))))
+                    :rule la_mult_pos)": false,
+            }
+        }
+    }
+
+    #[test]
+    fn la_mult_neg() {
+        test_cases! {
+            definitions = "
+                (declare-fun m () Real)
+                (declare-fun x () Real)
+                (declare-fun y () Real)
+            ",
+            "Simple working examples" {
+                "(step t1 (cl (=> (and (< m 0.0) (< x y)) (> (* m x) (* m y))))
+                    :rule la_mult_neg)": true,
+                "(step t1 (cl (not (and (< m 0.0) (>= x y))) (<= (* m x) (* m y)))
+                    :rule la_mult_neg)": true,
+                "(step t1 (cl (=> (and (< m 0.0) (= x y)) (= (* m x) (* m y))))
+                    :rule la_mult_neg)": true,
+            }
+            "Comparison is not flipped" {
+                "(step t1 (cl (=> (and (< m 0.0) (< x y)) (< (* m x) (* m y))))
+                    :rule la_mult_neg)": false,
+                "(step t1 (cl (=> (and (> m 0.0) (< x y)) (> (* m x) (* m y))))
+                    :rule la_mult_neg)": false,
+            }
+        }
+    }
 }
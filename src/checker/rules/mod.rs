@@ -1,19 +1,26 @@
-use super::Context;
+use super::{clause::ClauseCache, ContextStack, Strictness};
 use crate::ast::*;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
 
 pub type Rule = fn(RuleArgs) -> Option<()>;
 
 pub struct RuleArgs<'a> {
     pub(super) conclusion: &'a [ByRefRc<Term>],
-    pub(super) premises: Vec<&'a ProofCommand>,
+    pub(super) premises: Vec<Premise<'a>>,
     pub(super) args: &'a [ProofArg],
     pub(super) pool: &'a mut TermPool,
-    pub(super) context: &'a mut [Context],
+    pub(super) context: &'a mut ContextStack,
 
     // For rules that end a subproof, we need to pass all the commands of the subproof that it is
     // closing, because they may need to refer to some of them, and they are not given as premises.
     // If a rule is not ending a subproof, this should be `None`
     pub(super) subproof_commands: Option<&'a [ProofCommand]>,
+
+    pub(super) strictness: Strictness,
+
+    /// The canonical clauses of the premises, shared by all steps checked by the same checker.
+    pub(super) clause_cache: &'a mut ClauseCache,
 }
 
 /// Converts a `bool` into an `Option<()>`.
@@ -25,6 +32,54 @@ fn to_option(b: bool) -> Option<()> {
     }
 }
 
+/// A premise of a step, as seen by the rules. Premises are created once by the checker, so rules
+/// don't need to handle each kind of command that may be used as a premise.
+#[derive(Debug, Clone, Copy)]
+pub struct Premise<'a> {
+    pub id: &'a str,
+
+    /// The depth of the scope that contains the premise and its index in the commands of that
+    /// scope, like in `ProofStep::premises`.
+    pub location: (usize, usize),
+
+    /// The clause concluded by the premise. "assume" premises are interpreted as a clause with a
+    /// single term, and subproofs as the clause of their last step.
+    pub clause: &'a [ByRefRc<Term>],
+
+    /// The rule of the step that concluded the premise, or `None` if it is an assumption.
+    pub rule: Option<&'a str>,
+}
+
+impl<'a> Premise<'a> {
+    /// Creates the premise at `location` in `scopes`, the commands of the top-level proof and of
+    /// the subproofs that enclose the step.
+    pub fn new(scopes: &[&'a [ProofCommand]], location: (usize, usize)) -> Self {
+        let (depth, i) = location;
+        let command = &scopes[depth][i];
+        let mut last = command;
+        while let ProofCommand::Subproof { commands, .. } = last {
+            last = commands.last().unwrap();
+        }
+        let rule = match last {
+            ProofCommand::Step(s) => Some(s.rule.as_str()),
+            _ => None,
+        };
+        Self {
+            id: command.id(),
+            location,
+            clause: get_clause_from_command(command),
+            rule,
+        }
+    }
+}
+
+fn get_premise_term<'a>(premise: &Premise<'a>) -> Option<&'a ByRefRc<Term>> {
+    match premise.clause {
+        [t] => Some(t),
+        _ => None,
+    }
+}
+
 fn get_single_term_from_command(command: &ProofCommand) -> Option<&ByRefRc<Term>> {
     match get_clause_from_command(command) {
         [t] => Some(t),
@@ -32,17 +87,59 @@ fn get_single_term_from_command(command: &ProofCommand) -> Option<&ByRefRc<Term>
     }
 }
 
-fn get_clause_from_command(command: &ProofCommand) -> &[ByRefRc<Term>] {
+pub(super) fn get_clause_from_command(command: &ProofCommand) -> &[ByRefRc<Term>] {
     match command {
         // "assume" premises are interpreted as a clause with a single term
-        ProofCommand::Assume(term) => std::slice::from_ref(term),
-        ProofCommand::Step(ProofStep { clause, .. }) => &clause,
+        ProofCommand::Assume { term, .. } => std::slice::from_ref(term),
+        ProofCommand::Step(ProofStep { clause, .. }) => clause,
         ProofCommand::Subproof { commands, .. } => {
             get_clause_from_command(commands.last().unwrap())
         }
     }
 }
 
+/// Helper methods to extract the `:args` of a step.
+trait ProofArgs {
+    /// Returns the terms of all arguments, or `None` if any argument is an assignment.
+    fn as_terms(&self) -> Option<Vec<&ByRefRc<Term>>>;
+
+    /// Returns the symbol and value of all arguments, or `None` if any argument is not an
+    /// assignment.
+    fn as_assignments(&self) -> Option<Vec<(&str, &ByRefRc<Term>)>>;
+
+    /// Returns the `i`-th argument as an integer constant, or `None` if it doesn't exist or is not
+    /// an integer constant.
+    fn get_integer_arg(&self, i: usize) -> Option<&BigInt>;
+}
+
+impl ProofArgs for [ProofArg] {
+    fn as_terms(&self) -> Option<Vec<&ByRefRc<Term>>> {
+        self.iter().map(ProofArg::as_term).collect()
+    }
+
+    fn as_assignments(&self) -> Option<Vec<(&str, &ByRefRc<Term>)>> {
+        self.iter().map(ProofArg::as_assign).collect()
+    }
+
+    fn get_integer_arg(&self, i: usize) -> Option<&BigInt> {
+        match self.get(i)?.as_term()?.as_ref() {
+            Term::Terminal(Terminal::Integer(i)) => Some(i),
+            _ => None,
+        }
+    }
+}
+
+/// Gets the index argument of rules like "and" and "or_neg", that selects which term of the
+/// conjunction or disjunction is used in the conclusion. Returns `Some(None)` if no arguments were
+/// given, and `None` if the arguments are not a single non-negative integer.
+fn get_index_arg(args: &[ProofArg]) -> Option<Option<usize>> {
+    match args {
+        [] => Some(None),
+        [_] => Some(Some(args.get_integer_arg(0)?.to_usize()?)),
+        _ => None,
+    }
+}
+
 /// Asserts that the argument is true, and returns `None` otherwise. `rassert!(arg)` is identical
 /// to `to_option(arg)?`, but much more readable.
 macro_rules! rassert {
@@ -52,19 +149,23 @@ macro_rules! rassert {
 }
 
 #[cfg(test)]
-fn run_tests(test_name: &str, definitions: &str, cases: &[(&str, bool)]) {
+fn run_tests(test_name: &str, definitions: &str, cases: &[(&str, bool)], strictness: Strictness) {
     use crate::{
-        checker::{Correctness, ProofChecker},
-        parser::parse_problem_proof,
+        checker::{Config, Correctness, ProofChecker},
+        parser::parse_proof_with_definitions,
     };
-    use std::io::Cursor;
 
     for (i, (proof, expected)) in cases.iter().enumerate() {
         // This parses the definitions again for every case, which is not ideal
-        let (parsed, pool) = parse_problem_proof(Cursor::new(definitions), Cursor::new(proof))
+        let (parsed, mut pool) = parse_proof_with_definitions(definitions, proof)
             .unwrap_or_else(|e| panic!("parser error during test \"{}\": {:?}", test_name, e));
+        let config = Config {
+            allow_test_rule: true,
+            strictness,
+            ..Default::default()
+        };
         let got = matches!(
-            ProofChecker::new(pool, false, true).check(&parsed),
+            ProofChecker::new(&mut pool, config).check(&parsed),
             Ok(Correctness::True),
         );
         assert_eq!(
@@ -80,12 +181,23 @@ macro_rules! test_cases {
     (
         definitions = $defs:expr,
         $($name:literal { $($proof:literal: $exp:literal,)* } )*
+    ) => {
+        test_cases! {
+            definitions = $defs,
+            strictness = crate::checker::Strictness::Default,
+            $($name { $($proof: $exp,)* } )*
+        }
+    };
+    (
+        definitions = $defs:expr,
+        strictness = $strictness:expr,
+        $($name:literal { $($proof:literal: $exp:literal,)* } )*
     ) => {{
         let definitions: &str = $defs;
         $({
             let name: &str = $name;
             let cases = [ $(($proof, $exp),)* ];
-            crate::checker::rules::run_tests(name, definitions, &cases);
+            crate::checker::rules::run_tests(name, definitions, &cases, $strictness);
         })*
     }};
 }
@@ -94,6 +206,7 @@ macro_rules! test_cases {
 // macro is declared
 pub(super) mod clausification;
 pub(super) mod congruence;
+mod congruence_closure;
 pub(super) mod linear_arithmetic;
 pub(super) mod quantifier;
 pub(super) mod reflexivity;
@@ -1,6 +1,5 @@
-use super::{to_option, RuleArgs};
+use super::{get_premise_term, to_option, ProofArgs, RuleArgs};
 use crate::{ast::*, utils::DedupIterator};
-use std::collections::HashMap;
 
 pub fn forall_inst(
     RuleArgs {
@@ -12,34 +11,69 @@ pub fn forall_inst(
 ) -> Option<()> {
     rassert!(conclusion.len() == 1);
 
-    let (forall_term, substituted) = match_term!((or (not f) s) = conclusion[0], RETURN_RCS)?;
-    let (quant, bindings, original) = forall_term.unwrap_quant()?;
-    rassert!(quant == Quantifier::Forall);
+    let ((bindings, original), substituted) =
+        match_term!((or (not (forall ... f)) s) = conclusion[0], RETURN_RCS)?;
+    is_instance(pool, bindings, original, args, substituted)
+}
+
+/// The dual of "forall_inst", which introduces an existential quantifier from an instance of its
+/// body. In the premise-driven form, the premise is the instance and the conclusion is the
+/// existential term. Without premises, the conclusion is the tautology `(or (not s) e)`, where `s`
+/// is the instance.
+pub fn exists_inst(
+    RuleArgs {
+        conclusion,
+        premises,
+        args,
+        pool,
+        ..
+    }: RuleArgs,
+) -> Option<()> {
+    rassert!(conclusion.len() == 1);
 
-    rassert!(args.len() == bindings.len());
+    let (exists_term, substituted) = match premises.as_slice() {
+        [premise] => (&conclusion[0], get_premise_term(premise)?),
+        [] => {
+            let (s, e) = match_term!((or (not s) e) = conclusion[0], RETURN_RCS)?;
+            (e, s)
+        }
+        _ => return None,
+    };
+    let (bindings, original) = match_term!((exists ... f) = exists_term, RETURN_RCS)?;
+    is_instance(pool, bindings, original, args, substituted)
+}
+
+/// Checks that `substituted` is the result of replacing the variables in `bindings` by the terms
+/// given in `args` in `original`. The arguments must assign a term of the right sort to each bound
+/// variable, in order.
+fn is_instance(
+    pool: &mut TermPool,
+    bindings: &[SortedVar],
+    original: &ByRefRc<Term>,
+    args: &[ProofArg],
+    substituted: &ByRefRc<Term>,
+) -> Option<()> {
+    let assignments = args.as_assignments()?;
+    rassert!(assignments.len() == bindings.len());
 
-    let mut substitutions: HashMap<_, _> = bindings
+    let substitution: Substitution = bindings
         .iter()
-        .zip(args)
-        .map(|((binding_name, binding_sort), arg)| {
-            let (arg_name, arg_value) = match arg {
-                ProofArg::Assign(name, value) => (name, value),
-                ProofArg::Term(_) => return None,
-            };
-            let arg_sort = arg_value.sort();
-            rassert!(arg_name == binding_name && binding_sort.as_ref() == arg_sort);
-
-            // We must use `pool.add_term` so we don't create a new term for the argument sort, and
-            // instead use the one already in the term pool
-            let ident_term = terminal!(var arg_name; pool.add_term(arg_sort.clone()));
+        .zip(assignments)
+        .map(|((binding_name, binding_sort), (arg_name, arg_value))| {
+            let arg_sort = pool.sort(arg_value)?;
+            rassert!(arg_name == binding_name && *binding_sort == arg_sort);
+
+            let ident_term = terminal!(var arg_name; arg_sort.clone());
             Some((pool.add_term(ident_term), arg_value.clone()))
         })
         .collect::<Option<_>>()?;
 
-    // Equalities may be reordered in the final term, so we use `DeepEq::eq_modulo_reordering`
-    to_option(DeepEq::eq_modulo_reordering(
-        &pool.apply_substitutions(original, &mut substitutions),
+    // Equalities may be reordered in the final term, so we compare it modulo flipping equalities.
+    // No other differences are allowed, like renaming bound variables or reordering conjunctions
+    to_option(DeepEq::eq_with_policy(
+        &substitution.apply(pool, original),
         substituted,
+        DeepEqPolicy::FLIP_EQUALITIES,
     ))
 }
 
@@ -57,6 +91,35 @@ pub fn qnt_join(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
     to_option(bindings_3.iter().eq(combined))
 }
 
+/// A generalization of "qnt_join", used by some producers, that merges any number of nested
+/// quantifiers of the same kind into one. The merged quantifier may be on either side of the
+/// equality.
+pub fn qnt_merge(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
+    rassert!(conclusion.len() == 1);
+
+    let (left, right) = match_term!((= l r) = conclusion[0], RETURN_RCS)?;
+    is_merged_quantifier(left, right).or_else(|| is_merged_quantifier(right, left))
+}
+
+/// Checks that `merged` is the result of merging the nested quantifiers in `nested`, that is, that
+/// `nested` is of the form (Q x_1 (Q x_2 ... (Q x_n p))), with at least two quantifiers, and
+/// `merged` is (Q y p), where y are the variables in x_1, ..., x_n, without duplicates.
+fn is_merged_quantifier(nested: &ByRefRc<Term>, merged: &ByRefRc<Term>) -> Option<()> {
+    let (quant, bindings, body) = merged.unwrap_quant()?;
+    let mut combined = Vec::new();
+    let mut current = nested;
+    let mut num_quantifiers = 0;
+    while current != body {
+        let (q, b, inner) = current.unwrap_quant()?;
+        rassert!(q == quant);
+        combined.extend(b);
+        current = inner;
+        num_quantifiers += 1;
+    }
+    rassert!(num_quantifiers >= 2);
+    to_option(bindings.iter().eq(combined.into_iter().dedup()))
+}
+
 pub fn qnt_rm_unused(
     RuleArgs {
         conclusion, pool, ..
@@ -72,7 +135,7 @@ pub fn qnt_rm_unused(
     to_option(
         bindings_1
             .iter()
-            .filter(|(var, _)| free_vars.contains(var))
+            .filter(|(var, _)| free_vars.contains(var.as_str()))
             .eq(bindings_2),
     )
 }
@@ -158,6 +221,16 @@ mod tests {
                 "(step t1 (cl (or (not (forall ((x Real) (y Real)) (and (= x y) (= 1 0))))
                     (and (= b a) (= 1 0)))) :rule forall_inst :args ((:= x a) (:= y b)))": true,
             }
+            "No other differences are allowed" {
+                "(step t1 (cl (or (not (forall ((x Real) (y Real)) (and (= x y) (= 1 0))))
+                    (and (= 1 0) (= a b)))) :rule forall_inst :args ((:= x a) (:= y b)))": false,
+
+                "(step t1 (cl (or (not (forall ((x Real)) (forall ((y Real)) (= x y))))
+                    (forall ((y Real)) (= a y)))) :rule forall_inst :args ((:= x a)))": true,
+
+                "(step t1 (cl (or (not (forall ((x Real)) (forall ((y Real)) (= x y))))
+                    (forall ((z Real)) (= a z)))) :rule forall_inst :args ((:= x a)))": false,
+            }
             "Argument is not in quantifier bindings" {
                 "(step t1 (cl (or (not (forall ((x Real)) (= x a))) (= b 0.0)))
                     :rule forall_inst :args ((:= x b) (:= a 0.0)))": false,
@@ -181,6 +254,41 @@ mod tests {
         }
     }
 
+    #[test]
+    fn exists_inst() {
+        test_cases! {
+            definitions = "
+                (declare-fun p (Real) Bool)
+                (declare-fun a () Real)
+                (declare-fun b () Real)
+            ",
+            "Premise-driven form" {
+                "(assume h1 (p a))
+                (step t2 (cl (exists ((x Real)) (p x))) :rule exists_inst
+                    :premises (h1) :args ((:= x a)))": true,
+
+                "(assume h1 (and (p a) (= b a)))
+                (step t2 (cl (exists ((x Real) (y Real)) (and (p x) (= x y)))) :rule exists_inst
+                    :premises (h1) :args ((:= x a) (:= y b)))": true,
+
+                "(assume h1 (p b))
+                (step t2 (cl (exists ((x Real)) (p x))) :rule exists_inst
+                    :premises (h1) :args ((:= x a)))": false,
+
+                "(assume h1 (p a))
+                (step t2 (cl (forall ((x Real)) (p x))) :rule exists_inst
+                    :premises (h1) :args ((:= x a)))": false,
+            }
+            "Tautology form" {
+                "(step t1 (cl (or (not (p a)) (exists ((x Real)) (p x))))
+                    :rule exists_inst :args ((:= x a)))": true,
+
+                "(step t1 (cl (or (not (p a)) (exists ((x Real)) (p x))))
+                    :rule exists_inst :args ((:= y a)))": false,
+            }
+        }
+    }
+
     #[test]
     fn qnt_join() {
         test_cases! {
@@ -237,6 +345,65 @@ mod tests {
         }
     }
 
+    #[test]
+    fn qnt_merge() {
+        test_cases! {
+            definitions = "
+                (declare-fun p () Bool)
+                (declare-fun a () Real)
+            ",
+            "Simple working examples" {
+                "(step t1 (cl (=
+                    (forall ((x Real)) (forall ((y Real)) (= x y)))
+                    (forall ((x Real) (y Real)) (= x y))
+                )) :rule qnt_merge)": true,
+
+                "(step t1 (cl (=
+                    (exists ((x Real) (y Real)) (exists ((x Real) (y Real)) (= x y)))
+                    (exists ((x Real) (y Real)) (= x y))
+                )) :rule qnt_merge)": true,
+            }
+            "Merged quantifier on the left-hand side" {
+                "(step t1 (cl (=
+                    (forall ((x Real) (y Real)) (= x y))
+                    (forall ((x Real)) (forall ((y Real)) (= x y)))
+                )) :rule qnt_merge)": true,
+
+                "(step t1 (cl (=
+                    (forall ((p Bool)) p)
+                    (forall ((p Bool)) (forall ((p Bool)) p))
+                )) :rule qnt_merge)": true,
+            }
+            "More than two quantifiers" {
+                "(step t1 (cl (=
+                    (forall ((x Real)) (forall ((y Real)) (forall ((z Real)) (= x y z))))
+                    (forall ((x Real) (y Real) (z Real)) (= x y z))
+                )) :rule qnt_merge)": true,
+
+                "(step t1 (cl (=
+                    (forall ((x Real)) (forall ((y Real)) (forall ((z Real)) (= x y z))))
+                    (forall ((x Real) (y Real)) (forall ((z Real)) (= x y z)))
+                )) :rule qnt_merge)": true,
+            }
+            "Invalid merges" {
+                "(step t1 (cl (=
+                    (forall ((x Real)) (forall ((y Real)) (= x y)))
+                    (forall ((y Real) (x Real)) (= x y))
+                )) :rule qnt_merge)": false,
+
+                "(step t1 (cl (=
+                    (forall ((x Real)) (exists ((y Real)) (= x y)))
+                    (forall ((x Real) (y Real)) (= x y))
+                )) :rule qnt_merge)": false,
+
+                "(step t1 (cl (=
+                    (forall ((x Real)) (= x a))
+                    (forall ((x Real)) (= x a))
+                )) :rule qnt_merge)": false,
+            }
+        }
+    }
+
     #[test]
     fn qnt_rm_unused() {
         test_cases! {
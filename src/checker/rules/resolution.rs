@@ -1,13 +1,43 @@
-use super::{get_clause_from_command, to_option, RuleArgs};
-use crate::ast::*;
-use std::collections::HashSet;
+use super::{to_option, RuleArgs};
+use crate::{
+    ast::*,
+    checker::{clause::Clause, Strictness},
+};
+use std::{cell::RefCell, collections::HashSet};
+
+pub fn resolution(args: RuleArgs) -> Option<()> {
+    resolution_impl(args, false)
+}
+
+/// The "th_resolution" rule is used for resolution steps done by the theory reasoner, instead of
+/// the SAT solver. These steps may implicitly weaken the resolvent, so the conclusion is allowed to
+/// have literals that don't come from any premise.
+pub fn th_resolution(args: RuleArgs) -> Option<()> {
+    resolution_impl(args, true)
+}
+
+/// The sets used to check resolution steps. They are kept between steps, so checking the long
+/// resolution chains found in some proofs doesn't allocate once the sets are large enough.
+#[derive(Default)]
+struct ResolutionBuffers {
+    /// For each literal of the conclusion, whether it was already found in a premise.
+    working_clause: Vec<bool>,
+    pivots: HashSet<Literal>,
+}
+
+thread_local! {
+    static RESOLUTION_BUFFERS: RefCell<ResolutionBuffers> = RefCell::default();
+}
 
-pub fn resolution(
+fn resolution_impl(
     RuleArgs {
         conclusion,
         premises,
+        strictness,
+        clause_cache,
         ..
     }: RuleArgs,
+    allow_weakening: bool,
 ) -> Option<()> {
     // When checking this rule, we must look at what the conclusion clause looks like in order to
     // determine the pivots. The reason for that is because there is no other way to know which
@@ -21,105 +51,116 @@ pub fn resolution(
     // Without looking at the conclusion, it is unclear if the (not p) term should be removed by
     // the p term, if the (not (not p)) should be removed by the (not (not (not p))), or both. We
     // can only determine this by looking at the conlcusion and using it to derive the pivots.
-    let conclusion: HashSet<_> = conclusion
-        .iter()
-        .map(|t| t.remove_all_negations())
-        .map(|(n, t)| (n as i32, t))
-        .collect();
-
-    // The working clause contains the terms from the conclusion clause that we already encountered
-    let mut working_clause = HashSet::new();
-
-    // The pivots are the encountered terms that are not present in the conclusion clause, and so
-    // should be removed
-    let mut pivots = HashSet::new();
-
-    for command in premises {
-        let premise_clause = get_clause_from_command(command);
-        for term in premise_clause {
-            let (n, inner) = term.remove_all_negations();
-            let n = n as i32;
-
-            // There are two possible negations of a term, with one leading negation added, or with
-            // one leading negation removed (if the term had any in the first place)
-            let below = (n - 1, inner);
-            let above = (n + 1, inner);
-
-            // First, if the encountered term should be in the conclusion, but is not yet in the
-            // working clause, we insert it and don't try to remove it with a pivot
-            if conclusion.contains(&(n, inner)) && !working_clause.contains(&(n, inner)) {
-                working_clause.insert((n, inner));
-                continue;
-            }
+    let conclusion = Clause::new(conclusion);
+
+    RESOLUTION_BUFFERS.with(|buffers| {
+        let ResolutionBuffers {
+            working_clause,
+            pivots,
+        } = &mut *buffers.borrow_mut();
+        working_clause.clear();
+        working_clause.resize(conclusion.len(), false);
+        pivots.clear();
+
+        // The working clause marks the terms from the conclusion clause that we already
+        // encountered, and the pivots are the encountered terms that are not present in the
+        // conclusion clause, and so should be removed
+        let mut num_found = 0;
+        for premise in &premises {
+            for &literal in clause_cache.get(premise).terms() {
+                // First, if the encountered term should be in the conclusion, but is not yet in
+                // the working clause, we insert it and don't try to remove it with a pivot
+                let position = conclusion.position(&literal);
+                if let Some(i) = position {
+                    if !std::mem::replace(&mut working_clause[i], true) {
+                        num_found += 1;
+                        continue;
+                    }
+                }
 
-            // If the negation of the encountered term is present in the pivots set, we simply
-            // remove it. Otherwise, we insert the encountered term in the working clause or the
-            // pivots set, depending on wether it is present in the conclusion clause or not
-            let removed = n > 0 && pivots.remove(&below) || pivots.remove(&above);
-            if !removed {
-                if conclusion.contains(&(n, inner)) {
-                    working_clause.insert((n, inner));
-                } else {
-                    pivots.insert((n, inner));
+                // There are two possible negations of a term, with one leading negation added, or
+                // with one leading negation removed (if the term had any in the first place). If
+                // the negation of the encountered term is present in the pivots set, we simply
+                // remove it. Otherwise, we insert the encountered term in the pivots set, unless
+                // it is already in the working clause
+                let removed = literal
+                    .remove_negation()
+                    .is_some_and(|below| pivots.remove(&below))
+                    || pivots.remove(&literal.negate());
+                if !removed && position.is_none() {
+                    pivots.insert(literal);
                 }
             }
         }
-    }
 
-    // In some cases, when the result of the resolution is just one term, it may appear in the
-    // conclusion clause with an even number of leading negations added to it. The following is an
-    // example of this, adapted from a generated proof:
-    //
-    //     (step t1 (cl (not e)) :rule irrelevant)
-    //     (step t2 (cl (= (not e) (not (not f)))) :rule irrelevant)
-    //     (step t3 (cl (not (= (not e) (not (not f)))) e f) :rule irrelevant)
-    //     (step t4 (cl (not (not f))) :rule resolution :premises (t1 t2 t3))
-    //
-    // Usually, we would expect the clause in the t4 step to be (cl f).
-    if pivots.len() == 1 && conclusion.len() == 1 {
-        let (i, pivot) = pivots.into_iter().next().unwrap();
-        let (j, conclusion) = conclusion.into_iter().next().unwrap();
-        return to_option(conclusion == pivot && (i % 2) == (j % 2));
-    }
+        // In some cases, when the result of the resolution is just one term, it may appear in the
+        // conclusion clause with an even number of leading negations added to it. The following
+        // is an example of this, adapted from a generated proof:
+        //
+        //     (step t1 (cl (not e)) :rule irrelevant)
+        //     (step t2 (cl (= (not e) (not (not f)))) :rule irrelevant)
+        //     (step t3 (cl (not (= (not e) (not (not f)))) e f) :rule irrelevant)
+        //     (step t4 (cl (not (not f))) :rule resolution :premises (t1 t2 t3))
+        //
+        // Usually, we would expect the clause in the t4 step to be (cl f). This is not allowed
+        // when checking strictly.
+        if strictness != Strictness::Strict && pivots.len() == 1 && conclusion.len() == 1 {
+            let pivot = pivots.iter().next().unwrap();
+            let conclusion = conclusion.iter().next().unwrap();
+            return to_option(
+                conclusion.atom() == pivot.atom() && conclusion.polarity() == pivot.polarity(),
+            );
+        }
 
-    // At the end, we expect all pivots to have been removed, and the working clause to be equal to
-    // the conclusion clause
-    to_option(pivots.is_empty() && working_clause == conclusion)
+        // At the end, we expect all pivots to have been removed, and the working clause to be
+        // equal to the conclusion clause, that is, every term in the conclusion must have been
+        // found. If weakening is allowed, the conclusion may have terms that were not found
+        to_option(pivots.is_empty() && (allow_weakening || num_found == conclusion.len()))
+    })
 }
 
+/// The "tautology" rule either replaces a premise that contains complementary literals by "true",
+/// or concludes, without premises, a clause that contains complementary literals.
 pub fn tautology(
     RuleArgs {
         conclusion,
         premises,
+        clause_cache,
         ..
     }: RuleArgs,
 ) -> Option<()> {
-    rassert!(conclusion.len() == 1 && conclusion[0].is_bool_true() && premises.len() == 1);
-
-    let premise = get_clause_from_command(premises[0]);
-    let mut seen = HashSet::with_capacity(premise.len());
-    let with_negations_removed = premise
-        .iter()
-        .map(|t| t.remove_all_negations_with_polarity());
-    for (polarity, term) in with_negations_removed {
-        if seen.contains(&(!polarity, term)) {
-            return Some(());
-        }
-        seen.insert((polarity, term));
+    match premises.as_slice() {
+        [] => to_option(Clause::new(conclusion).has_complementary_literals()),
+        [premise] => to_option(
+            conclusion.len() == 1
+                && conclusion[0].is_bool_true()
+                && clause_cache.get(premise).has_complementary_literals(),
+        ),
+        _ => None,
     }
-    None
 }
 
 pub fn contraction(
     RuleArgs {
         conclusion,
         premises,
+        strictness,
+        clause_cache,
         ..
     }: RuleArgs,
 ) -> Option<()> {
     rassert!(premises.len() == 1);
 
-    let premise_clause = get_clause_from_command(premises[0]);
+    let premise_clause = premises[0].clause;
+
+    // When checking permissively, the order of the conclusion doesn't matter, so we only need to
+    // check that it has the same terms as the premise, without repetitions
+    if strictness == Strictness::Permissive {
+        let conclusion = Clause::new(conclusion);
+        return to_option(
+            !conclusion.had_repetitions() && *clause_cache.get(&premises[0]) == conclusion,
+        );
+    }
 
     // This set will be populated with the terms we enconter as we iterate through the premise
     let mut encountered = HashSet::<&Term>::with_capacity(premise_clause.len());
@@ -141,6 +182,30 @@ pub fn contraction(
     to_option(conclusion_iter.next().is_none())
 }
 
+pub fn weakening(
+    RuleArgs {
+        conclusion,
+        premises,
+        strictness,
+        clause_cache,
+        ..
+    }: RuleArgs,
+) -> Option<()> {
+    rassert!(premises.len() == 1);
+    let premise_clause = premises[0].clause;
+
+    // The new literals are added at the end of the clause. This is only required when checking
+    // strictly, otherwise the premise literals may appear anywhere in the conclusion
+    if strictness == Strictness::Strict {
+        return to_option(conclusion.get(..premise_clause.len()) == Some(premise_clause));
+    }
+    to_option(
+        clause_cache
+            .get(&premises[0])
+            .is_subset(&Clause::new(conclusion)),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -222,6 +287,46 @@ mod tests {
         }
     }
 
+    #[test]
+    fn th_resolution() {
+        test_cases! {
+            definitions = "
+                (declare-fun p () Bool)
+                (declare-fun q () Bool)
+                (declare-fun r () Bool)
+                (declare-fun s () Bool)
+            ",
+            "Simple working examples" {
+                "(assume h1 (not p))
+                (step t2 (cl p q) :rule trust_me)
+                (step t3 (cl q) :rule th_resolution :premises (h1 t2))": true,
+
+                "(assume h1 (not p))
+                (assume h2 q)
+                (step t3 (cl p (not q)) :rule trust_me)
+                (step t4 (cl) :rule th_resolution :premises (h1 h2 t3))": true,
+            }
+            "Conclusion may be weakened" {
+                "(assume h1 (not p))
+                (step t2 (cl p q r) :rule trust_me)
+                (step t3 (cl q r s) :rule th_resolution :premises (h1 t2))": true,
+
+                "(assume h1 (not p))
+                (step t2 (cl p q) :rule trust_me)
+                (step t3 (cl s) :rule th_resolution :premises (h1 t2))": false,
+            }
+            "Pivots must still be removed" {
+                "(assume h1 (not p))
+                (step t2 (cl p q r) :rule trust_me)
+                (step t3 (cl p q r) :rule th_resolution :premises (h1 t2))": false,
+
+                "(assume h1 (not p))
+                (step t2 (cl q r) :rule trust_me)
+                (step t3 (cl q r) :rule th_resolution :premises (h1 t2))": false,
+            }
+        }
+    }
+
     #[test]
     fn tautology() {
         test_cases! {
@@ -255,6 +360,18 @@ mod tests {
                 "(step t1 (cl p (not (not s)) q r s) :rule trust_me)
                 (step t2 (cl true) :rule tautology :premises (t1))": false,
             }
+            "Conclusion is a tautology" {
+                "(step t1 (cl p q (not q)) :rule tautology)": true,
+                "(step t1 (cl (not (not s)) r (not (not (not s)))) :rule tautology)": true,
+                "(step t1 (cl p q) :rule tautology)": false,
+                "(step t1 (cl p (not (not p))) :rule tautology)": false,
+                "(step t1 (cl true) :rule tautology)": false,
+            }
+            "More than one premise" {
+                "(step t1 (cl p (not p)) :rule trust_me)
+                (step t2 (cl q (not q)) :rule trust_me)
+                (step t3 (cl true) :rule tautology :premises (t1 t2))": false,
+            }
         }
     }
 
@@ -308,5 +425,151 @@ mod tests {
                 (step t2 (cl p q r s) :rule contraction :premises (t1))": false,
             }
         }
+        test_cases! {
+            definitions = "
+                (declare-fun p () Bool)
+                (declare-fun q () Bool)
+                (declare-fun r () Bool)
+            ",
+            strictness = crate::checker::Strictness::Permissive,
+            "Conclusion order doesn't matter when checking permissively" {
+                "(step t1 (cl p q q r) :rule trust_me)
+                (step t2 (cl r p q) :rule contraction :premises (t1))": true,
+
+                "(step t1 (cl p q q r) :rule trust_me)
+                (step t2 (cl r p q q) :rule contraction :premises (t1))": false,
+
+                "(step t1 (cl p q q r) :rule trust_me)
+                (step t2 (cl r p) :rule contraction :premises (t1))": false,
+            }
+        }
+    }
+
+    #[test]
+    fn weakening() {
+        test_cases! {
+            definitions = "
+                (declare-fun p () Bool)
+                (declare-fun q () Bool)
+                (declare-fun r () Bool)
+            ",
+            "Simple working examples" {
+                "(step t1 (cl p q) :rule trust_me)
+                (step t2 (cl p q r) :rule weakening :premises (t1))": true,
+
+                "(step t1 (cl p q) :rule trust_me)
+                (step t2 (cl p q) :rule weakening :premises (t1))": true,
+
+                "(step t1 (cl) :rule trust_me)
+                (step t2 (cl p) :rule weakening :premises (t1))": true,
+
+                "(step t1 (cl p q) :rule trust_me)
+                (step t2 (cl r q p) :rule weakening :premises (t1))": true,
+            }
+            "Premise literal missing from conclusion" {
+                "(step t1 (cl p q) :rule trust_me)
+                (step t2 (cl p r) :rule weakening :premises (t1))": false,
+
+                "(step t1 (cl p (not q)) :rule trust_me)
+                (step t2 (cl p (not (not q))) :rule weakening :premises (t1))": false,
+            }
+        }
+        test_cases! {
+            definitions = "
+                (declare-fun p () Bool)
+                (declare-fun q () Bool)
+                (declare-fun r () Bool)
+            ",
+            strictness = crate::checker::Strictness::Strict,
+            "New literals must be at the end when checking strictly" {
+                "(step t1 (cl p q) :rule trust_me)
+                (step t2 (cl p q r) :rule weakening :premises (t1))": true,
+
+                "(step t1 (cl p q) :rule trust_me)
+                (step t2 (cl r q p) :rule weakening :premises (t1))": false,
+            }
+        }
+    }
+
+    #[test]
+    fn large_clauses() {
+        // These are the clauses produced by encoding cardinality constraints, which have
+        // thousands of literals and are pushed through long resolution chains
+        const N: usize = 1000;
+        let vars: Vec<_> = (0..N).map(|i| format!("p{}", i)).collect();
+        let definitions: String = vars
+            .iter()
+            .map(|p| format!("(declare-fun {} () Bool)", p))
+            .collect();
+        let units: String = vars
+            .iter()
+            .enumerate()
+            .map(|(i, p)| format!("(assume h{} (not {}))", i, p))
+            .collect();
+        let all_units = (0..N).map(|i| format!("h{}", i)).collect::<Vec<_>>();
+        let clause = |range: std::ops::Range<usize>| vars[range].join(" ");
+
+        let mut chain = format!("{}(step t0 (cl {}) :rule trust_me)", units, clause(0..N));
+        for i in 1..=N {
+            chain += &format!(
+                "(step t{} (cl {}) :rule resolution :premises (t{} h{}))",
+                i,
+                clause(i..N),
+                i - 1,
+                i - 1,
+            );
+        }
+        let cases = [
+            (
+                format!(
+                    "{}(step t0 (cl {}) :rule trust_me)
+                    (step t1 (cl) :rule resolution :premises ({} t0))",
+                    units,
+                    clause(0..N),
+                    all_units.join(" "),
+                ),
+                true,
+            ),
+            (
+                format!(
+                    "{}(step t0 (cl {}) :rule trust_me)
+                    (step t1 (cl p0) :rule resolution :premises ({} t0))",
+                    units,
+                    clause(0..N),
+                    all_units.join(" "),
+                ),
+                false,
+            ),
+            (
+                format!(
+                    "(step t1 (cl {}) :rule trust_me)
+                    (step t2 (cl (not p0) {}) :rule trust_me)
+                    (step t3 (cl {}) :rule resolution :premises (t1 t2))",
+                    clause(0..N),
+                    clause(1..N),
+                    clause(1..N),
+                ),
+                true,
+            ),
+            (
+                format!(
+                    "(step t1 (cl {}) :rule trust_me)
+                    (step t2 (cl (not p0) {}) :rule trust_me)
+                    (step t3 (cl {}) :rule resolution :premises (t1 t2))",
+                    clause(0..N),
+                    clause(1..N),
+                    clause(2..N),
+                ),
+                false,
+            ),
+            (chain, true),
+        ];
+        let cases: Vec<_> = cases.iter().map(|(p, e)| (p.as_str(), *e)).collect();
+        crate::checker::rules::run_tests(
+            "Large clauses",
+            &definitions,
+            &cases,
+            crate::checker::Strictness::Default,
+        );
     }
 }
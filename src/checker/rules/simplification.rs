@@ -1,8 +1,11 @@
-use super::{to_option, RuleArgs};
-use crate::{ast::*, utils::DedupIterator};
+use super::{get_premise_term, to_option, RuleArgs};
+use crate::{
+    ast::*,
+    utils::{euclidean_div_mod, DedupIterator},
+};
 use num_rational::BigRational;
-use num_traits::{One, Zero};
-use std::collections::HashSet;
+use num_traits::{One, Signed, Zero};
+use std::collections::{HashMap, HashSet};
 
 /// A macro to define the possible transformations for a "simplify" rule.
 macro_rules! simplify {
@@ -88,7 +91,7 @@ pub fn or_simplify(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
     rassert!(conclusion.len() == 1);
 
     let (clause, result) = match_term!((= (or ...) psi) = conclusion[0], RETURN_RCS)?;
-    let result = match_term!((or ...) = result).unwrap_or_else(|| std::slice::from_ref(&result));
+    let result = match_term!((or ...) = result).unwrap_or_else(|| std::slice::from_ref(result));
 
     let mut seen = HashSet::with_capacity(clause.len());
     let mut expected = Vec::with_capacity(clause.len());
@@ -289,6 +292,66 @@ pub fn prod_simplify(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
     to_option(u_constant == constant_total && u_args.iter().map(ByRefRc::as_ref).eq(result))
 }
 
+/// Splits a term "(/ t1 t2)", "(div t1 t2)" or "(mod t1 t2)" into its operator, dividend and
+/// divisor.
+fn match_division(term: &Term) -> Option<(Operator, &Term, &Term)> {
+    match term {
+        Term::Op(op @ (Operator::Div | Operator::IntDiv | Operator::Mod), args)
+            if args.len() == 2 =>
+        {
+            Some((*op, args[0].as_ref(), args[1].as_ref()))
+        }
+        _ => None,
+    }
+}
+
+pub fn div_simplify(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
+    rassert!(conclusion.len() == 1);
+
+    let (division, result) = match_term!((= d u) = conclusion[0].as_ref())?;
+    let (op, t_1, t_2) = match_division(division).filter(|(op, _, _)| *op != Operator::Mod)?;
+    let divisor = t_2.try_as_ratio();
+    let result_value = result.try_as_ratio();
+    if t_1 == t_2 {
+        // Like in veriT, this assumes that the divisor is not zero, unless it is the constant zero
+        rassert!(!divisor.is_some_and(|d| d.is_zero()));
+        to_option(result_value.is_some_and(|r| r.is_one()))
+    } else if divisor.as_ref().is_some_and(One::is_one) {
+        to_option(result == t_1)
+    } else {
+        let (n, d) = (t_1.try_as_ratio()?, divisor?);
+        let expected = match op {
+            Operator::Div => {
+                rassert!(!d.is_zero());
+                n / d
+            }
+            _ => {
+                let (q, _) = euclidean_div_mod(&n.to_integer(), &d.to_integer())?;
+                BigRational::from_integer(q)
+            }
+        };
+        to_option(result_value == Some(expected))
+    }
+}
+
+pub fn mod_simplify(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
+    rassert!(conclusion.len() == 1);
+
+    let (modulo, result) = match_term!((= m u) = conclusion[0].as_ref())?;
+    let (_, t_1, t_2) = match_division(modulo).filter(|(op, _, _)| *op == Operator::Mod)?;
+    let divisor = t_2.try_as_ratio();
+    let result_value = result.try_as_ratio();
+    if t_1 == t_2 || divisor.as_ref().is_some_and(|d| d.abs().is_one()) {
+        // Like in veriT, this assumes that the divisor is not zero, unless it is the constant zero
+        rassert!(!divisor.is_some_and(|d| d.is_zero()));
+        to_option(result_value.is_some_and(|r| r.is_zero()))
+    } else {
+        let (n, d) = (t_1.try_as_ratio()?, divisor?);
+        let (_, r) = euclidean_div_mod(&n.to_integer(), &d.to_integer())?;
+        to_option(result_value == Some(BigRational::from_integer(r)))
+    }
+}
+
 pub fn ac_simp(
     RuleArgs {
         conclusion, pool, ..
@@ -344,6 +407,121 @@ pub fn ac_simp(
     to_option(flatten_operation(original, pool) == *flattened)
 }
 
+/// The "bfun_elim" rule, used by veriT to eliminate functions with Boolean arguments. The
+/// conclusion is the premise after two transformations. First, every quantified variable of sort
+/// Bool is expanded: a quantifier over "x" becomes the conjunction (or disjunction, for "exists")
+/// of its body with "x" replaced by "false" and by "true". Then, every application of a function to
+/// a Boolean argument "t" that is not a constant is replaced by "(ite t f(..true..) f(..false..))".
+pub fn bfun_elim(
+    RuleArgs {
+        conclusion,
+        premises,
+        pool,
+        ..
+    }: RuleArgs,
+) -> Option<()> {
+    rassert!(premises.len() == 1 && conclusion.len() == 1);
+
+    let premise = get_premise_term(&premises[0])?;
+    let mut cache = HashMap::new();
+    to_option(bfun_elim_term(pool, &mut cache, premise) == conclusion[0])
+}
+
+fn bfun_elim_term(
+    pool: &mut TermPool,
+    cache: &mut HashMap<ByRefRc<Term>, ByRefRc<Term>>,
+    term: &ByRefRc<Term>,
+) -> ByRefRc<Term> {
+    if let Some(result) = cache.get(term) {
+        return result.clone();
+    }
+    let result = match term.as_ref() {
+        Term::Quant(quant, bindings, inner) => {
+            let (bool_bindings, other_bindings): (Vec<_>, Vec<_>) = bindings
+                .iter()
+                .cloned()
+                .partition(|(_, sort)| sort.as_ref() == Term::BOOL_SORT);
+
+            // The instances for the first variable come before those for the second, and so on,
+            // with the "false" instance of each variable before its "true" instance
+            let mut instances = vec![inner.clone()];
+            for (name, sort) in bool_bindings.iter() {
+                let var = pool.add_term(terminal!(var name; sort.clone()));
+                let values = [pool.bool_false(), pool.bool_true()];
+                instances = values
+                    .iter()
+                    .flat_map(|value| {
+                        let substitution = Substitution::single(var.clone(), value.clone());
+                        instances
+                            .iter()
+                            .map(|t| substitution.apply(pool, t))
+                            .collect::<Vec<_>>()
+                    })
+                    .collect();
+            }
+            let inner = match instances.as_slice() {
+                [inner] => inner.clone(),
+                _ => {
+                    let op = match quant {
+                        Quantifier::Forall => Operator::And,
+                        Quantifier::Exists => Operator::Or,
+                    };
+                    pool.add_term(Term::Op(op, instances))
+                }
+            };
+            let inner = bfun_elim_term(pool, cache, &inner);
+            if other_bindings.is_empty() {
+                inner
+            } else {
+                pool.add_term(Term::Quant(*quant, other_bindings, inner))
+            }
+        }
+        Term::App(func, args) => {
+            let func = bfun_elim_term(pool, cache, func);
+            let args: Vec<_> = args
+                .iter()
+                .map(|a| bfun_elim_term(pool, cache, a))
+                .collect();
+            expand_bool_arguments(pool, &func, args, 0)
+        }
+        Term::Op(op, args) => {
+            let args = args
+                .iter()
+                .map(|a| bfun_elim_term(pool, cache, a))
+                .collect();
+            pool.add_term(Term::Op(*op, args))
+        }
+        _ => term.clone(),
+    };
+    cache.insert(term.clone(), result.clone());
+    result
+}
+
+/// Replaces the application of `func` to `args` by a tree of "ite" terms that branches on the value
+/// of each non-constant Boolean argument from index `start` onwards, in order.
+fn expand_bool_arguments(
+    pool: &mut TermPool,
+    func: &ByRefRc<Term>,
+    mut args: Vec<ByRefRc<Term>>,
+    start: usize,
+) -> ByRefRc<Term> {
+    let index = (start..args.len()).find(|&i| {
+        let arg = &args[i];
+        pool.sort(arg).as_deref() == Some(Term::BOOL_SORT)
+            && !arg.is_bool_true()
+            && !arg.is_bool_false()
+    });
+    let index = match index {
+        Some(i) => i,
+        None => return pool.add_term(Term::App(func.clone(), args)),
+    };
+    let condition = std::mem::replace(&mut args[index], pool.bool_true());
+    let if_true = expand_bool_arguments(pool, func, args.clone(), index + 1);
+    args[index] = pool.bool_false();
+    let if_false = expand_bool_arguments(pool, func, args, index + 1);
+    build_term!(pool, (ite {condition} {if_true} {if_false}))
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -681,4 +859,110 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn div_simplify() {
+        test_cases! {
+            definitions = "
+                (declare-fun i () Int)
+                (declare-fun x () Real)
+            ",
+            "Division by itself" {
+                "(step t1 (cl (= (div i i) 1)) :rule div_simplify)": true,
+                "(step t1 (cl (= (/ x x) 1.0)) :rule div_simplify)": true,
+                "(step t1 (cl (= (/ x x) x)) :rule div_simplify)": false,
+                "(step t1 (cl (= (div 0 0) 1)) :rule div_simplify)": false,
+            }
+            "Division by one" {
+                "(step t1 (cl (= (div i 1) i)) :rule div_simplify)": true,
+                "(step t1 (cl (= (/ x 1.0) x)) :rule div_simplify)": true,
+                "(step t1 (cl (= (div i 1) 1)) :rule div_simplify)": false,
+            }
+            "Constant division" {
+                "(step t1 (cl (= (/ 3.0 2.0) 1.5)) :rule div_simplify)": true,
+                "(step t1 (cl (= (div 7 2) 3)) :rule div_simplify)": true,
+                "(step t1 (cl (= (div (- 7) 2) (- 4))) :rule div_simplify)": true,
+                "(step t1 (cl (= (div 7 (- 2)) (- 3))) :rule div_simplify)": true,
+                "(step t1 (cl (= (div (- 7) (- 2)) 4)) :rule div_simplify)": true,
+
+                "(step t1 (cl (= (div (- 7) 2) (- 3))) :rule div_simplify)": false,
+                "(step t1 (cl (= (div 7 0) 0)) :rule div_simplify)": false,
+                "(step t1 (cl (= (/ 1.0 0.0) 0.0)) :rule div_simplify)": false,
+                "(step t1 (cl (= (mod 7 2) 3)) :rule div_simplify)": false,
+            }
+        }
+    }
+
+    #[test]
+    fn mod_simplify() {
+        test_cases! {
+            definitions = "(declare-fun i () Int)",
+            "Trivial divisors" {
+                "(step t1 (cl (= (mod i i) 0)) :rule mod_simplify)": true,
+                "(step t1 (cl (= (mod i 1) 0)) :rule mod_simplify)": true,
+                "(step t1 (cl (= (mod i (- 1)) 0)) :rule mod_simplify)": true,
+                "(step t1 (cl (= (mod i 1) i)) :rule mod_simplify)": false,
+                "(step t1 (cl (= (mod 0 0) 0)) :rule mod_simplify)": false,
+            }
+            "Constant modulo" {
+                "(step t1 (cl (= (mod 7 2) 1)) :rule mod_simplify)": true,
+                "(step t1 (cl (= (mod (- 7) 2) 1)) :rule mod_simplify)": true,
+                "(step t1 (cl (= (mod (- 7) (- 2)) 1)) :rule mod_simplify)": true,
+                "(step t1 (cl (= (mod 6 3) 0)) :rule mod_simplify)": true,
+
+                "(step t1 (cl (= (mod (- 7) 2) (- 1))) :rule mod_simplify)": false,
+                "(step t1 (cl (= (mod 7 0) 7)) :rule mod_simplify)": false,
+                "(step t1 (cl (= (div 7 2) 1)) :rule mod_simplify)": false,
+            }
+        }
+    }
+
+    #[test]
+    fn bfun_elim() {
+        test_cases! {
+            definitions = "
+                (declare-sort T 0)
+                (declare-fun p () Bool)
+                (declare-fun q () Bool)
+                (declare-fun a () T)
+                (declare-fun f (Bool) T)
+                (declare-fun g (Bool T Bool) Bool)
+            ",
+            "Quantified Boolean variables" {
+                "(assume h1 (forall ((x Bool)) (= (f x) a)))
+                (step t2 (cl (and (= (f false) a) (= (f true) a))) :rule bfun_elim :premises (h1))": true,
+
+                "(assume h1 (exists ((x Bool) (y Bool)) (g x a y)))
+                (step t2 (cl (or (g false a false) (g true a false) (g false a true) (g true a true)))
+                    :rule bfun_elim :premises (h1))": true,
+
+                "(assume h1 (forall ((x Bool) (y T)) (= (f x) y)))
+                (step t2 (cl (forall ((y T)) (and (= (f false) y) (= (f true) y))))
+                    :rule bfun_elim :premises (h1))": true,
+
+                "(assume h1 (forall ((x Bool)) (= (f x) a)))
+                (step t2 (cl (and (= (f true) a) (= (f false) a))) :rule bfun_elim :premises (h1))": false,
+            }
+            "Boolean arguments" {
+                "(assume h1 (= (f p) a))
+                (step t2 (cl (= (ite p (f true) (f false)) a)) :rule bfun_elim :premises (h1))": true,
+
+                "(assume h1 (g p a (not q)))
+                (step t2 (cl (ite p
+                    (ite (not q) (g true a true) (g true a false))
+                    (ite (not q) (g false a true) (g false a false))))
+                    :rule bfun_elim :premises (h1))": true,
+
+                "(assume h1 (g true a q))
+                (step t2 (cl (ite q (g true a true) (g true a false)))
+                    :rule bfun_elim :premises (h1))": true,
+
+                "(assume h1 (= (f p) a))
+                (step t2 (cl (= (ite p (f false) (f true)) a)) :rule bfun_elim :premises (h1))": false,
+
+                "(assume h1 (= (f p) a))
+                (step t2 (cl (= (f p) a)) :rule bfun_elim :premises (h1))": false,
+            }
+        }
+    }
 }
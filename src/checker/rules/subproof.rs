@@ -1,6 +1,6 @@
-use super::{get_single_term_from_command, to_option, RuleArgs};
+use super::{get_premise_term, get_single_term_from_command, to_option, RuleArgs};
 use crate::ast::*;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 
 pub fn subproof(
     RuleArgs {
@@ -10,16 +10,20 @@ pub fn subproof(
     }: RuleArgs,
 ) -> Option<()> {
     // TODO: We should get the series of assumptions from the ":discharge" attribute, but currently
-    // we just take the first `conclusion.len() - 1` steps in the subproof.
-    let assumptions = &subproof_commands?[..conclusion.len() - 1];
+    // we take every "assume" command in the subproof, in order. Assumptions don't need to come
+    // before the steps of the subproof, since some solvers interleave them
+    let assumptions: Vec<_> = subproof_commands?
+        .iter()
+        .filter_map(|c| match c {
+            ProofCommand::Assume { term, .. } => Some(term),
+            _ => None,
+        })
+        .collect();
 
-    rassert!(conclusion.len() == assumptions.len() + 1); // Currently, this is always true
+    rassert!(conclusion.len() == assumptions.len() + 1);
 
     for (assumption, term) in assumptions.iter().zip(conclusion) {
-        match assumption {
-            ProofCommand::Assume(t) => rassert!(t.as_ref() == term.remove_negation()?),
-            _ => return None,
-        };
+        rassert!(assumption.as_ref() == term.remove_negation()?);
     }
 
     let previous_command = &subproof_commands?[subproof_commands?.len() - 2];
@@ -107,11 +111,8 @@ pub fn r#let(
     // in it
     let substitutions = &context.last()?.substitutions;
 
-    let (let_term, u_prime) = match_term!((= l u) = conclusion[0], RETURN_RCS)?;
-    let (let_bindigns, u) = match let_term.as_ref() {
-        Term::Let(b, t) => (b, t),
-        _ => return None,
-    };
+    let ((let_bindigns, u), u_prime) =
+        match_term!((= (let ... u) u_prime) = conclusion[0], RETURN_RCS)?;
 
     // The u and u' in the conclusion must match the u and u' in the previous command in the
     // subproof
@@ -124,11 +125,11 @@ pub fn r#let(
 
     let mut premises = premises.iter();
     for (x, t) in let_bindigns {
-        let x_term = terminal!(var x; pool.add_term(t.sort().clone()));
+        let x_term = terminal!(var x; pool.sort(t)?);
         let s = substitutions.get(&pool.add_term(x_term))?;
         if s != t {
             let premise = premises.next()?;
-            let premise_equality = match_term!((= a b) = get_single_term_from_command(premise)?)?;
+            let premise_equality = match_term!((= a b) = get_premise_term(premise)?)?;
             rassert!(premise_equality == (s, t) || premise_equality == (t, s));
         }
     }
@@ -210,17 +211,11 @@ pub fn onepoint(
         .iter()
         .map(|var| pool.add_term(var.clone().into()))
         .collect();
-    let substitution_vars: HashSet<_> = context
-        .substitutions
-        .iter()
-        .map(|(k, _)| k.clone())
-        .collect();
+    let substitution_vars: HashSet<_> = context.substitutions.domain().cloned().collect();
 
     let points = extract_points(quant, left);
 
-    // We clone the substitutions so we don't pollute the hash map when applying the substitutions
-    // to `points`
-    let mut substitutions_clone = context.substitutions_until_fixed_point.clone();
+    let substitutions = &context.substitutions_until_fixed_point;
 
     // Since a substitution may use a varibale introduced in a previous substitution, we apply the
     // substitutions to the points in order to these variables. We also create a duplicate of every
@@ -228,8 +223,8 @@ pub fn onepoint(
     let points: HashSet<_> = points
         .into_iter()
         .flat_map(|(x, t)| {
-            let new_t = pool.apply_substitutions(&t, &mut substitutions_clone);
-            let new_x = pool.apply_substitutions(&x, &mut substitutions_clone);
+            let new_t = substitutions.apply(pool, &t);
+            let new_x = substitutions.apply(pool, &x);
             [(x, new_t), (t, new_x)]
         })
         .collect();
@@ -265,48 +260,42 @@ fn generic_skolemization_rule(
     let previous_equality = match_term!((= p q) = previous_term)?;
     rassert!(previous_equality == (phi, psi));
 
-    let mut current_phi = phi.clone();
-    // I have to extract the length into a separate variable (instead of just using it directly in
-    // the slice index) to please the borrow checker
-    let n = context.len();
-    for c in context[..n - 1].iter_mut() {
-        // Based on the test examples, we must first apply all previous context substitutions to
-        // phi, before applying the substitutions present in the current context
-        current_phi = pool.apply_substitutions(&current_phi, &mut c.substitutions);
-    }
+    // Based on the test examples, we must first apply all previous context substitutions to phi,
+    // before applying the substitutions present in the current context
+    let mut current_phi = context.apply_enclosing(pool, phi);
 
     let substitutions = &context.last()?.substitutions_until_fixed_point;
     for (i, x) in bindings.iter().enumerate() {
         let x_term = pool.add_term(Term::from(x.clone()));
         let t = substitutions.get(&x_term)?;
-        let (t_choice_var, t_bindings, t_inner) = match t.as_ref() {
-            Term::Choice(var, inner) => {
-                // If the rule is "sko_forall", the predicate in the choice term is negated
-                let inner = if rule_type == Quantifier::Forall {
-                    match_term!((not t) = inner, RETURN_RCS)?
-                } else {
-                    inner
-                };
-                // If this is the last binding, all bindigns were skolemized, so we don't need to
-                // unwrap any quantifier
-                if i == bindings.len() - 1 {
-                    (var, &[] as &[_], inner)
-                } else {
-                    let (q, b, t) = inner.unwrap_quant()?;
-                    rassert!(q == rule_type);
-                    (var, b.as_slice(), t)
-                }
-            }
-            _ => return None,
+        // If the rule is "sko_forall", the predicate in the choice term is negated
+        let (t_choice_var, inner) = match rule_type {
+            Quantifier::Forall => match_term!((choice ... (not t)) = t, RETURN_RCS)?,
+            Quantifier::Exists => match_term!((choice ... t) = t, RETURN_RCS)?,
+        };
+
+        // If this is the last binding, all bindigns were skolemized, so we don't need to unwrap
+        // any quantifier
+        let (t_bindings, t_inner) = if i == bindings.len() - 1 {
+            (&[] as &[_], inner)
+        } else {
+            let (q, b, t) = inner.unwrap_quant()?;
+            rassert!(q == rule_type);
+            (b.as_slice(), t)
         };
         rassert!(t_choice_var == x);
         rassert!(t_bindings == &bindings[i + 1..]);
-        rassert!(DeepEq::eq_modulo_reordering(t_inner, &current_phi));
+
+        // Like in "forall_inst", equalities may be flipped in the predicate of the choice term,
+        // but it must otherwise be the same as phi
+        rassert!(DeepEq::eq_with_policy(
+            t_inner,
+            &current_phi,
+            DeepEqPolicy::FLIP_EQUALITIES
+        ));
 
         // For every binding we skolemize, we must apply another substitution to phi
-        let mut s = HashMap::new();
-        s.insert(x_term, t.clone());
-        current_phi = pool.apply_substitutions(&current_phi, &mut s);
+        current_phi = Substitution::single(x_term, t.clone()).apply(pool, &current_phi);
     }
     Some(())
 }
@@ -344,6 +333,33 @@ mod tests {
                 (step t1.t3 (cl (= r s)) :rule trust_me)
                 (step t1 (cl (not p) (not q) (= r s)) :rule subproof)": true,
             }
+            "Steps using premises outside the subproof" {
+                "(assume h1 (and q s))
+                (anchor :step t2)
+                (assume t2.h1 p)
+                (step t2.t3 (cl q) :rule and :premises (h1))
+                (step t2 (cl (not p) q) :rule subproof)": true,
+
+                "(assume h1 (and q s))
+                (anchor :step t2)
+                (assume t2.h1 p)
+                (step t2.t3 (cl p) :rule and :premises (h1))
+                (step t2 (cl (not p) p) :rule subproof)": false,
+            }
+            "Assumptions after steps" {
+                "(anchor :step t1)
+                (step t1.t1 (cl (= r r)) :rule trust_me)
+                (assume t1.h1 p)
+                (step t1.t2 (cl (= r s)) :rule trust_me)
+                (assume t1.h2 q)
+                (step t1.t3 (cl (= r s)) :rule trust_me)
+                (step t1 (cl (not p) (not q) (= r s)) :rule subproof)": true,
+
+                "(anchor :step t1)
+                (step t1.t1 (cl (= r s)) :rule trust_me)
+                (assume t1.h1 p)
+                (step t1 (cl (not p) (= r s)) :rule subproof)": false,
+            }
             "Missing assumption" {
                 "(anchor :step t1)
                 (assume t1.h1 p)
@@ -597,6 +613,24 @@ mod tests {
                        (choice ((y Int)) (= (choice ((x Int)) (exists ((y Int)) (= x y))) y)))
                 )) :rule sko_ex)": true,
             }
+            "Only equalities may be flipped" {
+                "(anchor :step t1 :args ((:= (x Int) (choice ((x Int)) (= 0 x)))))
+                (step t1.t1 (cl (= (= x 0) (= (choice ((x Int)) (= 0 x)) 0))) :rule trust_me)
+                (step t1 (cl (= (exists ((x Int)) (= x 0)) (= (choice ((x Int)) (= 0 x)) 0)))
+                    :rule sko_ex)": true,
+
+                "(anchor :step t1 :args ((:= (x Int) (choice ((x Int)) (and (q x) (p x))))))
+                (step t1.t1 (cl (=
+                    (and (p x) (q x))
+                    (and (p (choice ((x Int)) (and (q x) (p x))))
+                         (q (choice ((x Int)) (and (q x) (p x)))))
+                )) :rule trust_me)
+                (step t1 (cl (=
+                    (exists ((x Int)) (and (p x) (q x)))
+                    (and (p (choice ((x Int)) (and (q x) (p x))))
+                         (q (choice ((x Int)) (and (q x) (p x)))))
+                )) :rule sko_ex)": false,
+            }
         }
     }
 
@@ -632,6 +666,19 @@ mod tests {
                             (not (= (choice ((x Int)) (not (forall ((y Int)) (= x y)))) y))))
                 )) :rule sko_forall)": true,
             }
+            "Only equalities may be flipped" {
+                "(anchor :step t1 :args ((:= (x Int) (choice ((x Int)) (not (or (q x) (p x)))))))
+                (step t1.t1 (cl (=
+                    (or (p x) (q x))
+                    (or (p (choice ((x Int)) (not (or (q x) (p x)))))
+                        (q (choice ((x Int)) (not (or (q x) (p x))))))
+                )) :rule trust_me)
+                (step t1 (cl (=
+                    (forall ((x Int)) (or (p x) (q x)))
+                    (or (p (choice ((x Int)) (not (or (q x) (p x)))))
+                        (q (choice ((x Int)) (not (or (q x) (p x))))))
+                )) :rule sko_forall)": false,
+            }
         }
     }
 }
@@ -1,4 +1,4 @@
-use super::{get_single_term_from_command, to_option, RuleArgs};
+use super::{get_index_arg, get_premise_term, to_option, RuleArgs};
 use crate::ast::*;
 
 pub fn r#true(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
@@ -17,14 +17,18 @@ pub fn not_not(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
     to_option(p == q)
 }
 
-pub fn and_pos(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
+pub fn and_pos(
+    RuleArgs {
+        conclusion, args, ..
+    }: RuleArgs,
+) -> Option<()> {
     rassert!(conclusion.len() == 2);
 
     let and_contents = match_term!((not (and ...)) = conclusion[0])?;
-    and_contents
-        .iter()
-        .find(|&t| *t == conclusion[1])
-        .map(|_| ())
+    match get_index_arg(args)? {
+        Some(i) => to_option(*and_contents.get(i)? == conclusion[1]),
+        None => to_option(and_contents.iter().any(|t| *t == conclusion[1])),
+    }
 }
 
 pub fn and_neg(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
@@ -44,15 +48,19 @@ pub fn or_pos(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
     to_option(or_contents.iter().eq(&conclusion[1..]))
 }
 
-pub fn or_neg(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
+pub fn or_neg(
+    RuleArgs {
+        conclusion, args, ..
+    }: RuleArgs,
+) -> Option<()> {
     rassert!(conclusion.len() >= 2);
 
     let or_contents = match_term!((or ...) = conclusion[0])?;
     let other = conclusion[1].remove_negation()?;
-    or_contents
-        .iter()
-        .find(|&t| t.as_ref() == other)
-        .map(|_| ())
+    match get_index_arg(args)? {
+        Some(i) => to_option(or_contents.get(i)?.as_ref() == other),
+        None => to_option(or_contents.iter().any(|t| t.as_ref() == other)),
+    }
 }
 
 pub fn equiv_pos1(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
@@ -78,7 +86,7 @@ pub fn ite1(
 ) -> Option<()> {
     rassert!(premises.len() == 1 && conclusion.len() == 2);
 
-    let premise_term = get_single_term_from_command(premises[0])?;
+    let premise_term = get_premise_term(&premises[0])?;
     let (phi_1, _, phi_3) = match_term!((ite phi_1 phi_2 phi_3) = premise_term)?;
 
     to_option(phi_1 == conclusion[0].as_ref() && phi_3 == conclusion[1].as_ref())
@@ -93,20 +101,24 @@ pub fn ite2(
 ) -> Option<()> {
     rassert!(premises.len() == 1 && conclusion.len() == 2);
 
-    let premise_term = get_single_term_from_command(premises[0])?;
+    let premise_term = get_premise_term(&premises[0])?;
     let (phi_1, phi_2, _) = match_term!((ite phi_1 phi_2 phi_3) = premise_term)?;
 
     to_option(phi_1 == conclusion[0].remove_negation()? && phi_2 == conclusion[1].as_ref())
 }
 
-pub fn ite_intro(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
+pub fn ite_intro(
+    RuleArgs {
+        conclusion, pool, ..
+    }: RuleArgs,
+) -> Option<()> {
     rassert!(conclusion.len() == 1);
 
-    let (root_term, right_side) = match_term!((= t u) = conclusion[0])?;
+    let (root_term, right_side) = match_term!((= t u) = conclusion[0], RETURN_RCS)?;
 
     // In some cases, no "ite" subterm is extracted from "t" (even if "t" has "ite" subterms), so
-    // the conjunction in the right side of the equality has only one term: "t" itself, modulo
-    // reordering of equalities. One example where this happens is the test file
+    // the right side of the equality is just "t" itself. One example where this happens is the
+    // test file
     // SH_problems_all_filtered/isabelle-mirabelle/HOL-Library/smt_verit/x2020_07_23_15_09_29_511_18566192.smt_in.proof
     // Step "t7" in that proof is:
     //     (step t7 (cl (=
@@ -115,47 +127,42 @@ pub fn ite_intro(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
     //         (= (times$ c$ (ite (< (g$ n$) 0.0) (- (g$ n$)) (g$ n$)))
     //            (times$ (ite (< (g$ n$) 0.0) (- (g$ n$)) (g$ n$)) c$))
     //     )) :rule ite_intro)
-    // For cases like this, we first check if "t" equals the right side term modulo reordering of
-    // equalities. If not, we unwrap the conjunction and continue checking the rule normally.
-    if DeepEq::eq_modulo_reordering(root_term, right_side) {
+    // For cases like this, we first check if "t" is the right side term. If not, we unwrap the
+    // conjunction and continue checking the rule normally.
+    if root_term == right_side {
         return Some(());
     }
     let us = match_term!((and ...) = right_side)?;
 
-    // "us" must be a conjunction where the first term is the root term
-    rassert!(DeepEq::eq_modulo_reordering(us[0].as_ref(), root_term));
+    // "us" must be a conjunction where the first term is the root term. The equalities in it may
+    // be flipped, but no other differences are allowed
+    rassert!(DeepEq::eq_with_policy(
+        us[0].as_ref(),
+        root_term.as_ref(),
+        DeepEqPolicy::FLIP_EQUALITIES
+    ));
 
     let us = &us[1..];
 
-    let subterms = root_term.subterms();
-    let mut ite_subterms = subterms.filter_map(|term| match_term!((ite a b c) = term));
-
-    // We assume that the "ite" terms appear in the conjunction in the same order as they
-    // appear as subterms of the root term
-    'outer: for u_i in &us[1..] {
-        let (cond, (a, b), (c, d)) = match_term!((ite cond (= a b) (= c d)) = u_i)?;
-
-        // For every term in "us", we find the next "ite" subterm that matches the expected form.
-        // This is because some "ite" subterms may be skipped, and may not have a corresponding "u"
-        // term
-        while let Some(s_i) = ite_subterms.next() {
-            // Since the (= r_1 s_1) and (= r_2 s_2) equalities may be flipped, we have to check
-            // all four possibilities: neither are flipped, either one is flipped, or both are
-            // flipped
-            let is_valid = |r_1, s_1, r_2, s_2: &Term| {
-                // s_i == s_1 == s_2 == (ite cond r_1 r_2)
-                s_1 == s_2 && (cond, r_1, r_2) == s_i && match_term!((ite a b c) = s_1) == Some(s_i)
-            };
-            let is_valid = is_valid(a, b, c, d)
+    for u_i in us {
+        let (cond, (a, b), (c, d)) = match_term!((ite cond (= a b) (= c d)) = u_i, RETURN_RCS)?;
+
+        // Since the (= r_1 s_1) and (= r_2 s_2) equalities may be flipped, we have to check all
+        // four possibilities: neither are flipped, either one is flipped, or both are flipped. The
+        // "ite" terms may appear in the conjunction in any order, so we only check that each one
+        // occurs somewhere in the root term
+        let mut is_valid = |r_1, s_1: &ByRefRc<Term>, r_2, s_2: &ByRefRc<Term>| {
+            // s_1 == s_2 == (ite cond r_1 r_2), and it is a subterm of the root term
+            s_1 == s_2
+                && match_term!((ite a b c) = s_1, RETURN_RCS) == Some((cond, r_1, r_2))
+                && pool.contains_subterm(root_term, s_1)
+        };
+        rassert!(
+            is_valid(a, b, c, d)
                 || is_valid(b, a, c, d)
                 || is_valid(a, b, d, c)
-                || is_valid(b, a, d, c);
-
-            if is_valid {
-                continue 'outer;
-            }
-        }
-        return None;
+                || is_valid(b, a, d, c)
+        );
     }
     Some(())
 }
@@ -276,6 +283,11 @@ mod tests {
                 "(step t1 (cl (not (and p q r)) s) :rule and_pos)": false,
                 "(step t1 (cl (not (and p (not q) r)) q) :rule and_pos)": false,
             }
+            "Index of conjunct given as argument" {
+                "(step t1 (cl (not (and p q r)) q) :rule and_pos :args (1))": true,
+                "(step t1 (cl (not (and p q r)) q) :rule and_pos :args (0))": false,
+                "(step t1 (cl (not (and p q r)) q) :rule and_pos :args (3))": false,
+            }
         }
     }
 
@@ -358,6 +370,10 @@ mod tests {
                 "(step t1 (cl (or p (not q) r) (not q)) :rule or_neg)": false,
 
             }
+            "Index of disjunct given as argument" {
+                "(step t1 (cl (or p q r) (not r)) :rule or_neg :args (2))": true,
+                "(step t1 (cl (or p q r) (not r)) :rule or_neg :args (0))": false,
+            }
         }
     }
 
@@ -507,6 +523,19 @@ mod tests {
                     (and (not (ite p a b)) (ite p (= a (ite p a b)) (= b (ite p a b))))
                 )) :rule ite_intro)": true,
             }
+            "Only equalities may be flipped in the first term of the conjunction" {
+                "(step t1 (cl (=
+                    (= a (ite p a b))
+                    (and (= (ite p a b) a) (ite p (= a (ite p a b)) (= b (ite p a b))))
+                )) :rule ite_intro)": true,
+
+                "(step t1 (cl (=
+                    (and c (ite p a b))
+                    (and (and (ite p a b) c) (ite p (= a (ite p a b)) (= b (ite p a b))))
+                )) :rule ite_intro)": false,
+
+                "(step t1 (cl (= (= a b) (= b a))) :rule ite_intro)": false,
+            }
             "Multiple \"ite\" subterms" {
                 "(step t1 (cl (=
                     (or (ite p a b) (ite q c d))
@@ -529,6 +558,25 @@ mod tests {
                     )
                 )) :rule ite_intro)": true,
             }
+            "\"ite\" terms in a different order" {
+                "(step t1 (cl (=
+                    (or (ite p a b) (ite q c d))
+                    (and
+                        (or (ite p a b) (ite q c d))
+                        (ite q (= c (ite q c d)) (= d (ite q c d)))
+                        (ite p (= a (ite p a b)) (= b (ite p a b)))
+                    )
+                )) :rule ite_intro)": true,
+
+                "(step t1 (cl (=
+                    (or (ite p a b) (ite q c d))
+                    (and
+                        (or (ite p a b) (ite q c d))
+                        (ite q (= c (ite q c d)) (= d (ite q c d)))
+                        (ite p (= a (ite p a c)) (= c (ite p a c)))
+                    )
+                )) :rule ite_intro)": false,
+            }
             "Clause term is not an equality" {
                 "(step t1 (cl) :rule ite_intro)": false,
                 "(step t1 (cl (not (= p q))) :rule ite_intro)": false,
@@ -1,9 +1,15 @@
-use super::{get_single_term_from_command, RuleArgs};
-use crate::ast::*;
+use super::{congruence_closure::equality_follows_from, get_premise_term, to_option, RuleArgs};
+use crate::{ast::*, checker::Strictness};
 
 /// Function to find a transitive chain given a conclusion equality and a series of premise
-/// equalities.
-fn find_chain(conclusion: (&Term, &Term), premises: &mut [(&Term, &Term)]) -> Option<()> {
+/// equalities. The premises may be used in any order. If `allow_flipping` is false, each premise
+/// equality can only be used in the orientation in which it is given, that is, `(= a b)` can link
+/// `a` to `b`, but not `b` to `a`.
+fn find_chain(
+    conclusion: (&Term, &Term),
+    premises: &mut [(&Term, &Term)],
+    allow_flipping: bool,
+) -> Option<()> {
     // When the conclusion is of the form (= a a), it is trivially valid
     if conclusion.0 == conclusion.1 {
         return Some(());
@@ -15,7 +21,7 @@ fn find_chain(conclusion: (&Term, &Term), premises: &mut [(&Term, &Term)]) -> Op
     let (index, eq) = premises.iter().enumerate().find_map(|(i, &(t, u))| {
         if t == conclusion.0 {
             Some((i, (t, u)))
-        } else if u == conclusion.0 {
+        } else if allow_flipping && u == conclusion.0 {
             Some((i, (u, t)))
         } else {
             None
@@ -29,10 +35,16 @@ fn find_chain(conclusion: (&Term, &Term), premises: &mut [(&Term, &Term)]) -> Op
     // The new conclusion will be the terms in the conclusion and the found equality that didn't
     // match. For example, if the conclusion was (= a d) and we found in the premises (= a b), the
     // new conclusion will be (= b d)
-    find_chain((eq.1, conclusion.1), &mut premises[1..])
+    find_chain((eq.1, conclusion.1), &mut premises[1..], allow_flipping)
 }
 
-pub fn eq_transitive(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
+pub fn eq_transitive(
+    RuleArgs {
+        conclusion,
+        strictness,
+        ..
+    }: RuleArgs,
+) -> Option<()> {
     if conclusion.len() < 3 {
         return None;
     }
@@ -49,13 +61,21 @@ pub fn eq_transitive(RuleArgs { conclusion, .. }: RuleArgs) -> Option<()> {
         premises.push((t, u));
     }
 
-    find_chain(chain_conclusion, &mut premises)
+    if strictness == Strictness::Permissive {
+        return to_option(equality_follows_from(premises, chain_conclusion));
+    }
+    find_chain(
+        chain_conclusion,
+        &mut premises,
+        strictness != Strictness::Strict,
+    )
 }
 
 pub fn trans(
     RuleArgs {
         conclusion,
         premises,
+        strictness,
         ..
     }: RuleArgs,
 ) -> Option<()> {
@@ -66,13 +86,16 @@ pub fn trans(
     let conclusion = match_term!((= t u) = conclusion[0])?;
     let mut premises: Vec<_> = premises
         .into_iter()
-        .map(|command| {
-            let term = get_single_term_from_command(command)?;
+        .map(|premise| {
+            let term = get_premise_term(&premise)?;
             match_term!((= t u) = term)
         })
         .collect::<Option<_>>()?;
 
-    find_chain(conclusion, &mut premises)
+    if strictness == Strictness::Permissive {
+        return to_option(equality_follows_from(premises, conclusion));
+    }
+    find_chain(conclusion, &mut premises, strictness != Strictness::Strict)
 }
 
 #[cfg(test)]
@@ -177,5 +200,50 @@ mod tests {
                 (step t3 (cl (= a c) (= c a)) :rule trans :premises (h1 h2))": false,
             }
         }
+        test_cases! {
+            definitions = "
+                (declare-sort T 0)
+                (declare-fun a () T)
+                (declare-fun b () T)
+                (declare-fun c () T)
+            ",
+            strictness = crate::checker::Strictness::Strict,
+            "Equalities can't be flipped when checking strictly" {
+                "(assume h1 (= a b)) (assume h2 (= b c))
+                (step t3 (cl (= a c)) :rule trans :premises (h1 h2))": true,
+
+                "(assume h1 (= b a)) (assume h2 (= b c))
+                (step t3 (cl (= a c)) :rule trans :premises (h1 h2))": false,
+
+                "(assume h1 (= a b)) (assume h2 (= c b))
+                (step t3 (cl (= a c)) :rule trans :premises (h1 h2))": false,
+            }
+        }
+        test_cases! {
+            definitions = "
+                (declare-sort T 0)
+                (declare-fun a () T)
+                (declare-fun b () T)
+                (declare-fun c () T)
+                (declare-fun f (T) T)
+            ",
+            strictness = crate::checker::Strictness::Permissive,
+            "Equalities follow by congruence closure when checking permissively" {
+                "(assume h1 (= a b)) (assume h2 (= c b))
+                (step t3 (cl (= c a)) :rule trans :premises (h1 h2))": true,
+
+                "(assume h1 (= a b)) (assume h2 (= (f b) c))
+                (step t3 (cl (= (f a) c)) :rule trans :premises (h1 h2))": true,
+
+                "(assume h1 (= a b)) (assume h2 (= b c)) (assume h3 (= a c))
+                (step t4 (cl (= a c)) :rule trans :premises (h1 h2 h3))": true,
+
+                "(assume h1 (= a b)) (assume h2 (= (f b) c))
+                (step t3 (cl (= a c)) :rule trans :premises (h1 h2))": false,
+
+                "(assume h1 (= a b)) (assume h2 (not (= b c)))
+                (step t3 (cl (= a c)) :rule trans :premises (h1 h2))": false,
+            }
+        }
     }
 }
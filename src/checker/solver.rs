@@ -0,0 +1,389 @@
+use super::rules::Premise;
+use crate::ast::*;
+use num_traits::Signed;
+use std::{
+    collections::HashSet,
+    fmt::{self, Write as _},
+    io::{self, Read, Write},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The configuration of an external SMT solver, used to check steps whose rules are not
+/// implemented.
+#[derive(Debug, Clone)]
+pub struct SolverConfig {
+    /// The solver executable, followed by its arguments. The solver must read an SMT-LIB script
+    /// from its standard input, and print the result of the "check-sat" command. For example,
+    /// `["z3", "-in"]` or `["cvc5", "--lang", "smt2"]`.
+    pub command: Vec<String>,
+
+    /// How long to wait for the solver. If it doesn't finish in time, it is killed and the step is
+    /// considered invalid.
+    pub timeout: Duration,
+}
+
+/// A standalone SMT-LIB problem for a step that failed to check, or that was skipped. The problem
+/// asserts the premises of the step and the negation of its conclusion, so it is unsatisfiable if
+/// and only if the step is semantically valid, even if its rule doesn't accept it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Obligation {
+    /// The id of the step.
+    pub id: String,
+
+    /// The SMT-LIB script.
+    pub query: String,
+}
+
+/// Builds an SMT-LIB script that is unsatisfiable if and only if the `premises` imply the
+/// `conclusion`. Each premise and the conclusion are clauses, that is, disjunctions of their
+/// literals. The assertion of each premise is preceded by a comment citing its id. Returns `None`
+/// if the terms can't be expressed in SMT-LIB, for example because they contain "choice" terms or
+/// inferred sorts.
+pub(super) fn build_query(premises: &[Premise], conclusion: &[ByRefRc<Term>]) -> Option<String> {
+    let mut declarations = Declarations::default();
+    let clauses = premises.iter().map(|p| p.clause);
+    for clause in clauses.chain(std::iter::once(conclusion)) {
+        for literal in clause.iter() {
+            declarations.collect(literal, &mut Vec::new())?;
+        }
+    }
+
+    let mut query = String::new();
+    for (name, arity) in &declarations.sorts {
+        writeln!(query, "(declare-sort {} {})", name, arity).unwrap();
+    }
+    for (name, sort) in &declarations.functions {
+        write_declare_fun(&mut query, name, sort);
+    }
+    for premise in premises {
+        match premise.rule {
+            Some(rule) => writeln!(query, "; premise {}, by rule {}", premise.id, rule),
+            None => writeln!(query, "; premise {}, assumed", premise.id),
+        }
+        .unwrap();
+        writeln!(query, "(assert {})", SmtLibClause(premise.clause)).unwrap();
+    }
+    writeln!(query, "(assert (not {}))", SmtLibClause(conclusion)).unwrap();
+    writeln!(query, "(check-sat)").unwrap();
+    Some(query)
+}
+
+/// Writes a "declare-fun" command that declares `name` with the sort `sort`, which is either a
+/// function sort or the sort of a constant.
+pub(crate) fn write_declare_fun(out: &mut String, name: &str, sort: &Term) {
+    let (ret, args) = match sort {
+        Term::Sort(SortKind::Function, sorts) => {
+            let (ret, args) = sorts.split_last().unwrap();
+            (ret.as_ref(), args)
+        }
+        other => (other, &[] as &[_]),
+    };
+    let args: Vec<_> = args.iter().map(|s| SmtLib(s).to_string()).collect();
+    writeln!(
+        out,
+        "(declare-fun {} ({}) {})",
+        name,
+        args.join(" "),
+        SmtLib(ret)
+    )
+    .unwrap();
+}
+
+/// Runs the solver on `query`, and returns `true` if it answers "unsat" in time.
+pub(super) fn run_solver(config: &SolverConfig, query: &str) -> io::Result<bool> {
+    let (program, args) = config
+        .command
+        .split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty solver command"))?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    // The output is read on a separate thread while we wait for the solver. Otherwise, a solver
+    // that writes more than the pipe buffer can hold would block until the timeout
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).map(|_| output)
+    });
+
+    // The standard input is closed when it is dropped, so the solver sees the end of the script
+    let mut stdin = child.stdin.take().unwrap();
+    let written = stdin.write_all(query.as_bytes());
+    drop(stdin);
+
+    let start = Instant::now();
+    while child.try_wait()?.is_none() {
+        if start.elapsed() > config.timeout {
+            child.kill()?;
+            child.wait()?;
+            let _ = reader.join();
+            return Ok(false);
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    let output = reader.join().expect("solver output thread panicked")?;
+
+    // The solver may exit before reading the whole query, which is not an error in itself
+    if let Err(e) = written {
+        if e.kind() != io::ErrorKind::BrokenPipe {
+            return Err(e);
+        }
+    }
+    Ok(output.lines().next().map(str::trim) == Some("unsat"))
+}
+
+/// The uninterpreted sorts and free symbols that must be declared in a query.
+#[derive(Default)]
+struct Declarations {
+    sorts: Vec<(String, usize)>,
+    functions: Vec<(String, ByRefRc<Term>)>,
+    seen_functions: HashSet<String>,
+}
+
+impl Declarations {
+    /// Collects the declarations needed by `term`. `bound` holds the variables bound by the
+    /// binders that enclose `term`.
+    fn collect(&mut self, term: &Term, bound: &mut Vec<String>) -> Option<()> {
+        match term {
+            Term::Terminal(Terminal::Var(Identifier::Simple(name), sort)) => {
+                let name = name.as_str();
+                let is_builtin =
+                    sort.as_ref() == Term::BOOL_SORT && (name == "true" || name == "false");
+                if !is_builtin
+                    && !bound.iter().any(|b| b == name)
+                    && self.seen_functions.insert(name.to_owned())
+                {
+                    self.collect_sort(sort)?;
+                    self.functions.push((name.to_owned(), sort.clone()));
+                }
+            }
+            Term::Terminal(Terminal::Var(Identifier::Indexed(..), _)) => return None,
+            Term::Terminal(_) => (),
+            Term::App(f, args) => {
+                self.collect(f, bound)?;
+                for a in args {
+                    self.collect(a, bound)?;
+                }
+            }
+            Term::Op(_, args) => {
+                for a in args {
+                    self.collect(a, bound)?;
+                }
+            }
+            Term::Quant(_, bindings, inner) | Term::Let(bindings, inner) => {
+                let is_let = matches!(term, Term::Let(..));
+                for (_, value) in bindings {
+                    if is_let {
+                        self.collect(value, bound)?;
+                    } else {
+                        self.collect_sort(value)?;
+                    }
+                }
+                let previous_len = bound.len();
+                bound.extend(bindings.iter().map(|(name, _)| name.clone()));
+                self.collect(inner, bound)?;
+                bound.truncate(previous_len);
+            }
+            Term::Sort(..) | Term::Choice(..) => return None,
+        }
+        Some(())
+    }
+
+    fn collect_sort(&mut self, sort: &Term) -> Option<()> {
+        match sort {
+            Term::Sort(SortKind::Atom, args) => {
+                let name = match args[0].as_ref() {
+                    Term::Terminal(Terminal::String(s)) => s,
+                    _ => return None,
+                };
+                // "Array" is a built-in sort, so it is not declared
+                let is_builtin = name == "Array" && args.len() == 3;
+                if !is_builtin && !self.sorts.iter().any(|(s, _)| s == name) {
+                    self.sorts.push((name.clone(), args.len() - 1));
+                }
+                for a in &args[1..] {
+                    self.collect_sort(a)?;
+                }
+            }
+            Term::Sort(SortKind::Function, args) => {
+                for a in args {
+                    self.collect_sort(a)?;
+                }
+            }
+            Term::Sort(SortKind::Inferred, _) => return None,
+            _ => (),
+        }
+        Some(())
+    }
+}
+
+/// Formats a term in SMT-LIB syntax. Unlike the `Debug` implementation of `Term`, this prints
+/// every non-integer real number as a division, like "(/ 1.0 8.0)", and prints the parameters of
+/// parametric sorts.
+pub(crate) struct SmtLib<'a>(pub(crate) &'a Term);
+
+impl fmt::Display for SmtLib<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let write_args =
+            |f: &mut fmt::Formatter, head: &dyn fmt::Display, args: &[ByRefRc<Term>]| {
+                write!(f, "({}", head)?;
+                for a in args {
+                    write!(f, " {}", SmtLib(a))?;
+                }
+                write!(f, ")")
+            };
+        let write_bindings = |f: &mut fmt::Formatter, bindings: &[SortedVar]| {
+            write!(f, "(")?;
+            for (i, (name, value)) in bindings.iter().enumerate() {
+                if i != 0 {
+                    write!(f, " ")?;
+                }
+                write!(f, "({} {})", name, SmtLib(value))?;
+            }
+            write!(f, ")")
+        };
+        match self.0 {
+            Term::Terminal(Terminal::Real(r)) if r.is_negative() => {
+                let r = Term::Terminal(Terminal::Real(-r));
+                write!(f, "(- {})", SmtLib(&r))
+            }
+            Term::Terminal(Terminal::Real(r)) if r.is_integer() => write!(f, "{}.0", r.numer()),
+            Term::Terminal(Terminal::Real(r)) => write!(f, "(/ {}.0 {}.0)", r.numer(), r.denom()),
+            Term::Terminal(t) => write!(f, "{:?}", t),
+            Term::App(func, args) => write_args(f, &SmtLib(func), args),
+            Term::Op(Operator::Divisible, args) => {
+                let head = format_args!("(_ divisible {})", SmtLib(&args[0]));
+                write_args(f, &head, &args[1..])
+            }
+            Term::Op(op, args) => write_args(f, &format_args!("{:?}", op), args),
+            Term::Sort(SortKind::Atom, args) if args.len() > 1 => match args[0].as_ref() {
+                Term::Terminal(Terminal::String(name)) => write_args(f, name, &args[1..]),
+                other => write!(f, "{:?}", other),
+            },
+            Term::Quant(quantifier, bindings, inner) => {
+                let quantifier = match quantifier {
+                    Quantifier::Forall => "forall",
+                    Quantifier::Exists => "exists",
+                };
+                write!(f, "({} ", quantifier)?;
+                write_bindings(f, bindings)?;
+                write!(f, " {})", SmtLib(inner))
+            }
+            Term::Let(bindings, inner) => {
+                write!(f, "(let ")?;
+                write_bindings(f, bindings)?;
+                write!(f, " {})", SmtLib(inner))
+            }
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+/// Formats a clause in SMT-LIB syntax, as the disjunction of its literals.
+struct SmtLibClause<'a>(&'a [ByRefRc<Term>]);
+
+impl fmt::Display for SmtLibClause<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            [] => write!(f, "false"),
+            [literal] => write!(f, "{}", SmtLib(literal)),
+            literals => {
+                write!(f, "(or")?;
+                for l in literals {
+                    write!(f, " {}", SmtLib(l))?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        checker::{Config, Correctness, ProofChecker},
+        parser::parse_proof_with_definitions,
+    };
+
+    #[test]
+    fn solver_query() {
+        let definitions = "
+            (declare-sort U 0)
+            (declare-fun f (U) Real)
+            (declare-fun a () U)
+            (declare-fun p () Bool)
+        ";
+        let proof = "
+            (assume h1 (< (f a) 0.5))
+            (step t2 (cl p (forall ((x U)) (< (f x) 1.5))) :rule unknown_rule :premises (h1))
+        ";
+        let (proof, _) = parse_proof_with_definitions(definitions, proof).unwrap();
+        let t2 = match &proof.0[1] {
+            ProofCommand::Step(step) => step,
+            _ => unreachable!(),
+        };
+        let expected = "\
+            (declare-sort U 0)\n\
+            (declare-fun f (U) Real)\n\
+            (declare-fun a () U)\n\
+            (declare-fun p () Bool)\n\
+            ; premise h1, assumed\n\
+            (assert (< (f a) (/ 1.0 2.0)))\n\
+            (assert (not (or p (forall ((x U)) (< (f x) (/ 3.0 2.0))))))\n\
+            (check-sat)\n";
+        let query = build_query(&[Premise::new(&[&proof.0], (0, 0))], &t2.clause).unwrap();
+        assert_eq!(expected, query);
+    }
+
+    #[test]
+    fn solver_checked_steps() {
+        let proof = "
+            (assume h1 false)
+            (step t2 (cl) :rule unknown_rule :premises (h1))
+        ";
+        let check = |answer: &str| {
+            let (proof, mut pool) = parse_proof_with_definitions("", proof).unwrap();
+            let solver = SolverConfig {
+                command: vec![
+                    "sh".to_owned(),
+                    "-c".to_owned(),
+                    format!("cat > /dev/null; echo {}", answer),
+                ],
+                timeout: Duration::from_secs(10),
+            };
+            let config = Config {
+                external_solver: Some(solver),
+                ..Config::default()
+            };
+            let mut checker = ProofChecker::new(&mut pool, config);
+            let result = checker.check(&proof).unwrap();
+            (result, checker.solver_checked_steps().to_vec())
+        };
+        assert!(matches!(check("unsat"), (Correctness::TrueModulo(_), ids) if ids == ["t2"]));
+        assert!(matches!(check("sat"), (Correctness::False(_), ids) if ids.is_empty()));
+    }
+
+    #[test]
+    fn solver_large_output() {
+        // The solver writes much more than a pipe buffer holds before exiting, so its output must
+        // be read while waiting for it, or it would only finish at the timeout
+        let solver = SolverConfig {
+            command: vec![
+                "sh".to_owned(),
+                "-c".to_owned(),
+                "cat > /dev/null; echo unsat; head -c 1000000 /dev/zero".to_owned(),
+            ],
+            timeout: Duration::from_secs(10),
+        };
+        let start = Instant::now();
+        assert!(run_solver(&solver, "(check-sat)").unwrap());
+        assert!(start.elapsed() < solver.timeout);
+    }
+}
@@ -0,0 +1,130 @@
+use super::{StepOutcome, StepTrace};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    time::Duration,
+};
+
+/// The time spent checking the steps of a single rule.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuleStats {
+    /// How many steps of this rule were checked.
+    pub count: usize,
+    pub total_time: Duration,
+
+    /// The longest time spent checking a single step of this rule.
+    pub max_time: Duration,
+}
+
+/// A step that was slow to check, as reported by `Stats::slowest_steps`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SlowStep {
+    pub duration: Duration,
+    pub id: String,
+    pub rule: String,
+}
+
+/// Statistics about the time spent checking each rule, and the slowest steps. These are collected
+/// if `Config::stats` is set. Skipped steps are not counted.
+#[derive(Debug, Default)]
+pub struct Stats {
+    rules: HashMap<String, RuleStats>,
+
+    /// The slowest steps seen so far, as a min-heap, so the fastest of them can be replaced when a
+    /// slower step is recorded.
+    slowest_steps: BinaryHeap<Reverse<SlowStep>>,
+    max_steps: usize,
+}
+
+impl Stats {
+    /// Creates an empty `Stats` that keeps the `max_steps` slowest steps.
+    pub fn new(max_steps: usize) -> Self {
+        Self {
+            max_steps,
+            ..Self::default()
+        }
+    }
+
+    pub(super) fn record(&mut self, event: &StepTrace) {
+        if event.outcome == StepOutcome::Skipped {
+            return;
+        }
+        let entry = self.rules.entry(event.rule.to_owned()).or_default();
+        entry.count += 1;
+        entry.total_time += event.duration;
+        entry.max_time = entry.max_time.max(event.duration);
+
+        let is_slow = self.slowest_steps.len() < self.max_steps
+            || self
+                .slowest_steps
+                .peek()
+                .is_some_and(|Reverse(fastest)| fastest.duration < event.duration);
+        if is_slow {
+            self.slowest_steps.push(Reverse(SlowStep {
+                duration: event.duration,
+                id: event.id.to_owned(),
+                rule: event.rule.to_owned(),
+            }));
+            if self.slowest_steps.len() > self.max_steps {
+                self.slowest_steps.pop();
+            }
+        }
+    }
+
+    /// Returns the statistics of the rules that were checked, sorted by total time, slowest first.
+    /// Ties are broken by the rule name.
+    pub fn rules_by_time(&self) -> Vec<(&str, &RuleStats)> {
+        let mut result: Vec<_> = self.rules.iter().map(|(r, s)| (r.as_str(), s)).collect();
+        result.sort_by(|a, b| b.1.total_time.cmp(&a.1.total_time).then(a.0.cmp(b.0)));
+        result
+    }
+
+    /// Returns the slowest steps that were checked, slowest first.
+    pub fn slowest_steps(&self) -> Vec<&SlowStep> {
+        let mut result: Vec<_> = self.slowest_steps.iter().map(|Reverse(s)| s).collect();
+        result.sort_by(|a, b| b.cmp(a));
+        result
+    }
+
+    /// The total time spent checking steps.
+    pub fn total_time(&self) -> Duration {
+        self.rules.values().map(|s| s.total_time).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats() {
+        let mut stats = Stats::new(2);
+        let steps = [
+            ("t1", "resolution", 5, StepOutcome::Valid),
+            ("t2", "and", 1, StepOutcome::Valid),
+            ("t3", "resolution", 3, StepOutcome::Invalid),
+            ("t4", "la_generic", 4, StepOutcome::SolverChecked),
+            ("t5", "unknown", 10, StepOutcome::Skipped),
+        ];
+        for &(id, rule, millis, outcome) in &steps {
+            stats.record(&StepTrace {
+                id,
+                rule,
+                depth: 0,
+                duration: Duration::from_millis(millis),
+                outcome,
+            });
+        }
+
+        let rules: Vec<_> = stats.rules_by_time().into_iter().map(|(r, _)| r).collect();
+        assert_eq!(vec!["resolution", "la_generic", "and"], rules);
+        let resolution = &stats.rules_by_time()[0].1;
+        assert_eq!(2, resolution.count);
+        assert_eq!(Duration::from_millis(8), resolution.total_time);
+        assert_eq!(Duration::from_millis(5), resolution.max_time);
+
+        let slowest: Vec<_> = stats.slowest_steps().iter().map(|s| &s.id[..]).collect();
+        assert_eq!(vec!["t1", "t4"], slowest);
+        assert_eq!(Duration::from_millis(13), stats.total_time());
+    }
+}
@@ -0,0 +1,437 @@
+//! An evaluator for terms over booleans, integers and reals, that can optionally use the values
+//! given by a model.
+
+use crate::{ast::*, utils::euclidean_div_mod};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, Zero};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+/// The value of an evaluated term.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Bool(bool),
+    Int(BigInt),
+    Real(BigRational),
+    String(String),
+
+    /// An element of an uninterpreted sort, identified by the name of the constant declared in the
+    /// model that denotes it. Different names denote different elements.
+    Element(String),
+}
+
+impl Value {
+    /// Returns the value as a rational number, if it is an integer or real value.
+    fn as_number(&self) -> Option<BigRational> {
+        match self {
+            Value::Int(i) => Some(BigRational::from_integer(i.clone())),
+            Value::Real(r) => Some(r.clone()),
+            _ => None,
+        }
+    }
+
+    fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    /// Formats the value in SMT-LIB syntax.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(i) if *i < BigInt::zero() => write!(f, "(- {})", -i),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Real(r) => {
+                let sign = if *r < BigRational::zero() { "- " } else { "" };
+                let r = r.abs();
+                match (sign, r.is_integer()) {
+                    ("", true) => write!(f, "{}.0", r.numer()),
+                    ("", false) => write!(f, "(/ {} {})", r.numer(), r.denom()),
+                    (_, true) => write!(f, "(- {}.0)", r.numer()),
+                    (_, false) => write!(f, "(- (/ {} {}))", r.numer(), r.denom()),
+                }
+            }
+            Value::String(s) => write!(f, "\"{}\"", s),
+            Value::Element(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Evaluates terms to values. Constants and functions are given values by the definitions in a
+/// model, if there is one. Terms that can't be evaluated, like quantifiers or applications of
+/// functions that are not defined, evaluate to `None`.
+#[derive(Default)]
+pub struct Evaluator<'a> {
+    definitions: HashMap<&'a str, &'a FunctionDef>,
+    elements: HashSet<&'a str>,
+
+    /// The values of the variables bound by "let" terms and function parameters in scope, with the
+    /// innermost ones last.
+    bindings: Vec<(&'a str, Value)>,
+}
+
+impl<'a> Evaluator<'a> {
+    /// Constructs an evaluator that uses no model, and so can only evaluate ground terms.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Constructs an evaluator that gives constants and functions the values defined in `model`.
+    pub fn with_model(model: &'a Model) -> Self {
+        let definitions = model
+            .definitions
+            .iter()
+            .map(|(name, def)| (name.as_str(), def))
+            .collect();
+        let elements = model
+            .elements
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        Self {
+            definitions,
+            elements,
+            bindings: Vec::new(),
+        }
+    }
+
+    pub fn eval(&mut self, term: &'a Term) -> Option<Value> {
+        match term {
+            Term::Terminal(Terminal::Integer(i)) => Some(Value::Int(i.clone())),
+            Term::Terminal(Terminal::Real(r)) => Some(Value::Real(r.clone())),
+            Term::Terminal(Terminal::String(s)) => Some(Value::String(s.clone())),
+            Term::Terminal(Terminal::Var(Identifier::Simple(name), sort)) => {
+                self.eval_var(name.as_str(), sort)
+            }
+            Term::App(f, args) => {
+                let def = self.definitions.get(f.try_as_var()?).copied()?;
+                to_option(def.params.len() == args.len())?;
+                let args = args
+                    .iter()
+                    .map(|a| self.eval(a))
+                    .collect::<Option<Vec<_>>>()?;
+                let bindings = def.params.iter().map(|(p, _)| p.as_str()).zip(args);
+
+                // Function bodies only refer to the function parameters, so the bindings in scope
+                // at the application must be hidden while evaluating the body
+                let outer = std::mem::replace(&mut self.bindings, bindings.collect());
+                let result = self.eval(&def.body);
+                self.bindings = outer;
+                result
+            }
+            Term::Op(op, args) => self.eval_op(*op, args),
+            Term::Let(bindings, inner) => {
+                let values = bindings
+                    .iter()
+                    .map(|(name, value)| Some((name.as_str(), self.eval(value)?)))
+                    .collect::<Option<Vec<_>>>()?;
+                let previous_len = self.bindings.len();
+                self.bindings.extend(values);
+                let result = self.eval(inner);
+                self.bindings.truncate(previous_len);
+                result
+            }
+            _ => None,
+        }
+    }
+
+    fn eval_var(&mut self, name: &'a str, sort: &Term) -> Option<Value> {
+        if let Some((_, value)) = self.bindings.iter().rev().find(|(n, _)| *n == name) {
+            return Some(value.clone());
+        }
+        match name {
+            "true" if sort == Term::BOOL_SORT => return Some(Value::Bool(true)),
+            "false" if sort == Term::BOOL_SORT => return Some(Value::Bool(false)),
+            _ => (),
+        }
+        match self.definitions.get(name).copied() {
+            Some(def) if def.params.is_empty() => {
+                let outer = std::mem::take(&mut self.bindings);
+                let result = self.eval(&def.body);
+                self.bindings = outer;
+                result
+            }
+            Some(_) => None,
+            None if self.elements.contains(name) => Some(Value::Element(name.to_owned())),
+            None => None,
+        }
+    }
+
+    fn eval_op(&mut self, op: Operator, args: &'a [ByRefRc<Term>]) -> Option<Value> {
+        match op {
+            Operator::Not => {
+                to_option(args.len() == 1)?;
+                Some(Value::Bool(!self.eval_bool(&args[0])?))
+            }
+            // Conjunctions and disjunctions are evaluated lazily, so a term like (and false p) is
+            // false even if "p" can't be evaluated
+            Operator::And | Operator::Or => {
+                let short_circuit = op == Operator::Or;
+                let mut unknown = false;
+                for arg in args {
+                    match self.eval_bool(arg) {
+                        Some(b) if b == short_circuit => return Some(Value::Bool(short_circuit)),
+                        Some(_) => (),
+                        None => unknown = true,
+                    }
+                }
+                to_option(!unknown)?;
+                Some(Value::Bool(!short_circuit))
+            }
+            Operator::Implies => {
+                // "=>" is right associative, so (=> p q r) is (=> p (=> q r))
+                let (last, rest) = args.split_last()?;
+                let mut result = self.eval_bool(last)?;
+                for arg in rest.iter().rev() {
+                    result = !self.eval_bool(arg)? || result;
+                }
+                Some(Value::Bool(result))
+            }
+            Operator::Xor => {
+                let mut result = false;
+                for arg in args {
+                    result ^= self.eval_bool(arg)?;
+                }
+                Some(Value::Bool(result))
+            }
+            Operator::Ite => {
+                to_option(args.len() == 3)?;
+                if self.eval_bool(&args[0])? {
+                    self.eval(&args[1])
+                } else {
+                    self.eval(&args[2])
+                }
+            }
+            Operator::Equals | Operator::Distinct => {
+                let values = self.eval_all(args)?;
+                let equal = |a: &Value, b: &Value| match (a.as_number(), b.as_number()) {
+                    (Some(a), Some(b)) => a == b,
+                    _ => a == b,
+                };
+                let result = if op == Operator::Equals {
+                    values.windows(2).all(|w| equal(&w[0], &w[1]))
+                } else {
+                    values
+                        .iter()
+                        .enumerate()
+                        .all(|(i, a)| values[i + 1..].iter().all(|b| !equal(a, b)))
+                };
+                Some(Value::Bool(result))
+            }
+            Operator::LessThan | Operator::GreaterThan | Operator::LessEq | Operator::GreaterEq => {
+                let values = self.eval_numbers(args)?;
+                let compare = |a: &BigRational, b: &BigRational| match op {
+                    Operator::LessThan => a < b,
+                    Operator::GreaterThan => a > b,
+                    Operator::LessEq => a <= b,
+                    _ => a >= b,
+                };
+                let result = values.windows(2).all(|w| compare(&w[0], &w[1]));
+                Some(Value::Bool(result))
+            }
+            Operator::Add | Operator::Sub | Operator::Neg | Operator::Mult | Operator::Div => {
+                let is_int = op != Operator::Div;
+                let values = self.eval_all(args)?;
+                let is_int = is_int && values.iter().all(|v| matches!(v, Value::Int(_)));
+                let mut numbers = values.iter().map(Value::as_number);
+                let first = numbers.next()??;
+                let result = match op {
+                    Operator::Neg => -first,
+                    Operator::Add => numbers.try_fold(first, |acc, n| Some(acc + n?))?,
+                    Operator::Sub => numbers.try_fold(first, |acc, n| Some(acc - n?))?,
+                    Operator::Mult => numbers.try_fold(first, |acc, n| Some(acc * n?))?,
+                    _ => numbers.try_fold(first, |acc, n| {
+                        let n = n?;
+                        // Division by zero is left unspecified by SMT-LIB
+                        to_option(!n.is_zero())?;
+                        Some(acc / n)
+                    })?,
+                };
+                Some(if is_int && result.denom().is_one() {
+                    Value::Int(result.to_integer())
+                } else {
+                    Value::Real(result)
+                })
+            }
+            Operator::IntDiv | Operator::Mod => {
+                let values = self.eval_numbers(args)?;
+                let mut values = values
+                    .iter()
+                    .map(|v| v.is_integer().then(|| v.to_integer()));
+                let first = values.next()??;
+                let result = values.try_fold(first, |acc, n| {
+                    let (q, r) = euclidean_div_mod(&acc, &n?)?;
+                    Some(if op == Operator::IntDiv { q } else { r })
+                })?;
+                Some(Value::Int(result))
+            }
+            Operator::Abs => {
+                to_option(args.len() == 1)?;
+                Some(Value::Int(
+                    self.eval(&args[0])?.as_number()?.to_integer().abs(),
+                ))
+            }
+            Operator::ToReal | Operator::ToInt | Operator::IsInt => {
+                to_option(args.len() == 1)?;
+                let value = self.eval(&args[0])?.as_number()?;
+                Some(match op {
+                    Operator::ToReal => Value::Real(value),
+                    // "to_int" rounds towards negative infinity
+                    Operator::ToInt => Value::Int(value.floor().to_integer()),
+                    _ => Value::Bool(value.is_integer()),
+                })
+            }
+            Operator::Divisible => {
+                let (n, t) = match args {
+                    [n, t] => (n.try_as_ratio()?, self.eval(t)?.as_number()?),
+                    _ => return None,
+                };
+                to_option(!n.is_zero())?;
+                Some(Value::Bool((t / n).is_integer()))
+            }
+        }
+    }
+
+    fn eval_bool(&mut self, term: &'a Term) -> Option<bool> {
+        self.eval(term)?.as_bool()
+    }
+
+    fn eval_all(&mut self, terms: &'a [ByRefRc<Term>]) -> Option<Vec<Value>> {
+        terms.iter().map(|t| self.eval(t)).collect()
+    }
+
+    fn eval_numbers(&mut self, terms: &'a [ByRefRc<Term>]) -> Option<Vec<BigRational>> {
+        terms.iter().map(|t| self.eval(t)?.as_number()).collect()
+    }
+}
+
+/// Converts a `bool` into an `Option<()>`.
+#[must_use]
+fn to_option(b: bool) -> Option<()> {
+    match b {
+        true => Some(()),
+        false => None,
+    }
+}
+
+/// Evaluates a clause that contains no free variables or uninterpreted symbols. Returns
+/// `Some(true)` if one of its literals is true, `Some(false)` if all of them are false, and `None`
+/// if some literal can't be evaluated and none is true.
+pub fn eval_ground_clause(clause: &[ByRefRc<Term>]) -> Option<bool> {
+    let mut evaluator = Evaluator::new();
+    let mut unknown = false;
+    for literal in clause {
+        match evaluator.eval_bool(literal) {
+            Some(true) => return Some(true),
+            Some(false) => (),
+            None => unknown = true,
+        }
+    }
+    to_option(!unknown)?;
+    Some(false)
+}
+
+/// Evaluates the assertions of `problem` under `model`. The assertions evaluated are the ones
+/// active at the last "check-sat" command, or all assertions if there is none. Returns, for each
+/// evaluated assertion, its index in `problem.assertions` and its value, or `None` if it couldn't
+/// be evaluated.
+pub fn check_model(problem: &Problem, model: &Model) -> Vec<(usize, Option<bool>)> {
+    let indices = match problem.queries.last() {
+        Some(query) => query.clone(),
+        None => (0..problem.assertions.len()).collect(),
+    };
+    let mut evaluator = Evaluator::with_model(model);
+    indices
+        .into_iter()
+        .map(|i| (i, evaluator.eval_bool(&problem.assertions[i].1)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{self, parse_problem_model};
+
+    #[test]
+    fn eval_ground_terms() {
+        let definitions = "(declare-fun p () Bool)";
+        let cases = [
+            ("(and true (not false))", Some(Value::Bool(true))),
+            ("(=> true true false)", Some(Value::Bool(false))),
+            ("(xor true true true)", Some(Value::Bool(true))),
+            ("(or p true)", Some(Value::Bool(true))),
+            ("(and p true)", None),
+            ("(ite (< 1 2 3) (- 5) 7)", Some(Value::Int((-5).into()))),
+            ("(* 2 (+ 1 2) (- 4 1))", Some(Value::Int(18.into()))),
+            ("(= (/ 1.0 2.0) 0.5)", Some(Value::Bool(true))),
+            ("(distinct 1 2 1)", Some(Value::Bool(false))),
+            ("(/ 1.0 0.0)", None),
+            ("(to_int (- 1.5))", Some(Value::Int((-2).into()))),
+            ("(is_int (to_real 3))", Some(Value::Bool(true))),
+            ("((_ divisible 3) (- 9))", Some(Value::Bool(true))),
+            ("((_ divisible 2) 7)", Some(Value::Bool(false))),
+            ("(div (- 7) 2)", Some(Value::Int((-4).into()))),
+            ("(mod (- 7) (- 2))", Some(Value::Int(1.into()))),
+            ("(div 100 7 2)", Some(Value::Int(7.into()))),
+            ("(mod 1 0)", None),
+            ("(abs (- 3))", Some(Value::Int(3.into()))),
+            (
+                "(let ((x 2)) (let ((x (+ x 1))) (* x x)))",
+                Some(Value::Int(9.into())),
+            ),
+            ("(forall ((x Int)) (= x x))", None),
+        ];
+        for (term, expected) in &cases {
+            let (term, _) = parser::parse_term_with_definitions(definitions, term).unwrap();
+            assert_eq!(*expected, Evaluator::new().eval(&term), "{:?}", term);
+        }
+    }
+
+    #[test]
+    fn check_model_assertions() {
+        let problem = "
+            (declare-sort U 0)
+            (declare-fun x () Int)
+            (declare-fun f (Int) Int)
+            (declare-fun a () U)
+            (declare-fun b () U)
+            (assert (> (f x) 3))
+            (assert (not (= a b)))
+            (push)
+            (assert (< x (- 3)))
+            (pop)
+            (assert (= (f 0) x))
+            (check-sat)
+        ";
+        let model = "
+            sat
+            (model
+                (declare-fun U!val!0 () U)
+                (declare-fun U!val!1 () U)
+                (define-fun x () Int (- 2))
+                (define-fun a () U U!val!0)
+                (define-fun b () U U!val!1)
+                (define-fun f ((x!0 Int)) Int (ite (= x!0 (- 2)) 4 0))
+            )
+        ";
+        let (problem, model, _) = parse_problem_model(
+            problem.as_bytes(),
+            model.as_bytes(),
+            parser::Config::default(),
+        )
+        .unwrap();
+        assert_eq!(4, model.definitions.len());
+        assert_eq!(2, model.elements.len());
+
+        // The assertion made inside the popped scope is not evaluated
+        let expected = vec![(0, Some(true)), (1, Some(true)), (3, Some(false))];
+        assert_eq!(expected, check_model(&problem, &model));
+    }
+}
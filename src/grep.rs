@@ -0,0 +1,112 @@
+//! Searching for the steps of a proof whose conclusions match a term pattern. See the `pattern`
+//! module for the syntax of patterns.
+
+use crate::{ast::*, pattern::Pattern};
+
+/// A command whose conclusion matches a pattern. See `grep`.
+#[derive(Debug)]
+pub struct GrepMatch<'a> {
+    pub id: &'a str,
+
+    /// The depth of the subproof that contains the command, where the top-level proof has depth 0.
+    pub depth: usize,
+
+    pub clause: &'a [ByRefRc<Term>],
+
+    /// Whether a whole term of the clause matches the pattern, as opposed to only a subterm of it.
+    pub is_whole_term: bool,
+}
+
+/// Returns the "assume" and "step" commands in `proof`, including those in subproofs, whose
+/// conclusions contain a subterm that matches `pattern`. If `whole_terms_only` is true, only the
+/// commands with a term in their conclusion that matches the pattern as a whole are returned. The
+/// matches are returned in the order the commands appear in the proof.
+pub fn grep<'a>(proof: &'a Proof, pattern: &Pattern, whole_terms_only: bool) -> Vec<GrepMatch<'a>> {
+    fn grep_commands<'a>(
+        commands: &'a [ProofCommand],
+        depth: usize,
+        pattern: &Pattern,
+        whole_terms_only: bool,
+        result: &mut Vec<GrepMatch<'a>>,
+    ) {
+        for command in commands {
+            let (id, clause) = match command {
+                ProofCommand::Assume { id, term } => (id, std::slice::from_ref(term)),
+                ProofCommand::Step(step) => (&step.id, step.clause.as_slice()),
+                ProofCommand::Subproof { commands, .. } => {
+                    grep_commands(commands, depth + 1, pattern, whole_terms_only, result);
+                    continue;
+                }
+            };
+            let is_whole_term = clause.iter().any(|t| pattern.matches(t));
+            if is_whole_term
+                || !whole_terms_only
+                    && clause
+                        .iter()
+                        .any(|t| Subterms::new(t).any(|sub| pattern.matches(sub)))
+            {
+                result.push(GrepMatch {
+                    id,
+                    depth,
+                    clause,
+                    is_whole_term,
+                });
+            }
+        }
+    }
+
+    let mut result = Vec::new();
+    grep_commands(&proof.0, 0, pattern, whole_terms_only, &mut result);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_proof_with_definitions;
+
+    #[test]
+    fn grep() {
+        let problem = "
+            (declare-fun a () Int)
+            (declare-fun b () Int)
+            (declare-fun f (Int Int) Int)
+            (declare-fun p (Int) Bool)
+        ";
+        let proof = "
+            (assume h1 (p (f a b)))
+            (step t2 (cl (= (f a b) (f a b))) :rule eq_reflexive)
+            (anchor :step t3)
+            (step t3.t1 (cl (= (f b a) (f a a))) :rule trust)
+            (step t3 (cl (not (p a)) (forall ((x Int)) (p x))) :rule trust)
+            (step t4 (cl (= a b) (p a)) :rule trust)
+        ";
+        let (proof, _) = parse_proof_with_definitions(problem, proof).unwrap();
+        let run = |pattern: &str, whole_terms_only: bool| -> Vec<(&str, usize, bool)> {
+            let pattern = Pattern::parse(pattern).unwrap();
+            super::grep(&proof, &pattern, whole_terms_only)
+                .into_iter()
+                .map(|m| (m.id, m.depth, m.is_whole_term))
+                .collect()
+        };
+
+        assert_eq!(
+            vec![("h1", 0, false), ("t2", 0, false)],
+            run("(f a b)", false)
+        );
+        assert_eq!(vec![("t2", 0, true)], run("(= ?x ?x)", false));
+        assert_eq!(
+            vec![("t2", 0, true), ("t3.t1", 1, true), ("t4", 0, true)],
+            run("(= ...)", false),
+        );
+        assert_eq!(vec![("t3.t1", 1, false)], run("(f ?x a)", false));
+        assert_eq!(vec![("t3", 1, true)], run("(forall ... (p _))", false));
+        assert_eq!(
+            vec![("h1", 0, true), ("t3", 1, false), ("t4", 0, true)],
+            run("(p _)", false),
+        );
+        assert_eq!(vec![("h1", 0, true), ("t4", 0, true)], run("(p _)", true));
+        assert_eq!(vec![("t4", 0, true)], run("(= a b)", true));
+        assert!(run("(f a b c)", false).is_empty());
+    }
+}
@@ -0,0 +1,258 @@
+//! A minimal JSON value type, used for the machine-readable output of the checker and by the
+//! JSON-RPC server.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Write as _},
+    iter::Peekable,
+    str::Chars,
+};
+
+/// A JSON value. Objects keep their keys sorted, so the output is deterministic.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+}
+
+impl Json {
+    /// The maximum nesting depth of arrays and objects accepted by `Json::parse`. Values are parsed
+    /// recursively, so deeper inputs are rejected instead of overflowing the stack.
+    pub const MAX_DEPTH: usize = 256;
+
+    /// Parses a JSON value. Returns `None` if the input is not valid JSON, or if it is nested
+    /// deeper than `Json::MAX_DEPTH`.
+    pub fn parse(input: &str) -> Option<Json> {
+        let mut chars = input.chars().peekable();
+        let value = Self::parse_value(&mut chars, 0)?;
+        Self::skip_whitespace(&mut chars);
+        match chars.next() {
+            None => Some(value),
+            Some(_) => None,
+        }
+    }
+
+    fn skip_whitespace(chars: &mut Peekable<Chars>) {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+    }
+
+    fn expect_literal(chars: &mut Peekable<Chars>, literal: &str, value: Json) -> Option<Json> {
+        for expected in literal.chars() {
+            if chars.next()? != expected {
+                return None;
+            }
+        }
+        Some(value)
+    }
+
+    /// Parses a value nested inside `depth` arrays or objects.
+    fn parse_value(chars: &mut Peekable<Chars>, depth: usize) -> Option<Json> {
+        Self::skip_whitespace(chars);
+        let next = *chars.peek()?;
+        if (next == '[' || next == '{') && depth >= Self::MAX_DEPTH {
+            return None;
+        }
+        match next {
+            'n' => Self::expect_literal(chars, "null", Json::Null),
+            't' => Self::expect_literal(chars, "true", Json::Bool(true)),
+            'f' => Self::expect_literal(chars, "false", Json::Bool(false)),
+            '"' => Self::parse_string(chars).map(Json::String),
+            '[' => {
+                chars.next();
+                let mut items = Vec::new();
+                Self::skip_whitespace(chars);
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                    return Some(Json::Array(items));
+                }
+                loop {
+                    items.push(Self::parse_value(chars, depth + 1)?);
+                    Self::skip_whitespace(chars);
+                    match chars.next()? {
+                        ',' => continue,
+                        ']' => return Some(Json::Array(items)),
+                        _ => return None,
+                    }
+                }
+            }
+            '{' => {
+                chars.next();
+                let mut map = BTreeMap::new();
+                Self::skip_whitespace(chars);
+                if chars.peek() == Some(&'}') {
+                    chars.next();
+                    return Some(Json::Object(map));
+                }
+                loop {
+                    Self::skip_whitespace(chars);
+                    let key = Self::parse_string(chars)?;
+                    Self::skip_whitespace(chars);
+                    if chars.next()? != ':' {
+                        return None;
+                    }
+                    map.insert(key, Self::parse_value(chars, depth + 1)?);
+                    Self::skip_whitespace(chars);
+                    match chars.next()? {
+                        ',' => continue,
+                        '}' => return Some(Json::Object(map)),
+                        _ => return None,
+                    }
+                }
+            }
+            _ => {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_digit() || "+-.eE".contains(c) {
+                        number.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                number.parse().ok().map(Json::Number)
+            }
+        }
+    }
+
+    fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+        if chars.next()? != '"' {
+            return None;
+        }
+        let mut result = String::new();
+        loop {
+            match chars.next()? {
+                '"' => return Some(result),
+                '\\' => match chars.next()? {
+                    'n' => result.push('\n'),
+                    't' => result.push('\t'),
+                    'r' => result.push('\r'),
+                    'b' => result.push('\u{8}'),
+                    'f' => result.push('\u{c}'),
+                    'u' => {
+                        let hex: String = chars.by_ref().take(4).collect();
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)?;
+                        result.push(c);
+                    }
+                    c => result.push(c),
+                },
+                c => result.push(c),
+            }
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn object<const N: usize>(entries: [(&str, Json); N]) -> Json {
+        Json::Object(
+            IntoIterator::into_iter(entries)
+                .map(|(k, v)| (k.to_owned(), v))
+                .collect(),
+        )
+    }
+
+    pub fn strings<T: ToString>(items: impl IntoIterator<Item = T>) -> Json {
+        Json::Array(
+            items
+                .into_iter()
+                .map(|s| Json::String(s.to_string()))
+                .collect(),
+        )
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => {
+                f.write_char('"')?;
+                for c in s.chars() {
+                    match c {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\t' => f.write_str("\\t")?,
+                        c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                        c => f.write_char(c)?,
+                    }
+                }
+                f.write_char('"')
+            }
+            Json::Array(items) => {
+                f.write_char('[')?;
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(map) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in map.iter().enumerate() {
+                    if i != 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}:{}", Json::String(key.clone()), value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_round_trip() {
+        let input = r#"{"a": [1, -2.5, true, null], "b": {"c": "x\"y\u0041\n"}}"#;
+        let value = Json::parse(input).unwrap();
+        assert_eq!(
+            Some("x\"yA\n"),
+            value
+                .get("b")
+                .and_then(|b| b.get("c"))
+                .and_then(Json::as_str)
+        );
+        assert_eq!(
+            r#"{"a":[1,-2.5,true,null],"b":{"c":"x\"yA\n"}}"#,
+            value.to_string()
+        );
+        assert_eq!(Some(value.clone()), Json::parse(&value.to_string()));
+
+        assert!(Json::parse("{\"a\": }").is_none());
+        assert!(Json::parse("[1, 2").is_none());
+        assert!(Json::parse("1 2").is_none());
+
+        // Deeply nested values are rejected instead of overflowing the stack
+        let nested = |depth| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(Json::parse(&nested(Json::MAX_DEPTH)).is_some());
+        assert!(Json::parse(&nested(Json::MAX_DEPTH + 1)).is_none());
+        assert!(Json::parse(&nested(1_000_000)).is_none());
+    }
+}
@@ -0,0 +1,120 @@
+//! Extracting the theory lemmas of a proof, that is, the steps whose rules reason about a
+//! background theory (see `RuleCategory::is_theory`), as standalone implications. These can be
+//! collected across many proofs to build lemma databases or training sets.
+
+use crate::{
+    ast::*,
+    checker::{replacement_rule_name, rule_info, RuleCategory},
+};
+
+/// A step of a proof whose rule is a theory rule.
+#[derive(Debug, Clone)]
+pub struct TheoryLemma {
+    pub id: String,
+
+    /// The rule of the step. Deprecated rule names are replaced by the names of the rules that
+    /// replaced them.
+    pub rule: &'static str,
+    pub category: RuleCategory,
+
+    /// The lemma as a single term. If the step has premises, this is an implication from the
+    /// conjunction of the premise clauses to the conclusion clause, and otherwise it is just the
+    /// conclusion clause. Each clause is written as a disjunction of its literals, the empty clause
+    /// as `false`, and a unit clause as its only literal.
+    pub term: ByRefRc<Term>,
+}
+
+/// Returns every step in the top-level proof whose rule is a theory rule, as a `TheoryLemma`, in
+/// the order in which they appear. Steps inside subproofs are ignored, since their validity depends
+/// on the context. The terms of the lemmas are added to `pool`.
+pub fn theory_lemmas(pool: &mut TermPool, proof: &Proof) -> Vec<TheoryLemma> {
+    let mut result = Vec::new();
+    for command in &proof.0 {
+        let step = match command {
+            ProofCommand::Step(step) => step,
+            _ => continue,
+        };
+        let info =
+            rule_info(&step.rule).or_else(|| replacement_rule_name(&step.rule).and_then(rule_info));
+        let info = match info {
+            Some(info) if info.category.is_theory() => info,
+            _ => continue,
+        };
+        let conclusion = clause_to_term(pool, &step.clause);
+        let premises: Vec<_> = step
+            .premises
+            .iter()
+            .map(|&(_, i)| clause_to_term(pool, proof.0[i].clause()))
+            .collect();
+        let term = match premises.len() {
+            0 => conclusion,
+            1 => build_term!(pool, (=> {premises[0].clone()} {conclusion})),
+            _ => {
+                let premises = pool.add_term(Term::Op(Operator::And, premises));
+                build_term!(pool, (=> {premises} {conclusion}))
+            }
+        };
+        result.push(TheoryLemma {
+            id: step.id.clone(),
+            rule: info.name,
+            category: info.category,
+            term,
+        });
+    }
+    result
+}
+
+/// Returns the disjunction of the literals in `clause`.
+fn clause_to_term(pool: &mut TermPool, clause: &[ByRefRc<Term>]) -> ByRefRc<Term> {
+    match clause {
+        [] => pool.bool_false(),
+        [literal] => literal.clone(),
+        _ => pool.add_term(Term::Op(Operator::Or, clause.to_vec())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_proof_with_definitions;
+
+    #[test]
+    fn lemmas() {
+        let definitions = "
+            (declare-sort T 0)
+            (declare-fun a () T)
+            (declare-fun b () T)
+            (declare-fun f (T) T)
+            (declare-fun x () Int)
+        ";
+        let proof = "
+            (assume h1 (= a b))
+            (assume h2 (> x 2))
+            (step t3 (cl (= (f a) (f b))) :rule cong :premises (h1))
+            (step t4 (cl (not (> x 2)) (> x 1)) :rule la_generic :args (1 1))
+            (step t5 (cl (> x 1)) :rule resolution :premises (t4 h2))
+            (step t6 (cl (not (= a b)) (= (f a) (f b))) :rule eq_congruent)
+            (anchor :step t7 :args ((y Int)))
+            (step t7.t1 (cl (= y y)) :rule refl)
+            (step t7 (cl (= (forall ((y Int)) (= y y)) (forall ((y Int)) (= y y)))) :rule bind)
+        ";
+        let (proof, mut pool) = parse_proof_with_definitions(definitions, proof).unwrap();
+        let lemmas = theory_lemmas(&mut pool, &proof);
+        let lemmas: Vec<_> = lemmas
+            .iter()
+            .map(|l| (l.id.as_str(), l.rule, format!("{:?}", l.term)))
+            .collect();
+        assert_eq!(
+            vec![
+                ("t3", "cong", "(=> (= a b) (= (f a) (f b)))".to_owned()),
+                ("t4", "la_generic", "(or (not (> x 2)) (> x 1))".to_owned()),
+                (
+                    "t6",
+                    "eq_congruent",
+                    "(or (not (= a b)) (= (f a) (f b)))".to_owned()
+                ),
+            ],
+            lemmas
+        );
+    }
+}
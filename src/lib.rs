@@ -1,57 +1,373 @@
+// `ParserError` is returned by value throughout the parser, so we don't want to box it
+#![allow(clippy::result_large_err)]
+
 extern crate num_bigint;
 extern crate num_rational;
 extern crate num_traits;
 
 #[macro_use]
 pub mod ast;
+pub mod anonymize;
+#[cfg(feature = "bench")]
+pub mod bench;
 pub mod checker;
+pub mod eval;
+pub mod grep;
+pub mod json;
+pub mod lemmas;
+pub mod lint;
+pub mod minimize;
+pub mod normalize;
 pub mod parser;
+pub mod pattern;
+pub mod reconstruct;
+pub mod testing;
 mod utils;
 
+use ast::{ByRefRc, Problem, Proof, Status, Term};
 use checker::CheckerError;
-use parser::error::ParserError;
+use parser::error::{ErrorKind, ParserError};
 use std::{
-    fs::File,
+    ffi::OsStr,
+    fs::{self, File},
     io::{self, BufReader},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 #[derive(Debug)]
 pub enum Error {
     Parser(ParserError),
     Checker(CheckerError),
+
+    /// The configured memory limit, in bytes, was exceeded during parsing or checking.
+    ResourceLimit(usize),
+
+    /// The query selected with a `QuerySelector` doesn't exist in the problem.
+    QueryNotFound(QuerySelector),
+
+    /// A query was selected, but some of the problem's assertions could not be parsed, so the
+    /// assertions of its queries are not known. This holds the number of skipped assertions.
+    SkippedAssertions(usize),
+
+    /// The proof depends on assumptions that are not in the unsat core reported by the solver. This
+    /// holds the ids of these assumptions.
+    OutsideUnsatCore(Vec<String>),
+
+    /// The problem is annotated with a status different from the one the user expected.
+    StatusMismatch {
+        expected: Status,
+        annotated: Status,
+    },
+
+    /// A file could not be opened, read or written, outside of the parser. Errors found while the
+    /// parser reads its input are returned as parser errors.
+    Io(io::Error),
 }
 
 impl From<ParserError> for Error {
     fn from(e: ParserError) -> Self {
-        Self::Parser(e)
+        match e {
+            ParserError(ErrorKind::MemoryLimitExceeded(limit), _) => Self::ResourceLimit(limit),
+            e => Self::Parser(e),
+        }
     }
 }
 
 impl From<CheckerError> for Error {
     fn from(e: CheckerError) -> Self {
-        Self::Checker(e)
+        match e {
+            CheckerError::MemoryLimitExceeded(limit) => Self::ResourceLimit(limit),
+            e => Self::Checker(e),
+        }
     }
 }
 
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Self {
-        Self::Parser(e.into())
+        Self::Io(e)
     }
 }
 
 pub fn check<P: AsRef<Path>>(
     problem_path: P,
     proof_path: P,
-    skip_unknown_rules: bool,
-    allow_test_rule: bool,
+    parser_config: parser::Config,
+    checker_config: checker::Config,
 ) -> Result<checker::Correctness, Error> {
-    let (proof, pool) = parser::parse_problem_proof(
-        BufReader::new(File::open(problem_path).unwrap()),
-        BufReader::new(File::open(proof_path).unwrap()),
+    let (_, proof, mut pool) = parser::parse_problem_proof(
+        BufReader::new(File::open(problem_path)?),
+        BufReader::new(File::open(proof_path)?),
+        parser_config,
     )?;
 
-    checker::ProofChecker::new(pool, skip_unknown_rules, allow_test_rule)
-        .check(&proof)
-        .map_err(Error::Checker)
+    Ok(checker::ProofChecker::new(&mut pool, checker_config).check(&proof)?)
+}
+
+/// How to select which query of an incremental problem a proof refers to, in `check_query`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuerySelector {
+    /// The query of the "check-sat" command with this index, counting from zero.
+    Index(usize),
+
+    /// The first query whose assertions include all of the proof's assumptions.
+    MatchAssumptions,
+}
+
+/// Returns the assertions of the query in `problem` that is selected by `query`. Returns an error
+/// if there is no such query, or if some assertions of the problem could not be parsed.
+pub fn select_query<'a>(
+    problem: &'a Problem,
+    proof: &Proof,
+    query: QuerySelector,
+) -> Result<Vec<&'a ByRefRc<Term>>, Error> {
+    if !problem.skipped_assertions.is_empty() {
+        return Err(Error::SkippedAssertions(problem.skipped_assertions.len()));
+    }
+    let index = match query {
+        QuerySelector::Index(i) => Some(i),
+        QuerySelector::MatchAssumptions => problem.find_query(proof.assumptions()),
+    };
+    index
+        .and_then(|i| problem.query_assertions(i))
+        .ok_or(Error::QueryNotFound(query))
+}
+
+/// Checks a proof of one of the queries in an incremental problem, that is, a problem with
+/// multiple "check-sat" commands. Besides checking the proof steps, this checks that the proof
+/// only assumes terms that were asserted in the selected query.
+pub fn check_query<P: AsRef<Path>>(
+    problem_path: P,
+    proof_path: P,
+    query: QuerySelector,
+    parser_config: parser::Config,
+    checker_config: checker::Config,
+) -> Result<checker::Correctness, Error> {
+    let (problem, proof, mut pool) = parser::parse_problem_proof(
+        BufReader::new(File::open(problem_path)?),
+        BufReader::new(File::open(proof_path)?),
+        parser_config,
+    )?;
+
+    let assertions = select_query(&problem, &proof, query)?;
+    Ok(checker::ProofChecker::new(&mut pool, checker_config)
+        .check_with_assertions(&proof, &assertions)?)
+}
+
+/// The result of comparing the unsat core reported by a solver with the assertions that a proof
+/// of the same problem depends on. See `compare_unsat_core`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsatCoreComparison {
+    /// The names of the named assertions that the proof depends on, in the order in which they
+    /// appear in the problem. This is the core derived from the proof.
+    pub proof_core: Vec<String>,
+
+    /// The ids of the "assume" commands that the proof depends on, whose terms are not asserted
+    /// by an assertion in the reported core, or by an unnamed assertion.
+    pub outside_core: Vec<String>,
+}
+
+impl UnsatCoreComparison {
+    /// Returns true if the proof only depends on assertions in the reported core.
+    pub fn is_consistent(&self) -> bool {
+        self.outside_core.is_empty()
+    }
+}
+
+/// Compares the unsat core `core` reported by a solver, given as the names of its assertions, with
+/// the assertions that `proof` depends on. Unnamed assertions can't be part of an unsat core, so
+/// assumptions of their terms are always considered to be in the core.
+pub fn compare_unsat_core(
+    problem: &Problem,
+    proof: &Proof,
+    core: &[String],
+) -> UnsatCoreComparison {
+    let in_core = |name: &Option<String>| name.as_ref().is_none_or(|n| core.contains(n));
+    let mut used_assertions = vec![false; problem.assertions.len()];
+    let mut outside_core = Vec::new();
+    for (id, term) in proof.used_assumptions() {
+        let matching = || {
+            problem
+                .assertions
+                .iter()
+                .enumerate()
+                .filter(move |(_, (_, t))| t == term)
+        };
+
+        // If the same term is asserted more than once, we prefer an assertion in the core
+        match matching()
+            .find(|(_, (name, _))| in_core(name))
+            .or_else(|| matching().next())
+        {
+            Some((i, (name, _))) => {
+                used_assertions[i] = true;
+                if !in_core(name) {
+                    outside_core.push(id.to_owned());
+                }
+            }
+            None => outside_core.push(id.to_owned()),
+        }
+    }
+    let proof_core = problem
+        .assertions
+        .iter()
+        .zip(used_assertions)
+        .filter_map(|((name, _), used)| if used { name.clone() } else { None })
+        .collect();
+    UnsatCoreComparison {
+        proof_core,
+        outside_core,
+    }
+}
+
+/// Checks that the status given in the ":status" annotation of `problem` doesn't contradict
+/// `expected`. Problems without an annotation, or annotated as "unknown", are accepted for any
+/// expected status.
+pub fn check_status(problem: &Problem, expected: Status) -> Result<(), Error> {
+    match problem.status {
+        Some(annotated) if annotated != Status::Unknown && annotated != expected => {
+            Err(Error::StatusMismatch {
+                expected,
+                annotated,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks a proof without its problem file. Since there are no assertions to compare them to, the
+/// "assume" commands in the proof are taken as given. See `parser::parse_proof_only`.
+pub fn check_proof_only<P: AsRef<Path>>(
+    proof_path: P,
+    parser_config: parser::Config,
+    checker_config: checker::Config,
+) -> Result<checker::Correctness, Error> {
+    let (proof, mut pool) =
+        parser::parse_proof_only(BufReader::new(File::open(proof_path)?), parser_config)?;
+
+    Ok(checker::ProofChecker::new(&mut pool, checker_config).check(&proof)?)
+}
+
+/// The result of checking one problem and proof pair in a corpus.
+#[derive(Debug)]
+pub struct CorpusEntry {
+    pub problem_path: PathBuf,
+    pub proof_path: PathBuf,
+    pub result: Result<checker::Correctness, Error>,
+}
+
+impl CorpusEntry {
+    /// Returns true if the proof was checked and found to be valid, even if some steps were not
+    /// checked by their rules.
+    pub fn is_valid(&self) -> bool {
+        matches!(&self.result, Ok(c) if c.is_valid())
+    }
+}
+
+/// Recursively walks the directory `dir`, and checks every proof file in it against its problem
+/// file. Proof files are the files with the ".proof" extension, and the problem file of a proof is
+/// the file with the same path, minus that extension (for example, "a.smt2" for "a.smt2.proof").
+/// Since the checker configuration can't be cloned, `checker_config` is called to create a new one
+/// for each proof. The entries are returned sorted by path.
+pub fn check_corpus<P, F>(
+    dir: P,
+    parser_config: parser::Config,
+    mut checker_config: F,
+) -> io::Result<Vec<CorpusEntry>>
+where
+    P: AsRef<Path>,
+    F: FnMut() -> checker::Config,
+{
+    fn find_proof_files(dir: &Path, result: &mut Vec<PathBuf>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                find_proof_files(&path, result)?;
+            } else if path.extension() == Some(OsStr::new("proof")) {
+                result.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let mut proof_files = Vec::new();
+    find_proof_files(dir.as_ref(), &mut proof_files)?;
+    proof_files.sort();
+
+    let entries = proof_files
+        .into_iter()
+        .map(|proof_path| {
+            let problem_path = proof_path.with_extension("");
+            let result = check(
+                &problem_path,
+                &proof_path,
+                parser_config.clone(),
+                checker_config(),
+            );
+            CorpusEntry {
+                problem_path,
+                proof_path,
+                result,
+            }
+        })
+        .collect();
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status() {
+        let parse = |problem: &str| {
+            let (problem, _, _) =
+                parser::parse_problem_proof(problem.as_bytes(), "".as_bytes(), Default::default())
+                    .unwrap();
+            problem
+        };
+        let sat = parse("(set-info :status sat)");
+        assert!(check_status(&sat, Status::Sat).is_ok());
+        assert!(matches!(
+            check_status(&sat, Status::Unsat),
+            Err(Error::StatusMismatch {
+                expected: Status::Unsat,
+                annotated: Status::Sat,
+            })
+        ));
+        for problem in ["(set-info :status unknown)", "(set-info :status)", ""] {
+            let problem = parse(problem);
+            assert!(check_status(&problem, Status::Sat).is_ok());
+            assert!(check_status(&problem, Status::Unsat).is_ok());
+        }
+    }
+
+    #[test]
+    fn unsat_core_comparison() {
+        let problem = "
+            (declare-fun p () Bool)
+            (declare-fun q () Bool)
+            (assert (! p :named a1))
+            (assert (! (not p) :named a2))
+            (assert (! q :named a3))
+            (assert (or p q))
+        ";
+        let proof = "
+            (assume h1 p)
+            (assume h2 (not p))
+            (assume h3 (or p q))
+            (step t4 (cl) :rule resolution :premises (h1 h2))
+        ";
+        let (problem, proof, _) =
+            parser::parse_problem_proof(problem.as_bytes(), proof.as_bytes(), Default::default())
+                .unwrap();
+        let core = |names: &[&str]| -> Vec<String> { names.iter().map(|&s| s.into()).collect() };
+
+        let comparison = compare_unsat_core(&problem, &proof, &core(&["a1", "a2", "a3"]));
+        assert!(comparison.is_consistent());
+        assert_eq!(core(&["a1", "a2"]), comparison.proof_core);
+
+        let comparison = compare_unsat_core(&problem, &proof, &core(&["a1", "a3"]));
+        assert!(!comparison.is_consistent());
+        assert_eq!(core(&["h2"]), comparison.outside_core);
+    }
 }
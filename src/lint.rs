@@ -0,0 +1,340 @@
+//! A linter for proofs. The linter looks for problems that don't make a proof invalid, but that
+//! make it larger or slower to check than it needs to be.
+
+use crate::{
+    ast::*,
+    checker::{self, rule_info, Correctness, ProofChecker, Warning},
+};
+use std::{collections::HashMap, fmt};
+
+/// A kind of problem that the linter looks for. Each lint has a name, which is used to allow or
+/// deny it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// A command whose conclusion is identical to the conclusion of an earlier command in the same
+    /// subproof.
+    DuplicateStep,
+
+    /// A premise given in the ":premises" of a step, that the step is still valid without.
+    UnusedPremise,
+
+    /// A step whose conclusion has more literals than `Config::max_clause_size`.
+    LargeClause,
+
+    /// A step that is also valid under a simpler rule than the one it uses.
+    SimplerRule,
+
+    /// An unused assumption, as found by the checker (see `checker::Warning::UnusedAssumption`).
+    UnusedAssumption,
+}
+
+impl Lint {
+    pub const ALL: &'static [Lint] = &[
+        Lint::DuplicateStep,
+        Lint::UnusedPremise,
+        Lint::LargeClause,
+        Lint::SimplerRule,
+        Lint::UnusedAssumption,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::DuplicateStep => "duplicate_step",
+            Lint::UnusedPremise => "unused_premise",
+            Lint::LargeClause => "large_clause",
+            Lint::SimplerRule => "simpler_rule",
+            Lint::UnusedAssumption => "unused_assumption",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|lint| lint.name() == name)
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// How a lint is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// The lint is not checked.
+    Allow,
+
+    /// The lint is reported as a warning.
+    Warn,
+
+    /// The lint is reported as an error.
+    Deny,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Level::Allow => write!(f, "allow"),
+            Level::Warn => write!(f, "warning"),
+            Level::Deny => write!(f, "error"),
+        }
+    }
+}
+
+/// The default value of `Config::max_clause_size`.
+pub const DEFAULT_MAX_CLAUSE_SIZE: usize = 64;
+
+pub struct Config {
+    levels: HashMap<Lint, Level>,
+
+    /// The maximum number of literals in a conclusion before the `large_clause` lint is reported.
+    pub max_clause_size: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            levels: HashMap::new(),
+            max_clause_size: DEFAULT_MAX_CLAUSE_SIZE,
+        }
+    }
+}
+
+impl Config {
+    pub fn set_level(&mut self, lint: Lint, level: Level) {
+        self.levels.insert(lint, level);
+    }
+
+    /// Returns the level of `lint`. Lints are reported as warnings by default.
+    pub fn level(&self, lint: Lint) -> Level {
+        self.levels.get(&lint).copied().unwrap_or(Level::Warn)
+    }
+}
+
+/// A problem found by the linter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub lint: Lint,
+    pub level: Level,
+
+    /// The id of the command where the problem was found.
+    pub id: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.level, self.lint, self.message)
+    }
+}
+
+/// The rules tried by the `simpler_rule` lint, in order. These rules are cheap to check, and a step
+/// that uses one of them is easier to understand than one that uses a more general rule.
+const SIMPLER_RULES: &[&str] = &["true", "false", "eq_reflexive", "not_not", "contraction"];
+
+/// Runs the linter over a proof, and returns the problems found, in the order of the commands in
+/// which they were found. `pool` must be the term pool the proof was parsed into.
+pub fn lint(proof: &Proof, pool: &mut TermPool, config: &Config) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        config,
+        checker: ProofChecker::new(pool, checker::Config::default()),
+        diagnostics: Vec::new(),
+    };
+    linter.lint_commands(&proof.0, true);
+    linter.find_unused_assumptions(&proof.0);
+    linter.diagnostics
+}
+
+struct Linter<'a> {
+    config: &'a Config,
+    checker: ProofChecker<'a>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    fn report(&mut self, lint: Lint, id: &str, message: String) {
+        let level = self.config.level(lint);
+        if level != Level::Allow {
+            self.diagnostics.push(Diagnostic {
+                lint,
+                level,
+                id: id.to_owned(),
+                message,
+            });
+        }
+    }
+
+    fn lint_commands(&mut self, commands: &[ProofCommand], is_top_level: bool) {
+        let mut conclusions: HashMap<&[ByRefRc<Term>], &str> = HashMap::new();
+        for command in commands {
+            let clause = clause_of(command);
+            match conclusions.get(clause) {
+                Some(first) => {
+                    let message = format!(
+                        "\"{}\" has the same conclusion as \"{}\"",
+                        command.id(),
+                        first
+                    );
+                    self.report(Lint::DuplicateStep, command.id(), message);
+                }
+                None => {
+                    conclusions.insert(clause, command.id());
+                }
+            }
+            match command {
+                ProofCommand::Step(step) => self.lint_step(step, commands, is_top_level),
+                ProofCommand::Subproof { commands, .. } => self.lint_commands(commands, false),
+                ProofCommand::Assume { .. } => (),
+            }
+        }
+    }
+
+    /// Runs the lints that apply to a single step. The lints that check the step with different
+    /// premises or rules are only run if `is_top_level` is true, because steps inside subproofs
+    /// depend on the subproof context.
+    fn lint_step(&mut self, step: &ProofStep, all_commands: &[ProofCommand], is_top_level: bool) {
+        if step.clause.len() > self.config.max_clause_size {
+            let message = format!(
+                "step \"{}\" has {} literals in its conclusion, more than the limit of {}",
+                step.id,
+                step.clause.len(),
+                self.config.max_clause_size
+            );
+            self.report(Lint::LargeClause, &step.id, message);
+        }
+
+        let check_premises = self.config.level(Lint::UnusedPremise) != Level::Allow;
+        let check_rule = self.config.level(Lint::SimplerRule) != Level::Allow
+            && !SIMPLER_RULES.contains(&step.rule.as_str());
+        if !is_top_level || !(check_premises || check_rule) || !self.is_valid(step, all_commands) {
+            return;
+        }
+
+        if check_premises {
+            for i in 0..step.premises.len() {
+                // A premise that is given more than once is only reported once
+                if step.premises[..i].contains(&step.premises[i]) {
+                    continue;
+                }
+                let mut modified = step.clone();
+                let removed = modified.premises.remove(i);
+                if self.is_valid(&modified, all_commands) {
+                    let message = format!(
+                        "step \"{}\" is valid without its premise \"{}\"",
+                        step.id,
+                        all_commands[removed.1].id()
+                    );
+                    self.report(Lint::UnusedPremise, &step.id, message);
+                }
+            }
+        }
+
+        if check_rule {
+            for &rule in SIMPLER_RULES {
+                let info = rule_info(rule).unwrap();
+                if !info.premises.allows(step.premises.len()) || !info.args.allows(step.args.len())
+                {
+                    continue;
+                }
+                let modified = ProofStep {
+                    rule: rule.to_owned(),
+                    ..step.clone()
+                };
+                if self.is_valid(&modified, all_commands) {
+                    let message = format!(
+                        "step \"{}\" uses rule \"{}\", but is also valid under the simpler rule \
+                        \"{}\"",
+                        step.id, step.rule, rule
+                    );
+                    self.report(Lint::SimplerRule, &step.id, message);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn is_valid(&mut self, step: &ProofStep, all_commands: &[ProofCommand]) -> bool {
+        matches!(
+            self.checker.check_standalone_step(step, all_commands),
+            Ok(Correctness::True)
+        )
+    }
+
+    /// Reports the unused assumptions found by the checker's warning pass.
+    fn find_unused_assumptions(&mut self, commands: &[ProofCommand]) {
+        for warning in checker::unused_command_warnings(commands) {
+            if let Warning::UnusedAssumption(id) = &warning {
+                self.report(Lint::UnusedAssumption, id, warning.to_string());
+            }
+        }
+    }
+}
+
+/// Returns the conclusion of a command. "assume" commands are seen as a clause with a single term,
+/// and subproofs as the conclusion of the step that ends them.
+fn clause_of(command: &ProofCommand) -> &[ByRefRc<Term>] {
+    match command {
+        ProofCommand::Assume { term, .. } => std::slice::from_ref(term),
+        ProofCommand::Step(step) => &step.clause,
+        ProofCommand::Subproof { commands, .. } => clause_of(commands.last().unwrap()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_proof_with_definitions;
+
+    fn run_lints(proof: &str, config: &Config) -> Vec<(Lint, String)> {
+        let (proof, mut pool) = parse_proof_with_definitions(
+            "(declare-fun p () Bool) (declare-fun q () Bool) (declare-fun r () Bool)",
+            proof,
+        )
+        .unwrap();
+        lint(&proof, &mut pool, config)
+            .into_iter()
+            .map(|d| (d.lint, d.id))
+            .collect()
+    }
+
+    #[test]
+    fn lints() {
+        let proof = "
+            (assume h1 (not p))
+            (assume h2 (or p q))
+            (assume h3 r)
+            (assume h4 q)
+            (step t5 (cl p q) :rule or :premises (h2))
+            (step t6 (cl q) :rule resolution :premises (t5 h1 h4))
+            (step t7 (cl q) :rule resolution :premises (t6))
+            (step t8 (cl p q r (not r)) :rule unknown)
+        ";
+        let mut config = Config {
+            max_clause_size: 3,
+            ..Config::default()
+        };
+        let expected = vec![
+            (Lint::DuplicateStep, "t6".to_owned()),
+            (Lint::UnusedPremise, "t6".to_owned()),
+            (Lint::DuplicateStep, "t7".to_owned()),
+            (Lint::SimplerRule, "t7".to_owned()),
+            (Lint::LargeClause, "t8".to_owned()),
+            (Lint::UnusedAssumption, "h3".to_owned()),
+        ];
+        assert_eq!(expected, run_lints(proof, &config));
+
+        config.set_level(Lint::SimplerRule, Level::Allow);
+        config.set_level(Lint::UnusedPremise, Level::Deny);
+        let diagnostics = run_lints(proof, &config);
+        assert!(!diagnostics.iter().any(|(l, _)| *l == Lint::SimplerRule));
+        assert_eq!(5, diagnostics.len());
+    }
+
+    #[test]
+    fn lint_names() {
+        for &lint in Lint::ALL {
+            assert_eq!(Some(lint), Lint::from_name(lint.name()));
+        }
+        assert_eq!(None, Lint::from_name("foo"));
+    }
+}
@@ -0,0 +1,59 @@
+//! A small logging layer for the command line interface. Every message has a verbosity level, and
+//! is only printed if the level selected with "--quiet", "--verbose" or "--trace" is at least as
+//! high. Results, like the verdict of "check", go to stdout, and other messages go to stderr.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// A verbosity level. Each level prints everything the levels before it print.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Prints nothing. The outcome is only reported through the exit code.
+    Quiet,
+
+    /// Prints the results and errors, like the one-line verdict of "check".
+    Normal,
+
+    /// Also prints warnings and statistics.
+    Verbose,
+
+    /// Also prints the progress of checking, step by step.
+    Trace,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Normal as u8);
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Returns `true` if messages of level `level` are printed.
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Prints a result to stdout, like `print!`, unless the level is `Level::Quiet`.
+macro_rules! out {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Normal) {
+            print!($($arg)*);
+        }
+    };
+}
+
+/// Prints a result to stdout, like `println!`, unless the level is `Level::Quiet`.
+macro_rules! outln {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Normal) {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Prints a message of the given level to stderr, like `eprintln!`.
+macro_rules! msg {
+    ($level:ident, $($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::$level) {
+            eprintln!($($arg)*);
+        }
+    };
+}
@@ -1,18 +1,30 @@
+#![allow(clippy::result_large_err)]
+
 extern crate clap;
 
+#[macro_use]
+mod log;
+mod repl;
+mod serve;
+
 use verit_proof_checker::*;
 
+use ast::{PrintLimits, Proof, Status};
 use checker::*;
 use error::*;
+use json::Json;
 use parser::*;
 
 use std::{
     collections::HashSet,
-    fs::File,
-    io::{BufReader, Write},
+    fs::{self, File},
+    io::{BufRead, BufReader, IsTerminal, Write},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
 };
 
-use clap::{App, AppSettings, Arg, ArgGroup, SubCommand};
+use clap::{App, AppSettings, Arg, ArgGroup, ArgMatches, SubCommand};
 
 fn main() -> Result<(), Error> {
     let matches = App::new("veriT proof checker")
@@ -24,17 +36,500 @@ fn main() -> Result<(), Error> {
                 .setting(AppSettings::DisableVersion)
                 .arg(Arg::with_name("PROBLEM_FILE").required(true))
                 .arg(Arg::with_name("PROOF_FILE").required(false))
+                .arg(include_arg().conflicts_with("proof-only"))
                 .arg(
                     Arg::with_name("skip-unknown-rules")
                         .short("s")
                         .long("skip-unknown-rules")
                         .help("Skips rules that are not yet implemented"),
-                ),
+                )
+                .arg(Arg::with_name("eval-fallback").long("eval-fallback").help(
+                    "Accepts failing steps whose conclusions only involve constants, if they \
+                        evaluate to true",
+                ))
+                .arg(
+                    Arg::with_name("proof-only")
+                        .long("proof-only")
+                        .conflicts_with("PROOF_FILE")
+                        .help(
+                            "Checks a proof without its problem file. The only file given should \
+                            be the proof file",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("query")
+                        .long("query")
+                        .takes_value(true)
+                        .value_name("N")
+                        .conflicts_with("proof-only")
+                        .validator(|v| match v.as_str() {
+                            "auto" => Ok(()),
+                            _ => v.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()),
+                        })
+                        .help(
+                            "For incremental problems, checks the proof against the assertions of \
+                            the N-th \"check-sat\" command, counting from zero. If N is \"auto\", \
+                            the first query that includes all of the proof's assumptions is used",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("strictness")
+                        .long("strictness")
+                        .takes_value(true)
+                        .possible_values(&["strict", "default", "permissive"])
+                        .default_value("default")
+                        .help(
+                            "How lenient to be with small deviations from the rule specifications",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("pipeline")
+                        .long("pipeline")
+                        .conflicts_with("query")
+                        .help(
+                            "Parses the proof on a separate thread, checking each command as soon \
+                            as it is parsed",
+                        ),
+                )
+                .arg(Arg::with_name("opaque-definitions").long("opaque-definitions").help(
+                    "Declares the functions defined with \"define-fun\" in the proof as \
+                    uninterpreted functions, instead of expanding their definitions",
+                ))
+                .arg(
+                    Arg::with_name("unsat-core")
+                        .long("unsat-core")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .conflicts_with("proof-only")
+                        .help(
+                            "Checks that the proof only depends on assertions in the unsat core \
+                            in FILE, as printed by the \"get-unsat-core\" command",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("expect-unsat")
+                        .long("expect-unsat")
+                        .conflicts_with_all(&["expect-sat", "proof-only"])
+                        .help(
+                            "Fails if the problem's \":status\" annotation is \"sat\". Without \
+                            this flag, only a warning is printed, with --verbose",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("expect-sat")
+                        .long("expect-sat")
+                        .conflicts_with("proof-only")
+                        .help(
+                            "Fails if the problem's \":status\" annotation is \"unsat\", and \
+                            doesn't warn if it is \"sat\"",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("deny-warnings")
+                        .long("deny-warnings")
+                        .help("Treat warnings, such as unused assumptions, as errors"),
+                )
+                .arg(Arg::with_name("check-sorts").long("check-sorts").help(
+                    "Checks again that every term in the conclusion of each step, and in its \
+                    premises, is of sort Bool",
+                ))
+                .arg(Arg::with_name("partial").long("partial").help(
+                    "Accepts proofs that never conclude the empty clause, checking only that \
+                    their steps are valid",
+                ))
+                .arg(
+                    Arg::with_name("target")
+                        .long("target")
+                        .takes_value(true)
+                        .value_name("CLAUSE")
+                        .conflicts_with_all(&["partial", "proof-only"])
+                        .help(
+                            "Checks that the proof concludes CLAUSE, given as \"(cl <term>*)\", \
+                            instead of the empty clause",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("failure-mode")
+                        .long("failure-mode")
+                        .takes_value(true)
+                        .value_name("MODE")
+                        .possible_values(&["stop", "finish-subproof", "check-all"])
+                        .default_value("stop")
+                        .help(
+                            "What to do after a step fails: stop checking, finish checking the \
+                            subproof that contains the step, or check every step and report all \
+                            failures",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("explain")
+                        .long("explain")
+                        .takes_value(true)
+                        .value_name("FORMAT")
+                        .possible_values(&["text", "json"])
+                        .help(
+                            "If a step fails, prints the step, its premises, the conclusion the \
+                            rule expected and the relevant sub-terms",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("normalize")
+                        .long("normalize")
+                        .takes_value(true)
+                        .value_name("PRESET")
+                        .possible_values(&["auto", "verit", "cvc5"])
+                        .help(
+                            "Checks failing steps again after normalizing their literals and the \
+                            literals of their premises, to work around the quirks of a proof \
+                            producer. \"auto\" picks the preset for the detected dialect",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("annotations")
+                        .long("annotations")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .help(
+                            "Reads solver annotations for the proof steps from FILE, and shows \
+                            the ones for the failing step, if a step fails",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("trace")
+                        .short("t")
+                        .long("trace")
+                        .help(
+                            "Like --verbose, but also prints the outcome and checking time of \
+                            every step to stderr",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("verbose")
+                        .short("v")
+                        .long("verbose")
+                        .visible_alias("stats")
+                        .help(
+                            "Also prints to stderr the warnings, the format of the proof and the \
+                            solver that produced it, the time spent checking each rule, and the \
+                            slowest steps",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("stats-top")
+                        .long("stats-top")
+                        .takes_value(true)
+                        .value_name("K")
+                        .default_value("10")
+                        .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                        .help("How many rules and steps are shown by --verbose"),
+                )
+                .arg(
+                    Arg::with_name("quiet")
+                        .short("q")
+                        .long("quiet")
+                        .conflicts_with_all(&["verbose", "trace", "explain"])
+                        .help("Prints nothing, and only reports the outcome through the exit code"),
+                )
+                .arg(
+                    Arg::with_name("memory-limit")
+                        .long("memory-limit")
+                        .takes_value(true)
+                        .value_name("MB")
+                        .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                        .help(
+                            "Aborts if the approximate memory used by terms and cached clauses \
+                            exceeds this limit",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("max-depth")
+                        .long("max-depth")
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                        .help(
+                            "Rejects inputs with terms or subproofs nested more than N levels \
+                            deep [default: 1000]",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("max-term-size")
+                        .long("max-term-size")
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                        .help(
+                            "Rejects inputs with terms that have more than N distinct subterms, \
+                            and stops checking if a rule builds one",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("max-clause-length")
+                        .long("max-clause-length")
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                        .help("Rejects proofs with clauses that have more than N terms"),
+                )
+                .arg(
+                    Arg::with_name("print-depth")
+                        .long("print-depth")
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                        .help(
+                            "Prints the subterms nested deeper than N as \"…\" in diagnostics \
+                            [default: 8]",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("print-width")
+                        .long("print-width")
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                        .help(
+                            "Prints only the first N arguments of each term in diagnostics \
+                            [default: 8]",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("full-terms")
+                        .long("full-terms")
+                        .conflicts_with_all(&["print-depth", "print-width"])
+                        .help("Prints terms in full in diagnostics, without truncating them"),
+                )
+                .arg(
+                    Arg::with_name("dump-obligations")
+                        .long("dump-obligations")
+                        .takes_value(true)
+                        .value_name("DIR")
+                        .help(
+                            "Writes a standalone SMT-LIB problem for each step that fails or is \
+                            skipped to DIR, asserting the premises and the negated conclusion",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("solver")
+                        .long("solver")
+                        .takes_value(true)
+                        .value_name("COMMAND")
+                        .help(
+                            "Checks steps whose rules are not implemented by running this SMT \
+                            solver command (e.g. \"z3 -in\"), which must read SMT-LIB from stdin",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("solver-timeout")
+                        .long("solver-timeout")
+                        .takes_value(true)
+                        .value_name("SECONDS")
+                        .default_value("10")
+                        .validator(|v| v.parse::<u64>().map(|_| ()).map_err(|e| e.to_string()))
+                        .help("The timeout for each call to the external solver"),
+                )
+                .arg(
+                    Arg::with_name("recover").long("recover").help(
+                        "Keeps parsing after syntax errors, and reports all of them at the end",
+                    ),
+                )
+                .arg(shadow_step_ids_arg())
+                .arg(strict_arities_arg())
+                .arg(format_arg()),
             SubCommand::with_name("parse")
                 .about("Parses a proof file and prints the AST")
                 .setting(AppSettings::DisableVersion)
                 .arg(Arg::with_name("PROBLEM_FILE").required(true))
+                .arg(Arg::with_name("PROOF_FILE").required(false))
+                .arg(include_arg())
+                .arg(
+                    Arg::with_name("recover").long("recover").help(
+                        "Keeps parsing after syntax errors, and reports all of them at the end",
+                    ),
+                )
+                .arg(shadow_step_ids_arg())
+                .arg(strict_arities_arg())
+                .arg(format_arg()),
+            SubCommand::with_name("lint")
+                .about(
+                    "Looks for problems in a proof that don't make it invalid, like unused premises \
+                    or duplicate steps",
+                )
+                .setting(AppSettings::DisableVersion)
+                .arg(Arg::with_name("PROBLEM_FILE").required(true))
+                .arg(Arg::with_name("PROOF_FILE").required(false))
+                .arg(include_arg())
+                .arg(
+                    Arg::with_name("allow")
+                        .short("A")
+                        .long("allow")
+                        .takes_value(true)
+                        .value_name("LINT")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .validator(validate_lint_name)
+                        .help("Doesn't report the given lint"),
+                )
+                .arg(
+                    Arg::with_name("warn")
+                        .short("W")
+                        .long("warn")
+                        .takes_value(true)
+                        .value_name("LINT")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .validator(validate_lint_name)
+                        .help("Reports the given lint as a warning"),
+                )
+                .arg(
+                    Arg::with_name("deny")
+                        .short("D")
+                        .long("deny")
+                        .takes_value(true)
+                        .value_name("LINT")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .validator(validate_lint_name)
+                        .help("Reports the given lint as an error, and fails if it is found"),
+                )
+                .arg(
+                    Arg::with_name("max-clause-size")
+                        .long("max-clause-size")
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                        .help("The number of literals above which a clause is reported as too large"),
+                )
+                .after_help(
+                    "LINTS:\n    duplicate_step, unused_premise, large_clause, simpler_rule, \
+                    unused_assumption",
+                ),
+            SubCommand::with_name("repl")
+                .about("Opens an interactive prompt for inspecting a proof")
+                .setting(AppSettings::DisableVersion)
+                .arg(Arg::with_name("PROBLEM_FILE").required(true))
+                .arg(Arg::with_name("PROOF_FILE").required(false)),
+            SubCommand::with_name("normalize")
+                .about(
+                    "Prints a proof in a canonical form, with deprecated rule names replaced and \
+                    redundant steps removed",
+                )
+                .setting(AppSettings::DisableVersion)
+                .arg(Arg::with_name("PROBLEM_FILE").required(true))
+                .arg(Arg::with_name("PROOF_FILE").required(false))
+                .arg(include_arg()),
+            SubCommand::with_name("grep")
+                .about("Lists the steps whose conclusions contain a term that matches a pattern")
+                .setting(AppSettings::DisableVersion)
+                .arg(
+                    Arg::with_name("PATTERN")
+                        .required(true)
+                        .validator(|p| pattern::Pattern::parse(&p).map(|_| ()).map_err(|e| format!("{:?}", e.0)))
+                        .help(
+                            "The pattern, like \"(= (f ?x) ?x)\". \"_\" matches any term, \
+                            \"?x\" matches any term but must match the same term everywhere, \
+                            and a trailing \"...\" matches any remaining arguments",
+                        ),
+                )
+                .arg(Arg::with_name("PROBLEM_FILE").required(true))
+                .arg(Arg::with_name("PROOF_FILE").required(false))
+                .arg(include_arg())
+                .arg(Arg::with_name("whole-terms").long("whole-terms").help(
+                    "Only lists the steps with a term in their conclusion that matches the pattern \
+                    as a whole, instead of any of its subterms",
+                )),
+            SubCommand::with_name("anonymize")
+                .about(
+                    "Renames all user symbols in a problem and its proof to generic names, so they \
+                    can be shared without revealing their origin",
+                )
+                .setting(AppSettings::DisableVersion)
+                .arg(Arg::with_name("PROBLEM_FILE").required(true))
+                .arg(Arg::with_name("PROOF_FILE").required(false))
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .value_name("DIR")
+                        .required(true)
+                        .help(
+                            "The directory where the anonymized problem and proof are written, as \
+                            \"problem.smt2\" and \"problem.smt2.proof\"",
+                        ),
+                ),
+            SubCommand::with_name("minimize")
+                .about(
+                    "Shrinks a failing proof to a small proof that still fails at the same step, \
+                    on the same rule",
+                )
+                .setting(AppSettings::DisableVersion)
+                .arg(Arg::with_name("PROBLEM_FILE").required(true))
+                .arg(Arg::with_name("PROOF_FILE").required(false))
+                .arg(include_arg())
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .help("Writes the minimized proof to FILE, instead of printing it"),
+                ),
+            SubCommand::with_name("reconstruct-problem")
+                .about(
+                    "Reconstructs a problem from a proof, declaring the symbols it uses and \
+                    asserting its assumptions, for when the original problem is not available",
+                )
+                .setting(AppSettings::DisableVersion)
+                .arg(Arg::with_name("PROOF_FILE").required(true))
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .help("Writes the problem to FILE, instead of printing it"),
+                ),
+            SubCommand::with_name("serve")
+                .about(
+                    "Keeps a proof in memory and answers JSON-RPC requests about it, read from \
+                    stdin one per line",
+                )
+                .setting(AppSettings::DisableVersion)
+                .arg(Arg::with_name("PROBLEM_FILE").required(true))
                 .arg(Arg::with_name("PROOF_FILE").required(false)),
+            SubCommand::with_name("check-model")
+                .about(
+                    "Evaluates the assertions of a problem under a model given by the \
+                    \"get-model\" command",
+                )
+                .setting(AppSettings::DisableVersion)
+                .arg(Arg::with_name("PROBLEM_FILE").required(true))
+                .arg(Arg::with_name("MODEL_FILE").required(true)),
+            SubCommand::with_name("rules")
+                .about("Lists the rules implemented by the checker")
+                .setting(AppSettings::DisableVersion)
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about(
+                            "Lists the rules implemented by the checker, with their categories. \
+                            This is the default if no subcommand is given",
+                        )
+                        .arg(Arg::with_name("json").long("json").help(
+                            "Prints the rules as JSON, with the expected number of premises and \
+                            arguments and the deprecated names of each rule",
+                        )),
+                )
+                .arg(
+                    Arg::with_name("describe")
+                        .long("describe")
+                        .takes_value(true)
+                        .value_name("NAME")
+                        .help(
+                            "Prints the category, expected premises and arguments, and \
+                            description of a rule",
+                        ),
+                ),
             SubCommand::with_name("progress-report")
                 .setting(AppSettings::DisableVersion)
                 .setting(AppSettings::DeriveDisplayOrder)
@@ -72,29 +567,720 @@ fn main() -> Result<(), Error> {
         ])
         .get_matches();
 
+    // Parsing and checking traverse terms recursively, so the stack must be large enough for the
+    // deepest terms allowed by the "--max-depth" option. The stack is only reserved, not
+    // allocated, so making it larger than needed doesn't use more memory
+    let max_depth = matches
+        .subcommand_matches("check")
+        .and_then(|m| m.value_of("max-depth"))
+        .map_or(parser::DEFAULT_MAX_DEPTH, |n| n.parse().unwrap());
+    let worker = thread::Builder::new()
+        .stack_size(stack_size(max_depth))
+        .spawn(move || run(matches))?;
+    match worker.join() {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => {
+            msg!(Normal, "Error: {:?}", e);
+            std::process::exit(exit_code::of_error(&e));
+        }
+
+        // The panic message was already printed by the panic hook
+        Err(_) => std::process::exit(exit_code::INTERNAL_ERROR),
+    }
+}
+
+/// The exit codes of the process, used when it doesn't succeed. They let scripts tell apart the
+/// outcomes of checking a proof without parsing the output. A valid proof exits with code 0, even
+/// if some steps were only checked modulo skipped rules. Other subcommands also use `INVALID` when
+/// their input fails their checks, like a proof with denied lints, a model that falsifies an
+/// assertion, a "grep" pattern that matches no step, or a proof to minimize that doesn't fail.
+mod exit_code {
+    use super::{CheckerError, Error, ErrorKind, ParserError};
+
+    /// The proof is invalid: a step failed to check, the proof doesn't conclude the empty clause
+    /// or the target clause, or it breaks some other requirement, like denied warnings. This is
+    /// also used when the problem doesn't match what was expected of it, like a status different
+    /// from the one given with "--expect-unsat", or no query that matches the proof.
+    pub const INVALID: i32 = 1;
+
+    /// The problem or the proof could not be parsed.
+    pub const PARSE_ERROR: i32 = 2;
+
+    /// The proof uses a rule that is not implemented, and "--skip-unknown-rules" was not given.
+    pub const UNKNOWN_RULE: i32 = 3;
+
+    /// The memory limit or the maximum nesting depth was exceeded.
+    pub const RESOURCE_LIMIT: i32 = 4;
+
+    /// An internal error, like a panic, or any other error, like a failure to run the external
+    /// solver.
+    pub const INTERNAL_ERROR: i32 = 5;
+
+    /// A file could not be opened, read or written.
+    pub const IO_ERROR: i32 = 6;
+
+    pub fn of_error(error: &Error) -> i32 {
+        match error {
+            Error::Parser(ParserError(
+                ErrorKind::MaxDepthExceeded(_)
+                | ErrorKind::TermSizeExceeded(_)
+                | ErrorKind::ClauseLengthExceeded(_),
+                _,
+            )) => RESOURCE_LIMIT,
+            Error::Parser(ParserError(ErrorKind::Io(_), _)) | Error::Io(_) => IO_ERROR,
+            Error::Parser(_) => PARSE_ERROR,
+            Error::Checker(CheckerError::TermSizeExceeded(_)) => RESOURCE_LIMIT,
+            Error::Checker(CheckerError::UnknownRule(_)) => UNKNOWN_RULE,
+            Error::Checker(CheckerError::Solver(_)) => INTERNAL_ERROR,
+            Error::Checker(_)
+            | Error::OutsideUnsatCore(_)
+            | Error::QueryNotFound(_)
+            | Error::StatusMismatch { .. } => INVALID,
+            Error::ResourceLimit(_) => RESOURCE_LIMIT,
+            Error::SkippedAssertions(_) => PARSE_ERROR,
+        }
+    }
+}
+
+/// The minimum stack size of the thread that runs the subcommands.
+const MIN_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// An upper bound on the stack space used for each level of term nesting, in debug builds.
+const STACK_SIZE_PER_LEVEL: usize = 32 * 1024;
+
+/// The stack size of the threads that parse or check terms nested up to `max_depth` levels deep.
+fn stack_size(max_depth: usize) -> usize {
+    std::cmp::max(
+        MIN_STACK_SIZE,
+        max_depth.saturating_mul(STACK_SIZE_PER_LEVEL),
+    )
+}
+
+/// The "--shadow-step-ids" argument, shared by the subcommands that parse a proof file.
+fn shadow_step_ids_arg() -> Arg<'static, 'static> {
+    Arg::with_name("shadow-step-ids")
+        .long("shadow-step-ids")
+        .help(
+            "Allows steps inside a subproof to reuse the ids of commands outside it, shadowing \
+            them until the end of the subproof. By default, repeated ids are an error",
+        )
+}
+
+/// The "--strict-arities" argument, shared by the subcommands that parse a proof file.
+fn strict_arities_arg() -> Arg<'static, 'static> {
+    Arg::with_name("strict-arities")
+        .long("strict-arities")
+        .help(
+            "Requires every operator to be applied to the number of arguments given by SMT-LIB. \
+            By default, \"and\", \"or\" and \"xor\" may be applied to a single argument, as \
+            veriT does in its proofs",
+        )
+}
+
+/// The "--format" argument, shared by the subcommands that parse a proof file.
+fn format_arg() -> Arg<'static, 'static> {
+    Arg::with_name("format")
+        .long("format")
+        .takes_value(true)
+        .possible_values(&["auto", "alethe", "legacy"])
+        .default_value("auto")
+        .help(
+            "The format of the proof file. \"legacy\" is the format of veriT releases older than \
+            Alethe, with \"set\" commands and \":conclusion\" attributes. With \"auto\", the format \
+            is detected from the first command of the proof",
+        )
+}
+
+/// Returns the format given with the "--format" argument, detecting it from the start of the
+/// proof file if it is "auto".
+fn proof_format(matches: &ArgMatches, proof_path: &str) -> Result<parser::ProofFormat, Error> {
+    Ok(match matches.value_of("format") {
+        Some("alethe") => parser::ProofFormat::Alethe,
+        Some("legacy") => parser::ProofFormat::Legacy,
+        _ => detect_proof_format(BufReader::new(File::open(proof_path)?))?.0,
+    })
+}
+
+/// The "--include" argument, shared by the subcommands that parse a problem file.
+fn include_arg() -> Arg<'static, 'static> {
+    Arg::with_name("include")
+        .long("include")
+        .visible_alias("prelude")
+        .takes_value(true)
+        .value_name("FILE")
+        .multiple(true)
+        .number_of_values(1)
+        .help(
+            "Parses the SMT-LIB file FILE before the problem, into the same symbol table. Can be \
+            given more than once, and the files are parsed in order",
+        )
+}
+
+/// Opens the files given with the "--include" argument, in order.
+fn open_includes(matches: &ArgMatches) -> Result<Vec<BufReader<File>>, Error> {
+    let paths = matches.values_of("include").into_iter().flatten();
+    Ok(paths
+        .map(|path| File::open(path).map(BufReader::new))
+        .collect::<Result<_, _>>()?)
+}
+
+fn validate_lint_name(name: String) -> Result<(), String> {
+    match lint::Lint::from_name(&name) {
+        Some(_) => Ok(()),
+        None => Err(format!("unknown lint \"{}\"", name)),
+    }
+}
+
+fn run(matches: ArgMatches<'static>) -> Result<(), Error> {
     if let Some(matches) = matches.subcommand_matches("check") {
+        let level = if matches.is_present("quiet") {
+            // Panics are also reported only through the exit code
+            std::panic::set_hook(Box::new(|_| ()));
+            log::Level::Quiet
+        } else if matches.is_present("trace") {
+            log::Level::Trace
+        } else if matches.is_present("verbose") {
+            log::Level::Verbose
+        } else {
+            log::Level::Normal
+        };
+        log::set_level(level);
+        let problem = matches.value_of("PROBLEM_FILE").unwrap();
+        let memory_limit = matches
+            .value_of("memory-limit")
+            .map(|mb| mb.parse::<usize>().unwrap() * 1024 * 1024);
+        let max_term_size = matches
+            .value_of("max-term-size")
+            .map(|n| n.parse().unwrap());
+        let max_depth = matches
+            .value_of("max-depth")
+            .map_or(parser::DEFAULT_MAX_DEPTH, |n| n.parse().unwrap());
+        let proof_path = if matches.is_present("proof-only") {
+            // In this case, the only file given is the proof file
+            problem.to_string()
+        } else {
+            matches
+                .value_of("PROOF_FILE")
+                .map(str::to_string)
+                .unwrap_or(problem.to_string() + ".proof")
+        };
+        let parser_config = parser::Config {
+            memory_limit,
+            error_recovery: matches.is_present("recover"),
+            max_depth,
+            max_term_size,
+            max_clause_length: matches
+                .value_of("max-clause-length")
+                .map(|n| n.parse().unwrap()),
+            opaque_definitions: matches.is_present("opaque-definitions"),
+            shadow_step_ids: matches.is_present("shadow-step-ids"),
+            permissive_arities: !matches.is_present("strict-arities"),
+            format: proof_format(matches, &proof_path)?,
+            ..Default::default()
+        };
+        let parser_format = parser_config.format;
+        let proof_only = matches.is_present("proof-only");
+        let open_proof = || File::open(&proof_path).map(BufReader::new);
+        let includes: Vec<_> = match proof_only {
+            true => Vec::new(),
+            false => open_includes(matches)?
+                .into_iter()
+                .map(|r| Box::new(r) as Box<dyn BufRead>)
+                .collect(),
+        };
+        let problem_reader = match proof_only {
+            true => None,
+            false => Some(Box::new(BufReader::new(File::open(problem)?)) as Box<dyn BufRead>),
+        };
+        let target = matches.value_of("target").filter(|_| !proof_only);
+        let mut stream = None;
+        let (problem, mut proof, target, mut pool) = match problem_reader {
+            _ if matches.is_present("pipeline") => {
+                let (problem, target, pool, proof_stream) = pipeline::parse_problem_stream_proof(
+                    includes,
+                    problem_reader,
+                    open_proof()?,
+                    target,
+                    parser_config,
+                    pipeline::DEFAULT_CAPACITY,
+                    stack_size(max_depth),
+                )?;
+                stream = Some(proof_stream);
+                let problem = (!proof_only).then_some(problem);
+                (problem, Proof(Vec::new()), target, pool)
+            }
+            None => {
+                let (proof, pool) = parse_proof_only(open_proof()?, parser_config)?;
+                (None, proof, None, pool)
+            }
+            Some(problem_reader) => {
+                let proof_reader = Box::new(open_proof()?);
+                if let Some(target) = target {
+                    let (problem, proof, target, pool) = parse_problem_proof_with_target(
+                        includes,
+                        problem_reader,
+                        proof_reader,
+                        target,
+                        parser_config,
+                    )?;
+                    (Some(problem), proof, Some(target), pool)
+                } else {
+                    let (problem, proof, pool) = parse_problem_proof_with_includes(
+                        includes,
+                        problem_reader,
+                        proof_reader,
+                        parser_config,
+                    )?;
+                    (Some(problem), proof, None, pool)
+                }
+            }
+        };
+        if let Some(problem) = &problem {
+            for ParserError(kind, position) in &problem.skipped_assertions {
+                let position = position.map_or(String::new(), |(l, c)| format!(" at {}:{}", l, c));
+                msg!(
+                    Verbose,
+                    "warning: skipped an assertion that could not be parsed{}: {:?}",
+                    position,
+                    kind
+                );
+            }
+            if matches.is_present("expect-unsat") {
+                check_status(problem, Status::Unsat)?;
+            } else if matches.is_present("expect-sat") {
+                check_status(problem, Status::Sat)?;
+            } else if problem.status == Some(Status::Sat) {
+                msg!(
+                    Verbose,
+                    "warning: the problem is annotated as \"sat\", but the proof is a proof of \
+                    unsatisfiability"
+                );
+            }
+        }
+        let query_assertions = match (matches.value_of("query"), &problem) {
+            (Some(query), Some(problem)) => {
+                let query = match query {
+                    "auto" => QuerySelector::MatchAssumptions,
+                    n => QuerySelector::Index(n.parse().unwrap()),
+                };
+                Some(select_query(problem, &proof, query)?)
+            }
+            _ => None,
+        };
+
+        // The progress bar is only shown if the output is a terminal, and it would be mixed up
+        // with the trace output, so it is also disabled when tracing. When the proof is parsed
+        // while it is checked, its number of steps is not known in advance, so there is no
+        // progress bar either
+        let show_progress = matches!(level, log::Level::Normal | log::Level::Verbose)
+            && std::io::stdout().is_terminal()
+            && stream.is_none();
+        let trace: Option<TraceCallback> = if level == log::Level::Trace {
+            Some(Box::new(print_step_trace))
+        } else if show_progress {
+            let mut progress_bar = ProgressBar::new(proof.num_steps());
+            Some(Box::new(move |_| progress_bar.step()))
+        } else {
+            None
+        };
+        let stats_top: usize = matches.value_of("stats-top").unwrap().parse().unwrap();
+        let print_limits = if matches.is_present("full-terms") {
+            PrintLimits::UNLIMITED
+        } else {
+            let value = |arg, default| {
+                matches
+                    .value_of(arg)
+                    .map_or(default, |n| n.parse().unwrap())
+            };
+            PrintLimits {
+                max_depth: Some(value("print-depth", PrintLimits::DEFAULT_MAX_DEPTH)),
+                max_width: Some(value("print-width", PrintLimits::DEFAULT_MAX_WIDTH)),
+            }
+        };
+        let normalization = match matches.value_of("normalize") {
+            Some("verit") => Some(NormalizationPreset::VeriT),
+            Some("cvc5") => Some(NormalizationPreset::Cvc5),
+            Some(_) => match detect_proof_format(BufReader::new(File::open(&proof_path)?))?.1 {
+                ProofDialect::VeriT => Some(NormalizationPreset::VeriT),
+                ProofDialect::Cvc5 => Some(NormalizationPreset::Cvc5),
+                ProofDialect::Unknown => None,
+            },
+            None => None,
+        };
+        let config = checker::Config {
+            skip_unknown_rules: matches.is_present("skip-unknown-rules"),
+            allow_test_rule: false,
+            strictness: match matches.value_of("strictness").unwrap() {
+                "strict" => Strictness::Strict,
+                "permissive" => Strictness::Permissive,
+                _ => Strictness::Default,
+            },
+            memory_limit,
+            max_term_size,
+            eval_fallback: matches.is_present("eval-fallback"),
+            external_solver: matches.value_of("solver").map(|command| SolverConfig {
+                command: command.split_whitespace().map(str::to_owned).collect(),
+                timeout: Duration::from_secs(
+                    matches.value_of("solver-timeout").unwrap().parse().unwrap(),
+                ),
+            }),
+            deny_warnings: matches.is_present("deny-warnings"),
+            lenient_warnings: log::enabled(log::Level::Verbose),
+            trace,
+            stats: log::enabled(log::Level::Verbose).then_some(stats_top),
+            require_empty_clause: !matches.is_present("partial"),
+            target,
+            check_sorts: matches.is_present("check-sorts"),
+            collect_obligations: matches.is_present("dump-obligations"),
+            print_limits,
+            normalization: normalization.map(NormalizationPreset::hook),
+            failure_mode: match matches.value_of("failure-mode").unwrap() {
+                "finish-subproof" => FailureMode::FinishSubproof,
+                "check-all" => FailureMode::CheckAll,
+                _ => FailureMode::Stop,
+            },
+        };
+        let mut checker = ProofChecker::new(&mut pool, config);
+        let result = match (&query_assertions, stream) {
+            (_, Some(mut stream)) => {
+                let result = checker.check_stream(&mut proof, |pool| stream.next_command(pool));
+                stream.finish()?;
+                result
+            }
+            (Some(assertions), None) => checker.check_with_assertions(&proof, assertions),
+            (None, None) => checker.check(&proof),
+        };
+        if show_progress {
+            ProgressBar::clear();
+        }
+        if let Some(stats) = checker.stats() {
+            let metadata =
+                parse_proof_metadata(BufReader::new(File::open(&proof_path)?), problem.as_ref())?;
+            let (_, dialect) = detect_proof_format(BufReader::new(File::open(&proof_path)?))?;
+            msg!(
+                Verbose,
+                "proof format: {} ({} dialect)",
+                parser_format,
+                dialect
+            );
+            print_metadata(&metadata);
+            print_stats(stats, stats_top);
+        }
+        let result = result?;
+        outln!("{}", result);
+        for warning in checker.warnings() {
+            msg!(Verbose, "warning: {}", warning);
+        }
+        let solver_checked = checker.solver_checked_steps();
+        if !solver_checked.is_empty() {
+            msg!(
+                Verbose,
+                "steps checked by the external solver: {}",
+                solver_checked.join(", ")
+            );
+        }
+        let mut explanations = checker.explanations().to_vec();
+        if let Some(path) = matches.value_of("annotations") {
+            let mut annotations = parse_annotations(BufReader::new(File::open(path)?))?;
+            for explanation in &mut explanations {
+                match annotations.remove(&explanation.id) {
+                    Some(attributes) => explanation.annotations = attributes,
+                    None => msg!(
+                        Verbose,
+                        "warning: there are no solver annotations for step \"{}\"",
+                        explanation.id
+                    ),
+                }
+            }
+        }
+        for explanation in &explanations {
+            match matches.value_of("explain") {
+                Some("json") => outln!("{}", explanation.to_json()),
+                Some(_) => out!("{}", explanation),
+                None => {
+                    let color = std::io::stderr().is_terminal();
+                    let diagnostic = explanation.to_diagnostic(&proof_path, color);
+                    msg!(Normal, "{}", diagnostic.trim_end());
+                }
+            }
+        }
+        if let Some(dir) = matches.value_of("dump-obligations") {
+            fs::create_dir_all(dir)?;
+            for obligation in checker.obligations() {
+                let path = Path::new(dir).join(format!("{}.smt2", obligation.id));
+                fs::write(&path, &obligation.query)?;
+                msg!(
+                    Normal,
+                    "wrote the proof obligation for step \"{}\" to {}",
+                    obligation.id,
+                    path.display()
+                );
+            }
+        }
+        if let (Some(path), Some(problem)) = (matches.value_of("unsat-core"), &problem) {
+            let core = parse_unsat_core(BufReader::new(File::open(path)?))?;
+            let comparison = compare_unsat_core(problem, &proof, &core);
+            if !comparison.is_consistent() {
+                return Err(Error::OutsideUnsatCore(comparison.outside_core));
+            }
+            if comparison.proof_core.len() < core.len() {
+                msg!(
+                    Verbose,
+                    "the proof only depends on {} of the {} assertions in the unsat core: ({})",
+                    comparison.proof_core.len(),
+                    core.len(),
+                    comparison.proof_core.join(" ")
+                );
+            }
+        }
+        if !result.is_valid() {
+            std::process::exit(exit_code::INVALID);
+        }
+    } else if let Some(matches) = matches.subcommand_matches("parse") {
+        let problem = matches.value_of("PROBLEM_FILE").unwrap();
+        let proof = matches
+            .value_of("PROOF_FILE")
+            .map(str::to_string)
+            .unwrap_or(problem.to_string() + ".proof");
+        let format = proof_format(matches, &proof)?;
+        let (problem, proof) = (
+            BufReader::new(File::open(problem)?),
+            BufReader::new(File::open(proof)?),
+        );
+        let config = parser::Config {
+            error_recovery: matches.is_present("recover"),
+            shadow_step_ids: matches.is_present("shadow-step-ids"),
+            permissive_arities: !matches.is_present("strict-arities"),
+            format,
+            ..Default::default()
+        };
+        let includes = open_includes(matches)?;
+        let (_, proof, _) = parse_problem_proof_with_includes(includes, problem, proof, config)?;
+        outln!("{:#?}", proof);
+    } else if let Some(matches) = matches.subcommand_matches("lint") {
         let problem = matches.value_of("PROBLEM_FILE").unwrap();
         let proof = matches
             .value_of("PROOF_FILE")
             .map(str::to_string)
             .unwrap_or(problem.to_string() + ".proof");
-        let skip = matches.is_present("skip-unknown-rules");
-        match check(problem, &proof, skip, false)? {
-            Correctness::True => println!("true"),
-            Correctness::False(r) => println!("false ({})", r),
+        let (_, proof, mut pool) = parse_problem_proof_with_includes(
+            open_includes(matches)?,
+            BufReader::new(File::open(problem)?),
+            BufReader::new(File::open(proof)?),
+            parser::Config::default(),
+        )?;
+        let mut config = lint::Config::default();
+        if let Some(n) = matches.value_of("max-clause-size") {
+            config.max_clause_size = n.parse().unwrap();
         }
-    } else if let Some(matches) = matches.subcommand_matches("parse") {
+        let levels = [
+            ("allow", lint::Level::Allow),
+            ("warn", lint::Level::Warn),
+            ("deny", lint::Level::Deny),
+        ];
+        for (arg, level) in levels {
+            for name in matches.values_of(arg).into_iter().flatten() {
+                config.set_level(lint::Lint::from_name(name).unwrap(), level);
+            }
+        }
+        let diagnostics = lint::lint(&proof, &mut pool, &config);
+        for d in &diagnostics {
+            outln!("{}", d);
+        }
+        if diagnostics.iter().any(|d| d.level == lint::Level::Deny) {
+            std::process::exit(exit_code::INVALID);
+        }
+    } else if let Some(matches) = matches.subcommand_matches("repl") {
         let problem = matches.value_of("PROBLEM_FILE").unwrap();
         let proof = matches
             .value_of("PROOF_FILE")
             .map(str::to_string)
             .unwrap_or(problem.to_string() + ".proof");
-        let (problem, proof) = (
+        let (_, proof, mut pool) = parse_problem_proof(
+            BufReader::new(File::open(problem)?),
+            BufReader::new(File::open(proof)?),
+            parser::Config::default(),
+        )?;
+        let config = checker::Config {
+            skip_unknown_rules: true,
+            ..Default::default()
+        };
+        repl::run(proof, ProofChecker::new(&mut pool, config))?;
+    } else if let Some(matches) = matches.subcommand_matches("normalize") {
+        let problem = matches.value_of("PROBLEM_FILE").unwrap();
+        let proof = matches
+            .value_of("PROOF_FILE")
+            .map(str::to_string)
+            .unwrap_or(problem.to_string() + ".proof");
+        let (_, proof, mut pool) = parse_problem_proof_with_includes(
+            open_includes(matches)?,
+            BufReader::new(File::open(problem)?),
+            BufReader::new(File::open(proof)?),
+            parser::Config::default(),
+        )?;
+        out!("{}", normalize::normalize(&mut pool, proof).to_alethe());
+    } else if let Some(matches) = matches.subcommand_matches("grep") {
+        let pattern = pattern::Pattern::parse(matches.value_of("PATTERN").unwrap()).unwrap();
+        let problem = matches.value_of("PROBLEM_FILE").unwrap();
+        let proof = matches
+            .value_of("PROOF_FILE")
+            .map(str::to_string)
+            .unwrap_or(problem.to_string() + ".proof");
+        let (_, proof, _) = parse_problem_proof_with_includes(
+            open_includes(matches)?,
+            BufReader::new(File::open(problem)?),
+            BufReader::new(File::open(proof)?),
+            parser::Config::default(),
+        )?;
+        let found = grep::grep(&proof, &pattern, matches.is_present("whole-terms"));
+        for m in &found {
+            out!("{}: (cl", m.id);
+            for term in m.clause {
+                out!(" {:?}", term);
+            }
+            outln!(")");
+        }
+
+        // Like the "grep" tool, we exit with an error if no step matched
+        if found.is_empty() {
+            std::process::exit(exit_code::INVALID);
+        }
+    } else if let Some(matches) = matches.subcommand_matches("anonymize") {
+        let problem = matches.value_of("PROBLEM_FILE").unwrap();
+        let proof = matches
+            .value_of("PROOF_FILE")
+            .map(str::to_string)
+            .unwrap_or(problem.to_string() + ".proof");
+        let (problem, proof) = anonymize::anonymize(
+            BufReader::new(File::open(problem)?),
+            BufReader::new(File::open(proof)?),
+        )?;
+        let dir = Path::new(matches.value_of("output").unwrap());
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("problem.smt2"), problem)?;
+        fs::write(dir.join("problem.smt2.proof"), proof)?;
+        msg!(
+            Normal,
+            "wrote the anonymized problem and proof to {}",
+            dir.display()
+        );
+    } else if let Some(matches) = matches.subcommand_matches("minimize") {
+        let problem = matches.value_of("PROBLEM_FILE").unwrap();
+        let proof = matches
+            .value_of("PROOF_FILE")
+            .map(str::to_string)
+            .unwrap_or(problem.to_string() + ".proof");
+        let (_, proof, mut pool) = parse_problem_proof_with_includes(
+            open_includes(matches)?,
             BufReader::new(File::open(problem)?),
             BufReader::new(File::open(proof)?),
+            parser::Config::default(),
+        )?;
+        let config = || checker::Config {
+            skip_unknown_rules: true,
+            ..Default::default()
+        };
+        let minimized = match minimize::minimize(&mut pool, &proof, config) {
+            Some(minimized) => minimized,
+            None => {
+                msg!(
+                    Normal,
+                    "the proof doesn't fail, so there is nothing to minimize"
+                );
+                std::process::exit(exit_code::INVALID);
+            }
+        };
+        msg!(
+            Normal,
+            "step {} still fails on rule \"{}\", with {} steps instead of {} ({} checks)",
+            minimized.id,
+            minimized.rule,
+            minimized.proof.num_steps(),
+            proof.num_steps(),
+            minimized.checks,
         );
-        let (proof, _) = parse_problem_proof(problem, proof)?;
-        println!("{:#?}", proof);
+        match matches.value_of("output") {
+            Some(path) => fs::write(path, minimized.proof.to_alethe())?,
+            None => out!("{}", minimized.proof.to_alethe()),
+        }
+    } else if let Some(matches) = matches.subcommand_matches("reconstruct-problem") {
+        let proof = matches.value_of("PROOF_FILE").unwrap();
+        let (declarations, proof, mut pool) = parse_proof_only_with_declarations(
+            BufReader::new(File::open(proof)?),
+            parser::Config::default(),
+        )?;
+        let problem = match reconstruct::reconstruct_problem(&mut pool, &declarations, &proof) {
+            Some(problem) => problem,
+            None => {
+                msg!(
+                    Normal,
+                    "couldn't infer consistent sorts for the symbols used in the proof"
+                );
+                std::process::exit(exit_code::PARSE_ERROR);
+            }
+        };
+        match matches.value_of("output") {
+            Some(path) => fs::write(path, problem)?,
+            None => out!("{}", problem),
+        }
+    } else if let Some(matches) = matches.subcommand_matches("serve") {
+        let problem = matches.value_of("PROBLEM_FILE").unwrap();
+        let proof = matches
+            .value_of("PROOF_FILE")
+            .map(str::to_string)
+            .unwrap_or(problem.to_string() + ".proof");
+        serve::run(
+            std::fs::read_to_string(problem)?,
+            std::fs::read_to_string(proof)?,
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("check-model") {
+        let (problem, model, _) = parse_problem_model(
+            BufReader::new(File::open(matches.value_of("PROBLEM_FILE").unwrap())?),
+            BufReader::new(File::open(matches.value_of("MODEL_FILE").unwrap())?),
+            parser::Config::default(),
+        )?;
+        let results = eval::check_model(&problem, &model);
+        for &(i, value) in &results {
+            let (name, term) = &problem.assertions[i];
+            let name = name.clone().unwrap_or_else(|| format!("#{}", i));
+            match value {
+                Some(true) => (),
+                Some(false) => outln!("assertion {} is false: {:?}", name, term),
+                None => outln!("assertion {} could not be evaluated: {:?}", name, term),
+            }
+        }
+        if results.iter().any(|&(_, value)| value == Some(false)) {
+            outln!("false");
+            std::process::exit(exit_code::INVALID);
+        } else if results.iter().any(|&(_, value)| value.is_none()) {
+            outln!("unknown");
+        } else {
+            outln!("true");
+        }
+    } else if let Some(matches) = matches.subcommand_matches("rules") {
+        match matches.value_of("describe") {
+            Some(name) => {
+                let info = rule_info(name)
+                    .ok_or_else(|| Error::Checker(CheckerError::UnknownRule(name.to_owned())))?;
+                outln!("{} ({})", info.name, info.category);
+                outln!("  {}", info.description);
+                outln!("premises: {}", info.premises);
+                outln!("arguments: {}", info.args);
+                outln!("conclusion: {}", info.conclusion);
+            }
+            None => {
+                let json = matches
+                    .subcommand_matches("list")
+                    .is_some_and(|m| m.is_present("json"));
+                if json {
+                    outln!("{}", rules_to_json());
+                } else {
+                    for info in RULES {
+                        outln!("{:<20}{}", info.name, info.category);
+                    }
+                }
+            }
+        }
     } else if let Some(matches) = matches.subcommand_matches("progress-report") {
         let files = matches
             .values_of("files")
@@ -107,46 +1293,197 @@ fn main() -> Result<(), Error> {
             report_by_rules(&files, quiet)?;
         } else if matches.is_present("by-files-and-rules") {
             for file in files {
-                println!("\x1b[0;0m{}:", file);
+                outln!("\x1b[0;0m{}:", file);
                 report_by_rules(&[file], quiet)?;
-                println!();
+                outln!();
             }
         }
     }
     Ok(())
 }
 
-fn get_used_rules(file_path: &str) -> ParserResult<Vec<String>> {
-    use parser::lexer::{Lexer, Token};
+/// A progress bar that is updated in place, showing how many steps were checked and an estimate of
+/// how long it will take to check the remaining ones.
+struct ProgressBar {
+    total: usize,
+    done: usize,
+    start_time: Instant,
+    last_update: Option<Instant>,
+}
 
-    let file = File::open(file_path)?;
-    let mut lex = Lexer::new(BufReader::new(file))?;
-    let mut result = Vec::new();
-    loop {
-        let tk = lex.next_token()?;
-        match tk {
-            Token::Eof => break,
-            Token::Keyword(s) if &s == "rule" => {
-                let rule_name = match lex.next_token()? {
-                    Token::Symbol(s) => s,
-                    Token::ReservedWord(r) => format!("{:?}", r),
-                    _ => continue,
-                };
-                result.push(rule_name)
+impl ProgressBar {
+    const WIDTH: usize = 40;
+    const UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+
+    fn new(total: usize) -> Self {
+        Self {
+            total,
+            done: 0,
+            start_time: Instant::now(),
+            last_update: None,
+        }
+    }
+
+    fn step(&mut self) {
+        self.done += 1;
+
+        // Redrawing the bar after every step would slow down checking considerably, so we only
+        // redraw it periodically
+        let now = Instant::now();
+        if let Some(last) = self.last_update {
+            if now - last < Self::UPDATE_INTERVAL && self.done < self.total {
+                return;
             }
-            _ => (),
         }
+        self.last_update = Some(now);
+
+        let ratio = self.done as f64 / self.total.max(1) as f64;
+        let filled = (ratio * Self::WIDTH as f64) as usize;
+        let elapsed = self.start_time.elapsed();
+        let eta = elapsed.mul_f64((1.0 - ratio) / ratio);
+        out!(
+            "\r[{}{}] {}/{} steps, ETA {}s\x1b[K",
+            "#".repeat(filled),
+            " ".repeat(Self::WIDTH - filled),
+            self.done,
+            self.total,
+            eta.as_secs(),
+        );
+        std::io::stdout().flush().unwrap();
+    }
+
+    /// Erases the progress bar from the current line.
+    fn clear() {
+        out!("\r\x1b[K");
+        std::io::stdout().flush().unwrap();
+    }
+}
+
+fn print_step_trace(event: &StepTrace) {
+    let outcome = match event.outcome {
+        StepOutcome::Valid => "valid",
+        StepOutcome::Invalid => "invalid",
+        StepOutcome::Skipped => "skipped",
+        StepOutcome::SolverChecked => "solver-checked",
+    };
+    msg!(
+        Trace,
+        "{:indent$}{}: {} ({}, {:?})",
+        "",
+        event.id,
+        event.rule,
+        outcome,
+        event.duration,
+        indent = event.depth * 2,
+    );
+}
+
+fn print_metadata(metadata: &ast::ProofMetadata) {
+    if let Some(producer) = metadata.producer() {
+        msg!(Verbose, "produced by: {}", producer);
+    }
+    if let Some(logic) = &metadata.logic {
+        msg!(Verbose, "logic: {}", logic);
+    }
+    for key in ["smt-lib-version", "category", "status"] {
+        if let Some(value) = metadata.info(key) {
+            msg!(Verbose, "{}: {}", key, value);
+        }
+    }
+}
+
+fn print_stats(stats: &Stats, top: usize) {
+    let rules = stats.rules_by_time();
+    msg!(
+        Verbose,
+        "total checking time: {:?}, in {} rules",
+        stats.total_time(),
+        rules.len()
+    );
+    msg!(Verbose, "slowest rules, by total time:");
+    msg!(
+        Verbose,
+        "  {:<24} {:>8} {:>14} {:>14}",
+        "rule",
+        "steps",
+        "total",
+        "max"
+    );
+    for (rule, s) in rules.into_iter().take(top) {
+        msg!(
+            Verbose,
+            "  {:<24} {:>8} {:>14} {:>14}",
+            rule,
+            s.count,
+            format!("{:?}", s.total_time),
+            format!("{:?}", s.max_time),
+        );
+    }
+    msg!(Verbose, "slowest steps:");
+    for step in stats.slowest_steps().into_iter().take(top) {
+        msg!(
+            Verbose,
+            "  {} ({}): {:?}",
+            step.id,
+            step.rule,
+            step.duration
+        );
+    }
+}
+
+/// Returns the rules implemented by the checker as a JSON object. The number of premises or
+/// arguments a rule expects is given as an object with its minimum and maximum, where the maximum
+/// is `null` if there is no limit.
+fn rules_to_json() -> Json {
+    fn count(count: Count) -> Json {
+        let (min, max) = match count {
+            Count::Exactly(n) => (n, Some(n)),
+            Count::AtMost(n) => (0, Some(n)),
+            Count::AtLeast(n) => (n, None),
+        };
+        Json::object([
+            ("min", Json::Number(min as f64)),
+            ("max", max.map_or(Json::Null, |n| Json::Number(n as f64))),
+        ])
     }
-    Ok(result)
+
+    let rules = RULES.iter().map(|info| {
+        let aliases = DEPRECATED_RULE_NAMES
+            .iter()
+            .filter(|(_, new)| *new == info.name)
+            .map(|(old, _)| old);
+        Json::object([
+            ("name", Json::String(info.name.to_owned())),
+            ("category", Json::String(info.category.to_string())),
+            ("premises", count(info.premises)),
+            ("args", count(info.args)),
+            ("description", Json::String(info.description.to_owned())),
+            ("conclusion", Json::String(info.conclusion.to_owned())),
+            ("lenient", Json::Bool(info.lenient)),
+            ("aliases", Json::strings(aliases)),
+        ])
+    });
+    Json::object([
+        (
+            "version",
+            Json::String(env!("CARGO_PKG_VERSION").to_owned()),
+        ),
+        ("rules", Json::Array(rules.collect())),
+    ])
+}
+
+fn get_used_rules(file_path: &str) -> ParserResult<Vec<String>> {
+    let file = File::open(file_path)?;
+    parser::get_used_rules(BufReader::new(file))
 }
 
 fn print_report_entry(s: &str, success: bool, quiet: bool) {
-    print!("{}", if success { "\x1b[1;32m" } else { "\x1b[0;31m" });
+    out!("{}", if success { "\x1b[1;32m" } else { "\x1b[0;31m" });
     if quiet {
-        print!(".");
+        out!(".");
         std::io::stdout().flush().unwrap();
     } else {
-        println!("{}", s);
+        outln!("{}", s);
     }
 }
 
@@ -160,9 +1497,9 @@ fn report_by_files(files: &[&str], quiet: bool) -> ParserResult<()> {
         implemented += all_implemented as i32;
     }
     if quiet {
-        println!();
+        outln!();
     }
-    println!(
+    outln!(
         "\x1b[0;0m{} / {} files with all rules implemented",
         implemented,
         files.len()
@@ -187,9 +1524,9 @@ fn report_by_rules(files: &[&str], quiet: bool) -> ParserResult<()> {
         }
     }
     if quiet {
-        println!();
+        outln!();
     }
-    println!(
+    outln!(
         "\x1b[0;0m{} / {} rules implemented",
         implemented,
         seen.len()
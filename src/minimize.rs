@@ -0,0 +1,362 @@
+//! A minimizer for failing proofs, that shrinks a proof to a small reproducer of the failure. The
+//! failure is identified by the id and rule of the first step that fails to check, and a reduction
+//! is only kept if checking the reduced proof still fails first at that step, on the same rule. The
+//! minimizer works in three passes:
+//!
+//! - the proof is sliced to the failing step and the commands it depends on, according to the
+//!   `DependencyGraph` of the proof;
+//! - the remaining commands are removed by delta debugging, trying to remove large groups of
+//!   commands first, and then smaller ones. Premises that refer to removed commands are dropped, as
+//!   in `rewrite_proof`;
+//! - each subterm is replaced by one of its arguments of the same sort, everywhere in the proof.
+//!
+//! The problem is not changed, since the "assume" commands are not checked against it.
+
+use crate::{
+    ast::*,
+    checker::{self, Correctness, ProofChecker},
+};
+use std::collections::HashSet;
+
+/// A minimized proof. See `minimize`.
+#[derive(Debug)]
+pub struct Minimized {
+    pub proof: Proof,
+
+    /// The id of the failing step, which is the same in the original and in the minimized proof.
+    pub id: String,
+
+    /// The rule of the failing step.
+    pub rule: String,
+
+    /// The number of times a reduced proof was checked.
+    pub checks: usize,
+}
+
+/// Minimizes `proof`, which must fail to check. Since the checker configuration can't be cloned,
+/// `checker_config` is called to create a new one for each check. New terms are added to `pool`.
+/// Returns `None` if the proof doesn't fail, or if checking it returns an error.
+pub fn minimize<F>(pool: &mut TermPool, proof: &Proof, checker_config: F) -> Option<Minimized>
+where
+    F: FnMut() -> checker::Config,
+{
+    let mut minimizer = Minimizer {
+        pool,
+        checker_config,
+        id: String::new(),
+        rule: String::new(),
+        checks: 0,
+    };
+    let (id, rule) = minimizer.first_failure(proof)?;
+    minimizer.id = id;
+    minimizer.rule = rule;
+
+    let proof = minimizer.slice(proof);
+    let proof = minimizer.remove_commands(proof);
+    let proof = minimizer.reduce_terms(proof);
+    Some(Minimized {
+        proof,
+        id: minimizer.id,
+        rule: minimizer.rule,
+        checks: minimizer.checks,
+    })
+}
+
+struct Minimizer<'p, F> {
+    pool: &'p mut TermPool,
+    checker_config: F,
+    id: String,
+    rule: String,
+    checks: usize,
+}
+
+impl<F: FnMut() -> checker::Config> Minimizer<'_, F> {
+    /// Returns the id and rule of the first step that fails to check in `proof`.
+    fn first_failure(&mut self, proof: &Proof) -> Option<(String, String)> {
+        let mut checker = ProofChecker::new(self.pool, (self.checker_config)());
+        match checker.check(proof) {
+            Ok(Correctness::False(_)) => {
+                let explanation = checker.explanation()?;
+                Some((explanation.id.clone(), explanation.rule.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if checking `proof` still fails first at the failing step, on its rule.
+    fn still_fails(&mut self, proof: &Proof) -> bool {
+        self.checks += 1;
+        let (id, rule) = (&self.id, &self.rule);
+        let mut checker = ProofChecker::new(self.pool, (self.checker_config)());
+        matches!(checker.check(proof), Ok(Correctness::False(_)))
+            && checker
+                .explanation()
+                .is_some_and(|e| e.id == *id && e.rule == *rule)
+    }
+
+    /// Removes the commands that the failing step doesn't depend on. The subproofs that contain
+    /// the failing step are always kept.
+    fn slice(&mut self, proof: &Proof) -> Proof {
+        let graph = DependencyGraph::new(proof);
+        let mut needed: HashSet<&str> = graph.dependencies(&self.id).unwrap().into_iter().collect();
+        needed.insert(&self.id);
+        let location = proof.find_command(&self.id).unwrap();
+        needed.extend(location.enclosing.iter().map(|c| c.id()));
+
+        let (removable, _) = removable_commands(proof);
+        let removed = removable
+            .into_iter()
+            .filter(|id| !needed.contains(id.as_str()))
+            .collect();
+        let sliced = without_commands(self.pool, proof.clone(), &removed);
+        if self.still_fails(&sliced) {
+            sliced
+        } else {
+            proof.clone()
+        }
+    }
+
+    /// Removes commands from `proof` by delta debugging. Commands are tried in groups, starting
+    /// with two halves of all removable commands, and the groups are split in half whenever no
+    /// group can be removed.
+    fn remove_commands(&mut self, mut proof: Proof) -> Proof {
+        // The failing step and the subproofs that contain it can never be removed
+        let location = proof.find_command(&self.id).unwrap();
+        let mut kept: HashSet<_> = location
+            .enclosing
+            .iter()
+            .map(|c| c.id().to_owned())
+            .collect();
+        kept.insert(self.id.clone());
+        let (mut candidates, _) = removable_commands(&proof);
+        candidates.retain(|id| !kept.contains(id));
+        let mut num_groups = 2;
+        while !candidates.is_empty() {
+            let group_size = candidates.len().div_ceil(num_groups);
+            let mut removed_any = false;
+            for start in (0..candidates.len()).step_by(group_size) {
+                let end = std::cmp::min(start + group_size, candidates.len());
+                let removed = candidates[start..end].iter().cloned().collect();
+                let reduced = without_commands(self.pool, proof.clone(), &removed);
+                if self.still_fails(&reduced) {
+                    proof = reduced;
+                    removed_any = true;
+                    break;
+                }
+            }
+            if removed_any {
+                // Removing a subproof also removes the commands inside it
+                let (remaining, _) = removable_commands(&proof);
+                candidates.retain(|id| remaining.contains(id));
+                num_groups = std::cmp::max(num_groups - 1, 2);
+            } else if group_size == 1 {
+                break;
+            } else {
+                num_groups = std::cmp::min(num_groups * 2, candidates.len());
+            }
+        }
+        proof
+    }
+
+    /// Replaces subterms of the terms in `proof` with their arguments of the same sort, one at a
+    /// time, until no replacement keeps the proof failing.
+    fn reduce_terms(&mut self, mut proof: Proof) -> Proof {
+        // Once a replacement fails, it is not tried again, even after other replacements change
+        // the proof. This may miss some reductions, but guarantees that the number of checks is
+        // at most the number of distinct replacements
+        let mut tried = HashSet::new();
+        'outer: loop {
+            for (term, replacement) in self.replacements(&proof) {
+                if !tried.insert((term.clone(), replacement.clone())) {
+                    continue;
+                }
+                let mut rewriter = Substitute {
+                    substitution: Substitution::single(term, replacement),
+                    changed: false,
+                };
+                let reduced = rewrite_proof(&mut rewriter, self.pool, proof.clone());
+                if rewriter.changed && self.still_fails(&reduced) {
+                    proof = reduced;
+                    continue 'outer;
+                }
+            }
+            return proof;
+        }
+    }
+
+    /// Returns the possible replacements of the subterms of the terms in `proof`, as pairs of a
+    /// subterm and one of its arguments of the same sort.
+    fn replacements(&mut self, proof: &Proof) -> Vec<(ByRefRc<Term>, ByRefRc<Term>)> {
+        let mut terms = Vec::new();
+        collect_terms(&proof.0, &mut terms);
+
+        let mut seen = HashSet::new();
+        let mut result = Vec::new();
+        for root in terms {
+            let mut stack = vec![root];
+            while let Some(term) = stack.pop() {
+                if !seen.insert(term.clone()) {
+                    continue;
+                }
+                let args: &[_] = match term.as_ref() {
+                    Term::App(_, args) | Term::Op(_, args) => args,
+                    Term::Quant(_, _, body) => std::slice::from_ref(body),
+                    _ => continue,
+                };
+                let sort = self.pool.sort(&term);
+                for arg in args {
+                    if self.pool.sort(arg) == sort {
+                        result.push((term.clone(), arg.clone()));
+                    }
+                }
+                stack.extend(args.iter().rev().cloned());
+            }
+        }
+        result
+    }
+}
+
+/// Returns the ids of the commands in `proof` that can be removed, in the order in which they
+/// appear, and the ids of the steps that end subproofs. A subproof is removed by removing the id
+/// of the step that ends it, which can't be removed by itself.
+fn removable_commands(proof: &Proof) -> (Vec<String>, HashSet<String>) {
+    fn collect(commands: &[ProofCommand], ids: &mut Vec<String>, ends: &mut HashSet<String>) {
+        for command in commands {
+            ids.push(command.id().to_owned());
+            if let ProofCommand::Subproof { commands, .. } = command {
+                ends.insert(command.id().to_owned());
+                collect(&commands[..commands.len() - 1], ids, ends);
+            }
+        }
+    }
+    let (mut ids, mut ends) = (Vec::new(), HashSet::new());
+    collect(&proof.0, &mut ids, &mut ends);
+    (ids, ends)
+}
+
+/// Returns `proof` without the commands whose ids are in `removed`. See `removable_commands`.
+fn without_commands(pool: &mut TermPool, proof: Proof, removed: &HashSet<String>) -> Proof {
+    let (_, subproof_ends) = removable_commands(&proof);
+    let mut rewriter = RemoveCommands {
+        removed,
+        subproof_ends: &subproof_ends,
+    };
+    rewrite_proof(&mut rewriter, pool, proof)
+}
+
+struct RemoveCommands<'a> {
+    removed: &'a HashSet<String>,
+    subproof_ends: &'a HashSet<String>,
+}
+
+impl ProofRewriter for RemoveCommands<'_> {
+    fn rewrite_command(
+        &mut self,
+        _: &mut TermPool,
+        command: ProofCommand,
+        _: usize,
+    ) -> Option<ProofCommand> {
+        let is_removed = match &command {
+            // The step that ends a subproof is only removed with the whole subproof
+            ProofCommand::Step(step) if self.subproof_ends.contains(&step.id) => false,
+            command => self.removed.contains(command.id()),
+        };
+        (!is_removed).then_some(command)
+    }
+}
+
+struct Substitute {
+    substitution: Substitution,
+    changed: bool,
+}
+
+impl ProofRewriter for Substitute {
+    fn rewrite_term(&mut self, pool: &mut TermPool, term: &ByRefRc<Term>) -> ByRefRc<Term> {
+        let (result, changed) = self.substitution.apply_checked(pool, term);
+        self.changed |= changed;
+        result
+    }
+}
+
+/// Collects the terms of the assumptions, clauses and arguments of `commands`, including the
+/// commands in subproofs.
+fn collect_terms(commands: &[ProofCommand], result: &mut Vec<ByRefRc<Term>>) {
+    for command in commands {
+        match command {
+            ProofCommand::Assume { term, .. } => result.push(term.clone()),
+            ProofCommand::Step(step) => {
+                result.extend(step.clause.iter().cloned());
+                result.extend(step.args.iter().map(|arg| match arg {
+                    ProofArg::Term(t) | ProofArg::Assign(_, t) => t.clone(),
+                }));
+            }
+            ProofCommand::Subproof {
+                commands,
+                assignment_args,
+                ..
+            } => {
+                result.extend(assignment_args.iter().map(|(_, t)| t.clone()));
+                collect_terms(commands, result);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_proof_with_definitions;
+
+    fn run(proof: &str) -> (String, Minimized) {
+        let problem = "
+            (declare-fun p () Bool)
+            (declare-fun q () Bool)
+            (declare-fun r () Bool)
+            (declare-fun a () Int)
+            (declare-fun f (Int) Int)
+        ";
+        let (proof, mut pool) = parse_proof_with_definitions(problem, proof).unwrap();
+        let config = || checker::Config {
+            skip_unknown_rules: true,
+            ..checker::Config::default()
+        };
+        let minimized = super::minimize(&mut pool, &proof, config).unwrap();
+        (minimized.proof.to_alethe(), minimized)
+    }
+
+    #[test]
+    fn minimize() {
+        let (proof, minimized) = run("
+            (assume h1 (and p q))
+            (assume h2 (= (f (+ a 1)) (f (+ a 1))))
+            (step t3 (cl p) :rule and :premises (h1))
+            (anchor :step t4)
+            (step t4.t1 (cl (= a a)) :rule eq_reflexive)
+            (step t4 (cl (= a a)) :rule trust)
+            (step t5 (cl (and r (= (f (+ a 1)) a))) :rule and :premises (h1))
+            (step t6 (cl) :rule resolution :premises (t3 t5))
+        ");
+        assert_eq!("(step t5 (cl r) :rule and)\n", proof);
+        assert_eq!(
+            ("t5", "and"),
+            (minimized.id.as_str(), minimized.rule.as_str())
+        );
+
+        // The subproof that contains the failing step is kept
+        let (proof, _) = run("
+            (assume h1 q)
+            (anchor :step t2)
+            (assume t2.a1 (or p q))
+            (step t2.t1 (cl (and p (not q))) :rule and :premises (t2.a1))
+            (step t2 (cl (not (or p q)) (and p (not q))) :rule trust)
+        ");
+        assert_eq!(
+            "(anchor :step t2)\n(step t2.t1 (cl p) :rule and)\n(step t2 (cl p p) :rule trust)\n",
+            proof
+        );
+
+        let (proof, mut pool) =
+            parse_proof_with_definitions("", "(step t1 (cl true) :rule true)").unwrap();
+        assert!(super::minimize(&mut pool, &proof, checker::Config::default).is_none());
+    }
+}
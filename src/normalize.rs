@@ -0,0 +1,114 @@
+//! A normalization pass that converts the variant encodings accepted by the checker into a single
+//! canonical form, so that tools that consume proofs only need to handle one of them. The
+//! normalized proof is checked in the same way as the original one. Currently, this:
+//!
+//! - replaces deprecated rule names with the names of the rules that replaced them;
+//! - writes the arguments of "forall_inst" and "exists_inst" steps as assignments of the form
+//!   `(:= x t)`, when they are given only as the terms `t`, in the order of the bound variables;
+//! - removes "contraction" steps in the top-level proof that don't remove any literal, making the
+//!   steps that use them use their premises instead.
+
+use crate::{ast::*, checker::replacement_rule_name};
+
+/// Normalizes `proof`. New terms are added to `pool`.
+pub fn normalize(pool: &mut TermPool, proof: Proof) -> Proof {
+    rewrite_proof(&mut Normalizer, pool, proof)
+}
+
+struct Normalizer;
+
+impl ProofRewriter for Normalizer {
+    fn replace_with_premise(
+        &mut self,
+        _: &mut TermPool,
+        step: &ProofStep,
+        scopes: &[Vec<ProofCommand>],
+    ) -> Option<usize> {
+        // Removing the last step of a subproof would change the conclusion of the subproof, so only
+        // steps in the top-level proof are removed
+        if scopes.len() != 1 || step.rule != "contraction" {
+            return None;
+        }
+        match step.premises.as_slice() {
+            &[(d, i)] if scopes[d][i].clause() == step.clause.as_slice() => Some(0),
+            _ => None,
+        }
+    }
+
+    fn rewrite_command(
+        &mut self,
+        _: &mut TermPool,
+        command: ProofCommand,
+        _: usize,
+    ) -> Option<ProofCommand> {
+        let mut step = match command {
+            ProofCommand::Step(step) => step,
+            other => return Some(other),
+        };
+        if let Some(replacement) = replacement_rule_name(&step.rule) {
+            step.rule = replacement.to_owned();
+        }
+        if let Some(bindings) = instantiated_bindings(&step) {
+            let terms: Option<Vec<_>> = step.args.iter().map(|a| a.as_term().cloned()).collect();
+            if let Some(terms) = terms.filter(|terms| terms.len() == bindings.len()) {
+                step.args = bindings
+                    .iter()
+                    .zip(terms)
+                    .map(|((name, _), t)| ProofArg::Assign(name.clone(), t))
+                    .collect();
+            }
+        }
+        Some(ProofCommand::Step(step))
+    }
+}
+
+/// If `step` is a "forall_inst" or "exists_inst" step, returns the variables bound by the
+/// quantifier that it instantiates.
+fn instantiated_bindings(step: &ProofStep) -> Option<Vec<SortedVar>> {
+    let quant = match (step.rule.as_str(), step.clause.as_slice()) {
+        ("forall_inst", [c]) => match_term!((or (not f) s) = c, RETURN_RCS)?.0,
+        ("exists_inst", [c]) if !step.premises.is_empty() => c,
+        ("exists_inst", [c]) => match_term!((or (not s) e) = c, RETURN_RCS)?.1,
+        _ => return None,
+    };
+    let (_, bindings, _) = quant.unwrap_quant()?;
+    Some(bindings.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        checker::{self, Correctness, ProofChecker},
+        parser::parse_proof_with_definitions,
+    };
+
+    #[test]
+    fn normalize() {
+        let definitions = "
+            (declare-sort U 0)
+            (declare-fun a () U)
+            (declare-fun p (U) Bool)
+            (declare-fun q () Bool)
+        ";
+        let proof = "
+            (assume h1 (forall ((x U)) (p x)))
+            (step t2 (cl (or (not (forall ((x U)) (p x))) (p a))) :rule forall_inst :args (a))
+            (step t3 (cl (not (forall ((x U)) (p x))) (p a)) :rule or :premises (t2))
+            (step t4 (cl (not (forall ((x U)) (p x))) (p a)) :rule contraction :premises (t3))
+            (step t5 (cl (p a)) :rule resolution :premises (h1 t4))
+            (step t6 (cl (= (= a a a) (and (= a a) (= a a)))) :rule tmp_nary_elim)
+        ";
+        let (proof, mut pool) = parse_proof_with_definitions(definitions, proof).unwrap();
+        let normalized = super::normalize(&mut pool, proof);
+        let expected = "\
+            (assume h1 (forall ((x U)) (p x)))\n\
+            (step t2 (cl (or (not (forall ((x U)) (p x))) (p a))) :rule forall_inst :args ((:= x a)))\n\
+            (step t3 (cl (not (forall ((x U)) (p x))) (p a)) :rule or :premises (t2))\n\
+            (step t5 (cl (p a)) :rule resolution :premises (h1 t3))\n\
+            (step t6 (cl (= (= a a a) (and (= a a) (= a a)))) :rule nary_elim)\n";
+        assert_eq!(expected, normalized.to_alethe());
+
+        let mut checker = ProofChecker::new(&mut pool, checker::Config::default());
+        assert!(matches!(checker.check(&normalized), Ok(Correctness::True)));
+    }
+}
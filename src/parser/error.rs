@@ -1,5 +1,5 @@
 use super::lexer::{Position, Token};
-use crate::ast::{Identifier, Term};
+use crate::ast::{Identifier, Operator, SortKind, Term};
 use num_bigint::BigInt;
 use std::io;
 use std::ops::RangeFrom;
@@ -38,8 +38,44 @@ pub enum ErrorKind {
     UndefinedSort(String),
     UndefinedStepIndex(String),
     WrongNumberOfArgs(usize, usize),
+
+    /// An operator was applied to fewer than `min` arguments, or to more than `max`, if it has a
+    /// maximum. See `Operator::arity` and `Config::permissive_arities`.
+    WrongNumberOfOperatorArgs {
+        op: Operator,
+        min: usize,
+        max: Option<usize>,
+        got: usize,
+    },
+
+    /// A command reused the id of an earlier command. See `Config::shadow_step_ids` for when ids
+    /// may be reused.
     RepeatedStepIndex(String),
     InvalidSortArity(BigInt),
+    InvalidScopeLevels(BigInt),
+
+    /// The index of an indexed operator, like the "n" in "(_ divisible n)", is not valid.
+    InvalidIndex(BigInt),
+    MemoryLimitExceeded(usize),
+    MaxDepthExceeded(usize),
+
+    /// A term in a clause, assumption or assertion has more distinct subterms than
+    /// `Config::max_term_size`.
+    TermSizeExceeded(usize),
+
+    /// A clause has more terms than `Config::max_clause_length`.
+    ClauseLengthExceeded(usize),
+
+    /// A symbol defined in the proof, for example a Skolem constant, was already declared in the
+    /// problem or in the proof.
+    FreshSymbolClash(String),
+
+    /// A symbol was defined more than once in the proof, with different definitions.
+    InconsistentDefinition(String),
+
+    /// All the errors found while parsing with error recovery enabled, in the order they were
+    /// found.
+    MultipleErrors(Vec<ParserError>),
     NotYetImplemented,
 }
 
@@ -91,14 +127,29 @@ impl PartialEq for ParserIoError {
 
 #[derive(Debug, PartialEq)]
 pub enum SortError {
-    Expected { expected: Term, got: Term },
-    ExpectedOneOf { possibilities: Vec<Term>, got: Term },
+    Expected {
+        expected: Term,
+        got: Term,
+    },
+    ExpectedOneOf {
+        possibilities: Vec<Term>,
+        got: Term,
+    },
+
+    /// The sort of a term couldn't be found, because it is not correctly sorted. See
+    /// `TermPool::sort`.
+    Unknown(Term),
+}
+
+/// Returns true if `sort` is a sort inferred by the parser, which is compatible with any sort.
+fn is_inferred(sort: &Term) -> bool {
+    matches!(sort, Term::Sort(SortKind::Inferred, _))
 }
 
 impl SortError {
     /// Returns an `Expected` sort error if `got` does not equal `expected`.
     pub fn assert_eq(expected: &Term, got: &Term) -> Result<(), Self> {
-        if expected == got {
+        if expected == got || is_inferred(expected) || is_inferred(got) {
             Ok(())
         } else {
             Err(Self::Expected {
@@ -111,14 +162,18 @@ impl SortError {
     /// Makes sure all terms in `sequence` are equal to each other, otherwise returns an `Expected`
     /// error.
     pub fn assert_all_eq(sequence: &[&Term]) -> Result<(), Self> {
-        for i in 1..sequence.len() {
-            Self::assert_eq(sequence[i - 1], sequence[i])?;
+        let known: Vec<_> = sequence.iter().filter(|s| !is_inferred(s)).collect();
+        for i in 1..known.len() {
+            Self::assert_eq(known[i - 1], known[i])?;
         }
         Ok(())
     }
 
     /// Returns an `ExpectedOneOf` sort error if `got` is not in `possibilities`.
     pub fn assert_one_of(possibilities: &[&Term], got: &Term) -> Result<(), Self> {
+        if is_inferred(got) {
+            return Ok(());
+        }
         match possibilities.iter().find(|&&s| s == got) {
             Some(_) => Ok(()),
             None => Err(Self::ExpectedOneOf {
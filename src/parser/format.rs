@@ -0,0 +1,98 @@
+//! Detection of the format of a proof file, and of the solver that produced it, by looking at the
+//! start of the file.
+
+use super::{
+    lexer::{Lexer, Token},
+    ParserResult,
+};
+use std::{
+    fmt,
+    io::{BufRead, Cursor, Read},
+};
+
+/// The format of a proof file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProofFormat {
+    /// The Alethe format, used by current veriT releases.
+    #[default]
+    Alethe,
+
+    /// The format of veriT releases older than Alethe, where commands are "set" commands with a
+    /// ":conclusion" attribute. See the `parser::legacy` module.
+    Legacy,
+}
+
+impl fmt::Display for ProofFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProofFormat::Alethe => write!(f, "alethe"),
+            ProofFormat::Legacy => write!(f, "legacy"),
+        }
+    }
+}
+
+/// The solver that produced a proof. Solvers differ in the parts of the format they use, so this
+/// helps to make sense of errors in proofs whose format was detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofDialect {
+    VeriT,
+    Cvc5,
+
+    /// The proof doesn't say which solver produced it.
+    Unknown,
+}
+
+impl fmt::Display for ProofDialect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProofDialect::VeriT => write!(f, "veriT"),
+            ProofDialect::Cvc5 => write!(f, "cvc5"),
+            ProofDialect::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Detects the format of a proof, and the solver that produced it, by reading the comments at the
+/// start of the proof and its first tokens. A proof is in the legacy format if its first command
+/// is a "set" command, and in the Alethe format otherwise. The solver is taken from the first
+/// comment, where solvers usually write their name and version, and proofs in the legacy format
+/// are always produced by veriT. Only the start of `proof` is read.
+pub fn detect_proof_format<T: BufRead>(mut proof: T) -> ParserResult<(ProofFormat, ProofDialect)> {
+    let mut producer = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if proof.read_line(&mut line)? == 0 {
+            break;
+        }
+        let trimmed = line.trim();
+        match trimmed.strip_prefix(';') {
+            Some(comment) => {
+                let comment = comment.trim_start_matches(';').trim();
+                if producer.is_none() && !comment.is_empty() {
+                    producer = Some(comment.to_lowercase());
+                }
+            }
+            None if trimmed.is_empty() => (),
+            None => break,
+        }
+    }
+
+    // The first line that is not a comment was already read, so it is put back in front of the
+    // rest of the input
+    let mut lexer = Lexer::new(Cursor::new(line).chain(proof))?;
+    let is_legacy = lexer.next_token()? == Token::OpenParen
+        && matches!(lexer.next_token()?, Token::Symbol(s) if s == "set");
+    let format = if is_legacy {
+        ProofFormat::Legacy
+    } else {
+        ProofFormat::Alethe
+    };
+    let dialect = match producer {
+        _ if is_legacy => ProofDialect::VeriT,
+        Some(p) if p.starts_with("verit") => ProofDialect::VeriT,
+        Some(p) if p.starts_with("cvc5") => ProofDialect::Cvc5,
+        _ => ProofDialect::Unknown,
+    };
+    Ok((format, dialect))
+}
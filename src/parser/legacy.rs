@@ -0,0 +1,125 @@
+//! A front end for the proof format of older veriT releases, which predates Alethe. In this format,
+//! every command has the form "(set <id> (<rule> <attribute>*))". The attributes are ":clauses",
+//! with the ids of the premises, ":conclusion", with the literals of the conclusion, and ":iargs"
+//! and ":args", with the arguments of the step. Input clauses use the rule "input". Terms are
+//! shared by naming them with the ":named" attribute, and referring to them by name in later
+//! commands. These proofs are lowered into the same `Proof` AST as Alethe proofs. Subproofs are not
+//! supported, since they are not needed to check the proofs that older releases emit without
+//! preprocessing steps.
+
+use super::*;
+
+impl<R: BufRead> Parser<R> {
+    /// Parses a proof in the legacy format.
+    pub(super) fn parse_legacy_proof(&mut self) -> ParserResult<Proof> {
+        let mut commands = Vec::new();
+        while self.current_token != Token::Eof {
+            self.check_memory_limit()?;
+            let (index, command) = match self.parse_legacy_command() {
+                Ok(c) => c,
+                Err(err) => {
+                    self.recover_from_error(err)?;
+                    continue;
+                }
+            };
+            commands.push(command);
+            self.state.step_ids.insert(index.clone());
+            self.state
+                .step_indices
+                .insert(index, (0, commands.len() - 1));
+        }
+        Ok(Proof(commands))
+    }
+
+    /// Parses a single "set" command, and returns it together with its index.
+    fn parse_legacy_command(&mut self) -> ParserResult<(String, ProofCommand)> {
+        let position = self.current_token_start;
+        self.expect_token(Token::OpenParen)?;
+        match self.next_token()? {
+            Token::Symbol(s) if s == "set" => (),
+            other => return Err(self.unexpected_token(other)),
+        }
+        let index = self.expect_symbol()?;
+        if self.state.step_ids.contains(&index) {
+            return Err(self.err(ErrorKind::RepeatedStepIndex(index)));
+        }
+
+        self.expect_token(Token::OpenParen)?;
+        let rule = match self.next_token()? {
+            Token::Symbol(s) => s.to_string(),
+            Token::ReservedWord(r) => format!("{:?}", r),
+            other => return Err(self.unexpected_token(other)),
+        };
+        let mut clause = None;
+        let mut premises = Vec::new();
+        let mut args = Vec::new();
+        while self.current_token != Token::CloseParen {
+            let attribute = self.expect_keyword()?;
+            if !matches!(
+                attribute.as_str(),
+                "conclusion" | "clauses" | "iargs" | "args"
+            ) {
+                // Other attributes are ignored
+                if self.next_token()? == Token::OpenParen {
+                    self.skip_until_close_paren()?;
+                }
+                continue;
+            }
+            self.expect_token(Token::OpenParen)?;
+            match attribute.as_str() {
+                "conclusion" => {
+                    let terms = self.parse_sequence(Self::parse_term, false)?;
+                    let terms = terms
+                        .into_iter()
+                        .map(|term| {
+                            self.assert_sort(Term::BOOL_SORT, &term)?;
+                            Ok(self.add_term(term))
+                        })
+                        .collect::<ParserResult<Vec<_>>>()?;
+                    self.check_clause_limits(&terms)?;
+                    clause = Some(terms);
+                }
+                "clauses" => {
+                    premises = self
+                        .parse_sequence(Self::expect_symbol, false)?
+                        .into_iter()
+                        .map(|index| {
+                            self.state
+                                .step_indices
+                                .get(&index)
+                                .copied()
+                                .ok_or_else(|| self.err(ErrorKind::UndefinedStepIndex(index)))
+                        })
+                        .collect::<ParserResult<_>>()?;
+                }
+                _ => args.extend(self.parse_sequence(Self::parse_proof_arg, false)?),
+            }
+        }
+        self.expect_token(Token::CloseParen)?;
+        self.expect_token(Token::CloseParen)?;
+
+        let clause = match clause {
+            Some(c) => c,
+            None => return Err(self.unexpected_token(Token::CloseParen)),
+        };
+        let command = if rule == "input" {
+            match clause.as_slice() {
+                [term] => ProofCommand::Assume {
+                    id: index.clone(),
+                    term: term.clone(),
+                },
+                _ => return Err(self.err(ErrorKind::WrongNumberOfArgs(1, clause.len()))),
+            }
+        } else {
+            ProofCommand::Step(ProofStep {
+                id: index.clone(),
+                clause,
+                rule,
+                premises,
+                args,
+                position,
+            })
+        };
+        Ok((index, command))
+    }
+}
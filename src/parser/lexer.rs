@@ -1,20 +1,26 @@
 use num_bigint::BigInt;
 use num_rational::BigRational;
-use num_traits::Num;
+use num_traits::{Num, Pow};
 
 use std::{
+    collections::HashSet,
     fmt::Debug,
     io::{self, BufRead},
     str::FromStr,
 };
 
 use super::error::*;
+use crate::ast::Symbol;
 
-#[derive(Debug, PartialEq, Eq)]
+/// A token in the SMT-LIB/veriT Proof Format syntax.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Token {
     OpenParen,
     CloseParen,
-    Symbol(String),
+    Symbol(Symbol),
+
+    /// Keywords are not interned, since they are never used as symbols in terms, and so interning
+    /// them would only add contention on the symbol table.
     Keyword(String),
     Numeral(BigInt),
     Decimal(BigRational),
@@ -23,6 +29,7 @@ pub enum Token {
     Eof,
 }
 
+/// A reserved word. Quoted symbols (like `|assume|`) are never read as reserved words.
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub enum Reserved {
     Underscore,  // _
@@ -60,49 +67,83 @@ impl_str_conversion_traits!(Reserved {
     DefineFun: "define-fun",
 });
 
+/// A position in the input, given as a line number and a column number. Both start from 1.
 pub type Position = (usize, usize);
 
+/// A lexer for the SMT-LIB/veriT Proof Format syntax. The lexer reads its input line by line, so it
+/// can be used on large files without reading them into memory.
+///
+/// Invariants:
+/// - `position` is always the position of the next character to be read, that is, the character
+///   immediately after the last token read.
+/// - Whitespace and comments are never returned as tokens.
+/// - Once the end of the input is reached, every following call to `next_token` returns
+///   `Token::Eof`.
+///
+/// Besides `next_token`, the lexer can also be used as an iterator over tokens, which stops before
+/// `Token::Eof`.
 pub struct Lexer<R> {
     input: R,
-    current_line: Option<std::vec::IntoIter<char>>,
+
+    /// The current line, and the offset in bytes of the next character to be read in it. The
+    /// line buffer is reused for every line, so reading the input doesn't allocate once the buffer
+    /// is large enough. The line is `None` once the end of the input is reached.
+    current_line: Option<String>,
+    line_offset: usize,
     current_char: Option<char>,
     pub position: Position,
+
+    /// The position of the first character of the last token read.
+    pub token_start: Position,
+
+    /// A buffer for the characters of the token being read, reused for every token.
+    buffer: String,
+
+    /// The symbols read recently. Interning a symbol locks the global symbol table, so symbols
+    /// that are read again, like the ids of steps used as premises, are taken from here instead.
+    /// The cache is cleared when it grows past `SYMBOL_CACHE_SIZE`, so it doesn't keep every step
+    /// id in the proof alive.
+    symbols: HashSet<Symbol>,
 }
 
+/// The maximum number of symbols kept in `Lexer::symbols`.
+const SYMBOL_CACHE_SIZE: usize = 1 << 16;
+
 impl<R: BufRead> Lexer<R> {
-    pub fn new(mut input: R) -> Result<Self, io::Error> {
-        let mut buf = String::new();
-        let read = input.read_line(&mut buf)?;
-        if read == 0 {
-            Ok(Lexer {
-                input,
-                current_line: None,
-                current_char: None,
-                position: (0, 0),
-            })
-        } else {
-            let mut line = buf.chars().collect::<Vec<_>>().into_iter();
-            let current_char = line.next();
-            Ok(Lexer {
-                input,
-                current_line: Some(line),
-                current_char,
-                position: (1, 1),
-            })
+    /// Constructs a new `Lexer` from a type that implements `BufRead`. This reads the first line of
+    /// the input, and can therefore fail if there is an IO error.
+    pub fn new(input: R) -> Result<Self, io::Error> {
+        let mut lexer = Lexer {
+            input,
+            current_line: Some(String::new()),
+            line_offset: 0,
+            current_char: None,
+            position: (0, 0),
+            token_start: (0, 0),
+            buffer: String::new(),
+            symbols: HashSet::new(),
+        };
+        lexer.next_line().map_err(|(err, _)| err)?;
+        if lexer.current_line.is_some() {
+            lexer.next_char().map_err(|(err, _)| err)?;
+            lexer.token_start = lexer.position;
         }
+        Ok(lexer)
     }
 
     fn next_char(&mut self) -> Result<Option<char>, (io::Error, Position)> {
         // If there are no more characters in the current line, go to the next line
         if let Some(line) = &self.current_line {
-            if line.as_slice().is_empty() {
+            if self.line_offset == line.len() {
                 self.next_line()?;
             }
         }
 
-        let new = if let Some(line) = &mut self.current_line {
+        let new = if let Some(line) = &self.current_line {
             self.position.1 += 1;
-            line.next()
+            let c = line[self.line_offset..].chars().next();
+            self.line_offset += c.map_or(0, char::len_utf8);
+            c
         } else {
             None
         };
@@ -111,49 +152,65 @@ impl<R: BufRead> Lexer<R> {
     }
 
     fn next_line(&mut self) -> Result<(), (io::Error, Position)> {
-        let mut buf = String::new();
+        let line = match &mut self.current_line {
+            Some(line) => line,
+            None => return Ok(()),
+        };
+        line.clear();
         let read = self
             .input
-            .read_line(&mut buf)
+            .read_line(line)
             .map_err(|io_err| (io_err, self.position))?;
         if read == 0 {
             self.current_line = None;
         } else {
-            let line = buf.chars().collect::<Vec<_>>().into_iter();
-            self.current_line = Some(line);
+            self.line_offset = 0;
             self.position.0 += 1;
             self.position.1 = 0;
         }
         Ok(())
     }
 
-    fn read_chars_while<P>(&mut self, predicate: P) -> Result<String, (io::Error, Position)>
+    /// Skips characters while `predicate` holds for them.
+    fn skip_chars_while<P>(&mut self, predicate: P) -> Result<(), (io::Error, Position)>
+    where
+        P: Fn(char) -> bool,
+    {
+        while self.current_char.is_some_and(&predicate) {
+            self.next_char()?;
+        }
+        Ok(())
+    }
+
+    /// Reads characters while `predicate` holds for them, and appends them to `self.buffer`.
+    fn read_chars_while<P>(&mut self, predicate: P) -> Result<(), (io::Error, Position)>
     where
         P: Fn(char) -> bool,
     {
-        let mut result = String::new();
         while let Some(c) = self.current_char {
             if !predicate(c) {
                 break;
             }
-            result.push(c);
+            self.buffer.push(c);
             self.next_char()?;
         }
-        Ok(result)
+        Ok(())
     }
 
     fn consume_whitespace(&mut self) -> Result<(), (io::Error, Position)> {
-        self.read_chars_while(char::is_whitespace)?;
+        self.skip_chars_while(char::is_whitespace)?;
         while self.current_char == Some(';') {
             self.next_line()?;
             self.next_char()?;
-            self.read_chars_while(char::is_whitespace)?;
+            self.skip_chars_while(char::is_whitespace)?;
         }
         Ok(())
     }
 
+    /// Reads the next token from the input.
     pub fn next_token(&mut self) -> ParserResult<Token> {
         self.consume_whitespace()?;
+        self.token_start = self.position;
         match self.current_char {
             Some('(') => {
                 self.next_char()?;
@@ -170,25 +227,46 @@ impl<R: BufRead> Lexer<R> {
             Some(c) if c.is_ascii_digit() => self.read_number(),
             Some(c) if Lexer::is_symbol_character(c) => self.read_simple_symbol(),
             None => Ok(Token::Eof),
-            other => Err(ParserError(
-                ErrorKind::UnexpectedChar(other),
-                Some(self.position),
-            )),
+            Some(other) => {
+                // The unexpected character is consumed, so the lexer can keep going if the caller
+                // wants to recover from the error
+                let position = self.position;
+                self.next_char()?;
+                Err(ParserError(
+                    ErrorKind::UnexpectedChar(Some(other)),
+                    Some(position),
+                ))
+            }
         }
     }
 
     fn read_simple_symbol(&mut self) -> Result<Token, ParserError> {
-        let symbol = self.read_chars_while(Lexer::is_symbol_character)?;
-        if let Ok(reserved) = Reserved::from_str(&symbol) {
+        self.buffer.clear();
+        self.read_chars_while(Lexer::is_symbol_character)?;
+        if let Ok(reserved) = Reserved::from_str(&self.buffer) {
             Ok(Token::ReservedWord(reserved))
         } else {
-            Ok(Token::Symbol(symbol))
+            Ok(Token::Symbol(self.intern_buffer()))
+        }
+    }
+
+    /// Returns the symbol whose name is in `self.buffer`.
+    fn intern_buffer(&mut self) -> Symbol {
+        if let Some(symbol) = self.symbols.get(self.buffer.as_str()) {
+            return symbol.clone();
         }
+        if self.symbols.len() >= SYMBOL_CACHE_SIZE {
+            self.symbols.clear();
+        }
+        let symbol = Symbol::new(&self.buffer);
+        self.symbols.insert(symbol.clone());
+        symbol
     }
 
     fn read_quoted_symbol(&mut self) -> Result<Token, ParserError> {
         self.next_char()?; // Consume '|'
-        let symbol = self.read_chars_while(|c| c != '|' && c != '\\')?;
+        self.buffer.clear();
+        self.read_chars_while(|c| c != '|' && c != '\\')?;
         match self.current_char {
             Some('\\') => Err(ParserError(
                 ErrorKind::BackslashInQuotedSymbol,
@@ -200,7 +278,7 @@ impl<R: BufRead> Lexer<R> {
             )),
             Some('|') => {
                 self.next_char()?;
-                Ok(Token::Symbol(symbol))
+                Ok(Token::Symbol(self.intern_buffer()))
             }
             _ => unreachable!(),
         }
@@ -208,8 +286,9 @@ impl<R: BufRead> Lexer<R> {
 
     fn read_keyword(&mut self) -> Result<Token, ParserError> {
         self.next_char()?; // Consume ':'
-        let symbol = self.read_chars_while(Lexer::is_symbol_character)?;
-        Ok(Token::Keyword(symbol))
+        self.buffer.clear();
+        self.read_chars_while(Lexer::is_symbol_character)?;
+        Ok(Token::Keyword(self.buffer.clone()))
     }
 
     fn read_number_with_base(&mut self) -> Result<Token, ParserError> {
@@ -224,54 +303,80 @@ impl<R: BufRead> Lexer<R> {
                 ))
             }
         };
-        let s = self.read_chars_while(|c| c.is_digit(base))?;
-        Ok(Token::Numeral(BigInt::from_str_radix(&s, base).unwrap()))
+        self.buffer.clear();
+        self.read_chars_while(|c| c.is_digit(base))?;
+        if self.buffer.is_empty() {
+            return Err(ParserError(
+                ErrorKind::UnexpectedChar(self.current_char),
+                Some(self.position),
+            ));
+        }
+        Ok(Token::Numeral(
+            BigInt::from_str_radix(&self.buffer, base).unwrap(),
+        ))
     }
 
     fn read_number(&mut self) -> Result<Token, ParserError> {
-        let int_part = self.read_chars_while(|c| c.is_ascii_digit())?;
+        self.buffer.clear();
+        self.read_chars_while(|c| c.is_ascii_digit())?;
 
-        if int_part.len() > 1 && int_part.starts_with('0') {
+        if self.buffer.len() > 1 && self.buffer.starts_with('0') {
             return Err(ParserError(
-                ErrorKind::LeadingZero(int_part),
+                ErrorKind::LeadingZero(self.buffer.clone()),
                 Some(self.position),
             ));
         }
 
         if self.current_char == Some('.') {
             self.next_char()?;
-            let frac_part = self.read_chars_while(|c| c.is_ascii_digit())?;
-            let denom = BigInt::from(10).pow(frac_part.len() as u32);
-            let numer = (int_part + &frac_part).parse::<BigInt>().unwrap();
+
+            // The fractional part is appended to the integer part in the buffer, so the buffer
+            // holds the numerator of the decimal
+            let int_part_len = self.buffer.len();
+            self.read_chars_while(|c| c.is_ascii_digit())?;
+            let frac_part_len = self.buffer.len() - int_part_len;
+            let denom = Pow::pow(BigInt::from(10), frac_part_len);
+            let numer = self.buffer.parse::<BigInt>().unwrap();
             let r = BigRational::new(numer, denom);
             Ok(Token::Decimal(r))
         } else {
-            Ok(Token::Numeral(int_part.parse().unwrap()))
+            Ok(Token::Numeral(self.buffer.parse().unwrap()))
         }
     }
 
     fn read_string(&mut self) -> Result<Token, ParserError> {
         self.next_char()?; // Consume '"'
-        let mut result = String::new();
+        self.buffer.clear();
         loop {
-            result += &self.read_chars_while(|c| c != '"')?;
+            self.read_chars_while(|c| c != '"')?;
             if self.current_char.is_none() {
                 return Err(ParserError(ErrorKind::EofInString, Some(self.position)));
             }
             self.next_char()?; // Consume '"'
             if self.current_char == Some('"') {
                 self.next_char()?;
-                result.push('"');
+                self.buffer.push('"');
             } else {
                 break;
             }
         }
-        Ok(Token::String(result))
+        Ok(Token::String(self.buffer.clone()))
+    }
+}
+
+impl<R: BufRead> Iterator for Lexer<R> {
+    type Item = ParserResult<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_token() {
+            Ok(Token::Eof) => None,
+            other => Some(other),
+        }
     }
 }
 
 impl Lexer<()> {
-    fn is_symbol_character(ch: char) -> bool {
+    pub(crate) fn is_symbol_character(ch: char) -> bool {
         match ch {
             ch if ch.is_ascii_alphanumeric() => true,
             '+' | '-' | '/' | '*' | '=' | '%' | '?' | '!' | '.' | '$' | '_' | '~' | '&' | '^'
@@ -386,6 +491,11 @@ mod tests {
             lex_one("#"),
             Err(ParserError(ErrorKind::UnexpectedChar(None), _)),
         ));
+
+        assert!(matches!(
+            lex_one("#x "),
+            Err(ParserError(ErrorKind::UnexpectedChar(Some(' ')), _)),
+        ));
     }
 
     #[test]
@@ -405,6 +515,33 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_iterator() {
+        let lex = Lexer::new(std::io::Cursor::new("(step t1)")).unwrap();
+        let got: Vec<_> = lex.collect::<Result<_, _>>().unwrap();
+        let expected = vec![
+            Token::OpenParen,
+            Token::ReservedWord(Reserved::Step),
+            Token::Symbol("t1".into()),
+            Token::CloseParen,
+        ];
+        assert_eq!(expected, got);
+    }
+
+    #[test]
+    fn test_positions() {
+        // Columns count characters, not bytes
+        let mut lex = Lexer::new(std::io::Cursor::new("|αβ| x\n  ; comment\n |é|")).unwrap();
+        let mut next = || {
+            let token = lex.next_token().unwrap();
+            (token, lex.token_start)
+        };
+        assert_eq!((Token::Symbol("αβ".into()), (1, 1)), next());
+        assert_eq!((Token::Symbol("x".into()), (1, 6)), next());
+        assert_eq!((Token::Symbol("é".into()), (3, 2)), next());
+        assert_eq!(Token::Eof, next().0);
+    }
+
     #[test]
     fn test_reserved_words() {
         let input = "_ ! as let exists |_| |!| |as| |let| |exists|";
@@ -1,26 +1,387 @@
 //! A parser for the veriT Proof Format.
 
 pub mod error;
+mod format;
+pub mod legacy;
 pub mod lexer;
+pub mod pipeline;
 pub mod tests;
 
 use crate::{ast::*, utils::Either};
 use error::*;
+pub use format::{detect_proof_format, ProofDialect, ProofFormat};
 use lexer::*;
 use num_bigint::BigInt;
-use num_traits::ToPrimitive;
-use std::{collections::HashMap, hash::Hash, io::BufRead, str::FromStr};
+use num_rational::BigRational;
+use num_traits::{Pow, ToPrimitive, Zero};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    io::{self, BufRead},
+    str::FromStr,
+};
+
+/// The default value of `Config::max_depth`.
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+/// Configuration options for the parser.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// An approximate limit, in bytes, on the memory used by the parsed terms. If it is exceeded,
+    /// parsing stops with an `ErrorKind::MemoryLimitExceeded` error.
+    pub memory_limit: Option<usize>,
+
+    /// If true, the parser doesn't stop at the first syntax error. Instead, it skips to the end of
+    /// the command that caused the error and continues parsing, and all errors are reported at the
+    /// end in an `ErrorKind::MultipleErrors` error. Since the skipped command is lost, later
+    /// commands may give spurious errors, for example if they reference a skipped step.
+    pub error_recovery: bool,
+
+    /// If true, sorts and symbols that were not declared are declared on their first use, instead
+    /// of causing an error. Undeclared sorts become new uninterpreted sorts. Undeclared symbols are
+    /// given fresh inferred sorts (see `SortKind::Inferred`), and if they are applied to arguments,
+    /// they are declared as functions that take arguments of those sorts. This is used to parse
+    /// proofs without their problem files. Since the inferred sorts are different from the real
+    /// ones, rules that depend on the sorts of terms, like the ones that substitute variables, may
+    /// fail to check proofs parsed this way.
+    pub infer_declarations: bool,
+
+    /// The maximum nesting depth of parentheses, of subproofs, and of terms built by expanding
+    /// function definitions. If it is exceeded, parsing stops with an
+    /// `ErrorKind::MaxDepthExceeded` error. Parsing and checking terms is done
+    /// recursively, so this stops deeply nested inputs from overflowing the stack. Raising it much
+    /// above the default may require running the parser and checker in a thread with a larger
+    /// stack.
+    pub max_depth: usize,
+
+    /// The maximum size of each term in the conclusion of a step, in an assumption or in an
+    /// assertion of the problem, counted as the number of distinct subterms, that is, the number of
+    /// nodes in the term's DAG. If it is exceeded, parsing stops with an
+    /// `ErrorKind::TermSizeExceeded` error.
+    pub max_term_size: Option<usize>,
+
+    /// The maximum number of terms in the conclusion of a step. If it is exceeded, parsing stops
+    /// with an `ErrorKind::ClauseLengthExceeded` error.
+    pub max_clause_length: Option<usize>,
+
+    /// If true, functions defined with "define-fun" commands in the proof are kept opaque. Instead
+    /// of being expanded where they are used, they are declared as uninterpreted functions of the
+    /// same sort. This keeps the terms in the proof small when the definitions are abbreviations,
+    /// but steps whose validity depends on a definition will fail to check. Definitions in the
+    /// problem are always expanded.
+    pub opaque_definitions: bool,
+
+    /// If true, a step inside a subproof may reuse the id of a command outside the subproof. The
+    /// new command shadows the old one until the end of the subproof, so premises inside the
+    /// subproof refer to the new command. Ids may also be reused after the subproof that
+    /// introduced them ends. Otherwise, every id must be unique in the whole proof, and a repeated
+    /// id causes an `ErrorKind::RepeatedStepIndex` error. Ids are never allowed to be repeated in
+    /// the same scope.
+    pub shadow_step_ids: bool,
+
+    /// If true, "and", "or" and "xor" may be applied to a single argument, as in "(or true)". This
+    /// is not allowed by SMT-LIB, but veriT prints such terms in its proofs, so it is enabled by
+    /// default. Otherwise, every operator must be applied to the number of arguments given by
+    /// SMT-LIB, and an `ErrorKind::WrongNumberOfOperatorArgs` error is returned if it isn't. See
+    /// `Operator::arity`.
+    pub permissive_arities: bool,
+
+    /// The format of the proof. The problem is always parsed as SMT-LIB.
+    pub format: ProofFormat,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            memory_limit: None,
+            error_recovery: false,
+            infer_declarations: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_term_size: None,
+            max_clause_length: None,
+            opaque_definitions: false,
+            shadow_step_ids: false,
+            permissive_arities: true,
+            format: ProofFormat::Alethe,
+        }
+    }
+}
+
+pub fn parse_problem_proof<T: BufRead>(
+    problem: T,
+    proof: T,
+    config: Config,
+) -> ParserResult<(Problem, Proof, TermPool)> {
+    parse_problem_proof_with_includes(Vec::new(), problem, proof, config)
+}
+
+/// Parses a problem that is split across several files, and a proof of it. The files in
+/// `includes`, like a prelude of shared declarations, are parsed in order before the problem, into
+/// the same symbol table. The returned `Problem` holds the declarations, definitions and assertions
+/// of all files, as if they were concatenated.
+pub fn parse_problem_proof_with_includes<T: BufRead>(
+    includes: Vec<T>,
+    problem: T,
+    proof: T,
+    config: Config,
+) -> ParserResult<(Problem, Proof, TermPool)> {
+    let mut state = parse_problem_files(includes, problem, config)?;
+    let problem = std::mem::take(&mut state.problem);
+
+    let (proof, pool) = Parser::with_state(Lexer::new(proof)?, state)?.parse_proof()?;
+    Ok((problem, proof, pool))
+}
 
-pub fn parse_problem_proof<T: BufRead>(problem: T, proof: T) -> ParserResult<(Proof, TermPool)> {
-    let mut problem_parser = Parser::new(problem)?;
-    problem_parser.parse_problem()?;
+/// Like `parse_problem_proof_with_includes`, but also parses `target`, a clause of the form
+/// "(cl <term>*)" that may use the declarations of the problem. The clause is parsed into the same
+/// term pool as the proof, so it can be given to the checker as `checker::Config::target`.
+pub fn parse_problem_proof_with_target<T: BufRead>(
+    includes: Vec<T>,
+    problem: T,
+    proof: T,
+    target: &str,
+    config: Config,
+) -> ParserResult<(Problem, Proof, Vec<ByRefRc<Term>>, TermPool)> {
+    let state = parse_problem_files(includes, problem, config)?;
+    let mut parser = Parser::with_state(Lexer::new(target.as_bytes())?, state)?;
+    let target = parser.parse_clause()?;
+    parser.expect_token(Token::Eof)?;
+    let mut state = parser.state;
+    let problem = std::mem::take(&mut state.problem);
+
+    let (proof, pool) = Parser::with_state(Lexer::new(proof)?, state)?.parse_proof()?;
+    Ok((problem, proof, target, pool))
+}
+
+/// Parses the declarations and definitions in each of `includes`, and then in `problem`, and
+/// returns the resulting parser state.
+fn parse_problem_files<T: BufRead>(
+    includes: Vec<T>,
+    problem: T,
+    config: Config,
+) -> ParserResult<ParserState> {
+    let mut files = includes.into_iter().chain(std::iter::once(problem));
+    let mut parser = Parser::with_config(files.next().unwrap(), config)?;
+    parser.parse_declarations()?;
+    for file in files {
+        parser = Parser::with_state(Lexer::new(file)?, parser.state)?;
+        parser.parse_declarations()?;
+    }
+    Ok(parser.state)
+}
+
+/// Parses a problem and several proofs of it. The problem is only parsed once, and all the proofs
+/// are parsed into the same term pool, so they can be checked by a single `ProofChecker`. Each
+/// proof is parsed independently, so definitions and step ids in one proof are not visible in the
+/// others.
+pub fn parse_problem_proofs<T: BufRead>(
+    problem: T,
+    proofs: Vec<T>,
+    config: Config,
+) -> ParserResult<(Problem, Vec<Proof>, TermPool)> {
+    let mut problem_parser = Parser::with_config(problem, config)?;
+    problem_parser.parse_declarations()?;
+    let problem = std::mem::take(&mut problem_parser.state.problem);
+
+    let mut state = problem_parser.state;
+    let mut parsed = Vec::with_capacity(proofs.len());
+    for proof in proofs {
+        let sorts_symbol_table = state.sorts_symbol_table.clone();
+        let function_defs = state.function_defs.clone();
+        let sort_declarations = state.sort_declarations.clone();
+        let symbol_origins = state.symbol_origins.clone();
+
+        let mut parser = Parser::with_state(Lexer::new(proof)?, state)?;
+        parsed.push(parser.parse_subproof(None)?);
+        parser.take_recovered_errors()?;
+
+        state = parser.state;
+        state.sorts_symbol_table = sorts_symbol_table;
+        state.function_defs = function_defs;
+        state.sort_declarations = sort_declarations;
+        state.symbol_origins = symbol_origins;
+        state.step_indices = SymbolTable::new();
+        state.step_ids.clear();
+    }
+    Ok((problem, parsed, state.term_pool))
+}
+
+/// Parses a problem and a model for it, as printed by the "get-model" SMT-LIB command.
+pub fn parse_problem_model<T: BufRead>(
+    problem: T,
+    model: T,
+    config: Config,
+) -> ParserResult<(Problem, Model, TermPool)> {
+    let mut problem_parser = Parser::with_config(problem, config)?;
+    let problem = problem_parser.parse_problem()?;
+
+    let mut model_parser = Parser::with_state(Lexer::new(model)?, problem_parser.state)?;
+    let model = model_parser.parse_model()?;
+    Ok((problem, model, model_parser.state.term_pool))
+}
+
+/// Parses an unsat core, as printed by the "get-unsat-core" SMT-LIB command, and returns the names
+/// of the assertions in it. The core may be preceded by the "unsat" result of a "check-sat"
+/// command.
+pub fn parse_unsat_core<T: BufRead>(core: T) -> ParserResult<Vec<String>> {
+    Parser::new(core)?.parse_unsat_core()
+}
 
-    Parser::with_state(proof, problem_parser.state)?.parse_proof()
+/// Parses a file of solver annotations for the steps of a proof, like the debug output that veriT
+/// can emit alongside a proof. The file is a series of entries of the form
+/// "(annotate <step-id> (:<attribute> <value>)*)", where each value is a string, a symbol or a
+/// numeral. Returns the attributes of each step, as pairs of the attribute name (without the
+/// leading ":") and its value, in the order they appear in the file.
+pub fn parse_annotations<T: BufRead>(
+    annotations: T,
+) -> ParserResult<HashMap<String, Vec<(String, String)>>> {
+    Parser::new(annotations)?.parse_annotations()
+}
+
+/// Parses a proof without its problem file. Declarations are taken from a prelude in the proof, if
+/// there is one, or are otherwise inferred from their first use. This always enables the
+/// `infer_declarations` option.
+pub fn parse_proof_only<T: BufRead>(proof: T, config: Config) -> ParserResult<(Proof, TermPool)> {
+    let config = Config {
+        infer_declarations: true,
+        ..config
+    };
+    Parser::with_config(proof, config)?.parse_proof()
+}
+
+/// Like `parse_proof_only`, but also returns the declarations used by the proof, as a `Problem`
+/// with no assertions. These are the sorts and functions declared in a prelude in the proof, and
+/// the ones whose declarations were inferred. The sorts of inferred declarations contain inferred
+/// sorts (see `SortKind::Inferred`).
+pub fn parse_proof_only_with_declarations<T: BufRead>(
+    proof: T,
+    config: Config,
+) -> ParserResult<(Problem, Proof, TermPool)> {
+    let config = Config {
+        infer_declarations: true,
+        ..config
+    };
+    let mut parser = Parser::with_config(proof, config)?;
+    let proof = parser.parse_proof_commands()?;
+    let problem = std::mem::take(&mut parser.state.problem);
+    Ok((problem, proof, parser.state.term_pool))
+}
+
+/// If any of `args` has sort Real, converts the integer literals among them into real literals, so
+/// that "(+ x 1)" is the same term as "(+ x 1.0)" when "x" is a real. This follows the SMT-LIB
+/// convention that numerals in real arithmetic denote reals. Integer terms that are not literals
+/// are not converted, and must be converted explicitly with "to_real".
+fn coerce_integer_literals(pool: &TermPool, args: Vec<Term>) -> Vec<Term> {
+    if !args
+        .iter()
+        .any(|a| pool.sort(a).as_deref() == Some(Term::REAL_SORT))
+    {
+        return args;
+    }
+    args.into_iter()
+        .map(|a| match a {
+            Term::Terminal(Terminal::Integer(i)) => terminal!(real BigRational::from_integer(i)),
+            other => other,
+        })
+        .collect()
+}
+
+/// Parses a series of definitions and declarations, and then parses a single term that may use
+/// them. This is useful to construct terms from SMT-LIB snippets, for example in tests and
+/// documentation. The term is added to the returned term pool.
+pub fn parse_term_with_definitions(
+    definitions: &str,
+    term: &str,
+) -> ParserResult<(ByRefRc<Term>, TermPool)> {
+    let mut parser = Parser::new(definitions.as_bytes())?;
+    parser.parse_problem()?;
+    let mut parser = Parser::with_state(Lexer::new(term.as_bytes())?, parser.state)?;
+    let term = parser.parse_term()?;
+    parser.expect_token(Token::Eof)?;
+    let term = parser.add_term(term);
+    Ok((term, parser.state.term_pool))
+}
+
+/// Parses a series of definitions and declarations, and then parses a proof that may use them, with
+/// the default configuration. This is a shorthand for `parse_problem_proof`, for when the problem
+/// itself is not needed, for example in tests.
+pub fn parse_proof_with_definitions(
+    definitions: &str,
+    proof: &str,
+) -> ParserResult<(Proof, TermPool)> {
+    let (_, proof, pool) =
+        parse_problem_proof(definitions.as_bytes(), proof.as_bytes(), Config::default())?;
+    Ok((proof, pool))
+}
+
+/// Parses a series of definitions and declarations, and then parses a single proof command that
+/// may use them. An "anchor" command and the subproof that follows it are parsed as a single
+/// command. Since there are no other commands, the command can't have premises.
+pub fn parse_proof_command(
+    definitions: &str,
+    command: &str,
+) -> ParserResult<(ProofCommand, TermPool)> {
+    let mut parser = Parser::new(definitions.as_bytes())?;
+    parser.parse_problem()?;
+    let mut parser = Parser::with_state(Lexer::new(command.as_bytes())?, parser.state)?;
+    let command = loop {
+        if let Some((_, command)) = parser.parse_proof_command(None)? {
+            break command;
+        }
+    };
+    parser.expect_token(Token::Eof)?;
+    Ok((command, parser.state.term_pool))
+}
+
+/// Scans a proof and returns the names of all rules used in it, in order of appearance and with
+/// repetitions. This only tokenizes the input, so it is much faster than parsing the whole proof,
+/// and doesn't need the problem file.
+pub fn get_used_rules<T: BufRead>(proof: T) -> ParserResult<Vec<String>> {
+    let mut lexer = Lexer::new(proof)?;
+    let mut result = Vec::new();
+    while let Some(token) = lexer.next() {
+        if token? == Token::Keyword("rule".into()) {
+            match lexer.next().transpose()? {
+                Some(Token::Symbol(s)) => result.push(s.to_string()),
+                Some(Token::ReservedWord(r)) => result.push(format!("{:?}", r)),
+                _ => (),
+            }
+        }
+    }
+    Ok(result)
 }
 
-type AnchorCommand = (String, Vec<(String, ByRefRc<Term>)>, Vec<SortedVar>);
+/// Reads the metadata of a proof: the comment lines at the start of `proof`, before its first
+/// command, and the logic and "set-info" attributes of `problem`, if it is given. Only the start of
+/// the proof is read, so this can be called on a second reader of the proof file without much
+/// overhead.
+pub fn parse_proof_metadata<T: BufRead>(
+    proof: T,
+    problem: Option<&Problem>,
+) -> io::Result<ProofMetadata> {
+    let mut comments = Vec::new();
+    for line in proof.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match line.strip_prefix(';') {
+            Some(comment) => comments.push(comment.trim_start_matches(';').trim().to_owned()),
+            None => break,
+        }
+    }
+    Ok(ProofMetadata {
+        comments,
+        logic: problem.and_then(|p| p.logic.clone()),
+        info: problem.map(|p| p.info.clone()).unwrap_or_default(),
+    })
+}
+
+type AnchorCommand = (String, Vec<(SortedVar, ByRefRc<Term>)>, Vec<SortedVar>);
 type StepCommand = (Vec<ByRefRc<Term>>, String, Vec<String>, Vec<ProofArg>);
 
+#[derive(Clone)]
 struct SymbolTable<K, V> {
     scopes: Vec<HashMap<K, V>>,
 }
@@ -45,6 +406,11 @@ impl<K, V> SymbolTable<K, V> {
             }
         }
     }
+
+    /// Pops scopes until there are at most `num_scopes` scopes. The last scope is never popped.
+    fn truncate_scopes(&mut self, num_scopes: usize) {
+        self.scopes.truncate(num_scopes.max(1));
+    }
 }
 
 impl<K: Eq + Hash, V> SymbolTable<K, V> {
@@ -52,6 +418,13 @@ impl<K: Eq + Hash, V> SymbolTable<K, V> {
         self.scopes.iter().rev().find_map(|scope| scope.get(key))
     }
 
+    /// Returns true if `key` is in the innermost scope of the symbol table.
+    fn contains_in_last_scope(&self, key: &K) -> bool {
+        self.scopes
+            .last()
+            .is_some_and(|scope| scope.contains_key(key))
+    }
+
     fn insert(&mut self, key: K, value: V) {
         self.scopes
             .last_mut()
@@ -66,53 +439,188 @@ impl<K, V> Default for SymbolTable<K, V> {
     }
 }
 
+/// Where a function symbol was introduced. This is used to check that the symbols defined in the
+/// proof, like Skolem constants, are fresh.
+#[derive(Clone)]
+enum SymbolOrigin {
+    /// Declared or defined in the problem.
+    Problem,
+
+    /// Declared with "declare-fun" in the proof, as part of a prelude.
+    ProofDeclaration,
+
+    /// Defined with "define-fun" in the proof.
+    ProofDefinition(FunctionDef),
+}
+
 #[derive(Default)]
 struct ParserState {
+    config: Config,
     sorts_symbol_table: SymbolTable<Identifier, ByRefRc<Term>>,
     function_defs: HashMap<String, FunctionDef>,
     term_pool: TermPool,
     sort_declarations: HashMap<String, (u64, ByRefRc<Term>)>,
-    step_indices: SymbolTable<String, usize>,
+    step_indices: SymbolTable<String, (usize, usize)>,
+
+    /// The ids of all commands parsed in the current proof, including the ones in subproofs that
+    /// have already ended.
+    step_ids: HashSet<String>,
+
+    /// The id of the step command being parsed, if its id was already read. This is used to tell
+    /// if an error happened in the last step of a subproof.
+    current_step_id: Option<String>,
+    errors: Vec<ParserError>,
+    problem: Problem,
+    num_inferred_sorts: usize,
+    symbol_origins: HashMap<String, SymbolOrigin>,
+
+    /// The indices in `problem.assertions` of the assertions that are currently active.
+    active_assertions: Vec<usize>,
+
+    /// The scopes opened with "push", as pairs of the number of active assertions when the scope
+    /// was opened and the number of consecutive scopes opened with that number. Storing the scopes
+    /// this way means a "push" command with a huge number of levels doesn't use more memory.
+    assertion_scopes: Vec<(usize, usize)>,
+}
+
+impl ParserState {
+    /// Creates the state of a parser that hasn't parsed anything yet, with only the built-in
+    /// symbols declared.
+    fn new(config: Config) -> Self {
+        let mut state = ParserState {
+            config,
+            ..Default::default()
+        };
+        let builtins = vec![("true", Term::BOOL_SORT), ("false", Term::BOOL_SORT)];
+        for (iden, sort) in builtins {
+            let iden = Identifier::Simple(iden.into());
+            let sort = state.term_pool.add_term(sort.clone());
+            state.sorts_symbol_table.insert(iden, sort);
+        }
+        state
+    }
 }
 
 /// A parser for the veriT Proof Format. The parser makes use of hash consing to reduce memory usage
 /// by sharing identical terms in the AST.
+///
+/// The parser always holds one token of lookahead, which can be inspected with `current_token`.
+/// Tools that need to do their own processing of the token stream (for example, to skip over some
+/// commands) can use `current_token` and `next_token` to consume tokens, and then resume parsing
+/// with one of the `parse_*` methods, as long as the parser is positioned at the start of what is
+/// being parsed. The term pool and symbol tables are shared by all calls on the same parser.
 pub struct Parser<R> {
     lexer: Lexer<R>,
     current_token: Token,
+    current_token_start: Position,
+    parens_depth: usize,
     state: ParserState,
+
+    /// If set, the top-level commands of a proof are given to this function as soon as they are
+    /// parsed, instead of being added to the parsed proof. Parsing stops if it returns `false`.
+    command_sink: Option<Box<dyn FnMut(ProofCommand) -> bool>>,
 }
 
 impl<R: BufRead> Parser<R> {
     /// Constructs a new `Parser` from a type that implements `BufRead`. This operation can fail if
     /// there is an IO or lexer error on the first token.
     pub fn new(input: R) -> ParserResult<Self> {
-        let mut state = ParserState::default();
-        let builtins = vec![("true", Term::BOOL_SORT), ("false", Term::BOOL_SORT)];
-        for (iden, sort) in builtins {
-            let iden = Identifier::Simple(iden.into());
-            let sort = state.term_pool.add_term(sort.clone());
-            state.sorts_symbol_table.insert(iden, sort);
-        }
-        Parser::with_state(input, state)
+        Parser::with_config(input, Config::default())
+    }
+
+    /// Constructs a new `Parser` using the given configuration options. This operation can fail if
+    /// there is an IO or lexer error on the first token.
+    pub fn with_config(input: R, config: Config) -> ParserResult<Self> {
+        Parser::from_lexer(Lexer::new(input)?, config)
+    }
+
+    /// Constructs a new `Parser` that reads its tokens from an existing `Lexer`. Any tokens already
+    /// read from the lexer are not seen by the parser. This operation can fail if there is an IO or
+    /// lexer error on the first token.
+    pub fn from_lexer(lexer: Lexer<R>, config: Config) -> ParserResult<Self> {
+        Parser::with_state(lexer, ParserState::new(config))
     }
 
     /// Constructs a new `Parser` using an existing `ParserState`. This operation can fail if there
     /// is an IO or lexer error on the first token.
-    fn with_state(input: R, state: ParserState) -> ParserResult<Self> {
-        let mut lexer = Lexer::new(input)?;
+    fn with_state(mut lexer: Lexer<R>, state: ParserState) -> ParserResult<Self> {
         let current_token = lexer.next_token()?;
         Ok(Parser {
+            current_token_start: lexer.token_start,
             lexer,
             current_token,
+            parens_depth: 0,
             state,
+            command_sink: None,
         })
     }
 
+    /// Returns the current token, that is, the next token that will be consumed by the parser.
+    pub fn current_token(&self) -> &Token {
+        &self.current_token
+    }
+
+    /// Returns the position in the input of the end of the current token.
+    pub fn position(&self) -> Position {
+        self.lexer.position
+    }
+
     /// Advances the parser one token, and returns the previous `current_token`.
-    fn next_token(&mut self) -> ParserResult<Token> {
+    pub fn next_token(&mut self) -> ParserResult<Token> {
         let new = self.lexer.next_token()?;
-        Ok(std::mem::replace(&mut self.current_token, new))
+        self.current_token_start = self.lexer.token_start;
+        let old = std::mem::replace(&mut self.current_token, new);
+        match old {
+            Token::OpenParen => {
+                self.parens_depth += 1;
+                if self.parens_depth > self.state.config.max_depth {
+                    let limit = self.state.config.max_depth;
+                    return Err(self.err(ErrorKind::MaxDepthExceeded(limit)));
+                }
+            }
+            Token::CloseParen => self.parens_depth = self.parens_depth.saturating_sub(1),
+            _ => (),
+        }
+        Ok(old)
+    }
+
+    /// If error recovery is enabled, records the error and skips tokens until the end of the
+    /// top-level command in which the error happened, so parsing can continue from the next
+    /// command. Otherwise, or if the error is not recoverable, the error is returned.
+    fn recover_from_error(&mut self, err: ParserError) -> ParserResult<()> {
+        let recoverable = !matches!(
+            err.0,
+            ErrorKind::Io(_)
+                | ErrorKind::MemoryLimitExceeded(_)
+                | ErrorKind::MaxDepthExceeded(_)
+                | ErrorKind::TermSizeExceeded(_)
+                | ErrorKind::ClauseLengthExceeded(_)
+        );
+        if !self.state.config.error_recovery || !recoverable {
+            return Err(err);
+        }
+        self.state.errors.push(err);
+        while self.parens_depth > 0 && self.current_token != Token::Eof {
+            // Errors found while skipping tokens are also recorded. The lexer always consumes the
+            // offending characters, so this can't loop forever
+            match self.next_token() {
+                Err(err @ ParserError(ErrorKind::Io(_), _)) => return Err(err),
+                Err(err) => self.state.errors.push(err),
+                Ok(_) => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the errors recorded during error recovery, if there are any. If there is only one
+    /// error, it is returned directly.
+    fn take_recovered_errors(&mut self) -> ParserResult<()> {
+        let mut errors = std::mem::take(&mut self.state.errors);
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.pop().unwrap()),
+            _ => Err(ParserError(ErrorKind::MultipleErrors(errors), None)),
+        }
     }
 
     /// Helper method to build a parser error with the current lexer position.
@@ -120,11 +628,71 @@ impl<R: BufRead> Parser<R> {
         ParserError(err, Some(self.lexer.position))
     }
 
+    /// Returns an error if the term pool has exceeded the memory limit given in the parser
+    /// configuration.
+    fn check_memory_limit(&self) -> ParserResult<()> {
+        match self.state.config.memory_limit {
+            Some(limit) if self.state.term_pool.memory_usage() > limit => {
+                Err(self.err(ErrorKind::MemoryLimitExceeded(limit)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns an error if `clause` is longer than the maximum clause length, or if one of its
+    /// terms is larger than the maximum term size, given in the parser configuration.
+    fn check_clause_limits(&self, clause: &[ByRefRc<Term>]) -> ParserResult<()> {
+        if let Some(limit) = self.state.config.max_clause_length {
+            if clause.len() > limit {
+                return Err(self.err(ErrorKind::ClauseLengthExceeded(limit)));
+            }
+        }
+        if let Some(limit) = self.state.config.max_term_size {
+            // We stop counting as soon as the limit is exceeded, so this takes time proportional
+            // to the limit, even if the term is much larger
+            if clause
+                .iter()
+                .any(|t| t.subterms().take(limit + 1).count() > limit)
+            {
+                return Err(self.err(ErrorKind::TermSizeExceeded(limit)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns an error if `term`, the result of expanding a function definition, would be nested
+    /// deeper than the maximum depth given in the parser configuration, counting the parentheses
+    /// that enclose it. Expanding chains of definitions can build terms much deeper than the input
+    /// is nested, so this is needed to stop them from overflowing the stack later on.
+    fn check_expanded_depth(&mut self, term: &ByRefRc<Term>) -> ParserResult<()> {
+        let limit = self.state.config.max_depth;
+        if self.parens_depth + self.state.term_pool.depth(term) > limit {
+            return Err(self.err(ErrorKind::MaxDepthExceeded(limit)));
+        }
+        Ok(())
+    }
+
     /// Shortcut for `self.state.term_pool.add_term`.
     fn add_term(&mut self, term: Term) -> ByRefRc<Term> {
         self.state.term_pool.add_term(term)
     }
 
+    /// Returns the sort of `term`, whose immediate subterms must already be in the term pool. See
+    /// `TermPool::sort`.
+    fn sort_of(&self, term: &Term) -> Result<ByRefRc<Term>, SortError> {
+        self.state
+            .term_pool
+            .sort(term)
+            .ok_or_else(|| SortError::Unknown(term.clone()))
+    }
+
+    /// Returns a sort error if the sort of `term` is not `expected`. See `SortError::assert_eq`.
+    fn assert_sort(&self, expected: &Term, term: &Term) -> ParserResult<()> {
+        self.sort_of(term)
+            .and_then(|sort| SortError::assert_eq(expected, &sort))
+            .map_err(|err| self.err(err.into()))
+    }
+
     /// Shortcut for `self.state.term_pool.add_all`.
     fn add_all(&mut self, term: Vec<Term>) -> Vec<ByRefRc<Term>> {
         self.state.term_pool.add_all(term)
@@ -134,7 +702,7 @@ impl<R: BufRead> Parser<R> {
     fn insert_sorted_var(&mut self, (symbol, sort): SortedVar) {
         self.state
             .sorts_symbol_table
-            .insert(Identifier::Simple(symbol), sort)
+            .insert(Identifier::Simple(symbol.into()), sort)
     }
 
     /// Helper method to build a `ErrorKind::UnexpectedToken` error.
@@ -144,86 +712,144 @@ impl<R: BufRead> Parser<R> {
 
     /// Constructs and sort checks a variable term.
     fn make_var(&mut self, iden: Identifier) -> Result<Term, ErrorKind> {
-        let sort = self
-            .state
-            .sorts_symbol_table
-            .get(&iden)
-            .ok_or_else(|| ErrorKind::UndefinedIden(iden.clone()))?;
-        Ok(Term::Terminal(Terminal::Var(iden, sort.clone())))
+        let sort = match self.state.sorts_symbol_table.get(&iden) {
+            Some(sort) => sort.clone(),
+            None if self.state.config.infer_declarations => {
+                let sort = self.fresh_inferred_sort();
+                self.declare_inferred(iden.clone(), sort.clone());
+                sort
+            }
+            None => return Err(ErrorKind::UndefinedIden(iden)),
+        };
+        Ok(Term::Terminal(Terminal::Var(iden, sort)))
+    }
+
+    /// Declares a new uninterpreted sort, and returns it.
+    fn declare_sort(&mut self, name: String, arity: u64) -> ByRefRc<Term> {
+        let declaration = (name.clone(), arity);
+        self.state.problem.sort_declarations.push(declaration);
+        // User declared sorts are represented with the `Atom` sort kind, and an argument which is
+        // a string terminal representing the sort name.
+        let sort = {
+            let arg = self.add_term(terminal!(string name.clone()));
+            self.add_term(Term::Sort(SortKind::Atom, vec![arg]))
+        };
+        self.state
+            .sort_declarations
+            .insert(name, (arity, sort.clone()));
+        sort
+    }
+
+    /// Creates a new inferred sort, different from all previous ones.
+    fn fresh_inferred_sort(&mut self) -> ByRefRc<Term> {
+        let id = self.add_term(terminal!(int self.state.num_inferred_sorts));
+        self.state.num_inferred_sorts += 1;
+        self.add_term(Term::Sort(SortKind::Inferred, vec![id]))
+    }
+
+    /// Declares a symbol whose declaration was inferred from its first use. The declaration is
+    /// always inserted in the outermost scope, so it is visible in the rest of the input.
+    fn declare_inferred(&mut self, iden: Identifier, sort: ByRefRc<Term>) {
+        if let Identifier::Simple(name) = &iden {
+            let declaration = (name.to_string(), sort.clone());
+            self.state.problem.function_declarations.push(declaration);
+        }
+        self.state.sorts_symbol_table.scopes[0].insert(iden, sort);
     }
 
     /// Constructs and sort checks an operation term.
     fn make_op(&mut self, op: Operator, args: Vec<Term>) -> Result<Term, ErrorKind> {
-        let sorts: Vec<_> = args.iter().map(Term::sort).collect();
+        // "-" is parsed as subtraction, but with only one argument it means negation
+        let op = match op {
+            Operator::Sub if args.len() == 1 => Operator::Neg,
+            op => op,
+        };
+        let args = match op {
+            Operator::Add
+            | Operator::Sub
+            | Operator::Mult
+            | Operator::Div
+            | Operator::LessThan
+            | Operator::GreaterThan
+            | Operator::LessEq
+            | Operator::GreaterEq => coerce_integer_literals(&self.state.term_pool, args),
+            _ => args,
+        };
+        let arity = if self.state.config.permissive_arities {
+            op.permissive_arity()
+        } else {
+            op.arity()
+        };
+        if !arity.contains(&args.len()) {
+            let max = *arity.end();
+            return Err(ErrorKind::WrongNumberOfOperatorArgs {
+                op,
+                min: *arity.start(),
+                max: (max != usize::MAX).then_some(max),
+                got: args.len(),
+            });
+        }
+        let arg_sorts = args
+            .iter()
+            .map(|a| self.sort_of(a))
+            .collect::<Result<Vec<_>, _>>()?;
+        let sorts: Vec<&Term> = arg_sorts.iter().map(AsRef::as_ref).collect();
         match op {
-            Operator::Not => {
-                ErrorKind::assert_num_of_args(&args, 1)?;
-                SortError::assert_eq(Term::BOOL_SORT, &sorts[0])?;
-            }
-            Operator::Implies => {
-                ErrorKind::assert_num_of_args_range(&args, 2..)?;
+            Operator::Not => SortError::assert_eq(Term::BOOL_SORT, sorts[0])?,
+            Operator::Implies | Operator::Or | Operator::And | Operator::Xor => {
                 for s in sorts {
-                    SortError::assert_eq(Term::BOOL_SORT, &s)?;
+                    SortError::assert_eq(Term::BOOL_SORT, s)?;
                 }
             }
-            Operator::Or | Operator::And | Operator::Xor => {
-                // These operators can be called with only one argument
-                ErrorKind::assert_num_of_args_range(&args, 1..)?;
-                for s in sorts {
-                    SortError::assert_eq(Term::BOOL_SORT, &s)?;
-                }
-            }
-            Operator::Equals | Operator::Distinct => {
-                ErrorKind::assert_num_of_args_range(&args, 2..)?;
-                SortError::assert_all_eq(&sorts)?;
-            }
+            Operator::Equals | Operator::Distinct => SortError::assert_all_eq(&sorts)?,
             Operator::Ite => {
-                ErrorKind::assert_num_of_args(&args, 3)?;
-                SortError::assert_eq(Term::BOOL_SORT, &sorts[0])?;
-                SortError::assert_eq(&sorts[1], &sorts[2])?;
+                SortError::assert_eq(Term::BOOL_SORT, sorts[0])?;
+                SortError::assert_eq(sorts[1], sorts[2])?;
             }
-            Operator::Add | Operator::Mult | Operator::Div => {
-                ErrorKind::assert_num_of_args_range(&args, 2..)?;
-
+            Operator::Add | Operator::Sub | Operator::Neg | Operator::Mult | Operator::Div => {
                 // All the arguments must have the same sort, and it must be either Int or Real
-                SortError::assert_one_of(&[Term::INT_SORT, Term::REAL_SORT], &sorts[0])?;
-                SortError::assert_all_eq(&sorts)?;
-            }
-            Operator::Sub => {
-                // The "-" operator, in particular, can be called with only one argument, in which
-                // case it means negation instead of subtraction
-                ErrorKind::assert_num_of_args_range(&args, 1..)?;
-                SortError::assert_one_of(&[Term::INT_SORT, Term::REAL_SORT], &sorts[0])?;
+                SortError::assert_one_of(&[Term::INT_SORT, Term::REAL_SORT], sorts[0])?;
                 SortError::assert_all_eq(&sorts)?;
             }
             Operator::LessThan | Operator::GreaterThan | Operator::LessEq | Operator::GreaterEq => {
-                ErrorKind::assert_num_of_args_range(&args, 2..)?;
                 // All the arguments must be either Int or Real sorted, but they don't need to all
                 // have the same sort
-                SortError::assert_one_of(&[Term::INT_SORT, Term::REAL_SORT], &sorts[0])?;
+                SortError::assert_one_of(&[Term::INT_SORT, Term::REAL_SORT], sorts[0])?;
+            }
+            Operator::IntDiv | Operator::Mod | Operator::Abs => {
+                for s in sorts {
+                    SortError::assert_eq(Term::INT_SORT, s)?;
+                }
+            }
+            Operator::ToReal | Operator::Divisible => {
+                // The index of "divisible" is validated when it is parsed, so only the last
+                // argument needs to be checked
+                SortError::assert_eq(Term::INT_SORT, sorts[sorts.len() - 1])?;
             }
+            Operator::ToInt | Operator::IsInt => SortError::assert_eq(Term::REAL_SORT, sorts[0])?,
         }
         let args = self.add_all(args);
-        Ok(Term::Op(op, args))
+        let term = Term::Op(op, args);
+        Ok(term.as_negative_constant().unwrap_or(term))
     }
 
     /// Constructs and sort checks an application term.
     fn make_app(&mut self, function: Term, args: Vec<Term>) -> Result<Term, ErrorKind> {
-        let sorts = {
-            let function_sort = function.sort();
-            if let Term::Sort(SortKind::Function, sorts) = function_sort {
-                sorts
-            } else {
+        let function_sort = self.sort_of(&function)?;
+        let sorts = match function_sort.as_ref() {
+            Term::Sort(SortKind::Function, sorts) if !sorts.is_empty() => sorts,
+            other => {
                 // Function does not have function sort
                 return Err(ErrorKind::SortError(SortError::Expected {
                     expected: Term::Sort(SortKind::Function, Vec::new()),
-                    got: function_sort.clone(),
+                    got: other.clone(),
                 }));
             }
         };
         ErrorKind::assert_num_of_args(&args, sorts.len() - 1)?;
-        for i in 0..args.len() {
-            SortError::assert_eq(sorts[i].as_ref(), &args[i].sort())?;
+        for (sort, arg) in sorts.iter().zip(&args) {
+            let arg_sort = self.sort_of(arg)?;
+            SortError::assert_eq(sort, &arg_sort)?;
         }
         let function = self.add_term(function);
         let args = self.add_all(args);
@@ -240,17 +866,17 @@ impl<R: BufRead> Parser<R> {
         }
     }
 
-    /// Consumes the current token if it is a symbol, and returns the inner `String`. Returns an
+    /// Consumes the current token if it is a symbol, and returns its name as a `String`. Returns an
     /// error otherwise.
     fn expect_symbol(&mut self) -> ParserResult<String> {
         match self.next_token()? {
-            Token::Symbol(s) => Ok(s),
+            Token::Symbol(s) => Ok(s.to_string()),
             other => Err(self.unexpected_token(other)),
         }
     }
 
-    /// Consumes the current token if it is a keyword, and returns the inner `String`. Returns an
-    /// error otherwise.
+    /// Consumes the current token if it is a keyword, and returns its name as a `String`. Returns
+    /// an error otherwise.
     fn expect_keyword(&mut self) -> ParserResult<String> {
         match self.next_token()? {
             Token::Keyword(s) => Ok(s),
@@ -258,8 +884,8 @@ impl<R: BufRead> Parser<R> {
         }
     }
 
-    /// Consumes the current token if it is a numeral, and returns the inner `u64`. Returns an
-    /// error otherwise.
+    /// Consumes the current token if it is a numeral, and returns its value. Returns an error
+    /// otherwise.
     fn expect_numeral(&mut self) -> ParserResult<BigInt> {
         match self.next_token()? {
             Token::Numeral(n) => Ok(n),
@@ -285,156 +911,483 @@ impl<R: BufRead> Parser<R> {
         }
     }
 
-    /// Reads an SMT-LIB script and parses the declarations and definitions. Ignores all other
-    /// SMT-LIB script commands.
-    pub fn parse_problem(&mut self) -> ParserResult<()> {
+    /// Reads an SMT-LIB script and parses the logic, declarations, definitions and assertions.
+    /// Ignores all other SMT-LIB script commands.
+    pub fn parse_problem(&mut self) -> ParserResult<Problem> {
+        self.parse_declarations()?;
+        self.take_recovered_errors()?;
+        Ok(std::mem::take(&mut self.state.problem))
+    }
+
+    /// Parses the declarations and definitions in an SMT-LIB script. Unlike `parse_problem`, this
+    /// doesn't report the errors recorded during error recovery, so they can be reported together
+    /// with the errors in the proof.
+    fn parse_declarations(&mut self) -> ParserResult<()> {
         while self.current_token != Token::Eof {
-            self.expect_token(Token::OpenParen)?;
-            match self.next_token()? {
-                Token::ReservedWord(Reserved::DeclareFun) => {
-                    let (name, sort) = self.parse_declare_fun()?;
-                    self.insert_sorted_var((name, sort));
-                    continue;
+            self.check_memory_limit()?;
+            if let Err(err) = self.parse_problem_command() {
+                self.recover_from_error(err)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses a single SMT-LIB script command, ignoring it if it is not a declaration or
+    /// definition.
+    fn parse_problem_command(&mut self) -> ParserResult<()> {
+        self.expect_token(Token::OpenParen)?;
+        match self.next_token()? {
+            Token::ReservedWord(Reserved::DeclareFun) => {
+                let (name, sort) = self.parse_declare_fun()?;
+                let declaration = (name.clone(), sort.clone());
+                self.state.problem.function_declarations.push(declaration);
+                self.state
+                    .symbol_origins
+                    .insert(name.clone(), SymbolOrigin::Problem);
+                self.insert_sorted_var((name, sort));
+            }
+            Token::ReservedWord(Reserved::DeclareSort) => {
+                let (name, arity) = self.parse_declare_sort()?;
+                self.declare_sort(name, arity);
+            }
+            Token::ReservedWord(Reserved::DefineFun) => {
+                let (name, func_def) = self.parse_define_fun()?;
+                let definition = (name.clone(), func_def.clone());
+                self.state.problem.function_definitions.push(definition);
+                self.state
+                    .symbol_origins
+                    .insert(name.clone(), SymbolOrigin::Problem);
+                self.state.function_defs.insert(name, func_def);
+            }
+            Token::Symbol(s) if s == "assert" => {
+                // Problem files may use features that are not supported by the parser, like
+                // integer literals in real arithmetic. Since the assertions are not needed to check
+                // the proof, if an assertion can't be parsed, we skip it instead of failing, and
+                // record the error in the problem
+                let num_scopes = self.state.sorts_symbol_table.scopes.len();
+                match self.parse_assertion() {
+                    Ok(assertion) => {
+                        let index = self.state.problem.assertions.len();
+                        self.state.problem.assertions.push(assertion);
+                        self.state.active_assertions.push(index);
+                        self.expect_token(Token::CloseParen)?;
+                    }
+                    Err(
+                        err @ ParserError(ErrorKind::Io(_) | ErrorKind::TermSizeExceeded(_), _),
+                    ) => return Err(err),
+                    Err(err) => {
+                        self.state.problem.skipped_assertions.push(err);
+                        self.state.sorts_symbol_table.scopes.truncate(num_scopes);
+                        while self.parens_depth > 0 {
+                            if self.next_token()? == Token::Eof {
+                                return Err(self.unexpected_token(Token::Eof));
+                            }
+                        }
+                    }
                 }
-                Token::ReservedWord(Reserved::DeclareSort) => {
-                    let (name, arity) = self.parse_declare_sort()?;
-                    // User declared sorts are represented with the `Atom` sort kind, and an
-                    // argument which is a string terminal representing the sort name.
-                    let sort = {
-                        let arg = self.add_term(terminal!(string name.clone()));
-                        self.add_term(Term::Sort(SortKind::Atom, vec![arg]))
-                    };
-                    self.state.sort_declarations.insert(name, (arity, sort));
-                    continue;
+            }
+            Token::Symbol(s) if s == "push" => {
+                let levels = self.parse_scope_levels()?;
+                let num_active = self.state.active_assertions.len();
+                let scopes = &mut self.state.assertion_scopes;
+                match scopes.last_mut() {
+                    Some((n, count)) if *n == num_active => *count = count.saturating_add(levels),
+                    _ if levels > 0 => scopes.push((num_active, levels)),
+                    _ => (),
                 }
-                Token::ReservedWord(Reserved::DefineFun) => {
-                    let (name, func_def) = self.parse_define_fun()?;
-                    self.state.function_defs.insert(name, func_def);
-                    continue;
+            }
+            Token::Symbol(s) if s == "pop" => {
+                let mut levels = self.parse_scope_levels()?;
+                let scopes = &mut self.state.assertion_scopes;
+
+                // Popping more scopes than were pushed is an error in SMT-LIB, but since it
+                // doesn't affect the proof, we just close all open scopes
+                while levels > 0 {
+                    let (num_active, count) = match scopes.last_mut() {
+                        Some(scope) => scope,
+                        None => break,
+                    };
+                    self.state.active_assertions.truncate(*num_active);
+                    if *count > levels {
+                        *count -= levels;
+                        break;
+                    }
+                    levels -= *count;
+                    scopes.pop();
                 }
-                _ => {
-                    // If the command is not a declaration or definition, we just ignore it. We do
-                    // that by reading tokens until the command parenthesis is closed
-                    let mut parens_depth = 1;
-                    while parens_depth > 0 {
-                        parens_depth += match self.next_token()? {
-                            Token::OpenParen => 1,
-                            Token::CloseParen => -1,
-                            Token::Eof => return Err(self.unexpected_token(Token::Eof)),
-                            _ => 0,
-                        };
+            }
+            Token::Symbol(s) if s == "check-sat" => {
+                let query = self.state.active_assertions.clone();
+                self.state.problem.queries.push(query);
+                self.expect_token(Token::CloseParen)?;
+            }
+            Token::Symbol(s) if s == "set-logic" => {
+                self.state.problem.logic = Some(self.expect_symbol()?);
+                self.expect_token(Token::CloseParen)?;
+            }
+            Token::Symbol(s) if s == "set-info" => {
+                let key = match self.next_token()? {
+                    Token::Keyword(key) => key,
+                    Token::CloseParen => return Ok(()),
+                    _ => return self.skip_until_close_paren(),
+                };
+                let value = match self.next_token()? {
+                    Token::CloseParen => {
+                        self.state.problem.info.push((key, String::new()));
+                        return Ok(());
+                    }
+                    Token::Symbol(value) => value.to_string(),
+                    Token::Numeral(value) => value.to_string(),
+                    Token::Decimal(value) => format!("{:?}", terminal!(real value)),
+                    Token::String(value) => value,
+                    Token::OpenParen => {
+                        self.skip_until_close_paren()?;
+                        String::new()
+                    }
+                    _ => String::new(),
+                };
+
+                // An unrecognized status is ignored, like any other attribute we don't use
+                if key == "status" {
+                    if let Some(status) = Status::from_name(&value) {
+                        self.state.problem.status = Some(status);
                     }
                 }
+                self.state.problem.info.push((key, value));
+                self.skip_until_close_paren()?;
             }
+            _ => {
+                // If the command is not one of the above, we just ignore it
+                self.skip_until_close_paren()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads tokens until the parenthesis of the current command is closed.
+    fn skip_until_close_paren(&mut self) -> ParserResult<()> {
+        let mut parens_depth = 1;
+        while parens_depth > 0 {
+            parens_depth += match self.next_token()? {
+                Token::OpenParen => 1,
+                Token::CloseParen => -1,
+                Token::Eof => return Err(self.unexpected_token(Token::Eof)),
+                _ => 0,
+            };
         }
         Ok(())
     }
 
+    /// Parses the optional number of levels in a "push" or "pop" command, and the closing
+    /// parenthesis of the command. If the number is omitted, it defaults to 1.
+    fn parse_scope_levels(&mut self) -> ParserResult<usize> {
+        let levels = if self.current_token == Token::CloseParen {
+            1
+        } else {
+            let n = self.expect_numeral()?;
+            n.to_usize()
+                .ok_or_else(|| self.err(ErrorKind::InvalidScopeLevels(n)))?
+        };
+        self.expect_token(Token::CloseParen)?;
+        Ok(levels)
+    }
+
+    /// Parses a model, as printed by the "get-model" SMT-LIB command. The model may be preceded by
+    /// the "sat" result of "check-sat", and may use the older "(model ...)" format. Elements of
+    /// uninterpreted sorts may be declared in the model with "declare-fun", and used in the
+    /// definitions that follow them. Other commands in the model are ignored.
+    pub fn parse_model(&mut self) -> ParserResult<Model> {
+        if self.current_token == Token::Symbol("sat".into()) {
+            self.next_token()?;
+        }
+        self.expect_token(Token::OpenParen)?;
+        if self.current_token == Token::Symbol("model".into()) {
+            self.next_token()?;
+        }
+        let mut model = Model::default();
+        while self.current_token != Token::CloseParen {
+            self.expect_token(Token::OpenParen)?;
+            match self.next_token()? {
+                Token::ReservedWord(Reserved::DefineFun) => {
+                    model.definitions.push(self.parse_define_fun()?);
+                }
+                Token::ReservedWord(Reserved::DeclareFun) => {
+                    let declaration = self.parse_declare_fun()?;
+                    model.elements.push(declaration.clone());
+                    self.insert_sorted_var(declaration);
+                }
+                Token::Eof => return Err(self.unexpected_token(Token::Eof)),
+                _ => self.skip_until_close_paren()?,
+            }
+        }
+        self.expect_token(Token::CloseParen)?;
+        self.expect_token(Token::Eof)?;
+        Ok(model)
+    }
+
+    /// Parses an unsat core, as printed by the "get-unsat-core" command, optionally preceded by
+    /// "unsat".
+    pub fn parse_unsat_core(&mut self) -> ParserResult<Vec<String>> {
+        if self.current_token == Token::Symbol("unsat".into()) {
+            self.next_token()?;
+        }
+        self.expect_token(Token::OpenParen)?;
+        let names = self.parse_sequence(Self::expect_symbol, false)?;
+        self.expect_token(Token::Eof)?;
+        Ok(names)
+    }
+
+    /// Parses a file of step annotations. See the `parse_annotations` function.
+    pub fn parse_annotations(&mut self) -> ParserResult<HashMap<String, Vec<(String, String)>>> {
+        let mut result: HashMap<_, Vec<_>> = HashMap::new();
+        while self.current_token != Token::Eof {
+            self.expect_token(Token::OpenParen)?;
+            self.expect_token(Token::Symbol("annotate".into()))?;
+            let id = self.expect_symbol()?;
+            let mut attributes = Vec::new();
+            while self.current_token != Token::CloseParen {
+                let name = self.expect_keyword()?;
+                let value = match self.next_token()? {
+                    Token::String(s) => s,
+                    Token::Symbol(s) => s.to_string(),
+                    Token::Numeral(n) => n.to_string(),
+                    other => return Err(self.unexpected_token(other)),
+                };
+                attributes.push((name, value));
+            }
+            self.next_token()?; // Consume ")" token
+            result.entry(id).or_default().extend(attributes);
+        }
+        Ok(result)
+    }
+
     /// Parses a proof.
     pub fn parse_proof(mut self) -> ParserResult<(Proof, TermPool)> {
-        let proof = self.parse_subproof(None)?;
+        let proof = self.parse_proof_commands()?;
         Ok((proof, self.state.term_pool))
     }
 
+    /// Parses the commands of a proof, in the format given in the configuration.
+    fn parse_proof_commands(&mut self) -> ParserResult<Proof> {
+        let proof = match self.state.config.format {
+            ProofFormat::Alethe => self.parse_subproof(None)?,
+            ProofFormat::Legacy => self.parse_legacy_proof()?,
+        };
+        self.take_recovered_errors()?;
+        Ok(proof)
+    }
+
     /// Parses a proof or subproof. Will stop parsing after encountering a command with index
     /// `end_step`. If `end_step` is `None`, stops at EOF.
     fn parse_subproof(&mut self, end_step: Option<&str>) -> ParserResult<Proof> {
+        let num_sort_scopes = self.state.sorts_symbol_table.scopes.len();
+        let num_index_scopes = self.state.step_indices.scopes.len();
         let mut commands = Vec::new();
+        let mut num_sent = 0;
         while self.current_token != Token::Eof {
-            self.expect_token(Token::OpenParen)?;
-            let (index, command) = match self.next_token()? {
-                Token::ReservedWord(Reserved::Assume) => self.parse_assume_command()?,
-                Token::ReservedWord(Reserved::Step) => {
-                    let (index, (clause, rule, premises, args)) = self.parse_step_command()?;
-
-                    // If this is the last step in the subproof, we pop the top scope of the step
-                    // indices symbol table before converting the premises into indices. We must do
-                    // this here because if the last step of a subproof has premises, they refer to
-                    // the outer scope, and not inside the subproof
-                    if end_step == Some(&index) {
-                        self.state.step_indices.pop_scope();
+            self.check_memory_limit()?;
+            let (index, command) = match self.parse_proof_command(end_step) {
+                Ok(Some(c)) => c,
+                Ok(None) => continue,
+                Err(err) => {
+                    let failed_step = self.state.current_step_id.take();
+                    self.recover_from_error(err)?;
+
+                    // The failed command may have opened scopes, like an "anchor" command with
+                    // invalid arguments, so we restore the scopes to how they were when this
+                    // subproof was opened. If the failed command was the last step of the
+                    // subproof, the subproof still ends there, so its scope is closed
+                    self.state
+                        .sorts_symbol_table
+                        .truncate_scopes(num_sort_scopes);
+                    if end_step.is_some() && failed_step.as_deref() == end_step {
+                        self.state
+                            .step_indices
+                            .truncate_scopes(num_index_scopes - 1);
+                        break;
                     }
-
-                    // For every premise index symbol, find the associated `usize` in the
-                    // `step_indices` hash map, or return an error
-                    let premises: Vec<_> = premises
-                        .into_iter()
-                        .map(|index| {
-                            self.state
-                                .step_indices
-                                .get(&index)
-                                .copied()
-                                .ok_or_else(|| self.err(ErrorKind::UndefinedStepIndex(index)))
-                        })
-                        .collect::<Result<_, _>>()?;
-
-                    let command = ProofCommand::Step(ProofStep {
-                        clause,
-                        rule,
-                        premises,
-                        args,
-                    });
-                    (index, command)
-                }
-                Token::ReservedWord(Reserved::DefineFun) => {
-                    let (name, func_def) = self.parse_define_fun()?;
-                    self.state.function_defs.insert(name, func_def);
+                    self.state.step_indices.truncate_scopes(num_index_scopes);
                     continue;
                 }
-                Token::ReservedWord(Reserved::Anchor) => {
-                    let (end_step_index, assignment_args, variable_args) =
-                        self.parse_anchor_command()?;
-
-                    self.state.step_indices.push_scope();
-                    let Proof(commands) = self.parse_subproof(Some(&end_step_index))?;
-                    // We don't need to pop the scope that we pushed because it is popped when the
-                    // last step of the subproof is parsed
-
-                    // Since `Parser::parse_anchor_command` pushes a scope into the symbol table, we
-                    // have to pop it now, after parsing the subproof
-                    self.state.sorts_symbol_table.pop_scope();
-
-                    let subproof = ProofCommand::Subproof {
-                        commands,
-                        assignment_args,
-                        variable_args,
-                    };
-                    (end_step_index, subproof)
-                }
-                other => return Err(self.unexpected_token(other)),
             };
-            if self.state.step_indices.get(&index).is_some() {
-                return Err(self.err(ErrorKind::RepeatedStepIndex(index)));
+            match (&mut self.command_sink, end_step) {
+                (Some(sink), None) => {
+                    if !sink(command) {
+                        break;
+                    }
+                    num_sent += 1;
+                }
+                _ => commands.push(command),
             }
-
-            commands.push(command);
             if end_step == Some(&index) {
                 break;
             }
-            self.state.step_indices.insert(index, commands.len() - 1);
+            let depth = self.state.step_indices.scopes.len() - 1;
+            self.state
+                .step_indices
+                .insert(index, (depth, num_sent + commands.len() - 1));
         }
         Ok(Proof(commands))
     }
 
+    /// Parses a single proof command, and returns it together with its index. Returns `None` if
+    /// the command doesn't add a command to the proof, like a function definition.
+    fn parse_proof_command(
+        &mut self,
+        end_step: Option<&str>,
+    ) -> ParserResult<Option<(String, ProofCommand)>> {
+        let position = self.current_token_start;
+        self.state.current_step_id = None;
+        self.expect_token(Token::OpenParen)?;
+        let (index, command) = match self.next_token()? {
+            Token::ReservedWord(Reserved::Assume) => self.parse_assume_command()?,
+            Token::ReservedWord(Reserved::Step) => {
+                let (index, (clause, rule, premises, args)) = self.parse_step_command()?;
+
+                // If this is the last step in the subproof, we pop the top scope of the step
+                // indices symbol table before converting the premises into indices. We must do
+                // this here because if the last step of a subproof has premises, they refer to
+                // the outer scope, and not inside the subproof
+                if end_step == Some(&index) {
+                    self.state.step_indices.pop_scope();
+                }
+
+                // For every premise index symbol, find the associated `usize` in the
+                // `step_indices` hash map, or return an error
+                let premises: Vec<_> = premises
+                    .into_iter()
+                    .map(|index| {
+                        self.state
+                            .step_indices
+                            .get(&index)
+                            .copied()
+                            .ok_or_else(|| self.err(ErrorKind::UndefinedStepIndex(index)))
+                    })
+                    .collect::<Result<_, _>>()?;
+
+                let command = ProofCommand::Step(ProofStep {
+                    id: index.clone(),
+                    clause,
+                    rule,
+                    premises,
+                    args,
+                    position,
+                });
+                (index, command)
+            }
+            // Some solvers define Skolem constants or abbreviations in the proof. These definitions
+            // are either expanded in the commands that follow them, like the definitions in the
+            // problem, or declared as opaque functions
+            Token::ReservedWord(Reserved::DefineFun) => {
+                let (name, func_def) = self.parse_define_fun()?;
+                self.register_proof_definition(&name, &func_def)?;
+                if self.state.config.opaque_definitions {
+                    let mut sorts: Vec<_> =
+                        func_def.params.iter().map(|(_, s)| s.clone()).collect();
+                    let body_sort = self
+                        .sort_of(&func_def.body)
+                        .map_err(|err| self.err(err.into()))?;
+                    sorts.push(body_sort);
+                    let sort = if sorts.len() == 1 {
+                        sorts.pop().unwrap()
+                    } else {
+                        self.add_term(Term::Sort(SortKind::Function, sorts))
+                    };
+                    self.insert_sorted_var((name, sort));
+                } else {
+                    self.state.function_defs.insert(name, func_def);
+                }
+                return Ok(None);
+            }
+            // Some solvers can print the declarations in the problem as a prelude to the proof,
+            // so the proof can be checked without the problem file
+            Token::ReservedWord(Reserved::DeclareFun) => {
+                let (name, sort) = self.parse_declare_fun()?;
+                match self.state.symbol_origins.get(&name) {
+                    Some(SymbolOrigin::ProofDefinition(_)) => {
+                        return Err(self.err(ErrorKind::FreshSymbolClash(name)));
+                    }
+                    Some(_) => (),
+                    None => {
+                        let origin = SymbolOrigin::ProofDeclaration;
+                        self.state.symbol_origins.insert(name.clone(), origin);
+                        let declaration = (name.clone(), sort.clone());
+                        self.state.problem.function_declarations.push(declaration);
+                    }
+                }
+                self.insert_sorted_var((name, sort));
+                return Ok(None);
+            }
+            Token::ReservedWord(Reserved::DeclareSort) => {
+                let (name, arity) = self.parse_declare_sort()?;
+                self.declare_sort(name, arity);
+                return Ok(None);
+            }
+            Token::ReservedWord(Reserved::Anchor) => {
+                let (end_step_index, assignment_args, variable_args) =
+                    self.parse_anchor_command()?;
+
+                self.state.step_indices.push_scope();
+                // The symbol table always has one scope for the top-level proof
+                if self.state.step_indices.scopes.len() - 1 > self.state.config.max_depth {
+                    let limit = self.state.config.max_depth;
+                    return Err(self.err(ErrorKind::MaxDepthExceeded(limit)));
+                }
+                let Proof(commands) = self.parse_subproof(Some(&end_step_index))?;
+                // We don't need to pop the scope that we pushed because it is popped when the
+                // last step of the subproof is parsed
+
+                // Since `Parser::parse_anchor_command` pushes a scope into the symbol table, we
+                // have to pop it now, after parsing the subproof
+                self.state.sorts_symbol_table.pop_scope();
+
+                let subproof = ProofCommand::Subproof {
+                    commands,
+                    assignment_args,
+                    variable_args,
+                };
+                (end_step_index, subproof)
+            }
+            other => return Err(self.unexpected_token(other)),
+        };
+        // The id of a subproof is the id of its last step, which was already checked when the step
+        // was parsed
+        if !matches!(command, ProofCommand::Subproof { .. }) {
+            let is_repeated = if self.state.config.shadow_step_ids {
+                self.state.step_indices.contains_in_last_scope(&index)
+            } else {
+                self.state.step_ids.contains(&index)
+            };
+            if is_repeated {
+                return Err(self.err(ErrorKind::RepeatedStepIndex(index)));
+            }
+            self.state.step_ids.insert(index.clone());
+        }
+        Ok(Some((index, command)))
+    }
+
     /// Parses an "assume" proof command. This method assumes that the "(" and "assume" tokens were
     /// already consumed.
     fn parse_assume_command(&mut self) -> ParserResult<(String, ProofCommand)> {
         let index = self.expect_symbol()?;
         let term = self.parse_term()?;
-        SortError::assert_eq(Term::BOOL_SORT, &term.sort()).map_err(|err| self.err(err.into()))?;
+        self.assert_sort(Term::BOOL_SORT, &term)?;
         let term = self.add_term(term);
+        self.check_clause_limits(std::slice::from_ref(&term))?;
         self.expect_token(Token::CloseParen)?;
-        Ok((index, ProofCommand::Assume(term)))
+        let id = index.clone();
+        Ok((index, ProofCommand::Assume { id, term }))
     }
 
     /// Parses a "step" proof command. This method assumes that the "(" and "step" tokens were
     /// already consumed.
     fn parse_step_command(&mut self) -> ParserResult<(String, StepCommand)> {
         let step_index = self.expect_symbol()?;
+        self.state.current_step_id = Some(step_index.clone());
         let clause = self.parse_clause()?;
         self.expect_token(Token::Keyword("rule".into()))?;
         let rule = match self.next_token()? {
-            Token::Symbol(s) => s,
+            Token::Symbol(s) => s.to_string(),
             Token::ReservedWord(r) => format!("{:?}", r),
             other => return Err(self.unexpected_token(other)),
         };
@@ -456,14 +1409,13 @@ impl<R: BufRead> Parser<R> {
         };
 
         // In some steps (notably those with the "subproof" rule) a ":discharge" attribute appears,
-        // with an assumption index as its value. While the checker already has support this rule,
-        // it still can't parse and interpret the ":discharge" attributes values properly, so we
-        // are simply consuming and ignoring the attribute if it appears
+        // with the indices of the discharged assumptions as its value. While the checker already
+        // has support this rule, it still can't interpret the ":discharge" attributes values
+        // properly, so we are simply consuming and ignoring the attribute if it appears
         if self.current_token == Token::Keyword("discharge".into()) {
             self.next_token()?;
             self.expect_token(Token::OpenParen)?;
-            self.expect_symbol()?;
-            self.expect_token(Token::CloseParen)?;
+            self.parse_sequence(Self::expect_symbol, true)?;
         }
 
         self.expect_token(Token::CloseParen)?;
@@ -492,9 +1444,7 @@ impl<R: BufRead> Parser<R> {
             let args = self.parse_sequence(Parser::parse_anchor_argument, true)?;
             for a in args {
                 match a {
-                    Either::Left(((a, _), b)) => {
-                        assignment_args.push((a.clone(), b));
-                    }
+                    Either::Left((var, b)) => assignment_args.push((var, b)),
                     Either::Right(var) => variable_args.push(var.clone()),
                 }
             }
@@ -515,12 +1465,11 @@ impl<R: BufRead> Parser<R> {
             let b = if let Token::Symbol(_) = &self.current_token {
                 let var = self.expect_symbol()?;
                 self.insert_sorted_var((var.clone(), sort.clone()));
-                let iden = Identifier::Simple(var);
+                let iden = Identifier::Simple(var.into());
                 Term::Terminal(Terminal::Var(iden, sort.clone()))
             } else {
                 let term = self.parse_term()?;
-                SortError::assert_eq(term.sort(), sort.as_ref())
-                    .map_err(|err| self.err(err.into()))?;
+                self.assert_sort(&sort, &term)?;
                 term
             };
             let b = self.add_term(b);
@@ -537,6 +1486,39 @@ impl<R: BufRead> Parser<R> {
         })
     }
 
+    /// Records that `name` was defined by a "define-fun" command in the proof. Returns an error if
+    /// the symbol was already declared, in the problem or in the proof, or if it was already
+    /// defined in the proof with a different definition. A prelude in the proof may repeat a
+    /// definition from the problem, so that is allowed.
+    fn register_proof_definition(
+        &mut self,
+        name: &str,
+        func_def: &FunctionDef,
+    ) -> ParserResult<()> {
+        let same_definition =
+            |other: &FunctionDef| other.params == func_def.params && other.body == func_def.body;
+        match self.state.symbol_origins.get(name) {
+            None => (),
+            Some(SymbolOrigin::Problem)
+                if self
+                    .state
+                    .function_defs
+                    .get(name)
+                    .is_some_and(same_definition) =>
+            {
+                return Ok(());
+            }
+            Some(SymbolOrigin::ProofDefinition(previous)) if same_definition(previous) => (),
+            Some(SymbolOrigin::ProofDefinition(_)) => {
+                return Err(self.err(ErrorKind::InconsistentDefinition(name.to_owned())));
+            }
+            Some(_) => return Err(self.err(ErrorKind::FreshSymbolClash(name.to_owned()))),
+        }
+        let origin = SymbolOrigin::ProofDefinition(func_def.clone());
+        self.state.symbol_origins.insert(name.to_owned(), origin);
+        Ok(())
+    }
+
     /// Parses a "declare-fun" proof command. Returns the function name and a term representing its
     /// sort. This method assumes that the "(" and "declare-fun" tokens were already consumed.
     fn parse_declare_fun(&mut self) -> ParserResult<(String, ByRefRc<Term>)> {
@@ -587,7 +1569,7 @@ impl<R: BufRead> Parser<R> {
 
         self.expect_token(Token::CloseParen)?;
 
-        SortError::assert_eq(&return_sort, body.sort()).map_err(|err| self.err(err.into()))?;
+        self.assert_sort(&return_sort, &body)?;
         let body = self.add_term(body);
         Ok((name, FunctionDef { params, body }))
     }
@@ -600,11 +1582,11 @@ impl<R: BufRead> Parser<R> {
             .parse_sequence(Self::parse_term, false)?
             .into_iter()
             .map(|term| -> ParserResult<ByRefRc<Term>> {
-                SortError::assert_eq(Term::BOOL_SORT, &term.sort())
-                    .map_err(|err| self.err(err.into()))?;
+                self.assert_sort(Term::BOOL_SORT, &term)?;
                 Ok(self.add_term(term))
             })
-            .collect::<Result<_, _>>()?;
+            .collect::<Result<Vec<_>, _>>()?;
+        self.check_clause_limits(&terms)?;
         Ok(terms)
     }
 
@@ -629,12 +1611,50 @@ impl<R: BufRead> Parser<R> {
                 let term = self.parse_application()?;
                 Ok(ProofArg::Term(self.add_term(term)))
             }
+        } else if let Some(term) = self.try_parse_signed_constant() {
+            self.next_token()?; // Consume the symbol token
+            Ok(ProofArg::Term(self.add_term(term)))
         } else {
             let term = self.parse_term()?;
             Ok(ProofArg::Term(self.add_term(term)))
         }
     }
 
+    /// Some proof producers write negative constant arguments using a shorthand like "-1", "-0.5"
+    /// or "-1/3", which the lexer reads as a symbol. If the current token is such a symbol, this
+    /// returns the equivalent term, "(- 1)", "(- 0.5)" or "(- 1/3)" respectively, where the
+    /// fractions are represented as real constants. Otherwise, returns `None` and leaves the
+    /// current token untouched.
+    fn try_parse_signed_constant(&mut self) -> Option<Term> {
+        let digits = match &self.current_token {
+            Token::Symbol(s) => s.strip_prefix('-')?,
+            _ => return None,
+        };
+        let is_numeral = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+        let constant = if let Some((numer, denom)) = digits.split_once('/') {
+            if !is_numeral(numer) || !is_numeral(denom) {
+                return None;
+            }
+            let denom: BigInt = denom.parse().unwrap();
+            if denom.is_zero() {
+                return None;
+            }
+            terminal!(real BigRational::new(numer.parse().unwrap(), denom))
+        } else if let Some((int_part, frac_part)) = digits.split_once('.') {
+            if !is_numeral(int_part) || !is_numeral(frac_part) {
+                return None;
+            }
+            let denom = Pow::pow(BigInt::from(10), frac_part.len());
+            let numer: BigInt = format!("{}{}", int_part, frac_part).parse().unwrap();
+            terminal!(real BigRational::new(numer, denom))
+        } else if is_numeral(digits) {
+            terminal!(int digits.parse::<BigInt>().unwrap())
+        } else {
+            return None;
+        };
+        Some(Term::Op(Operator::Neg, vec![self.add_term(constant)]))
+    }
+
     /// Parses a sorted variable of the form "(<symbol> <sort>)".
     fn parse_sorted_var(&mut self) -> ParserResult<SortedVar> {
         self.expect_token(Token::OpenParen)?;
@@ -652,11 +1672,13 @@ impl<R: BufRead> Parser<R> {
             Token::String(s) => Ok(terminal!(string s)),
             Token::Symbol(s) => {
                 // Check to see if there is a nullary function defined with this name
-                if let Some(func_def) = self.state.function_defs.get(&s) {
+                if let Some(func_def) = self.state.function_defs.get(s.as_str()) {
                     if func_def.params.is_empty() {
                         // This has to clone the function body term, even though it is already
                         // added to the term pool
-                        Ok(func_def.body.as_ref().clone())
+                        let body = func_def.body.clone();
+                        self.check_expanded_depth(&body)?;
+                        Ok(body.as_ref().clone())
                     } else {
                         Err(self.err(ErrorKind::WrongNumberOfArgs(func_def.params.len(), 0)))
                     }
@@ -682,7 +1704,7 @@ impl<R: BufRead> Parser<R> {
             true,
         )?;
         let term = self.parse_term()?;
-        SortError::assert_eq(Term::BOOL_SORT, term.sort()).map_err(|e| self.err(e.into()))?;
+        self.assert_sort(Term::BOOL_SORT, &term)?;
         let term = self.add_term(term);
         self.state.sorts_symbol_table.pop_scope();
         self.expect_token(Token::CloseParen)?;
@@ -708,7 +1730,7 @@ impl<R: BufRead> Parser<R> {
                 let name = p.expect_symbol()?;
                 let value = p.parse_term()?;
                 let value = p.add_term(value);
-                let sort = p.add_term(value.sort().clone());
+                let sort = p.sort_of(&value).map_err(|err| p.err(err.into()))?;
                 p.insert_sorted_var((name.clone(), sort));
                 p.expect_token(Token::CloseParen)?;
                 Ok((name, value))
@@ -722,20 +1744,49 @@ impl<R: BufRead> Parser<R> {
         Ok(Term::Let(bindings, inner))
     }
 
-    fn parse_annotated_term(&mut self) -> ParserResult<Term> {
+    /// Parses an annotated term, and returns the name given to it with the `:named` attribute, if
+    /// there is one. This method assumes that the "(" and "!" tokens were already consumed.
+    fn parse_annotated_term_with_name(&mut self) -> ParserResult<(Option<String>, Term)> {
         let inner = self.parse_term()?;
+        let mut name = None;
         self.parse_sequence(
             |p| {
-                // Simply consume and discard the attributes and their values
-                p.expect_keyword()?;
+                // Other than the name, we simply consume and discard the attributes and their
+                // values
+                let attribute = p.expect_keyword()?;
                 if let Token::Symbol(_) = p.current_token {
-                    p.next_token()?;
+                    if let Token::Symbol(s) = p.next_token()? {
+                        if attribute == "named" {
+                            name = Some(s.to_string());
+                        }
+                    }
                 }
                 Ok(())
             },
             true,
         )?;
-        Ok(inner)
+        Ok((name, inner))
+    }
+
+    /// Parses the term in an "assert" command, and returns it together with the name given to it
+    /// with the `:named` attribute, if there is one.
+    fn parse_assertion(&mut self) -> ParserResult<(Option<String>, ByRefRc<Term>)> {
+        let (name, term) = if self.current_token == Token::OpenParen {
+            // To find out if the term is annotated, we need to look at the token after the "("
+            self.next_token()?;
+            if self.current_token == Token::ReservedWord(Reserved::Bang) {
+                self.next_token()?;
+                self.parse_annotated_term_with_name()?
+            } else {
+                (None, self.parse_application()?)
+            }
+        } else {
+            (None, self.parse_term()?)
+        };
+        self.assert_sort(Term::BOOL_SORT, &term)?;
+        let term = self.add_term(term);
+        self.check_clause_limits(std::slice::from_ref(&term))?;
+        Ok((name, term))
     }
 
     fn parse_application(&mut self) -> ParserResult<Term> {
@@ -746,8 +1797,23 @@ impl<R: BufRead> Parser<R> {
                     Reserved::Exists => self.parse_quantifier(Quantifier::Exists),
                     Reserved::Forall => self.parse_quantifier(Quantifier::Forall),
                     Reserved::Choice => self.parse_choice_term(),
-                    Reserved::Bang => self.parse_annotated_term(),
+                    Reserved::Bang => {
+                        let (name, term) = self.parse_annotated_term_with_name()?;
+
+                        // In the legacy proof format, terms are shared by naming them, and
+                        // referring to them by name in later commands
+                        if let (Some(name), ProofFormat::Legacy) = (name, self.state.config.format)
+                        {
+                            let body = self.add_term(term.clone());
+                            let params = Vec::new();
+                            self.state
+                                .function_defs
+                                .insert(name, FunctionDef { params, body });
+                        }
+                        Ok(term)
+                    }
                     Reserved::Let => self.parse_let_term(),
+                    Reserved::As => self.parse_qualified_identifier(),
                     _ => Err(self.err(ErrorKind::NotYetImplemented)),
                 }
             }
@@ -757,13 +1823,14 @@ impl<R: BufRead> Parser<R> {
             //
             // However, `if let` guards are still nightly only. For more info, see:
             // https://github.com/rust-lang/rust/issues/51114
-            Token::Symbol(s) if Operator::from_str(s).is_ok() => {
+            // "divisible" is an indexed operator, so it can only be used as "(_ divisible n)"
+            Token::Symbol(s) if Operator::from_str(s).is_ok_and(|op| op != Operator::Divisible) => {
                 let operator = Operator::from_str(s).unwrap();
                 self.next_token()?;
                 let args = self.parse_sequence(Self::parse_term, true)?;
                 self.make_op(operator, args).map_err(|err| self.err(err))
             }
-            Token::Symbol(s) if self.state.function_defs.get(s).is_some() => {
+            Token::Symbol(s) if self.state.function_defs.contains_key(s.as_str()) => {
                 let func_name = self.expect_symbol()?;
                 let args = self.parse_sequence(Self::parse_term, true)?;
                 let func = self.state.function_defs.get(&func_name).unwrap();
@@ -773,13 +1840,12 @@ impl<R: BufRead> Parser<R> {
                 ErrorKind::assert_num_of_args(&args, func.params.len())
                     .map_err(|err| self.err(err))?;
                 for (arg, param) in args.iter().zip(func.params.iter()) {
-                    SortError::assert_eq(param.1.as_ref(), arg.sort())
-                        .map_err(|err| self.err(err.into()))?;
+                    self.assert_sort(&param.1, arg)?;
                 }
 
                 // Build a hash map of all the parameter names and the values they will
                 // take
-                let mut substitutions = {
+                let substitution: Substitution = {
                     // We have to take a reference to the term pool here, so the closure in
                     // the `map` call later on doesn't have to capture all of `self`, and
                     // can just capture the term pool. We need this to please the borrow
@@ -796,15 +1862,44 @@ impl<R: BufRead> Parser<R> {
                         .collect()
                 };
 
-                // Since `apply_substitutions` returns a `ByRefRc<Term>`, we have to go
-                // into the inner term and clone it, even though it is already added to the
-                // term pool
-                Ok(self
-                    .state
-                    .term_pool
-                    .apply_substitutions(&func.body, &mut substitutions)
-                    .as_ref()
-                    .clone())
+                // Since `Substitution::apply` returns a `ByRefRc<Term>`, we have to go into
+                // the inner term and clone it, even though it is already added to the term
+                // pool
+                let result = substitution.apply(&mut self.state.term_pool, &func.body);
+                self.check_expanded_depth(&result)?;
+                Ok(result.as_ref().clone())
+            }
+            Token::Symbol(s)
+                if self.state.config.infer_declarations
+                    && self
+                        .state
+                        .sorts_symbol_table
+                        .get(&Identifier::Simple(s.clone()))
+                        .is_none() =>
+            {
+                // If the function was not declared, we infer its sort from the sorts of the
+                // arguments it is applied to
+                let iden = Identifier::Simple(self.expect_symbol()?.into());
+                let args = self.parse_sequence(Self::parse_term, true)?;
+                let mut sorts = args
+                    .iter()
+                    .map(|a| self.sort_of(a))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|err| self.err(err.into()))?;
+                sorts.push(self.fresh_inferred_sort());
+                let sort = self.add_term(Term::Sort(SortKind::Function, sorts));
+                self.declare_inferred(iden.clone(), sort.clone());
+                let func = Term::Terminal(Terminal::Var(iden, sort));
+                self.make_app(func, args).map_err(|err| self.err(err))
+            }
+            Token::OpenParen => {
+                self.next_token()?;
+                if self.current_token == Token::ReservedWord(Reserved::Underscore) {
+                    return self.parse_indexed_application();
+                }
+                let func = self.parse_application()?;
+                let args = self.parse_sequence(Self::parse_term, true)?;
+                self.make_app(func, args).map_err(|err| self.err(err))
             }
             _ => {
                 let func = self.parse_term()?;
@@ -814,17 +1909,94 @@ impl<R: BufRead> Parser<R> {
         }
     }
 
-    /// Parses a sort.
+    /// Parses the application of an indexed operator, like "((_ divisible n) t)", after the first
+    /// "(" token. The only indexed operator supported is "divisible".
+    fn parse_indexed_application(&mut self) -> ParserResult<Term> {
+        self.expect_token(Token::ReservedWord(Reserved::Underscore))?;
+        if self.expect_symbol()? != "divisible" {
+            return Err(self.err(ErrorKind::NotYetImplemented));
+        }
+        let index = self.expect_numeral()?;
+        if index.is_zero() {
+            return Err(self.err(ErrorKind::InvalidIndex(index)));
+        }
+        self.expect_token(Token::CloseParen)?;
+        let mut args = vec![terminal!(int index)];
+        args.extend(self.parse_sequence(Self::parse_term, true)?);
+        self.make_op(Operator::Divisible, args)
+            .map_err(|err| self.err(err))
+    }
+
+    /// Parses a qualified identifier of the form "(as <symbol> <sort>)", after the "(" and "as"
+    /// tokens. The sort is used to resolve the symbol: if the symbol is a constant, the sort must
+    /// be its sort, and if it is a function, the sort must be its result sort. In both cases, the
+    /// qualified identifier is the same term as the symbol alone. The symbol "const" may also be
+    /// qualified with an array sort "(Array I E)", which denotes the function that takes a value
+    /// of sort "E" and returns the constant array with that value.
+    fn parse_qualified_identifier(&mut self) -> ParserResult<Term> {
+        let symbol = self.expect_symbol()?;
+        let sort = self.parse_sort()?;
+        self.expect_token(Token::CloseParen)?;
+
+        if symbol == "const" {
+            if let Term::Sort(SortKind::Atom, args) = &sort {
+                if args.len() == 3 && args[0].as_ref() == &terminal!(string "Array") {
+                    let element = args[2].clone();
+                    let sort = self.add_term(sort);
+                    let function_sort = Term::Sort(SortKind::Function, vec![element, sort.clone()]);
+                    let iden = Identifier::Qualified(symbol.into(), sort);
+                    return Ok(Term::Terminal(Terminal::Var(
+                        iden,
+                        self.add_term(function_sort),
+                    )));
+                }
+            }
+        }
+        let var = self
+            .make_var(Identifier::Simple(symbol.into()))
+            .map_err(|err| self.err(err))?;
+        let var_sort = self.sort_of(&var).map_err(|err| self.err(err.into()))?;
+        let declared = match var_sort.as_ref() {
+            Term::Sort(SortKind::Function, sorts) => sorts.last().unwrap_or(&var_sort),
+            _ => &var_sort,
+        };
+        SortError::assert_eq(&sort, declared).map_err(|err| self.err(err.into()))?;
+        Ok(var)
+    }
+
+    /// Parses a sort. Parametric sorts, like "(List Int)", are represented as atom sorts whose
+    /// first argument is the sort name and whose other arguments are the parameters. "Array" is a
+    /// built-in parametric sort with two parameters, the index and element sorts.
     fn parse_sort(&mut self) -> ParserResult<Term> {
         match self.next_token()? {
+            Token::OpenParen => {
+                let name = self.expect_symbol()?;
+                let params = self.parse_sequence(Self::parse_sort, true)?;
+                let arity = match self.state.sort_declarations.get(name.as_str()) {
+                    Some((arity, _)) => *arity as usize,
+                    None if name == "Array" => 2,
+                    None => return Err(self.err(ErrorKind::UndefinedSort(name))),
+                };
+                ErrorKind::assert_num_of_args(&params, arity).map_err(|err| self.err(err))?;
+                let mut args = vec![self.add_term(terminal!(string name))];
+                args.extend(self.add_all(params));
+                Ok(Term::Sort(SortKind::Atom, args))
+            }
             Token::Symbol(s) => match s.as_ref() {
                 "Bool" => Ok(Term::BOOL_SORT.clone()),
                 "Int" => Ok(Term::INT_SORT.clone()),
                 "Real" => Ok(Term::REAL_SORT.clone()),
                 "String" => Ok(Term::STRING_SORT.clone()),
                 other => {
-                    if let Some((_, sort)) = self.state.sort_declarations.get(other) {
+                    if let Some((arity, sort)) = self.state.sort_declarations.get(other) {
+                        // Parametric sorts must be applied to their parameters
+                        ErrorKind::assert_num_of_args::<Term>(&[], *arity as usize)
+                            .map_err(|err| self.err(err))?;
                         Ok((**sort).clone())
+                    } else if self.state.config.infer_declarations {
+                        // Undeclared sorts are declared as new uninterpreted sorts
+                        let sort = self.declare_sort(other.to_owned(), 0);
+                        Ok(sort.as_ref().clone())
                     } else {
                         Err(self.err(ErrorKind::UndefinedSort(other.into())))
                     }
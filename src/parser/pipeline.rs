@@ -0,0 +1,242 @@
+//! Parsing a proof on a separate thread, so that parsing overlaps with checking.
+//!
+//! The problem is parsed first, on the current thread. Then, the parser continues on a new thread,
+//! and sends each top-level command of the proof through a bounded channel as soon as it is parsed.
+//! The parser and the checker can't share a term pool, since both of them add terms to it, so the
+//! receiving side starts with a clone of the pool that holds the terms of the problem, and imports
+//! the terms of each command into it (see `TermPool::import`). This means the terms of the proof
+//! are stored twice, once in each pool, so both pools count their memory usage together (see
+//! `TermPool::share_memory_usage`), and the memory limits of the parser and the checker apply to
+//! the total.
+
+use super::*;
+use std::{
+    panic,
+    sync::mpsc::{self, Receiver},
+    thread::{self, JoinHandle},
+};
+
+/// The default number of commands that the parser may be ahead of the checker.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// The problem, the target clause, the term pool and the stream of commands returned by
+/// `parse_problem_stream_proof`.
+type StreamedProof = (Problem, Option<Vec<ByRefRc<Term>>>, TermPool, ProofStream);
+
+/// The top-level commands of a proof that is being parsed on another thread.
+pub struct ProofStream {
+    receiver: Receiver<ProofCommand>,
+    parser: JoinHandle<ParserResult<()>>,
+
+    /// The terms received so far, and the equal terms in the receiving pool.
+    imported: HashMap<ByRefRc<Term>, ByRefRc<Term>>,
+}
+
+impl ProofStream {
+    /// Waits for the next command of the proof, and returns it with its terms added to `pool`,
+    /// which must be the pool returned together with the stream. Returns `None` when there are no
+    /// more commands, either because the proof ended or because the parser stopped at an error. In
+    /// that case, the error is returned by `ProofStream::finish`.
+    pub fn next_command(&mut self, pool: &mut TermPool) -> Option<ProofCommand> {
+        let command = self.receiver.recv().ok()?;
+        Some(import_command(pool, command, &mut self.imported))
+    }
+
+    /// Stops the parser, if it is still running, and returns the error that made it stop, if
+    /// there was one. If the parser is stopped before the end of the proof, errors in the rest of
+    /// the proof are not found.
+    pub fn finish(self) -> ParserResult<()> {
+        drop(self.receiver);
+        self.parser
+            .join()
+            .unwrap_or_else(|payload| panic::resume_unwind(payload))
+    }
+}
+
+/// Parses the files in `includes` and the problem, and the `target` clause if it is given, and then
+/// starts parsing `proof` on a new thread, with a stack of `stack_size` bytes. At most `capacity`
+/// commands are parsed ahead of the ones received from the returned `ProofStream`. If `problem` is
+/// `None`, only the proof is parsed, like in `parse_proof_only`. The returned pool holds the terms
+/// of the problem and of the target, and the terms of the commands are added to it as they are
+/// received.
+pub fn parse_problem_stream_proof<T: BufRead, P: BufRead + Send + 'static>(
+    includes: Vec<T>,
+    problem: Option<T>,
+    proof: P,
+    target: Option<&str>,
+    config: Config,
+    capacity: usize,
+    stack_size: usize,
+) -> ParserResult<StreamedProof> {
+    let mut state = match problem {
+        Some(problem) => parse_problem_files(includes, problem, config)?,
+        None => ParserState::new(Config {
+            infer_declarations: true,
+            ..config
+        }),
+    };
+    let target = match target {
+        Some(target) => {
+            let mut parser = Parser::with_state(Lexer::new(target.as_bytes())?, state)?;
+            let clause = parser.parse_clause()?;
+            parser.expect_token(Token::Eof)?;
+            state = parser.state;
+            Some(clause)
+        }
+        None => None,
+    };
+    let problem = std::mem::take(&mut state.problem);
+    state.term_pool.share_memory_usage();
+    let pool = state.term_pool.clone();
+
+    let (sender, receiver) = mpsc::sync_channel(capacity);
+    let parser = thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(move || {
+            let mut parser = Parser::with_state(Lexer::new(proof)?, state)?;
+            let sink_sender = sender.clone();
+            parser.command_sink = Some(Box::new(move |command| sink_sender.send(command).is_ok()));
+
+            // Proofs in the legacy format are not parsed command by command, so their commands are
+            // only sent once the whole proof was parsed
+            let (proof, _) = parser.parse_proof()?;
+            for command in proof.0 {
+                if sender.send(command).is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        })?;
+    let stream = ProofStream {
+        receiver,
+        parser,
+        imported: HashMap::new(),
+    };
+    Ok((problem, target, pool, stream))
+}
+
+/// Imports the terms of `command`, and of the commands in it if it is a subproof, into `pool`.
+fn import_command(
+    pool: &mut TermPool,
+    command: ProofCommand,
+    imported: &mut HashMap<ByRefRc<Term>, ByRefRc<Term>>,
+) -> ProofCommand {
+    match command {
+        ProofCommand::Assume { id, term } => ProofCommand::Assume {
+            id,
+            term: pool.import(&term, imported),
+        },
+        ProofCommand::Step(step) => {
+            let clause = step
+                .clause
+                .iter()
+                .map(|t| pool.import(t, imported))
+                .collect();
+            let args = step
+                .args
+                .iter()
+                .map(|arg| match arg {
+                    ProofArg::Term(t) => ProofArg::Term(pool.import(t, imported)),
+                    ProofArg::Assign(name, t) => {
+                        ProofArg::Assign(name.clone(), pool.import(t, imported))
+                    }
+                })
+                .collect();
+            ProofCommand::Step(ProofStep {
+                clause,
+                args,
+                ..step
+            })
+        }
+        ProofCommand::Subproof {
+            commands,
+            assignment_args,
+            variable_args,
+        } => ProofCommand::Subproof {
+            commands: commands
+                .into_iter()
+                .map(|c| import_command(pool, c, imported))
+                .collect(),
+            assignment_args: assignment_args
+                .into_iter()
+                .map(|((name, sort), t)| {
+                    let sort = pool.import(&sort, imported);
+                    ((name, sort), pool.import(&t, imported))
+                })
+                .collect(),
+            variable_args: variable_args
+                .into_iter()
+                .map(|(name, sort)| (name, pool.import(&sort, imported)))
+                .collect(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::{self, Correctness, ProofChecker};
+
+    #[test]
+    fn check_stream() {
+        let problem = "
+            (declare-fun p () Bool)
+            (declare-fun q () Bool)
+            (assert (and p q))
+        ";
+        let proof = "
+            (assume h1 (and p q))
+            (step t2 (cl q) :rule and :premises (h1))
+            (anchor :step t3)
+            (assume t3.h1 p)
+            (step t3.t2 (cl p) :rule and :premises (h1))
+            (step t3 (cl (not p) p) :rule subproof :discharge (t3.h1))
+            (step t4 (cl (= q q)) :rule eq_reflexive)
+        ";
+        let check = |proof: &'static str, capacity| {
+            let (problem, _, mut pool, mut stream) = parse_problem_stream_proof(
+                Vec::new(),
+                Some(problem.as_bytes()),
+                proof.as_bytes(),
+                None,
+                Config::default(),
+                capacity,
+                1024 * 1024,
+            )
+            .unwrap();
+            let config = checker::Config {
+                require_empty_clause: false,
+                ..Default::default()
+            };
+            let mut received = Proof(Vec::new());
+            let result = ProofChecker::new(&mut pool, config)
+                .check_stream(&mut received, |pool| stream.next_command(pool));
+            (problem, received, result, stream.finish())
+        };
+
+        let (problem, received, result, finished) = check(proof, 1);
+        assert!(matches!(result, Ok(Correctness::True)));
+        assert!(finished.is_ok());
+        assert_eq!(4, received.0.len());
+
+        // The terms of the proof are in the same pool as the ones of the problem
+        let (_, assertion) = &problem.assertions[0];
+        assert!(received.assumptions().any(|t| t == assertion));
+
+        // If checking stops early, the parser is stopped too
+        let failing = "
+            (assume h1 q)
+            (step t2 (cl p) :rule and :premises (h1))
+            (step t3 (cl p q) :rule and :premises (h1))
+        ";
+        let (_, received, result, finished) = check(failing, 1);
+        assert!(matches!(result, Ok(Correctness::False(_))));
+        assert!(finished.is_ok());
+        assert_eq!(2, received.0.len());
+
+        // Parser errors are returned once the stream is finished
+        let (_, received, _, finished) = check("(assume h1 p) (step t2 (cl p)", 1);
+        assert_eq!(1, received.0.len());
+        assert!(finished.is_err());
+    }
+}
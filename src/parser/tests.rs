@@ -18,13 +18,14 @@ pub fn parse_term_err(input: &str) -> ParserError {
 
 /// Parses a series of definitions and declarations, and then parses a term and returns it.
 pub fn parse_term_with_definitions(definitions: &str, term: &str) -> Term {
-    let mut parser = Parser::new(definitions.as_bytes()).expect(ERROR_MESSAGE);
-    parser.parse_problem().expect(ERROR_MESSAGE);
+    let (term, _) = super::parse_term_with_definitions(definitions, term).expect(ERROR_MESSAGE);
+    term.as_ref().clone()
+}
 
-    // To keep the definitions and delcarations, we transfer the parser state to the new
-    // parser.
-    let mut new_parser = Parser::with_state(term.as_bytes(), parser.state).expect(ERROR_MESSAGE);
-    new_parser.parse_term().expect(ERROR_MESSAGE)
+/// Parses a term, like `parse_term_with_definitions`, and returns its sort.
+pub fn parse_sort_of_term(definitions: &str, term: &str) -> Term {
+    let (term, pool) = super::parse_term_with_definitions(definitions, term).expect(ERROR_MESSAGE);
+    pool.sort(&term).expect("term has no sort").as_ref().clone()
 }
 
 pub fn parse_proof(input: &str) -> Proof {
@@ -69,9 +70,12 @@ fn test_hash_consing() {
     //   (* 2 2)
     // Note that the outer term (- (- ...) (* 2 2)) is not added to the hash map
     let expected = vec![
-        // The "Bool" sort and the boolean constants "true" and "false" are always added to the
+        // The built-in sorts and the boolean constants "true" and "false" are always added to the
         // terms map
         "Bool",
+        "Int",
+        "Real",
+        "String",
         "true",
         "false",
         "1",
@@ -92,6 +96,63 @@ fn test_hash_consing() {
     }
 }
 
+#[test]
+fn test_int_real_conversions() {
+    let definitions = "
+        (declare-fun x () Real)
+        (declare-fun n () Int)
+    ";
+    let parse = |term| parse_term_with_definitions(definitions, term);
+
+    // Integer literals are read as reals when they are used with real terms
+    assert_deep_eq!(&parse("(+ x 1.0)"), &parse("(+ x 1)"));
+    assert_deep_eq!(&parse("(< x 1.0)"), &parse("(< x 1)"));
+    assert_deep_eq!(&parse("(* x (- 2.0))"), &parse("(* x (- 2))"));
+    let sort = |term| parse_sort_of_term(definitions, term);
+    assert_eq!(Term::REAL_SORT, &sort("(+ 1 x)"));
+
+    // But other integer terms must be converted explicitly
+    let err = |term: &str| {
+        let (definitions, term) = (definitions.as_bytes(), term.as_bytes());
+        let mut parser = Parser::new(definitions).unwrap();
+        parser.parse_problem().unwrap();
+        let mut parser = Parser::with_state(Lexer::new(term).unwrap(), parser.state).unwrap();
+        parser.parse_term().expect_err("expected error")
+    };
+    assert!(matches!(
+        err("(+ x n)"),
+        ParserError(ErrorKind::SortError(_), _)
+    ));
+    assert_eq!(Term::REAL_SORT, &sort("(+ x (to_real n))"));
+
+    assert_eq!(Term::INT_SORT, &sort("(to_int x)"));
+    assert_eq!(Term::BOOL_SORT, &sort("(is_int x)"));
+    assert!(matches!(
+        err("(to_real x)"),
+        ParserError(ErrorKind::SortError(_), _)
+    ));
+    assert!(matches!(
+        err("(to_int n)"),
+        ParserError(ErrorKind::SortError(_), _)
+    ));
+
+    let divisible = parse("((_ divisible 3) n)");
+    assert_eq!(Term::BOOL_SORT, &sort("((_ divisible 3) n)"));
+    assert_eq!("((_ divisible 3) n)", format!("{:?}", divisible));
+    assert!(matches!(
+        err("((_ divisible 0) n)"),
+        ParserError(ErrorKind::InvalidIndex(_), _)
+    ));
+    assert!(matches!(
+        err("((_ divisible 3) x)"),
+        ParserError(ErrorKind::SortError(_), _)
+    ));
+    assert!(matches!(
+        err("(divisible 3 n)"),
+        ParserError(ErrorKind::UndefinedIden(_), _)
+    ));
+}
+
 #[test]
 fn test_constant_terms() {
     assert_eq!(terminal!(int 42), parse_term("42"));
@@ -124,9 +185,24 @@ fn test_arithmetic_ops() {
                 ],
             ),
         ),
+        ("(- 5)", terminal!(int - 5)),
         (
-            "(- 5)",
-            Term::Op(Operator::Sub, vec![ByRefRc::new(terminal!(int 5))]),
+            "(- (+ 1 1))",
+            Term::Op(
+                Operator::Neg,
+                vec![ByRefRc::new(Term::Op(
+                    Operator::Add,
+                    vec![
+                        ByRefRc::new(terminal!(int 1)),
+                        ByRefRc::new(terminal!(int 1)),
+                    ],
+                ))],
+            ),
+        ),
+        ("(- 2.5)", terminal!(real - 5 / 2)),
+        (
+            "(- (- 5))",
+            Term::Op(Operator::Neg, vec![ByRefRc::new(terminal!(int - 5))]),
         ),
         (
             "(- (+ 1 1) 2)",
@@ -152,6 +228,67 @@ fn test_arithmetic_ops() {
     ));
 }
 
+#[test]
+fn test_operator_arities() {
+    // The number of arguments is checked before the sorts, so the arguments can be anything
+    let call = |op: Operator, n: usize| {
+        // The index of "divisible" counts as one of its arguments
+        match op {
+            Operator::Divisible => format!("((_ divisible 2){})", vec![" true"; n - 1].concat()),
+            _ => format!("({:?}{})", op, vec![" true"; n].concat()),
+        }
+    };
+    for permissive_arities in [false, true] {
+        let config = Config {
+            permissive_arities,
+            ..Config::default()
+        };
+        let parse_err = |input: &str| {
+            Parser::with_config(input.as_bytes(), config.clone())
+                .and_then(|mut p| p.parse_term())
+                .expect_err("expected error")
+        };
+        for &op in Operator::ALL {
+            // Negation and subtraction are both written "-", and the parser chooses between them
+            // by the number of arguments, so neither can be called with the wrong number of
+            // arguments
+            if matches!(op, Operator::Sub | Operator::Neg) {
+                continue;
+            }
+            let arity = match permissive_arities {
+                true => op.permissive_arity(),
+                false => op.arity(),
+            };
+            let (min, max) = (*arity.start(), *arity.end());
+            // Applications with no arguments are rejected before the operator is checked, and
+            // "divisible" always receives its index, so it can't be called with too few arguments
+            if min > 1 && op != Operator::Divisible {
+                assert!(
+                    matches!(
+                        parse_err(&call(op, min - 1)),
+                        ParserError(ErrorKind::WrongNumberOfOperatorArgs { op: o, min: m, .. }, _)
+                            if o == op && m == min,
+                    ),
+                    "{:?} accepted {} arguments",
+                    op,
+                    min - 1,
+                );
+            }
+            if max != usize::MAX {
+                assert!(
+                    matches!(
+                        parse_err(&call(op, max + 1)),
+                        ParserError(ErrorKind::WrongNumberOfOperatorArgs { .. }, _),
+                    ),
+                    "{:?} accepted {} arguments",
+                    op,
+                    max + 1,
+                );
+            }
+        }
+    }
+}
+
 #[test]
 fn test_logic_ops() {
     run_parser_tests(&[
@@ -266,7 +403,14 @@ fn test_logic_ops() {
     ));
     assert!(matches!(
         parse_term_err("(not 1 2 3)"),
-        ParserError(ErrorKind::WrongNumberOfArgs(1, 3), _),
+        ParserError(
+            ErrorKind::WrongNumberOfOperatorArgs {
+                op: Operator::Not,
+                got: 3,
+                ..
+            },
+            _,
+        ),
     ));
     assert!(matches!(
         parse_term_err("(distinct 2 1.0)"),
@@ -274,7 +418,14 @@ fn test_logic_ops() {
     ));
     assert!(matches!(
         parse_term_err("(distinct 0)"),
-        ParserError(ErrorKind::WrongNumberOfArgs(2, 1), _),
+        ParserError(
+            ErrorKind::WrongNumberOfOperatorArgs {
+                op: Operator::Distinct,
+                got: 1,
+                ..
+            },
+            _,
+        ),
     ));
     assert!(matches!(
         parse_term_err("(=> true 0)"),
@@ -318,7 +469,14 @@ fn test_ite() {
 
     assert!(matches!(
         parse_term_err("(ite true 0)"),
-        ParserError(ErrorKind::WrongNumberOfArgs(3, 2), _),
+        ParserError(
+            ErrorKind::WrongNumberOfOperatorArgs {
+                op: Operator::Ite,
+                got: 2,
+                ..
+            },
+            _,
+        ),
     ));
     assert!(matches!(
         parse_term_err("(ite 0 1 2)"),
@@ -484,6 +642,53 @@ fn test_declare_sort() {
     assert_deep_eq!(&terminal!(var "x"; ByRefRc::new(expected_sort)), &got);
 }
 
+#[test]
+fn test_qualified_identifiers() {
+    let definitions = "
+        (declare-sort List 1)
+        (declare-fun nil () (List Int))
+        (declare-fun cons (Int (List Int)) (List Int))
+        (declare-fun a () (Array Int Bool))
+    ";
+    let parse = |term| super::parse_term_with_definitions(definitions, term).map(|(t, _)| t);
+
+    // A qualified symbol is the same term as the symbol alone, both for constants and functions
+    let nil = parse("nil").unwrap();
+    assert_deep_eq!(nil.as_ref(), parse("(as nil (List Int))").unwrap().as_ref());
+    assert_deep_eq!(
+        parse("(cons 1 nil)").unwrap().as_ref(),
+        parse("((as cons (List Int)) 1 (as nil (List Int)))")
+            .unwrap()
+            .as_ref(),
+    );
+    let nil_sort = parse_sort_of_term(definitions, "nil");
+    assert_eq!("(List Int)", format!("{:?}", nil_sort));
+
+    // "const" can only be used qualified with an array sort, and is kept qualified
+    let got = parse("(= a ((as const (Array Int Bool)) false))").unwrap();
+    assert_eq!(
+        "(= a ((as const (Array Int Bool)) false))",
+        format!("{:?}", got)
+    );
+
+    assert!(matches!(
+        parse("(as nil (List Bool))"),
+        Err(ParserError(ErrorKind::SortError(_), _)),
+    ));
+    assert!(matches!(
+        parse("((as const (Array Int Bool)) 0)"),
+        Err(ParserError(ErrorKind::SortError(_), _)),
+    ));
+    assert!(matches!(
+        parse("(as const Int)"),
+        Err(ParserError(ErrorKind::UndefinedIden(_), _)),
+    ));
+    assert!(matches!(
+        super::parse_term_with_definitions("(declare-sort List 1) (declare-fun l () List)", "l"),
+        Err(ParserError(ErrorKind::WrongNumberOfArgs(1, 0), _)),
+    ));
+}
+
 #[test]
 fn test_define_fun() {
     let got = parse_term_with_definitions(
@@ -515,30 +720,44 @@ fn test_step() {
     ";
     let proof = parse_proof(input);
     assert_eq!(proof.0.len(), 5);
+    let positions: Vec<_> = proof
+        .0
+        .iter()
+        .map(|c| match c {
+            ProofCommand::Step(s) => s.position,
+            _ => panic!(),
+        })
+        .collect();
+    assert_eq!(vec![(2, 9), (3, 9), (4, 9), (5, 9), (6, 9)], positions);
 
     assert_deep_eq!(
         &proof.0[0],
         &ProofCommand::Step(ProofStep {
+            id: "t1".into(),
             clause: vec![ByRefRc::new(parse_term("(= (+ 2 3) (- 1 2))"))],
             rule: "rule-name".into(),
             premises: Vec::new(),
             args: Vec::new(),
+            position: (2, 9),
         })
     );
 
     assert_deep_eq!(
         &proof.0[1],
         &ProofCommand::Step(ProofStep {
+            id: "t2".into(),
             clause: Vec::new(),
             rule: "rule-name".into(),
-            premises: vec![0],
+            premises: vec![(0, 0)],
             args: Vec::new(),
+            position: (3, 9),
         })
     );
 
     assert_deep_eq!(
         &proof.0[2],
         &ProofCommand::Step(ProofStep {
+            id: "t3".into(),
             clause: Vec::new(),
             rule: "rule-name".into(),
             premises: Vec::new(),
@@ -552,12 +771,14 @@ fn test_step() {
                 .map(|term| ProofArg::Term(ByRefRc::new(term)))
                 .collect()
             },
+            position: (4, 9),
         })
     );
 
     assert_deep_eq!(
         &proof.0[3],
         &ProofCommand::Step(ProofStep {
+            id: "t4".into(),
             clause: Vec::new(),
             rule: "rule-name".into(),
             premises: Vec::new(),
@@ -571,16 +792,920 @@ fn test_step() {
                 .map(|(name, term)| ProofArg::Assign(name.into(), ByRefRc::new(term)))
                 .collect()
             },
+            position: (5, 9),
         })
     );
 
     assert_deep_eq!(
         &proof.0[4],
         &ProofCommand::Step(ProofStep {
+            id: "t5".into(),
             clause: Vec::new(),
             rule: "rule-name".into(),
-            premises: vec![0, 1, 2],
+            premises: vec![(0, 0), (0, 1), (0, 2)],
             args: vec![ProofArg::Term(ByRefRc::new(terminal!(int 42)))],
+            position: (6, 9),
+        })
+    );
+}
+
+#[test]
+fn test_scoped_premises() {
+    let input = "
+        (assume h1 true)
+        (step t2 (cl) :rule rule-name)
+        (anchor :step t3)
+        (step t3.t1 (cl) :rule rule-name :premises (h1))
+        (step t3.t2 (cl) :rule rule-name :premises (t3.t1 t2))
+        (step t3 (cl) :rule rule-name :premises (h1))
+        (step t4 (cl) :rule rule-name :premises (t3))
+    ";
+    let proof = parse_proof(input);
+    let premises = |command: &ProofCommand| match command {
+        ProofCommand::Step(s) => s.premises.clone(),
+        _ => panic!(),
+    };
+    let inner = match &proof.0[2] {
+        ProofCommand::Subproof { commands, .. } => commands,
+        _ => panic!(),
+    };
+
+    // Premises outside the subproof are resolved in the scope that contains them
+    assert_eq!(vec![(0, 0)], premises(&inner[0]));
+    assert_eq!(vec![(1, 0), (0, 1)], premises(&inner[1]));
+    assert_eq!(vec![(0, 0)], premises(&inner[2]));
+    assert_eq!(vec![(0, 2)], premises(&proof.0[3]));
+
+    // Steps inside a subproof can't be referenced from outside it
+    let input = "
+        (anchor :step t1)
+        (step t1.t1 (cl) :rule rule-name)
+        (step t1 (cl) :rule rule-name)
+        (step t2 (cl) :rule rule-name :premises (t1.t1))
+    ";
+    assert!(Parser::new(input.as_bytes())
+        .and_then(Parser::parse_proof)
+        .is_err());
+}
+
+#[test]
+fn test_repeated_step_ids() {
+    let parse = |input: &str, shadow_step_ids| {
+        let config = Config {
+            shadow_step_ids,
+            ..Default::default()
+        };
+        Parser::with_config(input.as_bytes(), config)
+            .and_then(Parser::parse_proof)
+            .map(|(proof, _)| proof)
+    };
+    let is_repeated = |result: ParserResult<Proof>, id: &str| match result {
+        Err(ParserError(ErrorKind::RepeatedStepIndex(i), _)) => i == id,
+        _ => false,
+    };
+
+    let same_scope = "
+        (assume h1 true)
+        (step t2 (cl) :rule rule-name)
+        (step t2 (cl) :rule rule-name)
+    ";
+    assert!(is_repeated(parse(same_scope, false), "t2"));
+    assert!(is_repeated(parse(same_scope, true), "t2"));
+
+    let inner_scope = "
+        (assume h1 true)
+        (anchor :step t3)
+        (assume h1 false)
+        (step t3.t1 (cl) :rule rule-name :premises (h1))
+        (step t3 (cl) :rule rule-name :premises (h1))
+    ";
+    assert!(is_repeated(parse(inner_scope, false), "h1"));
+
+    // With shadowing, premises inside the subproof refer to the inner command, and the last step
+    // of the subproof refers to the outer one
+    let proof = parse(inner_scope, true).expect(ERROR_MESSAGE);
+    let inner = match &proof.0[1] {
+        ProofCommand::Subproof { commands, .. } => commands,
+        _ => panic!(),
+    };
+    let premises = |command: &ProofCommand| match command {
+        ProofCommand::Step(s) => s.premises.clone(),
+        _ => panic!(),
+    };
+    assert_eq!(vec![(1, 0)], premises(&inner[1]));
+    assert_eq!(vec![(0, 0)], premises(&inner[2]));
+
+    let closed_scope = "
+        (anchor :step t1)
+        (step t1.t1 (cl) :rule rule-name)
+        (step t1 (cl) :rule rule-name)
+        (step t1.t1 (cl) :rule rule-name)
+    ";
+    assert!(is_repeated(parse(closed_scope, false), "t1.t1"));
+    assert!(parse(closed_scope, true).is_ok());
+
+    // The id of the step that ends a subproof can't be used inside it
+    let end_step = "
+        (anchor :step t1)
+        (step t1 (cl) :rule rule-name)
+        (step t1 (cl) :rule rule-name)
+    ";
+    assert!(parse(end_step, true).is_err());
+}
+
+#[test]
+fn test_signed_constant_args() {
+    let input = "(step t1 (cl) :rule rule-name :args (-2 -0.25 -1/3 (- 5) (/ 1 2)))";
+    let proof = parse_proof(input);
+    let args = match proof.0.as_slice() {
+        [ProofCommand::Step(step)] => &step.args,
+        _ => panic!(),
+    };
+    let expected = [
+        parse_term("(- 2)"),
+        parse_term("(- 0.25)"),
+        terminal!(real - 1 / 3),
+        terminal!(int - 5),
+        parse_term("(/ 1 2)"),
+    ];
+    assert_eq!(args.len(), expected.len());
+    for (got, expected) in args.iter().zip(&expected) {
+        assert_deep_eq!(got.as_term().unwrap().as_ref(), expected);
+    }
+
+    let input = "(step t1 (cl) :rule rule-name :args (-x))";
+    assert!(Parser::new(input.as_bytes())
+        .and_then(Parser::parse_proof)
+        .is_err());
+}
+
+#[test]
+fn test_huge_constants() {
+    let digits = "9".repeat(1000);
+    let scale: BigInt = Pow::pow(BigInt::from(10), 1000usize);
+    let huge: BigInt = &scale - 1;
+    assert_deep_eq!(&terminal!(int huge.clone()), &parse_term(&digits));
+    assert_eq!(digits, format!("{:?}", parse_term(&digits)));
+
+    let decimal = format!("{}.{}", digits, digits);
+    let expected = BigRational::new(&huge * &scale + &huge, scale.clone());
+    assert_deep_eq!(&terminal!(real expected.clone()), &parse_term(&decimal));
+    assert_eq!(decimal, format!("{:?}", parse_term(&decimal)));
+
+    let hex = format!("#x{}", "f".repeat(1000));
+    let expected: BigInt = Pow::pow(BigInt::from(16), 1000usize) - 1;
+    assert_deep_eq!(&terminal!(int expected), &parse_term(&hex));
+
+    let input = format!(
+        "(step t1 (cl) :rule rule-name :args (-{} -{} -{}/{}))",
+        digits, decimal, digits, scale
+    );
+    let proof = parse_proof(&input);
+    let args = match proof.0.as_slice() {
+        [ProofCommand::Step(step)] => &step.args,
+        _ => panic!(),
+    };
+    let expected = [
+        parse_term(&format!("(- {})", digits)),
+        parse_term(&format!("(- {})", decimal)),
+        Term::Terminal(Terminal::Real(-BigRational::new(huge, scale))),
+    ];
+    for (got, expected) in args.iter().zip(&expected) {
+        assert_deep_eq!(got.as_term().unwrap().as_ref(), expected);
+    }
+}
+
+#[test]
+fn test_memory_limit() {
+    let input = "
+        (step t1 (cl (= (+ 2 3) (- 1 2))) :rule rule-name)
+        (step t2 (cl (= (* 4 5) (- 6 7))) :rule rule-name)
+    ";
+    let config = Config {
+        memory_limit: Some(256),
+        ..Default::default()
+    };
+    let got = Parser::with_config(input.as_bytes(), config).and_then(Parser::parse_proof);
+    assert!(matches!(
+        got,
+        Err(ParserError(ErrorKind::MemoryLimitExceeded(256), _))
+    ));
+
+    let config = Config {
+        memory_limit: Some(1024 * 1024),
+        ..Default::default()
+    };
+    let got = Parser::with_config(input.as_bytes(), config).and_then(Parser::parse_proof);
+    assert!(got.is_ok());
+}
+
+#[test]
+fn test_max_depth() {
+    let parse = |input: &str, max_depth| {
+        let config = Config {
+            max_depth,
+            ..Default::default()
+        };
+        Parser::with_config(input.as_bytes(), config).and_then(Parser::parse_proof)
+    };
+    let nested_term = |depth| {
+        format!(
+            "(assume h1 {}true{})",
+            "(not ".repeat(depth),
+            ")".repeat(depth)
+        )
+    };
+
+    // The "assume" command itself is one level deep
+    assert!(parse(&nested_term(9), 10).is_ok());
+    assert!(matches!(
+        parse(&nested_term(10), 10),
+        Err(ParserError(ErrorKind::MaxDepthExceeded(10), _))
+    ));
+
+    // Inputs that are too deep are rejected with an error, instead of overflowing the stack
+    assert!(matches!(
+        parse(&nested_term(1_000_000), 100),
+        Err(ParserError(ErrorKind::MaxDepthExceeded(100), _))
+    ));
+
+    let nested_subproofs = |depth| {
+        let mut proof = String::new();
+        for i in 0..depth {
+            proof += &format!("(anchor :step t{})\n", i);
+        }
+        for i in (0..depth).rev() {
+            proof += &format!("(step t{} (cl) :rule rule-name)\n", i);
+        }
+        proof
+    };
+    assert!(parse(&nested_subproofs(3), 3).is_ok());
+    assert!(matches!(
+        parse(&nested_subproofs(4), 3),
+        Err(ParserError(ErrorKind::MaxDepthExceeded(3), _))
+    ));
+
+    // Chains of definitions build terms much deeper than the input is nested, so the depth of
+    // each expanded definition is also limited
+    let chained_definitions = |length, with_args: bool| {
+        // Each function is defined as the negation of the previous one, which is either applied
+        // to the argument or used as a constant
+        let (params, base) = if with_args {
+            ("((x Bool))", "x")
+        } else {
+            ("()", "true")
+        };
+        let call = |i: usize, arg: &str| match with_args {
+            true => format!("(f{} {})", i, arg),
+            false => format!("f{}", i),
+        };
+        let mut proof = format!("(define-fun f0 {} Bool {})\n", params, base);
+        for i in 1..length {
+            proof += &format!(
+                "(define-fun f{} {} Bool (not {}))\n",
+                i,
+                params,
+                call(i - 1, "x")
+            );
+        }
+        proof + &format!("(assume h1 {})\n", call(length - 1, "true"))
+    };
+    assert!(parse(&chained_definitions(5, false), 10).is_ok());
+    assert!(parse(&chained_definitions(5, true), 10).is_ok());
+    assert!(matches!(
+        parse(&chained_definitions(12, false), 10),
+        Err(ParserError(ErrorKind::MaxDepthExceeded(10), _))
+    ));
+    assert!(matches!(
+        parse(&chained_definitions(200_000, false), 100),
+        Err(ParserError(ErrorKind::MaxDepthExceeded(100), _))
+    ));
+    assert!(matches!(
+        parse(&chained_definitions(200_000, true), 100),
+        Err(ParserError(ErrorKind::MaxDepthExceeded(100), _))
+    ));
+}
+
+#[test]
+fn test_term_size_and_clause_length_limits() {
+    let parse = |input: &str, max_term_size, max_clause_length| {
+        let config = Config {
+            max_term_size,
+            max_clause_length,
+            ..Default::default()
+        };
+        Parser::with_config(input.as_bytes(), config).and_then(Parser::parse_proof)
+    };
+
+    // The size of a term is the number of distinct subterms, so shared subterms are counted once
+    let step = "(step t1 (cl (= (+ 2 3) (+ 2 3)) (= 2 3)) :rule rule-name)";
+    assert!(parse(step, Some(4), Some(2)).is_ok());
+    assert!(matches!(
+        parse(step, Some(3), None),
+        Err(ParserError(ErrorKind::TermSizeExceeded(3), _))
+    ));
+    assert!(matches!(
+        parse("(assume h1 (= (+ 2 3) 5))", Some(3), None),
+        Err(ParserError(ErrorKind::TermSizeExceeded(3), _))
+    ));
+    assert!(matches!(
+        parse(step, None, Some(1)),
+        Err(ParserError(ErrorKind::ClauseLengthExceeded(1), _))
+    ));
+
+    // Assertions in the problem are also limited, even though assertions that fail to parse are
+    // usually skipped
+    let config = Config {
+        max_term_size: Some(3),
+        ..Default::default()
+    };
+    let result = Parser::with_config("(assert (= (+ 2 3) 5))".as_bytes(), config)
+        .and_then(|mut parser| parser.parse_problem());
+    assert!(matches!(
+        result,
+        Err(ParserError(ErrorKind::TermSizeExceeded(3), _))
+    ));
+}
+
+#[test]
+fn test_get_used_rules() {
+    let input = "
+        (assume h1 (not true))
+        (anchor :step t3)
+        (step t2 (cl) :rule trust_me)
+        (step t3 (cl) :rule subproof :discharge (t2))
+        (step t4 (cl) :rule resolution :premises (h1 t3))
+        (step t5 (cl) :rule resolution :premises (h1 t4))
+    ";
+    let got = get_used_rules(input.as_bytes()).unwrap();
+    assert_eq!(
+        vec!["trust_me", "subproof", "resolution", "resolution"],
+        got
+    );
+}
+
+#[test]
+fn test_proof_metadata() {
+    let problem = "
+        (set-logic QF_UF)
+        (set-info :smt-lib-version 2.6)
+        (set-info :source |a
+        benchmark|)
+        (set-info :attributes (a b))
+        (set-info :status unsat)
+    ";
+    let proof = "
+        ; veriT 2021.06.2
+        ;; --proof-prune
+
+        (assume h1 false) ; not metadata
+        ; neither is this
+    ";
+    let (problem, _, _) = parse_problem_proof(
+        problem.as_bytes(),
+        "(assume h1 false)".as_bytes(),
+        Config::default(),
+    )
+    .expect(ERROR_MESSAGE);
+    assert_eq!(Some(Status::Unsat), problem.status);
+
+    let metadata = parse_proof_metadata(proof.as_bytes(), Some(&problem)).unwrap();
+    assert_eq!(vec!["veriT 2021.06.2", "--proof-prune"], metadata.comments);
+    assert_eq!(Some("veriT 2021.06.2"), metadata.producer());
+    assert_eq!(Some("QF_UF"), metadata.logic.as_deref());
+    assert_eq!(Some("a\n        benchmark"), metadata.info("source"));
+    assert_eq!(Some(""), metadata.info("attributes"));
+    assert_eq!(Some("unsat"), metadata.info("status"));
+    assert_eq!(Some("2.6"), metadata.info("smt-lib-version"));
+
+    let metadata = parse_proof_metadata("(assume h1 false)".as_bytes(), None).unwrap();
+    assert_eq!(ProofMetadata::default(), metadata);
+}
+
+#[test]
+fn test_token_stream_api() {
+    // Skips over the first command using only the token-level methods, and then resumes parsing
+    let mut parser = Parser::new("(set-info :status unsat) (declare-fun a () Int)".as_bytes())
+        .expect(ERROR_MESSAGE);
+    assert_eq!(&Token::OpenParen, parser.current_token());
+    let mut depth = 0;
+    loop {
+        match parser.next_token().expect(ERROR_MESSAGE) {
+            Token::OpenParen => depth += 1,
+            Token::CloseParen if depth == 1 => break,
+            Token::CloseParen => depth -= 1,
+            _ => (),
+        }
+    }
+    assert_eq!(&Token::OpenParen, parser.current_token());
+    parser.parse_problem().expect(ERROR_MESSAGE);
+}
+
+#[test]
+fn test_error_recovery() {
+    let problem = "
+        (declare-fun a () Int)
+        (declare-fun b (Int) Foo)
+        (declare-fun p () Bool)
+    "
+    .as_bytes();
+    let proof = "
+        (assume h1 p)
+        (step t1 (cl (= a a)) :rule)
+        (step t2 (cl (= a a)) :rule refl)
+        (step t3 (cl (= (+ a p) a)) :rule refl)
+        (step t4 (cl) :rule resolution :premises (h1 t2))
+    "
+    .as_bytes();
+    let config = Config {
+        error_recovery: true,
+        ..Default::default()
+    };
+    let errors = match parse_problem_proof(problem, proof, config) {
+        Err(ParserError(ErrorKind::MultipleErrors(errors), None)) => errors,
+        other => panic!("expected multiple errors, got {:?}", other.map(|_| ())),
+    };
+    assert_eq!(3, errors.len());
+    assert!(matches!(errors[0].0, ErrorKind::UndefinedSort(_)));
+    assert!(matches!(
+        errors[1].0,
+        ErrorKind::UnexpectedToken(Token::CloseParen)
+    ));
+    assert!(matches!(errors[2].0, ErrorKind::SortError(_)));
+
+    // Without error recovery, only the first error is reported
+    let result = parse_problem_proof(problem, proof, Config::default());
+    assert!(matches!(
+        result,
+        Err(ParserError(ErrorKind::UndefinedSort(_), _))
+    ));
+}
+
+#[test]
+fn test_error_recovery_in_subproofs() {
+    let problem = "
+        (declare-fun a () Int)
+        (declare-fun p () Bool)
+    ";
+    let proof = "
+        (assume h1 p)
+        (anchor :step t2)
+        (step t2.t1 (cl p) :rule trust)
+        (step t2 (cl (= a p)) :rule trust :premises (t2.t1))
+        (step t3 (cl p) :rule trust :premises (h1))
+        (step t4 (cl p) :rule trust :premises (t2.t1))
+        (anchor :step t5 :args ((x Foo)))
+        (step t5 (cl p) :rule trust)
+        (step t6 (cl p) :rule trust :premises (t3))
+    ";
+    let config = Config {
+        error_recovery: true,
+        ..Default::default()
+    };
+    let errors = match parse_problem_proof(problem.as_bytes(), proof.as_bytes(), config) {
+        Err(ParserError(ErrorKind::MultipleErrors(errors), None)) => errors,
+        other => panic!("expected multiple errors, got {:?}", other.map(|_| ())),
+    };
+
+    // The error in the last step of the subproof ends it, so "t2.t1" can't be used after it. The
+    // invalid "anchor" command is skipped, so its last step is parsed as a top-level step
+    let kinds: Vec<_> = errors.iter().map(|e| &e.0).collect();
+    assert!(
+        matches!(
+            kinds.as_slice(),
+            [
+                ErrorKind::SortError(_),
+                ErrorKind::UndefinedStepIndex(id),
+                ErrorKind::UndefinedSort(_),
+            ] if id == "t2.t1"
+        ),
+        "{:?}",
+        kinds
+    );
+}
+
+#[test]
+fn test_problem() {
+    let problem = "
+        (set-logic QF_UF)
+        (set-info :source |a benchmark|)
+        (set-info :status unsat)
+        (declare-sort T 0)
+        (declare-fun a () T)
+        (declare-fun p (T) Bool)
+        (define-fun q () Bool (p a))
+        (assert (p a))
+        (assert (! (not q) :named h2))
+        (check-sat)
+    ";
+    let (problem, _, _) = parse_problem_proof(problem.as_bytes(), "".as_bytes(), Config::default())
+        .expect(ERROR_MESSAGE);
+
+    assert_eq!(Some("QF_UF"), problem.logic.as_deref());
+    assert_eq!(Some(Status::Unsat), problem.status);
+    assert_eq!(vec![("T".to_owned(), 0)], problem.sort_declarations);
+    let declared: Vec<_> = problem
+        .function_declarations
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    assert_eq!(vec!["a", "p"], declared);
+    assert_eq!(1, problem.function_definitions.len());
+
+    let assertions: Vec<_> = problem
+        .assertions
+        .iter()
+        .map(|(name, term)| (name.as_deref(), format!("{:?}", term)))
+        .collect();
+    assert_eq!(
+        vec![
+            (None, "(p a)".to_owned()),
+            (Some("h2"), "(not (p a))".to_owned()),
+        ],
+        assertions,
+    );
+
+    // Assertions that can't be parsed are skipped
+    let problem = "(declare-fun a () Int) (assert (let ((x a)) x)) (assert (= a 1))";
+    let problem = Parser::new(problem.as_bytes())
+        .and_then(|mut p| p.parse_problem())
+        .expect(ERROR_MESSAGE);
+    assert_eq!(1, problem.assertions.len());
+}
+
+#[test]
+fn test_includes() {
+    let prelude = "
+        (declare-sort T 0)
+        (declare-fun a () T)
+    ";
+    let definitions = "
+        (declare-fun p (T) Bool)
+        (define-fun q () Bool (p a))
+    ";
+    let problem = "(assert q)";
+    let proof = "(assume h1 (p a))";
+    let (problem, proof, _) = parse_problem_proof_with_includes(
+        vec![prelude.as_bytes(), definitions.as_bytes()],
+        problem.as_bytes(),
+        proof.as_bytes(),
+        Config::default(),
+    )
+    .expect(ERROR_MESSAGE);
+
+    let declared: Vec<_> = problem
+        .function_declarations
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    assert_eq!(vec!["a", "p"], declared);
+    assert_eq!(1, problem.assertions.len());
+    assert_eq!(proof.assumptions().next(), Some(&problem.assertions[0].1));
+
+    // Declarations in an included file are not visible in the files before it
+    let result = parse_problem_proof_with_includes(
+        vec![definitions.as_bytes(), prelude.as_bytes()],
+        "".as_bytes(),
+        "".as_bytes(),
+        Config::default(),
+    );
+    assert!(matches!(
+        result,
+        Err(ParserError(ErrorKind::UndefinedSort(_), _))
+    ));
+}
+
+#[test]
+fn test_proof_definitions() {
+    let proof = "
+        (define-fun k () Int (+ 1 2))
+        (define-fun f ((x Int)) Int (* x k))
+        (step t1 (cl (= (f k) 9)) :rule rule-name)
+    ";
+    let conclusion = |config| {
+        let (_, proof, _) =
+            parse_problem_proof("".as_bytes(), proof.as_bytes(), config).expect(ERROR_MESSAGE);
+        match proof.0.as_slice() {
+            [ProofCommand::Step(step)] => format!("{:?}", step.clause[0]),
+            _ => panic!(),
+        }
+    };
+    assert_eq!("(= (* (+ 1 2) (+ 1 2)) 9)", conclusion(Config::default()));
+    let config = Config {
+        opaque_definitions: true,
+        ..Config::default()
+    };
+    assert_eq!("(= (f k) 9)", conclusion(config));
+}
+
+#[test]
+fn test_fresh_symbols() {
+    let parse = |problem: &str, proof: &str| {
+        parse_problem_proof(problem.as_bytes(), proof.as_bytes(), Config::default()).map(|_| ())
+    };
+    let problem = "(declare-fun a () Int) (define-fun b () Int 2)";
+    let ok = [
+        "(define-fun sko () Int (+ a 1))",
+        "(define-fun sko () Int (+ a 1)) (define-fun sko () Int (+ a 1))",
+        "(declare-fun a () Int) (define-fun sko () Int a)",
+        "(define-fun b () Int 2)",
+    ];
+    for proof in ok {
+        assert!(parse(problem, proof).is_ok(), "{}", proof);
+    }
+    let clashes = [
+        "(define-fun a () Int 1)",
+        "(define-fun b () Int 3)",
+        "(declare-fun c () Int) (define-fun c () Int 1)",
+        "(define-fun c () Int 1) (declare-fun c () Int)",
+    ];
+    for proof in clashes {
+        assert!(
+            matches!(
+                parse(problem, proof),
+                Err(ParserError(ErrorKind::FreshSymbolClash(_), _))
+            ),
+            "{}",
+            proof
+        );
+    }
+    assert!(matches!(
+        parse(problem, "(define-fun c () Int 1) (define-fun c () Int 2)"),
+        Err(ParserError(ErrorKind::InconsistentDefinition(s), _)) if s == "c"
+    ));
+}
+
+#[test]
+fn test_unsat_core() {
+    let parse = |core: &str| parse_unsat_core(core.as_bytes());
+    assert_eq!(vec!["a", "b c"], parse("(a |b c|)").expect(ERROR_MESSAGE));
+    assert_eq!(vec!["a"], parse("unsat\n(a)\n").expect(ERROR_MESSAGE));
+    assert!(parse("()").expect(ERROR_MESSAGE).is_empty());
+    assert!(parse("(a (b))").is_err());
+    assert!(parse("sat (a)").is_err());
+}
+
+#[test]
+fn test_annotations() {
+    let parse = |annotations: &str| parse_annotations(annotations.as_bytes());
+    let annotations = parse(
+        "(annotate t1 :justification \"cc: a = b\" :module cc)
+        (annotate t2.t1 :depth 2)
+        (annotate t1 :justification \"second\")
+        (annotate t3)",
+    )
+    .expect(ERROR_MESSAGE);
+    let pairs = |items: &[(&str, &str)]| -> Vec<(String, String)> {
+        items.iter().map(|&(k, v)| (k.into(), v.into())).collect()
+    };
+    assert_eq!(
+        pairs(&[
+            ("justification", "cc: a = b"),
+            ("module", "cc"),
+            ("justification", "second")
+        ]),
+        annotations["t1"]
+    );
+    assert_eq!(pairs(&[("depth", "2")]), annotations["t2.t1"]);
+    assert!(annotations["t3"].is_empty());
+    assert!(parse("").expect(ERROR_MESSAGE).is_empty());
+    assert!(parse("(annotate t1 justification \"x\")").is_err());
+    assert!(parse("(annotate t1 :justification (x))").is_err());
+    assert!(parse("(step t1 :justification \"x\")").is_err());
+}
+
+#[test]
+fn test_snippet_parsing() {
+    let definitions = "
+        (declare-sort T 0)
+        (declare-fun a () T)
+        (declare-fun f (T) T)
+    ";
+    let (term, pool) =
+        super::parse_term_with_definitions(definitions, "(= (f a) a)").expect(ERROR_MESSAGE);
+    assert_eq!("(= (f a) a)", format!("{:?}", term));
+    assert_eq!(Some(&term), pool.terms.get(term.as_ref()).map(|(t, _)| t));
+    assert!(super::parse_term_with_definitions(definitions, "(f b)").is_err());
+    assert!(super::parse_term_with_definitions(definitions, "a a").is_err());
+
+    let (command, _) =
+        parse_proof_command(definitions, "(step t1 (cl (= a a)) :rule eq_reflexive)")
+            .expect(ERROR_MESSAGE);
+    assert_eq!("t1", command.id());
+    let (command, _) = parse_proof_command(
+        definitions,
+        "(anchor :step t2 :args ((x T)))
+        (step t2.t1 (cl (= (f x) (f x))) :rule refl)
+        (step t2 (cl (= (forall ((x T)) (= (f x) (f x))) (forall ((x T)) (= (f x) (f x))))) :rule bind)",
+    )
+    .expect(ERROR_MESSAGE);
+    assert!(matches!(command, ProofCommand::Subproof { .. }));
+    assert!(parse_proof_command(definitions, "(step t1 (cl) :rule trans :premises (t0))").is_err());
+    assert!(parse_proof_command(definitions, "(assume h1 true) (assume h2 true)").is_err());
+}
+
+#[test]
+fn test_queries() {
+    let problem = "
+        (declare-fun p () Bool)
+        (declare-fun q () Bool)
+        (declare-fun r () Bool)
+        (assert p)
+        (check-sat)
+        (push 1)
+        (assert q)
+        (push)
+        (assert r)
+        (check-sat)
+        (pop 2)
+        (assert (not p))
+        (check-sat)
+        (pop 5)
+        (check-sat)
+    ";
+    let proof = "(assume h1 q) (assume h2 p)";
+    let (problem, proof, _) =
+        parse_problem_proof(problem.as_bytes(), proof.as_bytes(), Config::default())
+            .expect(ERROR_MESSAGE);
+
+    assert_eq!(
+        vec![vec![0], vec![0, 1, 2], vec![0, 3], vec![0, 3]],
+        problem.queries
+    );
+    let query: Vec<_> = problem
+        .query_assertions(2)
+        .unwrap()
+        .iter()
+        .map(|t| format!("{:?}", t))
+        .collect();
+    assert_eq!(vec!["p", "(not p)"], query);
+    assert!(problem.query_assertions(4).is_none());
+    assert_eq!(Some(1), problem.find_query(proof.assumptions()));
+}
+
+#[test]
+fn test_query_scope_levels() {
+    // A huge number of levels must not allocate memory for each level
+    let problem = "
+        (declare-fun p () Bool)
+        (declare-fun q () Bool)
+        (push 1000000000000)
+        (assert p)
+        (push 3)
+        (assert q)
+        (check-sat)
+        (pop 2)
+        (check-sat)
+        (pop 1)
+        (check-sat)
+        (pop 999999999999)
+        (check-sat)
+        (pop 1)
+        (check-sat)
+    ";
+    let (problem, _, _) = parse_problem_proof(problem.as_bytes(), "".as_bytes(), Config::default())
+        .expect(ERROR_MESSAGE);
+    assert_eq!(
+        vec![vec![0, 1], vec![0], vec![0], Vec::new(), Vec::new()],
+        problem.queries
+    );
+}
+
+#[test]
+fn test_skipped_assertions() {
+    let problem = "
+        (declare-fun p () Bool)
+        (assert p)
+        (assert (undeclared p))
+        (assert (not p))
+        (check-sat)
+    ";
+    let (problem, proof, _) =
+        parse_problem_proof(problem.as_bytes(), "".as_bytes(), Config::default())
+            .expect(ERROR_MESSAGE);
+    assert_eq!(2, problem.assertions.len());
+    assert!(matches!(
+        problem.skipped_assertions.as_slice(),
+        [ParserError(ErrorKind::UndefinedIden(_), Some((4, _)))]
+    ));
+
+    // Since the skipped assertion may be part of any query, queries can't be selected
+    let query = crate::select_query(&problem, &proof, crate::QuerySelector::Index(0));
+    assert!(matches!(query, Err(crate::Error::SkippedAssertions(1))));
+}
+
+#[test]
+fn test_parse_proof_only() {
+    let proof = "
+        (declare-sort U 0)
+        (declare-fun a () U)
+        (assume h1 (= a b))
+        (assume h2 (f a c))
+        (step t3 (cl (not (f b c)) (p (g a) 1)) :rule trust_me :premises (h1 h2))
+        (step t4 (cl (= (g b) (g a))) :rule trust_me :args ((h V 2)))
+    ";
+    let (proof, _) = parse_proof_only(proof.as_bytes(), Config::default()).expect(ERROR_MESSAGE);
+    assert_eq!(4, proof.0.len());
+
+    // The sort of `b` is inferred, so it can be compared with `a`, and the two uses of `f` and `g`
+    // refer to the same function
+    let term = match &proof.0[2] {
+        ProofCommand::Step(step) => &step.clause[0],
+        _ => panic!(),
+    };
+    assert_eq!("(not (f b c))", format!("{:?}", term));
+
+    // Without inferring declarations, undeclared symbols are errors
+    let proof = "(assume h1 (= a b))";
+    let got = Parser::new(proof.as_bytes()).and_then(Parser::parse_proof);
+    assert!(matches!(
+        got,
+        Err(ParserError(ErrorKind::UndefinedIden(_), _))
+    ));
+}
+
+#[test]
+fn test_legacy_format() {
+    let problem = "
+        (declare-fun p () Bool)
+        (declare-fun q () Bool)
+    ";
+    let proof = "
+        (set .c1 (input :conclusion ((! (or p q) :named @p_1))))
+        (set .c2 (input :conclusion ((not p))))
+        (set .c3 (or :clauses (.c1) :conclusion (p q)))
+        (set .c4 (resolution :clauses (.c3 .c2) :conclusion (q) :comment (ignored \"()\")))
+        (set .c5 (forall_inst :iargs (p) :conclusion ((or (not @p_1) q))))
+    ";
+    let config = Config {
+        format: ProofFormat::Legacy,
+        ..Config::default()
+    };
+    let (_, proof, _) = parse_problem_proof(problem.as_bytes(), proof.as_bytes(), config.clone())
+        .expect(ERROR_MESSAGE);
+    assert_eq!(5, proof.0.len());
+    assert!(matches!(&proof.0[0], ProofCommand::Assume { id, .. } if id == ".c1"));
+    let steps: Vec<_> = proof.0[2..]
+        .iter()
+        .map(|c| match c {
+            ProofCommand::Step(s) => s,
+            _ => panic!(),
         })
+        .collect();
+    assert_eq!(
+        ("or", vec![(0, 0)]),
+        (steps[0].rule.as_str(), steps[0].premises.clone())
+    );
+    assert_eq!(vec![(0, 2), (0, 1)], steps[1].premises);
+    assert_eq!(1, steps[2].args.len());
+
+    // Named terms are shared with later commands
+    let named = match &proof.0[0] {
+        ProofCommand::Assume { term, .. } => term,
+        _ => unreachable!(),
+    };
+    assert_eq!(
+        format!("(or (not {:?}) q)", named),
+        format!("{:?}", steps[2].clause[0])
     );
+
+    // A proof in the legacy format is not a valid Alethe proof, and vice versa
+    let legacy = "(set .c1 (input :conclusion (p)))";
+    assert!(parse_problem_proof(problem.as_bytes(), legacy.as_bytes(), Config::default()).is_err());
+    let alethe = "(assume h1 p)";
+    assert!(parse_problem_proof(problem.as_bytes(), alethe.as_bytes(), config).is_err());
+}
+
+#[test]
+fn test_detect_proof_format() {
+    let cases = [
+        (
+            "(assume h1 p)",
+            (ProofFormat::Alethe, ProofDialect::Unknown),
+        ),
+        (
+            "; veriT 2021.06.2\n(assume h1 p)",
+            (ProofFormat::Alethe, ProofDialect::VeriT),
+        ),
+        (
+            ";; cvc5 1.0.0\n\n; other comment\n(\n  assume h1 p)",
+            (ProofFormat::Alethe, ProofDialect::Cvc5),
+        ),
+        (
+            "(set .c1 (input :conclusion (p)))",
+            (ProofFormat::Legacy, ProofDialect::VeriT),
+        ),
+        (
+            "; z3\n  (  set .c1",
+            (ProofFormat::Legacy, ProofDialect::VeriT),
+        ),
+        (
+            "(declare-fun set () Bool)",
+            (ProofFormat::Alethe, ProofDialect::Unknown),
+        ),
+        ("", (ProofFormat::Alethe, ProofDialect::Unknown)),
+    ];
+    for (proof, expected) in cases {
+        assert_eq!(expected, detect_proof_format(proof.as_bytes()).unwrap());
+    }
 }
@@ -0,0 +1,306 @@
+//! Term patterns, used to search for terms in the `grep` subcommand and in the interactive prompt.
+//! Patterns use the syntax of the patterns in the `match_term!` macro, but are parsed at runtime:
+//!
+//! - `_` matches any term;
+//! - `?x` matches any term, but all occurrences of `?x` in a pattern must match the same term;
+//! - other symbols match variables and constants with the same name, and numerals, decimals and
+//!   string literals match themselves;
+//! - `(f p1 ... pn)` matches applications of the operator or function `f` whose arguments match
+//!   `p1`, ..., `pn`. The head can also be `_` or `?f`, which match any function, and the last
+//!   argument can be `...`, which matches any number of remaining arguments;
+//! - `(forall ... p)`, `(exists ... p)`, `(let ... p)` and `(choice ... p)` match binder terms of
+//!   the given kind whose bodies match `p`, regardless of their bindings.
+
+use crate::{
+    ast::*,
+    parser::{
+        error::{ErrorKind, ParserResult},
+        lexer::{Lexer, Reserved, Token},
+    },
+};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    /// The `_` pattern, that matches any term.
+    Any,
+
+    /// A pattern variable, like `?x`. The name is stored without the leading "?".
+    Var(String),
+
+    /// A symbol, that matches variables and constants with the same name.
+    Symbol(String),
+
+    Integer(BigInt),
+    Real(BigRational),
+    String(String),
+
+    /// An application pattern. If `has_rest` is true, the pattern ended with `...`, and the
+    /// application may have more arguments than `args`.
+    App {
+        head: Box<Pattern>,
+        args: Vec<Pattern>,
+        has_rest: bool,
+    },
+
+    /// A binder pattern, that matches the body of a quantifier, "let" or "choice" term.
+    Binder(BinderKind, Box<Pattern>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinderKind {
+    Forall,
+    Exists,
+    Let,
+    Choice,
+}
+
+impl Pattern {
+    /// Parses a pattern from a string. Returns an error if the string is not a single, well-formed
+    /// pattern.
+    pub fn parse(input: &str) -> ParserResult<Self> {
+        let mut lexer = Lexer::new(input.as_bytes())?;
+        let token = lexer.next_token()?;
+        let pattern = Self::parse_token(&mut lexer, token)?;
+        match lexer.next_token()? {
+            Token::Eof => Ok(pattern),
+            other => Err((ErrorKind::UnexpectedToken(other), lexer.token_start).into()),
+        }
+    }
+
+    fn parse_token(lexer: &mut Lexer<&[u8]>, token: Token) -> ParserResult<Self> {
+        Ok(match token {
+            Token::ReservedWord(Reserved::Underscore) => Pattern::Any,
+            Token::Symbol(s) if s.as_str() == "..." => {
+                return Err((
+                    ErrorKind::UnexpectedToken(Token::Symbol(s)),
+                    lexer.token_start,
+                )
+                    .into())
+            }
+            Token::Symbol(s) => match s.as_str().strip_prefix('?') {
+                Some(name) if !name.is_empty() => Pattern::Var(name.to_owned()),
+                _ => Pattern::Symbol(s.as_str().to_owned()),
+            },
+            Token::Numeral(n) => Pattern::Integer(n),
+            Token::Decimal(r) => Pattern::Real(r),
+            Token::String(s) => Pattern::String(s),
+            Token::OpenParen => return Self::parse_application(lexer),
+            other => return Err((ErrorKind::UnexpectedToken(other), lexer.token_start).into()),
+        })
+    }
+
+    /// Parses the rest of an application or binder pattern, after the opening parenthesis.
+    fn parse_application(lexer: &mut Lexer<&[u8]>) -> ParserResult<Self> {
+        let binder = match lexer.next_token()? {
+            Token::ReservedWord(Reserved::Forall) => Some(BinderKind::Forall),
+            Token::ReservedWord(Reserved::Exists) => Some(BinderKind::Exists),
+            Token::ReservedWord(Reserved::Let) => Some(BinderKind::Let),
+            Token::ReservedWord(Reserved::Choice) => Some(BinderKind::Choice),
+            // The head of an application pattern can't itself be an application
+            Token::OpenParen => {
+                return Err((
+                    ErrorKind::UnexpectedToken(Token::OpenParen),
+                    lexer.token_start,
+                )
+                    .into())
+            }
+            token => {
+                let head = Self::parse_token(lexer, token)?;
+                return Self::parse_arguments(lexer, head);
+            }
+        };
+
+        // The bindings of a binder pattern are always `...`
+        match lexer.next_token()? {
+            Token::Symbol(s) if s.as_str() == "..." => (),
+            other => return Err((ErrorKind::UnexpectedToken(other), lexer.token_start).into()),
+        }
+        let token = lexer.next_token()?;
+        let body = Self::parse_token(lexer, token)?;
+        match lexer.next_token()? {
+            Token::CloseParen => Ok(Pattern::Binder(binder.unwrap(), Box::new(body))),
+            other => Err((ErrorKind::UnexpectedToken(other), lexer.token_start).into()),
+        }
+    }
+
+    fn parse_arguments(lexer: &mut Lexer<&[u8]>, head: Pattern) -> ParserResult<Self> {
+        let mut args = Vec::new();
+        let has_rest = loop {
+            match lexer.next_token()? {
+                Token::CloseParen => break false,
+                Token::Symbol(s) if s.as_str() == "..." => match lexer.next_token()? {
+                    Token::CloseParen => break true,
+                    other => {
+                        return Err((ErrorKind::UnexpectedToken(other), lexer.token_start).into())
+                    }
+                },
+                token => args.push(Self::parse_token(lexer, token)?),
+            }
+        };
+        if args.is_empty() && !has_rest {
+            return Err((ErrorKind::EmptySequence, lexer.token_start).into());
+        }
+        Ok(Pattern::App {
+            head: Box::new(head),
+            args,
+            has_rest,
+        })
+    }
+
+    /// Returns `true` if `term` matches the pattern.
+    pub fn matches(&self, term: &Term) -> bool {
+        self.matches_with(term, &mut HashMap::new())
+    }
+
+    /// If `term` matches the pattern, returns the term matched by each pattern variable, keyed by
+    /// the name of the variable without the leading "?".
+    pub fn match_bindings<'a>(&self, term: &'a Term) -> Option<HashMap<String, &'a Term>> {
+        let mut bindings = HashMap::new();
+        self.matches_with(term, &mut bindings).then_some(bindings)
+    }
+
+    fn matches_with<'a>(&self, term: &'a Term, bindings: &mut HashMap<String, &'a Term>) -> bool {
+        match (self, term) {
+            (Pattern::Any, _) => true,
+            (Pattern::Var(name), _) => match bindings.get(name) {
+                // Since terms are hash consed, comparing them by reference is enough
+                Some(bound) => std::ptr::eq(*bound, term),
+                None => {
+                    bindings.insert(name.clone(), term);
+                    true
+                }
+            },
+            (Pattern::Symbol(s), Term::Terminal(Terminal::Var(Identifier::Simple(name), _))) => {
+                name.as_str() == s
+            }
+            (Pattern::Integer(n), Term::Terminal(Terminal::Integer(i))) => n == i,
+            (Pattern::Real(r), Term::Terminal(Terminal::Real(x))) => r == x,
+            (Pattern::String(s), Term::Terminal(Terminal::String(x))) => s == x,
+            (
+                Pattern::App {
+                    head,
+                    args,
+                    has_rest,
+                },
+                Term::App(func, func_args),
+            ) => {
+                head.matches_with(func, bindings)
+                    && Self::arguments_match(args, *has_rest, func_args, bindings)
+            }
+            (
+                Pattern::App {
+                    head,
+                    args,
+                    has_rest,
+                },
+                Term::Op(op, op_args),
+            ) => {
+                let head_matches = match head.as_ref() {
+                    Pattern::Any => true,
+                    // Negation and subtraction are both written "-", so operators are compared by
+                    // name
+                    Pattern::Symbol(s) => format!("{:?}", op) == *s,
+                    _ => false,
+                };
+                head_matches && Self::arguments_match(args, *has_rest, op_args, bindings)
+            }
+            (Pattern::Binder(kind, body), term) => {
+                let inner = match (kind, term) {
+                    (BinderKind::Forall, Term::Quant(Quantifier::Forall, _, t))
+                    | (BinderKind::Exists, Term::Quant(Quantifier::Exists, _, t))
+                    | (BinderKind::Let, Term::Let(_, t))
+                    | (BinderKind::Choice, Term::Choice(_, t)) => t,
+                    _ => return false,
+                };
+                body.matches_with(inner, bindings)
+            }
+            _ => false,
+        }
+    }
+
+    fn arguments_match<'a>(
+        patterns: &[Pattern],
+        has_rest: bool,
+        args: &'a [ByRefRc<Term>],
+        bindings: &mut HashMap<String, &'a Term>,
+    ) -> bool {
+        let length_matches = if has_rest {
+            args.len() >= patterns.len()
+        } else {
+            args.len() == patterns.len()
+        };
+        length_matches
+            && patterns
+                .iter()
+                .zip(args)
+                .all(|(p, a)| p.matches_with(a, bindings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_term_with_definitions;
+
+    #[test]
+    fn parse_patterns() {
+        let valid = [
+            "_",
+            "?x",
+            "(f a b)",
+            "(= ?x ?x)",
+            "(and ...)",
+            "(or _ (not ?x) ...)",
+            "(_ a)",
+            "(forall ... (p ?x))",
+            "(let ... _)",
+            "(+ 1 1.5 \"s\")",
+        ];
+        for input in valid {
+            assert!(Pattern::parse(input).is_ok(), "{}", input);
+        }
+        let invalid = [
+            "",
+            "...",
+            "(f)",
+            "(f ... a)",
+            "(forall (x Int) _)",
+            "((f a) b)",
+            "(f a",
+            "a b",
+        ];
+        for input in invalid {
+            assert!(Pattern::parse(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn match_bindings() {
+        let definitions = "
+            (declare-fun a () Int)
+            (declare-fun b () Int)
+            (declare-fun f (Int) Int)
+        ";
+        let (term, _) = parse_term_with_definitions(definitions, "(= (f a) (+ b 1))").unwrap();
+        let bindings = |pattern: &str| {
+            let bindings = Pattern::parse(pattern).unwrap().match_bindings(&term)?;
+            let mut bindings: Vec<_> = bindings
+                .into_iter()
+                .map(|(name, term)| format!("{} = {:?}", name, term))
+                .collect();
+            bindings.sort();
+            Some(bindings)
+        };
+        assert_eq!(
+            Some(vec!["x = a".to_owned(), "y = (+ b 1)".to_owned()]),
+            bindings("(= (f ?x) ?y)")
+        );
+        assert_eq!(Some(vec!["x = b".to_owned()]), bindings("(= _ (+ ?x 1))"));
+        assert_eq!(Some(Vec::new()), bindings("(= (f a) _)"));
+        assert_eq!(None, bindings("(= (f b) _)"));
+        assert_eq!(None, bindings("(= ?x ?x)"));
+    }
+}
@@ -0,0 +1,381 @@
+//! Reconstruction of a problem from a proof that was parsed without its problem file. The problem
+//! declares the sorts and functions used in the proof, and asserts the term of each "assume"
+//! command at the top level of the proof, so the proof can be checked against it and shared when
+//! the original benchmark is not available.
+//!
+//! The parser gives symbols whose declarations were inferred fresh inferred sorts, which are
+//! resolved here by unifying the sorts that must be equal for the proof to be well sorted. For
+//! example, the arguments of an "and" term must be Bool, and the two sides of an equality must have
+//! the same sort. Inferred sorts that are still unknown after that are declared as new
+//! uninterpreted sorts, or are taken to be Int if they are the sorts of arithmetic terms.
+
+use crate::{
+    ast::*,
+    checker::{write_declare_fun, SmtLib},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write as _,
+};
+
+/// Builds an SMT-LIB problem for `proof`. `declarations` holds the declarations used by the proof,
+/// as returned by `parser::parse_proof_only_with_declarations`. New sorts are added to `pool`.
+/// Returns `None` if the inferred sorts can't be resolved consistently, for example if a symbol is
+/// used both as a Bool and as an Int.
+pub fn reconstruct_problem(
+    pool: &mut TermPool,
+    declarations: &Problem,
+    proof: &Proof,
+) -> Option<String> {
+    let mut inference = SortInference::default();
+    let mut visited = HashSet::new();
+    inference.constrain_commands(pool, &proof.0, &mut visited)?;
+
+    let mut sort_names: HashSet<_> = declarations
+        .sort_declarations
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect();
+    let mut problem = String::from("(set-logic ALL)\n");
+    for (name, arity) in &declarations.sort_declarations {
+        writeln!(problem, "(declare-sort {} {})", name, arity).unwrap();
+    }
+    let mut functions = Vec::with_capacity(declarations.function_declarations.len());
+    for (name, sort) in &declarations.function_declarations {
+        let sort = inference.resolve(pool, sort, &mut sort_names, &mut problem);
+        functions.push((name, sort));
+    }
+    for (name, sort) in functions {
+        write_declare_fun(&mut problem, name, &sort);
+    }
+    for command in &proof.0 {
+        if let ProofCommand::Assume { term, .. } = command {
+            writeln!(problem, "(assert {})", SmtLib(term)).unwrap();
+        }
+    }
+    writeln!(problem, "(check-sat)").unwrap();
+    Some(problem)
+}
+
+/// The constraints on the inferred sorts of a proof.
+#[derive(Default)]
+struct SortInference {
+    /// The sort each inferred sort was unified with, which may be another inferred sort.
+    bindings: HashMap<Term, Term>,
+
+    /// The inferred sorts that must be either Int or Real.
+    numeric: HashSet<Term>,
+
+    /// The sorts given to the inferred sorts that were left unknown.
+    defaults: HashMap<Term, ByRefRc<Term>>,
+}
+
+fn is_inferred(sort: &Term) -> bool {
+    matches!(sort, Term::Sort(SortKind::Inferred, _))
+}
+
+impl SortInference {
+    /// Follows the bindings of `sort`, if it is an inferred sort.
+    fn find(&self, sort: &Term) -> Term {
+        let mut sort = sort;
+        while let Some(bound) = self.bindings.get(sort) {
+            sort = bound;
+        }
+        sort.clone()
+    }
+
+    /// Returns true if the inferred sort `var` appears in `sort`.
+    fn occurs(&self, var: &Term, sort: &Term) -> bool {
+        let sort = self.find(sort);
+        match &sort {
+            Term::Sort(_, args) => sort == *var || args.iter().any(|a| self.occurs(var, a)),
+            _ => false,
+        }
+    }
+
+    fn unify(&mut self, a: &Term, b: &Term) -> Option<()> {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return Some(());
+        }
+        match (&a, &b) {
+            _ if is_inferred(&a) => self.bind(a, b),
+            _ if is_inferred(&b) => self.bind(b, a),
+            (Term::Sort(a_kind, a_args), Term::Sort(b_kind, b_args))
+                if a_kind == b_kind && a_args.len() == b_args.len() =>
+            {
+                for (a, b) in a_args.iter().zip(b_args) {
+                    self.unify(a, b)?;
+                }
+                Some(())
+            }
+            _ => None,
+        }
+    }
+
+    fn bind(&mut self, var: Term, sort: Term) -> Option<()> {
+        if self.occurs(&var, &sort) {
+            return None;
+        }
+        if self.numeric.contains(&var) {
+            self.require_numeric(&sort)?;
+        }
+        self.bindings.insert(var, sort);
+        Some(())
+    }
+
+    fn require_numeric(&mut self, sort: &Term) -> Option<()> {
+        let sort = self.find(sort);
+        if is_inferred(&sort) {
+            self.numeric.insert(sort);
+            Some(())
+        } else {
+            (sort == *Term::INT_SORT || sort == *Term::REAL_SORT).then_some(())
+        }
+    }
+
+    fn constrain_commands<'a>(
+        &mut self,
+        pool: &TermPool,
+        commands: &'a [ProofCommand],
+        visited: &mut HashSet<&'a Term>,
+    ) -> Option<()> {
+        for command in commands {
+            match command {
+                ProofCommand::Assume { term, .. } => self.constrain_formula(pool, term, visited)?,
+                ProofCommand::Step(step) => {
+                    for literal in &step.clause {
+                        self.constrain_formula(pool, literal, visited)?;
+                    }
+                    for arg in &step.args {
+                        let (ProofArg::Term(term) | ProofArg::Assign(_, term)) = arg;
+                        self.constrain_term(pool, term, visited)?;
+                    }
+                }
+                ProofCommand::Subproof {
+                    commands,
+                    assignment_args,
+                    ..
+                } => {
+                    for (_, value) in assignment_args {
+                        self.constrain_term(pool, value, visited)?;
+                    }
+                    self.constrain_commands(pool, commands, visited)?;
+                }
+            }
+        }
+        Some(())
+    }
+
+    fn constrain_formula<'a>(
+        &mut self,
+        pool: &TermPool,
+        term: &'a Term,
+        visited: &mut HashSet<&'a Term>,
+    ) -> Option<()> {
+        let sort = pool.sort(term)?;
+        self.unify(&sort, Term::BOOL_SORT)?;
+        self.constrain_term(pool, term, visited)
+    }
+
+    /// Adds the constraints that make `term` and its subterms well sorted. These follow the sort
+    /// checks done by the parser.
+    fn constrain_term<'a>(
+        &mut self,
+        pool: &TermPool,
+        term: &'a Term,
+        visited: &mut HashSet<&'a Term>,
+    ) -> Option<()> {
+        if !visited.insert(term) {
+            return Some(());
+        }
+        match term {
+            Term::Terminal(_) | Term::Sort(..) => (),
+            Term::App(f, args) => {
+                if let Term::Sort(SortKind::Function, sorts) = pool.sort(f)?.as_ref() {
+                    for (sort, arg) in sorts.iter().zip(args) {
+                        let arg_sort = pool.sort(arg)?;
+                        self.unify(sort, &arg_sort)?;
+                    }
+                }
+                for a in args {
+                    self.constrain_term(pool, a, visited)?;
+                }
+            }
+            Term::Op(op, args) => {
+                self.constrain_op(pool, *op, args)?;
+                for a in args {
+                    self.constrain_term(pool, a, visited)?;
+                }
+            }
+            Term::Quant(_, _, inner) | Term::Choice(_, inner) => {
+                self.constrain_formula(pool, inner, visited)?;
+            }
+            Term::Let(bindings, inner) => {
+                for (_, value) in bindings {
+                    self.constrain_term(pool, value, visited)?;
+                }
+                self.constrain_term(pool, inner, visited)?;
+            }
+        }
+        Some(())
+    }
+
+    fn constrain_op(
+        &mut self,
+        pool: &TermPool,
+        op: Operator,
+        args: &[ByRefRc<Term>],
+    ) -> Option<()> {
+        let arg_sorts = args
+            .iter()
+            .map(|a| pool.sort(a))
+            .collect::<Option<Vec<_>>>()?;
+        let sorts: Vec<&Term> = arg_sorts.iter().map(AsRef::as_ref).collect();
+        match op {
+            Operator::Not | Operator::Implies | Operator::And | Operator::Or | Operator::Xor => {
+                for s in sorts {
+                    self.unify(s, Term::BOOL_SORT)?;
+                }
+            }
+            Operator::Equals | Operator::Distinct => {
+                for s in &sorts[1..] {
+                    self.unify(sorts[0], s)?;
+                }
+            }
+            Operator::Ite => {
+                self.unify(sorts[0], Term::BOOL_SORT)?;
+                self.unify(sorts[1], sorts[2])?;
+            }
+            Operator::Add | Operator::Sub | Operator::Neg | Operator::Mult | Operator::Div => {
+                self.require_numeric(sorts[0])?;
+                for s in &sorts[1..] {
+                    self.unify(sorts[0], s)?;
+                }
+            }
+            Operator::LessThan | Operator::GreaterThan | Operator::LessEq | Operator::GreaterEq => {
+                // The parser accepts comparisons between Int and Real terms, but an argument of
+                // unknown sort is assumed to have the same sort as the others
+                for s in &sorts {
+                    self.require_numeric(s)?;
+                }
+                let known = sorts.iter().find(|s| !is_inferred(&self.find(s)));
+                let target = known.copied().unwrap_or(sorts[0]);
+                for s in &sorts {
+                    if is_inferred(&self.find(s)) {
+                        self.unify(s, target)?;
+                    }
+                }
+            }
+            Operator::IntDiv | Operator::Mod | Operator::Abs => {
+                for s in sorts {
+                    self.unify(s, Term::INT_SORT)?;
+                }
+            }
+            Operator::ToReal | Operator::Divisible => {
+                self.unify(sorts[sorts.len() - 1], Term::INT_SORT)?;
+            }
+            Operator::ToInt | Operator::IsInt => self.unify(sorts[0], Term::REAL_SORT)?,
+        }
+        Some(())
+    }
+
+    /// Replaces the inferred sorts in `sort` by the sorts they were unified with. Inferred sorts
+    /// that are still unknown are given a default sort, and the declarations of the new
+    /// uninterpreted sorts are written to `problem`. `sort_names` holds the names of the sorts
+    /// that were already declared.
+    fn resolve(
+        &mut self,
+        pool: &mut TermPool,
+        sort: &Term,
+        sort_names: &mut HashSet<String>,
+        problem: &mut String,
+    ) -> ByRefRc<Term> {
+        let sort = self.find(sort);
+        match sort {
+            Term::Sort(SortKind::Inferred, _) => {
+                if let Some(default) = self.defaults.get(&sort) {
+                    return default.clone();
+                }
+                let default = if self.numeric.contains(&sort) {
+                    pool.add_term(Term::INT_SORT.clone())
+                } else {
+                    let name = (0..)
+                        .map(|i| format!("S{}", i))
+                        .find(|name| !sort_names.contains(name))
+                        .unwrap();
+                    writeln!(problem, "(declare-sort {} 0)", name).unwrap();
+                    sort_names.insert(name.clone());
+                    let name = pool.add_term(Term::Terminal(Terminal::String(name)));
+                    pool.add_term(Term::Sort(SortKind::Atom, vec![name]))
+                };
+                self.defaults.insert(sort, default.clone());
+                default
+            }
+            Term::Sort(kind, args) => {
+                let args = args
+                    .iter()
+                    .map(|a| self.resolve(pool, a, sort_names, problem))
+                    .collect();
+                pool.add_term(Term::Sort(kind, args))
+            }
+            other => pool.add_term(other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        checker::{self, ProofChecker},
+        parser::{self, parse_proof_only_with_declarations, parse_proof_with_definitions},
+    };
+
+    fn run(proof: &str) -> Option<String> {
+        let (declarations, proof, mut pool) =
+            parse_proof_only_with_declarations(proof.as_bytes(), parser::Config::default())
+                .unwrap();
+        reconstruct_problem(&mut pool, &declarations, &proof)
+    }
+
+    #[test]
+    fn reconstruct() {
+        let proof = "
+            (assume h1 (and p (= (f a) (+ x 1))))
+            (assume h2 (not (= (f b) x)))
+            (assume h3 (= a b))
+            (assume h4 (forall ((y U)) (g y c)))
+            (step t5 (cl p) :rule and :premises (h1))
+            (step t6 (cl (= (f a) (f b))) :rule cong :premises (h3))
+        ";
+        let problem = run(proof).unwrap();
+        assert_eq!(
+            problem,
+            "(set-logic ALL)\n\
+             (declare-sort U 0)\n\
+             (declare-sort S0 0)\n\
+             (declare-sort S1 0)\n\
+             (declare-fun p () Bool)\n\
+             (declare-fun a () S0)\n\
+             (declare-fun f (S0) Int)\n\
+             (declare-fun x () Int)\n\
+             (declare-fun b () S0)\n\
+             (declare-fun c () S1)\n\
+             (declare-fun g (U S1) Bool)\n\
+             (assert (and p (= (f a) (+ x 1))))\n\
+             (assert (not (= (f b) x)))\n\
+             (assert (= a b))\n\
+             (assert (forall ((y U)) (g y c)))\n\
+             (check-sat)\n"
+        );
+
+        // The proof can be checked against the reconstructed problem
+        let (proof, mut pool) = parse_proof_with_definitions(&problem, proof).unwrap();
+        let result = ProofChecker::new(&mut pool, checker::Config::default()).check(&proof);
+        assert!(result.unwrap().is_valid());
+
+        // "x" must be both Bool and Int
+        assert_eq!(None, run("(assume h1 (or x (< x 0)))"));
+    }
+}
@@ -0,0 +1,281 @@
+//! An interactive prompt for inspecting a parsed proof.
+
+use verit_proof_checker::{ast::*, checker::*, pattern::Pattern};
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io::{self, BufRead, Write},
+};
+
+const HELP: &str = "\
+commands:
+  show <id>               prints the command with the given id
+  premises <id>           prints the premises of the command
+  dependents <id>         prints the commands that use the command as a premise
+  dependencies <id>       prints the ids of the commands the command depends on, transitively
+  check <id>              checks the command, assuming its premises are valid
+  match <id> <pattern>    matches the literals of the command's conclusion against a pattern
+  help                    prints this message
+  quit                    exits the prompt
+
+Patterns use the same syntax as the \"grep\" subcommand. For example, the pattern
+\"(= (f ?x) ?y)\" matches an equality whose left side is an application of \"f\", and binds the
+variables \"?x\" and \"?y\". A variable that appears more than once must match the same term
+every time, \"_\" matches any term without binding it, and other symbols match themselves.";
+
+/// A command read from the prompt.
+#[derive(Debug, PartialEq, Eq)]
+enum Command<'a> {
+    Empty,
+    Quit,
+    Help,
+    Dependencies(&'a str),
+    Show(&'a str),
+    Premises(&'a str),
+    Dependents(&'a str),
+    Check(&'a str),
+    Match { id: &'a str, pattern: &'a str },
+    Unknown(&'a str),
+}
+
+impl<'a> Command<'a> {
+    fn parse(line: &'a str) -> Self {
+        let (command, rest) = match line.trim().split_once(char::is_whitespace) {
+            Some((command, rest)) => (command, rest.trim()),
+            None => (line.trim(), ""),
+        };
+        match command {
+            "" => Command::Empty,
+            "quit" | "exit" => Command::Quit,
+            "help" => Command::Help,
+            "dependencies" => Command::Dependencies(rest),
+            "show" => Command::Show(rest),
+            "premises" => Command::Premises(rest),
+            "dependents" => Command::Dependents(rest),
+            "check" => Command::Check(rest),
+            "match" => {
+                let (id, pattern) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                Command::Match {
+                    id,
+                    pattern: pattern.trim(),
+                }
+            }
+            other => Command::Unknown(other),
+        }
+    }
+}
+
+pub fn run(proof: Proof, mut checker: ProofChecker) -> io::Result<()> {
+    let graph = DependencyGraph::new(&proof);
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        let command = Command::parse(&line);
+        if command == Command::Quit {
+            break;
+        }
+        let mut output = String::new();
+        execute(command, &proof, &graph, &mut checker, &mut output);
+        print!("{}", output);
+    }
+    Ok(())
+}
+
+/// Runs a command, and writes its output to `out`.
+fn execute(
+    command: Command,
+    proof: &Proof,
+    graph: &DependencyGraph,
+    checker: &mut ProofChecker,
+    out: &mut String,
+) {
+    let (id, argument) = match command {
+        Command::Empty | Command::Quit => return,
+        Command::Help => return writeln!(out, "{}", HELP).unwrap(),
+        Command::Unknown(other) => {
+            return writeln!(out, "unknown command \"{}\", type \"help\" for help", other).unwrap()
+        }
+        Command::Dependencies(id) => {
+            return match graph.dependencies(id) {
+                Some(ids) => writeln!(out, "{}", ids.join(" ")).unwrap(),
+                None => writeln!(out, "no command with id \"{}\"", id).unwrap(),
+            }
+        }
+        Command::Show(id)
+        | Command::Premises(id)
+        | Command::Dependents(id)
+        | Command::Check(id) => (id, ""),
+        Command::Match { id, pattern } => (id, pattern),
+    };
+    let location = match proof.find_command(id) {
+        Some(location) => location,
+        None => return writeln!(out, "no command with id \"{}\"", id).unwrap(),
+    };
+    let scopes = &location.scopes;
+    match command {
+        Command::Show(_) => writeln!(out, "{}", location.command().to_alethe(scopes)).unwrap(),
+        Command::Premises(_) => {
+            for &(d, i) in get_premises(location.command()) {
+                writeln!(out, "{}", scopes[d][i].to_alethe(scopes)).unwrap();
+            }
+        }
+        Command::Dependents(_) => {
+            let depth = scopes.len() - 1;
+            for c in location.commands {
+                if c.premises_at_depth(depth).contains(&location.index) {
+                    writeln!(out, "{}", c.to_alethe(scopes)).unwrap();
+                }
+            }
+        }
+        Command::Check(_) => check_step(proof, id, checker, out),
+        Command::Match { .. } => {
+            let pattern = match Pattern::parse(argument) {
+                Ok(p) => p,
+                Err(_) => return writeln!(out, "invalid pattern \"{}\"", argument).unwrap(),
+            };
+            let mut any_matched = false;
+            for (i, literal) in get_clause(location.command()).iter().enumerate() {
+                if let Some(bindings) = pattern.match_bindings(literal) {
+                    any_matched = true;
+                    writeln!(out, "literal {}: {}", i, format_bindings(bindings)).unwrap();
+                }
+            }
+            if !any_matched {
+                writeln!(out, "no literal matches").unwrap();
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn check_step(proof: &Proof, id: &str, checker: &mut ProofChecker, out: &mut String) {
+    match checker.check_step(proof, id) {
+        Ok(Correctness::True) => writeln!(out, "valid").unwrap(),
+        Ok(Correctness::TrueModulo(holes)) => {
+            writeln!(out, "valid, checked modulo {}", holes).unwrap()
+        }
+        Ok(Correctness::False(rule)) => {
+            writeln!(out, "invalid ({})", rule).unwrap();
+            if let Some(explanation) = checker.explanation() {
+                write!(out, "{}", explanation).unwrap();
+            }
+        }
+        Err(e) => writeln!(out, "error: {:?}", e).unwrap(),
+    }
+}
+
+fn get_premises(command: &ProofCommand) -> &[(usize, usize)] {
+    match command {
+        ProofCommand::Assume { .. } => &[],
+        ProofCommand::Step(s) => &s.premises,
+        ProofCommand::Subproof { commands, .. } => get_premises(commands.last().unwrap()),
+    }
+}
+
+fn get_clause(command: &ProofCommand) -> &[ByRefRc<Term>] {
+    match command {
+        ProofCommand::Assume { term, .. } => std::slice::from_ref(term),
+        ProofCommand::Step(s) => &s.clause,
+        ProofCommand::Subproof { commands, .. } => get_clause(commands.last().unwrap()),
+    }
+}
+
+/// Formats the bindings of a pattern match, sorted by variable name.
+fn format_bindings(bindings: HashMap<String, &Term>) -> String {
+    let mut bindings: Vec<_> = bindings.into_iter().collect();
+    bindings.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let bindings: Vec<_> = bindings
+        .into_iter()
+        .map(|(name, term)| format!("?{} = {:?}", name, term))
+        .collect();
+    bindings.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use verit_proof_checker::parser::parse_proof_with_definitions;
+
+    #[test]
+    fn parse_commands() {
+        let cases = [
+            ("", Command::Empty),
+            ("   ", Command::Empty),
+            ("quit", Command::Quit),
+            ("exit", Command::Quit),
+            ("help", Command::Help),
+            ("show t1", Command::Show("t1")),
+            ("  premises   t2.t1  ", Command::Premises("t2.t1")),
+            ("dependents h1", Command::Dependents("h1")),
+            ("dependencies t3", Command::Dependencies("t3")),
+            ("check t3", Command::Check("t3")),
+            (
+                "match t3 (= (f ?x)  ?y)",
+                Command::Match {
+                    id: "t3",
+                    pattern: "(= (f ?x)  ?y)",
+                },
+            ),
+            (
+                "match t3",
+                Command::Match {
+                    id: "t3",
+                    pattern: "",
+                },
+            ),
+            ("frobnicate t1", Command::Unknown("frobnicate")),
+        ];
+        for (line, expected) in cases {
+            assert_eq!(expected, Command::parse(line), "{}", line);
+        }
+    }
+
+    #[test]
+    fn execute_commands() {
+        let definitions = "
+            (declare-fun a () Int)
+            (declare-fun b () Int)
+            (declare-fun f (Int) Int)
+        ";
+        let proof = "
+            (assume h1 (= a b))
+            (step t2 (cl (= (f a) (f b))) :rule cong :premises (h1))
+            (step t3 (cl (= (f a) (f a))) :rule eq_reflexive)
+        ";
+        let (proof, mut pool) = parse_proof_with_definitions(definitions, proof).unwrap();
+        let graph = DependencyGraph::new(&proof);
+        let mut checker = ProofChecker::new(&mut pool, Config::default());
+        let mut run = |line: &str| {
+            let mut out = String::new();
+            execute(Command::parse(line), &proof, &graph, &mut checker, &mut out);
+            out
+        };
+
+        assert_eq!("(assume h1 (= a b))\n", run("show h1"));
+        assert_eq!("(assume h1 (= a b))\n", run("premises t2"));
+        assert_eq!(
+            "(step t2 (cl (= (f a) (f b))) :rule cong :premises (h1))\n",
+            run("dependents h1")
+        );
+        assert_eq!("valid\n", run("check t2"));
+        assert_eq!(
+            "literal 0: ?x = a, ?y = b\n",
+            run("match t2 (= (f ?x) (f ?y))")
+        );
+        assert_eq!("literal 0: ?x = (f a)\n", run("match t3 (= ?x ?x)"));
+        assert_eq!("no literal matches\n", run("match t2 (= ?x ?x)"));
+        assert_eq!("invalid pattern \"(= ?x\"\n", run("match t2 (= ?x"));
+        assert_eq!("no command with id \"t9\"\n", run("show t9"));
+        assert_eq!(
+            "unknown command \"frobnicate\", type \"help\" for help\n",
+            run("frobnicate")
+        );
+    }
+}
@@ -0,0 +1,252 @@
+//! A server that keeps a parsed problem and proof in memory, and answers JSON-RPC requests about
+//! them. Requests and responses are read from stdin and written to stdout, one per line.
+
+use verit_proof_checker::{ast::*, checker::*, json::Json, parser, Error};
+
+use std::io::{self, BufRead, Write};
+
+// The error codes defined by JSON-RPC, plus a code for errors specific to this server
+const PARSE_ERROR: f64 = -32700.0;
+const METHOD_NOT_FOUND: f64 = -32601.0;
+const INVALID_PARAMS: f64 = -32602.0;
+const SERVER_ERROR: f64 = -32000.0;
+
+type RequestResult = Result<Json, (f64, String)>;
+
+/// The state of the server: the problem and proof texts, and the result of parsing them.
+struct Server {
+    problem_text: String,
+    proof_text: String,
+    proof: Proof,
+    pool: TermPool,
+}
+
+impl Server {
+    fn new(problem_text: String, proof_text: String) -> Result<Self, Error> {
+        let (_, proof, pool) = parser::parse_problem_proof(
+            problem_text.as_bytes(),
+            proof_text.as_bytes(),
+            parser::Config::default(),
+        )?;
+        Ok(Self {
+            problem_text,
+            proof_text,
+            proof,
+            pool,
+        })
+    }
+
+    fn checker_config() -> Config {
+        Config {
+            skip_unknown_rules: true,
+            ..Default::default()
+        }
+    }
+
+    fn handle(&mut self, method: &str, params: &Json) -> RequestResult {
+        if !["getConclusion", "getPremises", "checkStep", "reparseStep"].contains(&method) {
+            return Err((METHOD_NOT_FOUND, format!("unknown method \"{}\"", method)));
+        }
+        let id = params
+            .get("id")
+            .and_then(Json::as_str)
+            .ok_or((INVALID_PARAMS, "missing step id".to_owned()))?;
+        if method == "reparseStep" {
+            let text = params
+                .get("text")
+                .and_then(Json::as_str)
+                .ok_or((INVALID_PARAMS, "missing step text".to_owned()))?;
+            return self.reparse_step(id, text);
+        }
+
+        let location = self
+            .proof
+            .find_command(id)
+            .ok_or((SERVER_ERROR, format!("no command with id \"{}\"", id)))?;
+        let command = location.command();
+        match method {
+            "getConclusion" => {
+                let clause: &[ByRefRc<Term>] = match command {
+                    ProofCommand::Assume { term, .. } => std::slice::from_ref(term),
+                    ProofCommand::Step(s) => &s.clause,
+                    ProofCommand::Subproof { commands, .. } => match commands.last() {
+                        Some(ProofCommand::Step(s)) => &s.clause,
+                        _ => &[],
+                    },
+                };
+                let clause = clause.iter().map(|t| format!("{:?}", t));
+                Ok(Json::object([
+                    ("command", Json::String(command.to_alethe(&location.scopes))),
+                    ("clause", Json::strings(clause)),
+                ]))
+            }
+            "getPremises" => {
+                let premises: &[(usize, usize)] = match command {
+                    ProofCommand::Assume { .. } => &[],
+                    ProofCommand::Step(s) => &s.premises,
+                    ProofCommand::Subproof { commands, .. } => match commands.last() {
+                        Some(ProofCommand::Step(s)) => &s.premises,
+                        _ => &[],
+                    },
+                };
+                let premises = premises.iter().map(|&(d, i)| location.scopes[d][i].id());
+                Ok(Json::object([("premises", Json::strings(premises))]))
+            }
+            "checkStep" => {
+                let mut checker = ProofChecker::new(&mut self.pool, Self::checker_config());
+                match checker.check_step(&self.proof, id) {
+                    Ok(Correctness::True) => Ok(Json::object([("valid", Json::Bool(true))])),
+                    Ok(Correctness::TrueModulo(holes)) => Ok(Json::object([
+                        ("valid", Json::Bool(true)),
+                        ("modulo", Json::String(holes.to_string())),
+                    ])),
+                    Ok(Correctness::False(rule)) => {
+                        let explanation = checker
+                            .explanation()
+                            .map(Explanation::to_json)
+                            .unwrap_or(Json::Null);
+                        Ok(Json::object([
+                            ("valid", Json::Bool(false)),
+                            ("rule", Json::String(rule)),
+                            ("explanation", explanation),
+                        ]))
+                    }
+                    Err(e) => Err((SERVER_ERROR, format!("{:?}", e))),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Replaces the text of the command with id `id` in the proof by `text`, and parses the
+    /// problem and proof again. If parsing fails, the previous proof is kept.
+    fn reparse_step(&mut self, id: &str, text: &str) -> RequestResult {
+        let (start, end) = find_command_span(&self.proof_text, id)
+            .ok_or((SERVER_ERROR, format!("no command with id \"{}\"", id)))?;
+        let mut new_text = self.proof_text.clone();
+        new_text.replace_range(start..end, text);
+
+        let new_server = Server::new(self.problem_text.clone(), new_text)
+            .map_err(|e| (SERVER_ERROR, format!("{:?}", e)))?;
+        *self = new_server;
+        Ok(Json::object([(
+            "numSteps",
+            Json::Number(self.proof.num_steps() as f64),
+        )]))
+    }
+}
+
+/// Finds the byte range in `proof` of the top-level command with id `id`. For anchors, this is the
+/// "step" command that ends the subproof, and not the "anchor" command.
+fn find_command_span(proof: &str, id: &str) -> Option<(usize, usize)> {
+    let mut depth = 0;
+    let mut start = 0;
+    let mut chars = proof.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '(' => {
+                if depth == 0 {
+                    start = i;
+                }
+                depth += 1;
+            }
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    let mut words = proof[start + 1..i].split_whitespace();
+                    let is_match = matches!(words.next(), Some("step" | "assume"))
+                        && words.next().map(|w| w.trim_end_matches('(')) == Some(id);
+                    if is_match {
+                        return Some((start, i + 1));
+                    }
+                }
+            }
+            // Parentheses inside strings, quoted symbols and comments are ignored
+            '"' | '|' => {
+                for (_, d) in chars.by_ref() {
+                    if d == c {
+                        break;
+                    }
+                }
+            }
+            ';' => {
+                for (_, d) in chars.by_ref() {
+                    if d == '\n' {
+                        break;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+pub fn run(problem_text: String, proof_text: String) -> Result<(), Error> {
+    let mut server = Server::new(problem_text, proof_text)?;
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (request_id, result) = match Json::parse(&line) {
+            Some(request) => {
+                let request_id = request.get("id").cloned().unwrap_or(Json::Null);
+                let method = request.get("method").and_then(Json::as_str);
+                let params = request.get("params").cloned().unwrap_or(Json::Null);
+                let result = match method {
+                    Some("shutdown") => break,
+                    Some(method) => server.handle(method, &params),
+                    None => Err((INVALID_PARAMS, "missing method".to_owned())),
+                };
+                (request_id, result)
+            }
+            None => (Json::Null, Err((PARSE_ERROR, "invalid JSON".to_owned()))),
+        };
+        let response = match result {
+            Ok(result) => Json::object([
+                ("jsonrpc", Json::String("2.0".to_owned())),
+                ("id", request_id),
+                ("result", result),
+            ]),
+            Err((code, message)) => Json::object([
+                ("jsonrpc", Json::String("2.0".to_owned())),
+                ("id", request_id),
+                (
+                    "error",
+                    Json::object([
+                        ("code", Json::Number(code)),
+                        ("message", Json::String(message)),
+                    ]),
+                ),
+            ]),
+        };
+        let mut stdout = io::stdout();
+        writeln!(stdout, "{}", response)?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_spans() {
+        let proof = "(assume h1 (= a b))
+            ; (step t2 (cl) :rule false)
+            (anchor :step t3)
+            (step t3.t1 (cl (= |)| \"(\")) :rule refl)
+            (step t3 (cl) :rule bind)";
+        let span = |id| find_command_span(proof, id).map(|(start, end)| &proof[start..end]);
+        assert_eq!(Some("(assume h1 (= a b))"), span("h1"));
+        assert_eq!(None, span("t2"));
+        assert_eq!(
+            Some("(step t3.t1 (cl (= |)| \"(\")) :rule refl)"),
+            span("t3.t1")
+        );
+        assert_eq!(Some("(step t3 (cl) :rule bind)"), span("t3"));
+    }
+}
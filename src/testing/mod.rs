@@ -0,0 +1,26 @@
+//! Utilities for testing rule implementations.
+
+pub mod proof_gen;
+
+use crate::{
+    checker::{self, Correctness, ProofChecker},
+    parser::parse_proof_with_definitions,
+};
+
+/// Parses and checks a proof, given as a string, using the definitions and declarations in
+/// `definitions`. The "trust_me" test rule is allowed in the proof. Returns `true` if the proof is
+/// valid, and `false` if it is invalid or if there was an error.
+pub fn check_proof_str(definitions: &str, proof: &str) -> bool {
+    let (proof, mut pool) = match parse_proof_with_definitions(definitions, proof) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    let config = checker::Config {
+        allow_test_rule: true,
+        ..Default::default()
+    };
+    matches!(
+        ProofChecker::new(&mut pool, config).check(&proof),
+        Ok(Correctness::True)
+    )
+}
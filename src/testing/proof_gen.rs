@@ -0,0 +1,399 @@
+//! A generator of random proofs, used to test rule implementations. For each supported rule, the
+//! generator can produce random valid instances of the rule, to test if the implementation accepts
+//! them, and random invalid instances, that are only subtly different from valid ones, to test if
+//! the implementation rejects them.
+
+use std::fmt::Write;
+
+/// The rules for which proofs can be generated.
+pub const SUPPORTED_RULES: &[&str] = &[
+    "trans",
+    "eq_transitive",
+    "cong",
+    "resolution",
+    "contraction",
+    "and",
+];
+
+/// A small xorshift pseudo-random number generator. We use our own generator so the generated
+/// proofs only depend on the seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // The state of a xorshift generator can't be zero
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    /// Returns a random number in the range `[low, high)`.
+    fn range(&mut self, low: usize, high: usize) -> usize {
+        low + (self.next_u64() % (high - low) as u64) as usize
+    }
+
+    fn coin_flip(&mut self) -> bool {
+        self.next_u64() & 1 == 0
+    }
+
+    fn shuffle<T>(&mut self, slice: &mut [T]) {
+        for i in (1..slice.len()).rev() {
+            let j = self.range(0, i + 1);
+            slice.swap(i, j);
+        }
+    }
+}
+
+/// A generated instance of a rule.
+#[derive(Debug, Clone)]
+pub struct GeneratedProof {
+    pub rule: &'static str,
+
+    /// The declarations needed by the proof, in the form of an SMT-LIB problem.
+    pub definitions: String,
+
+    /// The proof itself. Any premises are introduced by "assume" commands, or by steps that use
+    /// the "trust_me" test rule.
+    pub proof: String,
+
+    /// Whether the last step in the proof is a valid instance of the rule.
+    pub is_valid: bool,
+}
+
+impl GeneratedProof {
+    /// Checks the generated proof, and returns `true` if the result agrees with `is_valid`.
+    pub fn check(&self) -> bool {
+        super::check_proof_str(&self.definitions, &self.proof) == self.is_valid
+    }
+}
+
+/// A generator of random proofs. Given the same seed, the generator always produces the same
+/// sequence of proofs.
+pub struct ProofGenerator {
+    rng: Rng,
+}
+
+impl ProofGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// Generates a random instance of the rule `rule`. If `valid` is false, the instance is
+    /// changed so it is no longer valid. Returns `None` if the rule is not supported.
+    pub fn generate(&mut self, rule: &str, valid: bool) -> Option<GeneratedProof> {
+        let (rule, definitions, proof) = match rule {
+            "trans" => ("trans", Self::sort_decls(), self.trans(valid)),
+            "eq_transitive" => (
+                "eq_transitive",
+                Self::sort_decls(),
+                self.eq_transitive(valid),
+            ),
+            "cong" => ("cong", Self::sort_decls(), self.cong(valid)),
+            "resolution" => ("resolution", Self::bool_decls(), self.resolution(valid)),
+            "contraction" => ("contraction", Self::bool_decls(), self.contraction(valid)),
+            "and" => ("and", Self::bool_decls(), self.and(valid)),
+            _ => return None,
+        };
+        Some(GeneratedProof {
+            rule,
+            definitions,
+            proof,
+            is_valid: valid,
+        })
+    }
+
+    /// The maximum number of constants or variables used in a proof.
+    const MAX_SYMBOLS: usize = 8;
+
+    /// Declares a sort "T", and the constants "a0", "a1", ..., "b0", "b1", ..., of that sort,
+    /// besides the constant "d", which is used as a fresh constant. Also declares the functions
+    /// "f1", "f2", ..., where "fn" takes `n` arguments.
+    fn sort_decls() -> String {
+        let mut result = String::from("(declare-sort T 0)\n(declare-fun d () T)\n");
+        for i in 0..Self::MAX_SYMBOLS {
+            writeln!(result, "(declare-fun a{} () T)", i).unwrap();
+            writeln!(result, "(declare-fun b{} () T)", i).unwrap();
+            let args = vec!["T"; i + 1].join(" ");
+            writeln!(result, "(declare-fun f{} ({}) T)", i + 1, args).unwrap();
+        }
+        result
+    }
+
+    /// Declares the boolean constants "p0", "p1", ..., "x0", "x1", ..., and "r", which is used as
+    /// a fresh constant.
+    fn bool_decls() -> String {
+        let mut result = String::from("(declare-fun r () Bool)\n");
+        for i in 0..Self::MAX_SYMBOLS {
+            writeln!(result, "(declare-fun p{} () Bool)", i).unwrap();
+            writeln!(result, "(declare-fun x{} () Bool)", i).unwrap();
+        }
+        result
+    }
+
+    /// Returns the equality between `a` and `b`, possibly flipped.
+    fn random_equality(&mut self, a: &str, b: &str) -> String {
+        if self.rng.coin_flip() {
+            format!("(= {} {})", a, b)
+        } else {
+            format!("(= {} {})", b, a)
+        }
+    }
+
+    /// Generates a shuffled transitive chain of equalities from "a0" to "a{n}", and the two ends
+    /// of the chain. If `valid` is false, either a link in the chain is removed, or one of the ends
+    /// is replaced by the fresh constant "d".
+    fn transitive_chain(&mut self, valid: bool) -> (Vec<String>, String, String) {
+        let n = self.rng.range(2, Self::MAX_SYMBOLS);
+        let mut links: Vec<_> = (0..n)
+            .map(|i| self.random_equality(&format!("a{}", i), &format!("a{}", i + 1)))
+            .collect();
+        let (mut first, mut last) = ("a0".to_owned(), format!("a{}", n));
+        if !valid {
+            match self.rng.range(0, 3) {
+                0 => {
+                    let i = self.rng.range(0, n);
+                    links.remove(i);
+                }
+                1 => first = "d".to_owned(),
+                _ => last = "d".to_owned(),
+            }
+        }
+        self.rng.shuffle(&mut links);
+        (links, first, last)
+    }
+
+    fn trans(&mut self, valid: bool) -> String {
+        let (links, first, last) = self.transitive_chain(valid);
+        let mut proof = String::new();
+        let mut premises = Vec::new();
+        for (i, link) in links.iter().enumerate() {
+            writeln!(proof, "(assume h{} {})", i, link).unwrap();
+            premises.push(format!("h{}", i));
+        }
+        writeln!(
+            proof,
+            "(step t (cl (= {} {})) :rule trans :premises ({}))",
+            first,
+            last,
+            premises.join(" "),
+        )
+        .unwrap();
+        proof
+    }
+
+    fn eq_transitive(&mut self, valid: bool) -> String {
+        let (links, first, last) = self.transitive_chain(valid);
+        let clause: Vec<_> = links.iter().map(|l| format!("(not {})", l)).collect();
+        format!(
+            "(step t (cl {} (= {} {})) :rule eq_transitive)",
+            clause.join(" "),
+            first,
+            last,
+        )
+    }
+
+    /// Generates an instance of "cong" using a function application with some of its arguments
+    /// justified by premises, and the others being directly equal. If `valid` is false, one of the
+    /// arguments justified by a premise is replaced by the fresh constant "d".
+    fn cong(&mut self, valid: bool) -> String {
+        let arity = self.rng.range(1, Self::MAX_SYMBOLS + 1);
+        let mut f_args = Vec::new();
+        let mut g_args = Vec::new();
+        let mut premises = Vec::new();
+        for i in 0..arity {
+            let a = format!("a{}", i);
+            // We make sure that at least one argument is justified by a premise
+            if self.rng.coin_flip() || (i == arity - 1 && premises.is_empty()) {
+                let b = format!("b{}", i);
+                premises.push((i, self.random_equality(&a, &b)));
+                f_args.push(a);
+                g_args.push(b);
+            } else {
+                f_args.push(a.clone());
+                g_args.push(a);
+            }
+        }
+        if !valid {
+            let (i, _) = premises[self.rng.range(0, premises.len())];
+            g_args[i] = "d".to_owned();
+        }
+
+        let mut proof = String::new();
+        let mut premise_names = Vec::new();
+        for (i, premise) in premises {
+            writeln!(proof, "(assume h{} {})", i, premise).unwrap();
+            premise_names.push(format!("h{}", i));
+        }
+        writeln!(
+            proof,
+            "(step t (cl (= (f{n} {}) (f{n} {}))) :rule cong :premises ({}))",
+            f_args.join(" "),
+            g_args.join(" "),
+            premise_names.join(" "),
+            n = arity,
+        )
+        .unwrap();
+        proof
+    }
+
+    /// Returns `p` or `(not p)`, at random.
+    fn random_literal(&mut self, p: String) -> String {
+        if self.rng.coin_flip() {
+            p
+        } else {
+            format!("(not {})", p)
+        }
+    }
+
+    /// Generates a resolution chain, in which the pivots are "x0", "x1", ..., and each premise
+    /// contributes one literal of "p0", "p1", ... to the conclusion. If `valid` is false, either a
+    /// literal is removed from the conclusion, or the fresh literal "r" is added to it.
+    fn resolution(&mut self, valid: bool) -> String {
+        let num_pivots = self.rng.range(1, Self::MAX_SYMBOLS);
+        let mut proof = String::new();
+        let mut conclusion = Vec::new();
+        let mut premises = Vec::new();
+        for i in 0..=num_pivots {
+            let literal = self.random_literal(format!("p{}", i));
+            let mut clause = vec![literal.clone()];
+            if i > 0 {
+                clause.push(format!("(not x{})", i - 1));
+            }
+            if i < num_pivots {
+                clause.push(format!("x{}", i));
+            }
+            self.rng.shuffle(&mut clause);
+            writeln!(
+                proof,
+                "(step h{} (cl {}) :rule trust_me)",
+                i,
+                clause.join(" ")
+            )
+            .unwrap();
+            premises.push(format!("h{}", i));
+            conclusion.push(literal);
+        }
+        if !valid {
+            if self.rng.coin_flip() {
+                let i = self.rng.range(0, conclusion.len());
+                conclusion.remove(i);
+            } else {
+                conclusion.push("r".to_owned());
+            }
+        }
+        self.rng.shuffle(&mut conclusion);
+        writeln!(
+            proof,
+            "(step t (cl {}) :rule resolution :premises ({}))",
+            conclusion.join(" "),
+            premises.join(" "),
+        )
+        .unwrap();
+        proof
+    }
+
+    /// Generates a clause with repeated literals, and its contraction. If `valid` is false, either
+    /// a literal is removed from the conclusion, or two literals in the conclusion are swapped.
+    fn contraction(&mut self, valid: bool) -> String {
+        let n = self.rng.range(2, Self::MAX_SYMBOLS);
+        let mut premise = Vec::new();
+        for i in 0..n {
+            let literal = self.random_literal(format!("p{}", i));
+            for _ in 0..self.rng.range(1, 4) {
+                premise.push(literal.clone());
+            }
+        }
+        self.rng.shuffle(&mut premise);
+
+        let mut conclusion = Vec::new();
+        for literal in &premise {
+            if !conclusion.contains(literal) {
+                conclusion.push(literal.clone());
+            }
+        }
+        if !valid {
+            let i = self.rng.range(0, n);
+            if self.rng.coin_flip() {
+                conclusion.remove(i);
+            } else {
+                conclusion.swap(i, (i + 1) % n);
+            }
+        }
+        format!(
+            "(step h (cl {}) :rule trust_me)\n(step t (cl {}) :rule contraction :premises (h))",
+            premise.join(" "),
+            conclusion.join(" "),
+        )
+    }
+
+    /// Generates an instance of the "and" rule, possibly with the index of the conjunct as an
+    /// argument. If `valid` is false, either the conclusion is replaced by the fresh constant "r",
+    /// or the index argument is wrong.
+    fn and(&mut self, valid: bool) -> String {
+        let n = self.rng.range(2, Self::MAX_SYMBOLS);
+        let conjuncts: Vec<_> = (0..n)
+            .map(|i| self.random_literal(format!("p{}", i)))
+            .collect();
+        let index = self.rng.range(0, n);
+        let use_index_arg = self.rng.coin_flip();
+        let (conclusion, index) = match (valid, use_index_arg) {
+            (true, _) => (conjuncts[index].clone(), index),
+            (false, true) => (conjuncts[index].clone(), (index + 1) % n),
+            (false, false) => ("r".to_owned(), index),
+        };
+        let args = if use_index_arg {
+            format!(" :args ({})", index)
+        } else {
+            String::new()
+        };
+        format!(
+            "(assume h (and {}))\n(step t (cl {}) :rule and :premises (h){})",
+            conjuncts.join(" "),
+            conclusion,
+            args,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_proofs() {
+        const NUM_CASES: u64 = 50;
+
+        for &rule in SUPPORTED_RULES {
+            for seed in 0..NUM_CASES {
+                let mut generator = ProofGenerator::new(seed);
+                for &valid in &[true, false] {
+                    let generated = generator.generate(rule, valid).unwrap();
+                    assert!(
+                        generated.check(),
+                        "generated proof for rule \"{}\" with seed {} should be {}:\n{}",
+                        rule,
+                        seed,
+                        if valid { "valid" } else { "invalid" },
+                        generated.proof,
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unsupported_rule() {
+        assert!(ProofGenerator::new(0)
+            .generate("not_a_rule", true)
+            .is_none());
+    }
+}
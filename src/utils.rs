@@ -1,3 +1,5 @@
+use num_bigint::BigInt;
+use num_traits::{Signed, Zero};
 use std::{collections::HashSet, hash::Hash};
 
 /// An enum that can hold one of two types. Similar to `Result`, but doesn't imply that one of the
@@ -50,3 +52,21 @@ impl<T, I: Iterator<Item = T>> DedupIterator<T> for I {
         }
     }
 }
+
+/// Computes the quotient and remainder of the integer division of `m` by `n`, following the
+/// semantics of the SMT-LIB "div" and "mod" operators. That is, the remainder is always
+/// non-negative, so for example the quotient and remainder of -7 by 2 are -4 and 1. Returns `None`
+/// if `n` is zero, since division by zero is left unspecified by SMT-LIB.
+pub fn euclidean_div_mod(m: &BigInt, n: &BigInt) -> Option<(BigInt, BigInt)> {
+    if n.is_zero() {
+        return None;
+    }
+    let (q, r) = (m / n, m % n);
+    Some(if !r.is_negative() {
+        (q, r)
+    } else if n.is_positive() {
+        (q - 1, r + n)
+    } else {
+        (q + 1, r - n)
+    })
+}
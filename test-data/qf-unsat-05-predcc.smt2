@@ -0,0 +1,10 @@
+(set-logic QF_UF)
+(set-info :status unsat)
+(declare-sort U 0)
+(declare-fun a () U)
+(declare-fun b () U)
+(declare-fun p (U) Bool)
+(assert (p a))
+(assert (= a b))
+(assert (not (p b)))
+(check-sat)
@@ -0,0 +1,11 @@
+(set-logic QF_LRA)
+(set-info :smt-lib-version 2.0)
+(set-info :category "crafted")
+(set-info :status unsat)
+(declare-fun a () Real)
+(assert (= a 1))
+(assert (= a 2))
+(assert (< a 0))
+(check-sat)
+(exit)
+
@@ -0,0 +1,37 @@
+use std::{env, path::Path};
+
+use verit_proof_checker::*;
+
+/// Checks all problem and proof pairs in the "test-data" directory. Proofs from real solver runs
+/// can be added there to make sure they keep being checked correctly as rules are changed. If the
+/// directory is missing, the test is skipped, unless the `REQUIRE_TEST_DATA` environment variable
+/// is set, in which case it fails.
+#[test]
+fn test_corpus() {
+    let dir = Path::new("test-data");
+    if !dir.is_dir() {
+        assert!(
+            env::var_os("REQUIRE_TEST_DATA").is_none(),
+            "\"test-data\" directory not found, but REQUIRE_TEST_DATA is set",
+        );
+        eprintln!("skipping corpus test: \"test-data\" directory not found");
+        return;
+    }
+    let entries = check_corpus(dir, parser::Config::default(), checker::Config::default)
+        .expect("error while reading test data directory");
+
+    let failures: Vec<_> = entries.iter().filter(|e| !e.is_valid()).collect();
+    for entry in &failures {
+        eprintln!(
+            "file \"{}\" failed: {:?}",
+            entry.proof_path.display(),
+            entry.result,
+        );
+    }
+    assert!(
+        failures.is_empty(),
+        "{} out of {} files failed",
+        failures.len(),
+        entries.len(),
+    );
+}
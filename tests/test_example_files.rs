@@ -3,11 +3,15 @@ use std::{ffi::OsStr, fs, io, path::Path};
 use verit_proof_checker::*;
 
 fn test_file(problem_path: &Path, proof_path: &Path) {
-    use checker::Correctness;
+    use checker::{Config, Correctness};
     use parser::error::{ErrorKind, ParserError};
 
-    match check(problem_path, proof_path, true, false) {
-        Ok(Correctness::True)
+    let config = Config {
+        skip_unknown_rules: true,
+        ..Default::default()
+    };
+    match check(problem_path, proof_path, parser::Config::default(), config) {
+        Ok(Correctness::True | Correctness::TrueModulo(_))
         | Err(Error::Parser(ParserError(ErrorKind::NotYetImplemented, _))) => (),
         Ok(Correctness::False(rule)) => panic!(
             "\ntest file \"{}\"\nfailed on rule \"{}\"\n",